@@ -0,0 +1,183 @@
+use crate::error::Error;
+use crate::fixed::saturate_i16;
+
+/// Format code of IMA ADPCM in the fmt chunk
+pub(crate) const IMA_ADPCM: u16 = 0x11;
+/// Largest channel count the IMA ADPCM decoder handles
+pub(crate) const MAX_ADPCM_CHANNELS: usize = 8;
+
+const INDEX_TABLE: [i8; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+const STEP_TABLE: [i16; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+/// Decoder state of a single channel, reset by the header at the start of every block
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ImaState {
+    predictor: i16,
+    step_index: u8,
+}
+
+impl ImaState {
+    /// Read the 4 byte block header of a channel: predictor, step index and a reserved byte
+    pub(crate) fn from_header(bytes: &[u8]) -> Self {
+        match bytes {
+            [p0, p1, step_index, ..] => ImaState {
+                predictor: i16::from_le_bytes([*p0, *p1]),
+                step_index: (*step_index).min(88),
+            },
+            _ => ImaState::default(),
+        }
+    }
+
+    /// First sample of the block, stored uncompressed in the header
+    pub(crate) fn predictor(&self) -> i16 {
+        self.predictor
+    }
+
+    /// Expand a 4 bit code into the next 16 bit sample
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.step_index as usize] as i32;
+
+        let mut diff = step >> 3;
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+
+        let predictor = if nibble & 8 != 0 {
+            self.predictor as i32 - diff
+        } else {
+            self.predictor as i32 + diff
+        };
+
+        self.predictor = saturate_i16(predictor);
+        self.step_index =
+            (self.step_index as i8 + INDEX_TABLE[(nibble & 0x0f) as usize]).clamp(0, 88) as u8;
+
+        self.predictor
+    }
+}
+
+/// Number of frames in a block of `block_size` bytes
+pub(crate) fn frames_per_block(block_size: usize, num_channels: usize) -> usize {
+    let header = 4 * num_channels;
+
+    match block_size.checked_sub(header) {
+        Some(data) if num_channels > 0 => data / header * 8 + 1,
+        _ => 0,
+    }
+}
+
+/// Decode one group of 4 bytes per channel into 8 interleaved frames.
+///
+/// `group` holds `4 * states.len()` bytes and `out` has room for `8 * states.len()` samples.
+pub(crate) fn decode_group(states: &mut [ImaState], group: &[u8], out: &mut [i16]) {
+    let channels = states.len();
+
+    for (channel, (state, word)) in states.iter_mut().zip(group.chunks_exact(4)).enumerate() {
+        for (i, byte) in word.iter().enumerate() {
+            out[2 * i * channels + channel] = state.decode(byte & 0x0f);
+            out[(2 * i + 1) * channels + channel] = state.decode(byte >> 4);
+        }
+    }
+}
+
+/// Decode a single IMA ADPCM block into interleaved 16 bit PCM, returns the number of samples written.
+///
+/// A truncated block decodes as far as it goes. Does no IO and never panics, whatever the input.
+pub fn decode_ima_block(num_channels: u16, block: &[u8], out: &mut [i16]) -> Result<usize, Error> {
+    let channels = num_channels as usize;
+
+    if channels == 0 || channels > MAX_ADPCM_CHANNELS {
+        return Err(Error::UnsupportedFormat(IMA_ADPCM));
+    }
+
+    let header = block.get(..4 * channels).ok_or(Error::CantParseSliceInto)?;
+    let needed = frames_per_block(block.len(), channels) * channels;
+
+    if out.len() < needed {
+        return Err(Error::BufferTooSmall(needed * 2));
+    }
+
+    let mut states = [ImaState::default(); MAX_ADPCM_CHANNELS];
+    let states = &mut states[..channels];
+
+    for ((state, header), out) in states
+        .iter_mut()
+        .zip(header.chunks_exact(4))
+        .zip(out.iter_mut())
+    {
+        *state = ImaState::from_header(header);
+        *out = state.predictor();
+    }
+
+    let mut written = channels;
+
+    for group in block[4 * channels..].chunks_exact(4 * channels) {
+        decode_group(states, group, &mut out[written..written + 8 * channels]);
+        written += 8 * channels;
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_decode_mono_block() {
+        // predictor 0, step index 0, then codes 0..7 and 8..15
+        let block = [0, 0, 0, 0, 0x10, 0x32, 0x98, 0xba];
+        let mut out = [0; 9];
+
+        assert_eq!(decode_ima_block(1, &block, &mut out).unwrap(), 9);
+        assert_eq!(out, [0, 0, 1, 4, 8, 8, 7, 4, 0]);
+    }
+
+    #[test]
+    fn should_interleave_stereo_block() {
+        let block = [
+            0x10, 0x00, 0, 0, // left predictor 16
+            0xf0, 0xff, 0, 0, // right predictor -16
+            0x77, 0x77, 0x77, 0x77, // left keeps rising
+            0xff, 0xff, 0xff, 0xff, // right keeps falling
+        ];
+        let mut out = [0; 18];
+
+        assert_eq!(decode_ima_block(2, &block, &mut out).unwrap(), 18);
+        assert_eq!(out[..2], [16, -16]);
+        assert!(out.chunks(2).all(|frame| frame[0] >= 16 && frame[1] <= -16));
+        assert!(out[16] > out[2] && out[17] < out[3]);
+    }
+
+    #[test]
+    fn should_not_panic_on_bad_blocks() {
+        let mut out = [0; 64];
+
+        assert_eq!(
+            decode_ima_block(1, &[0, 0], &mut out),
+            Err(Error::CantParseSliceInto)
+        );
+        assert_eq!(
+            decode_ima_block(0, &[0; 8], &mut out),
+            Err(Error::UnsupportedFormat(IMA_ADPCM))
+        );
+        assert_eq!(
+            decode_ima_block(1, &[0xff; 36], &mut out[..8]),
+            Err(Error::BufferTooSmall(130))
+        );
+        assert_eq!(decode_ima_block(1, &[0xff; 33], &mut out), Ok(57));
+    }
+}
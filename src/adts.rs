@@ -0,0 +1,193 @@
+use crate::error::Error;
+use crate::sink::AudioSink;
+
+/// Size of an ADTS header without CRC
+const HEADER_SIZE: usize = 7;
+/// Size of an ADTS header followed by a CRC
+const HEADER_SIZE_CRC: usize = 9;
+/// Largest frame length, header included, the 13 bit length field holds
+const MAX_FRAME_LEN: usize = 0x1fff;
+
+/// Stream parameters repeated in every ADTS header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdtsConfig {
+    /// MPEG-4 audio object type minus one, `1` for AAC LC
+    pub profile: u8,
+    /// Index into the MPEG-4 sample rate table, e.g. `3` for 48 kHz or `4` for 44.1 kHz
+    pub sample_rate_index: u8,
+    /// MPEG-4 channel configuration, `2` for stereo
+    pub channel_config: u8,
+}
+
+/// Header in front of every AAC frame of an ADTS stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdtsHeader {
+    /// Stream parameters of the frame
+    pub config: AdtsConfig,
+    /// Length of the whole frame in bytes, header included
+    pub frame_len: usize,
+    /// True when a CRC follows the header
+    pub has_crc: bool,
+}
+
+impl AdtsHeader {
+    /// Parse the header at the start of `bytes`
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let b = bytes.get(..HEADER_SIZE).ok_or(Error::InvalidFrame)?;
+
+        // 12 bit sync word and layer 0
+        if b[0] != 0xff || b[1] & 0xf6 != 0xf0 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let header = AdtsHeader {
+            config: AdtsConfig {
+                profile: b[2] >> 6,
+                sample_rate_index: (b[2] >> 2) & 0x0f,
+                channel_config: ((b[2] & 0x01) << 2) | (b[3] >> 6),
+            },
+            frame_len: ((b[3] as usize & 0x03) << 11)
+                | ((b[4] as usize) << 3)
+                | (b[5] as usize >> 5),
+            has_crc: b[1] & 0x01 == 0,
+        };
+
+        if header.frame_len < header.header_len() {
+            return Err(Error::InvalidFrame);
+        }
+
+        Ok(header)
+    }
+
+    /// Size of the header in bytes, CRC included
+    pub fn header_len(&self) -> usize {
+        if self.has_crc {
+            HEADER_SIZE_CRC
+        } else {
+            HEADER_SIZE
+        }
+    }
+
+    /// Serialize as an MPEG-4 header without CRC and with a variable bitrate buffer fullness
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let AdtsConfig {
+            profile,
+            sample_rate_index,
+            channel_config,
+        } = self.config;
+        let len = self.frame_len;
+
+        [
+            0xff,
+            0xf1,
+            (profile << 6) | ((sample_rate_index & 0x0f) << 2) | ((channel_config >> 2) & 0x01),
+            ((channel_config & 0x03) << 6) | ((len >> 11) as u8 & 0x03),
+            (len >> 3) as u8,
+            ((len as u8 & 0x07) << 5) | 0x1f,
+            0xfc,
+        ]
+    }
+}
+
+/// What an [`AdtsSink`] does with the ADTS header of every frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdtsMode {
+    /// Frames are written as they come
+    Keep,
+    /// Frames come with a header that is removed, for decoders expecting raw AAC
+    Strip,
+    /// Raw frames get a header with the given parameters, for decoders expecting ADTS
+    Add(AdtsConfig),
+}
+
+/// [`AudioSink`] adapter handing AAC frames to a hardware decoder in the framing it expects
+#[derive(Debug)]
+pub struct AdtsSink<K> {
+    inner: K,
+    mode: AdtsMode,
+}
+
+impl<K: AudioSink> AdtsSink<K> {
+    /// Wrap `inner`, treating the header of every frame according to `mode`
+    pub fn new(inner: K, mode: AdtsMode) -> Self {
+        AdtsSink { inner, mode }
+    }
+
+    /// Hand back the wrapped sink
+    pub fn into_inner(self) -> K {
+        self.inner
+    }
+
+    /// Write one whole AAC frame, stripping or adding its ADTS header as configured
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<(), Error> {
+        match self.mode {
+            AdtsMode::Keep => self.inner.write(frame).map_err(|_| Error::Io),
+            AdtsMode::Strip => {
+                let header = AdtsHeader::parse(frame)?;
+                let payload = frame
+                    .get(header.header_len()..header.frame_len)
+                    .ok_or(Error::InvalidFrame)?;
+
+                self.inner.write(payload).map_err(|_| Error::Io)
+            }
+            AdtsMode::Add(config) => {
+                let header = AdtsHeader {
+                    config,
+                    frame_len: HEADER_SIZE + frame.len(),
+                    has_crc: false,
+                };
+
+                if header.frame_len > MAX_FRAME_LEN {
+                    return Err(Error::InvalidFrame);
+                }
+
+                self.inner
+                    .write(&header.to_bytes())
+                    .map_err(|_| Error::Io)?;
+                self.inner.write(frame).map_err(|_| Error::Io)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+
+    const CONFIG: AdtsConfig = AdtsConfig {
+        profile: 1,
+        sample_rate_index: 3,
+        channel_config: 2,
+    };
+
+    #[test]
+    fn should_add_and_strip_headers() {
+        let payload = [0x21, 0x10, 0x05, 0x00];
+
+        let mut framed = [0; 16];
+        let mut sink = AdtsSink::new(SliceSink::new(&mut framed), AdtsMode::Add(CONFIG));
+        sink.write_frame(&payload).unwrap();
+        let framed = sink.into_inner();
+
+        let header = AdtsHeader::parse(framed.written()).unwrap();
+        assert_eq!(header.config, CONFIG);
+        assert_eq!(header.frame_len, 11);
+        assert!(!header.has_crc);
+
+        let mut raw = [0; 16];
+        let mut sink = AdtsSink::new(SliceSink::new(&mut raw), AdtsMode::Strip);
+        sink.write_frame(framed.written()).unwrap();
+
+        assert_eq!(sink.into_inner().written(), payload);
+    }
+
+    #[test]
+    fn should_reject_frames_without_sync() {
+        let mut raw = [0; 16];
+        let mut sink = AdtsSink::new(SliceSink::new(&mut raw), AdtsMode::Strip);
+
+        assert_eq!(sink.write_frame(&[0; 8]), Err(Error::InvalidFrame));
+        assert_eq!(AdtsHeader::parse(&[0xff, 0xf1]), Err(Error::InvalidFrame));
+    }
+}
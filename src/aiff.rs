@@ -0,0 +1,228 @@
+use crate::error::Error;
+use crate::fmt::{AudioCodec, Fmt};
+use crate::riff::{Chunk, ChunkTag, ChunkWalker};
+use crate::source::AudioSource;
+use crate::wav::{read_full, Header};
+use core::convert::TryInto;
+use heapless::Vec;
+
+/// Size of the fixed part of a `COMM` chunk, AIFF-C appends the compression type
+const COMM_SIZE: usize = 18;
+/// Size of the offset and block size fields in front of the samples of a `SSND` chunk
+const SSND_HEADER_SIZE: usize = 8;
+
+/// Convert the 80 bit extended float sample rate of a `COMM` chunk, fractions are dropped
+fn sample_rate_from_extended(bytes: &[u8; 10]) -> u32 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32 - 16383;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap_or_default());
+
+    match exponent {
+        0..=31 => (mantissa >> (63 - exponent)) as u32,
+        _ => 0,
+    }
+}
+
+/// Map the body of a `COMM` chunk onto a [`Fmt`], `aifc` tells whether a compression type follows
+pub(crate) fn fmt_from_comm(body: &[u8], aifc: bool) -> Result<Fmt, Error> {
+    if aifc {
+        match body.get(COMM_SIZE..COMM_SIZE + 4) {
+            None | Some(b"NONE") | Some(b"twos") => {}
+            Some(kind) => {
+                return Err(Error::UnsupportedCompression(
+                    kind.try_into().unwrap_or_default(),
+                ))
+            }
+        }
+    }
+
+    let body = body
+        .get(..COMM_SIZE)
+        .ok_or(Error::CantParseChunk(ChunkTag::Comm))?;
+
+    let num_channels = u16::from_be_bytes([body[0], body[1]]);
+    let sample_size = u16::from_be_bytes([body[6], body[7]]);
+    let sample_rate = sample_rate_from_extended(body[8..18].try_into().unwrap_or(&[0; 10]));
+
+    // samples are left aligned in whole bytes, so e.g. 12 bit audio reads as 16 bit
+    let bit_depth = match sample_size.div_ceil(8) {
+        bytes @ 1..=3 => bytes * 8,
+        _ => return Err(Error::UnsupportedBitDepth(sample_size)),
+    };
+
+    Ok(Fmt {
+        codec: AudioCodec::PcmBigEndian,
+        sample_rate,
+        num_channels,
+        bit_depth,
+        block_size: num_channels.saturating_mul(bit_depth / 8),
+    })
+}
+
+/// Walk the big endian chunk headers of an AIFF or AIFF-C file, whose `FORM` header was read into
+/// `form`, until both the `COMM` and the `SSND` chunk are found
+pub(crate) fn scan_header<S: AudioSource>(
+    source: &mut S,
+    form: &[u8],
+) -> Result<Header, Error<S::Error>> {
+    let aifc = match form.get(8..12) {
+        Some(b"AIFF") => false,
+        Some(b"AIFC") => true,
+        _ => return Err(Error::NoWaveTagFound),
+    };
+
+    let length = source.length() as usize;
+    let mut chunks = Vec::new();
+    let mut fmt = None;
+    let mut data = None;
+
+    let mut walker = ChunkWalker::new(length).big_endian();
+
+    while fmt.is_none() || data.is_none() {
+        let chunk = match walker.next_chunk(source)? {
+            Some(chunk) => chunk,
+            None => break,
+        };
+
+        // the sound data is checked by the caller
+        if chunk.id != ChunkTag::Ssnd {
+            chunk.check_within(length).map_err(Error::widen)?;
+        }
+
+        match chunk.id {
+            ChunkTag::Comm => {
+                let mut body = [0; COMM_SIZE + 4];
+                let len = body.len().min(chunk.end - chunk.start);
+                let read = read_full(source, &mut body[..len])?;
+
+                fmt = Some(fmt_from_comm(&body[..read], aifc).map_err(Error::widen)?);
+            }
+            ChunkTag::Ssnd => {
+                let mut offset = [0; SSND_HEADER_SIZE];
+
+                if read_full(source, &mut offset)? != offset.len() {
+                    return Err(Error::CantParseChunk(ChunkTag::Ssnd));
+                }
+
+                let skip = u32::from_be_bytes([offset[0], offset[1], offset[2], offset[3]]);
+                let start = chunk.start + SSND_HEADER_SIZE + skip as usize;
+
+                data = Some(Chunk {
+                    id: ChunkTag::Data,
+                    start: start.min(chunk.end),
+                    end: chunk.end,
+                });
+            }
+            // chunks past MAX_CHUNKS are skipped
+            _ => {
+                let _ = chunks.push(chunk);
+            }
+        }
+    }
+
+    Ok(Header {
+        fmt: fmt.ok_or(Error::NoFmtChunkFound)?,
+        data: data.ok_or(Error::NoDataChunkFound)?,
+        chunks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::{decode_block, DataBulk, Wav};
+
+    const AIFF: [u8; 74] = [
+        0x46, 0x4f, 0x52, 0x4d, // FORM
+        0x00, 0x00, 0x00, 0x42, // size 66
+        0x41, 0x49, 0x46, 0x46, // AIFF
+        0x43, 0x4f, 0x4d, 0x4d, // COMM
+        0x00, 0x00, 0x00, 0x12, // size 18
+        0x00, 0x02, // 2 channels
+        0x00, 0x00, 0x00, 0x02, // 2 frames
+        0x00, 0x10, // 16 bit
+        0x40, 0x0e, 0xac, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // 44_100
+        0x4e, 0x41, 0x4d, 0x45, // NAME
+        0x00, 0x00, 0x00, 0x03, // size 3
+        0x61, 0x62, 0x63, 0x00, // "abc" and padding
+        0x53, 0x53, 0x4e, 0x44, // SSND
+        0x00, 0x00, 0x00, 0x10, // size 16
+        0x00, 0x00, 0x00, 0x00, // offset
+        0x00, 0x00, 0x00, 0x00, // block size
+        0x01, 0x02, 0xff, 0xfe, 0x7f, 0xff, 0x80, 0x00, // samples
+    ];
+
+    #[test]
+    fn should_convert_extended_sample_rates() {
+        let rate = |bytes: [u8; 2], mantissa: u16| {
+            let mut extended = [0; 10];
+            extended[..2].copy_from_slice(&bytes);
+            extended[2..4].copy_from_slice(&mantissa.to_be_bytes());
+            sample_rate_from_extended(&extended)
+        };
+
+        assert_eq!(rate([0x40, 0x0e], 0xac44), 44_100);
+        assert_eq!(rate([0x40, 0x0e], 0xbb80), 48_000);
+        assert_eq!(rate([0x40, 0x0b], 0xfa00), 8_000);
+    }
+
+    #[test]
+    fn should_read_big_endian_samples() {
+        let mut wav = Wav::from_bytes(&AIFF).unwrap();
+
+        assert_eq!(wav.fmt.codec, AudioCodec::PcmBigEndian);
+        assert_eq!(wav.fmt.sample_rate, 44_100);
+        assert_eq!(wav.fmt.num_channels, 2);
+        assert_eq!(wav.fmt.bit_depth, 16);
+        assert_eq!(wav.chunks.len(), 1);
+
+        match wav.next_n::<8>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [0x0102, -2, i16::MAX, i16::MIN]),
+            _ => unreachable!(),
+        }
+
+        assert!(wav.is_end());
+    }
+
+    #[test]
+    fn should_clamp_a_sound_data_offset_past_the_end() {
+        let mut bytes = AIFF;
+        bytes[54..58].copy_from_slice(&0x1000u32.to_be_bytes());
+        bytes[58..62].copy_from_slice(&0x800u32.to_be_bytes());
+
+        let mut wav = Wav::new(crate::source::SliceSource::new(&bytes)).unwrap();
+        assert_eq!((wav.data.start, wav.data.end), (74, 74));
+        assert!(wav.is_end());
+
+        let _: crate::metadata::Metadata<16> = wav.load_trailing_metadata().unwrap();
+        assert_eq!(Wav::from_bytes(&bytes).unwrap().data.start, 74);
+    }
+
+    #[test]
+    fn should_flip_sign_of_8_bit_samples() {
+        let fmt = fmt_from_comm(
+            &[
+                0, 1, 0, 0, 0, 2, 0, 8, 0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            false,
+        )
+        .unwrap();
+
+        match decode_block::<4>(&fmt, &[0x00, 0x80, 0x7f]).unwrap() {
+            DataBulk::BitDepth8(samples) => assert_eq!(samples, [0x80, 0x00, 0xff]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_reject_compressed_aifc() {
+        let mut comm = [0; COMM_SIZE + 4];
+        comm[1] = 1;
+        comm[7] = 16;
+        comm[COMM_SIZE..].copy_from_slice(b"ulaw");
+
+        assert_eq!(
+            fmt_from_comm(&comm, true).unwrap_err(),
+            Error::UnsupportedCompression(*b"ulaw")
+        );
+    }
+}
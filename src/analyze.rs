@@ -0,0 +1,202 @@
+use crate::error::Error;
+use crate::source::AudioSource;
+use crate::timestamp::Timestamp;
+use crate::wav::{DataBulk, Wav};
+
+/// Level statistics of one channel, sample values are relative to full scale, i.e. -1.0 to 1.0.
+/// All `0.0` for a file without samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelStats {
+    /// Lowest sample value
+    pub min: f32,
+    /// Highest sample value
+    pub max: f32,
+    /// Root mean square level
+    pub rms: f32,
+    /// Number of samples at or beyond full scale
+    pub clipped: u64,
+}
+
+/// Outcome of scanning a whole file with [`Wav::analyze`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Analysis<const CHANNELS: usize> {
+    /// Statistics of every channel, in channel order
+    pub channels: [ChannelStats; CHANNELS],
+    /// Length of the sample data, counted in whole frames
+    pub duration: Timestamp,
+}
+
+/// Square root by Newton's method, `core` has none for floats
+fn sqrt(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    let mut root = if x > 1.0 { x } else { 1.0 };
+
+    for _ in 0..64 {
+        let next = (root + x / root) / 2.0;
+
+        if next >= root {
+            break;
+        }
+
+        root = next;
+    }
+
+    root
+}
+
+/// Sample value relative to full scale and whether it sits at or beyond full scale
+pub(crate) fn normalize<const NUM: usize>(
+    bulk: &DataBulk<NUM>,
+    index: usize,
+) -> Option<(f32, bool)> {
+    match bulk {
+        DataBulk::BitDepth8(s) => s
+            .get(index)
+            .map(|s| ((*s as f32 - 128.0) / 128.0, *s == 0 || *s == u8::MAX)),
+        DataBulk::BitDepth16(s) => s
+            .get(index)
+            .map(|s| (*s as f32 / 32_768.0, *s == i16::MIN || *s == i16::MAX)),
+        DataBulk::BitDepth24(s) => s.get(index).map(|s| {
+            let full_scale = 1 << 23;
+            (
+                *s as f32 / full_scale as f32,
+                *s <= -full_scale || *s >= full_scale - 1,
+            )
+        }),
+        DataBulk::Float32(s) => s.get(index).map(|s| (*s, s.abs() >= 1.0)),
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Scan the whole sample data for the level statistics of every channel and the true duration.
+    ///
+    /// Runs in bounded memory whatever the file length, meant to sanity check recordings after
+    /// capture. The read position is left unchanged.
+    pub fn analyze<const CHANNELS: usize>(
+        &mut self,
+    ) -> Result<Analysis<CHANNELS>, Error<S::Error>> {
+        if self.fmt.num_channels as usize != CHANNELS || CHANNELS == 0 {
+            return Err(Error::FormatMismatch);
+        }
+
+        // widened by the first sample of every channel
+        let mut channels = [ChannelStats {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            rms: 0.0,
+            clipped: 0,
+        }; CHANNELS];
+        let mut squares = [0f64; CHANNELS];
+
+        let data_end = self.data_end();
+        let total = data_end.saturating_sub(self.data.start) / self.fmt.block_align().max(1);
+
+        let position = self.data_offset();
+        self.seek_data(0)?;
+
+        let mut frames = 0;
+
+        while frames < total {
+            let bulk = self.next_frames::<256>(total - frames)?;
+            let read = bulk.len() / CHANNELS;
+
+            if read == 0 {
+                break;
+            }
+
+            for index in 0..read * CHANNELS {
+                let stats = &mut channels[index % CHANNELS];
+
+                if let Some((sample, clipped)) = normalize(&bulk, index) {
+                    stats.min = stats.min.min(sample);
+                    stats.max = stats.max.max(sample);
+                    stats.clipped += clipped as u64;
+                    squares[index % CHANNELS] += sample as f64 * sample as f64;
+                }
+            }
+
+            frames += read;
+        }
+
+        self.seek_data(position)?;
+
+        for (stats, squares) in channels.iter_mut().zip(squares) {
+            if frames == 0 {
+                *stats = ChannelStats {
+                    min: 0.0,
+                    max: 0.0,
+                    rms: 0.0,
+                    clipped: 0,
+                };
+            } else {
+                stats.rms = sqrt(squares / frames as f64) as f32;
+            }
+        }
+
+        Ok(Analysis {
+            channels,
+            duration: Timestamp::from_frames(frames as u64, self.fmt.sample_rate),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_take_square_roots() {
+        assert_eq!(sqrt(0.0), 0.0);
+        assert_eq!(sqrt(0.25), 0.5);
+        assert_eq!(sqrt(16.0), 4.0);
+    }
+
+    #[test]
+    fn should_analyze_whole_file() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+
+        let analysis = wav.analyze::<2>().unwrap();
+        let frames = (wav.data.end - wav.data.start) / 4;
+
+        assert_eq!(analysis.duration.frames, frames as u64);
+        assert_eq!(wav.timestamp().frames, 0);
+
+        for stats in analysis.channels {
+            assert!(stats.min <= 0.0 && stats.max >= 0.0);
+            assert!(stats.rms > 0.0 && stats.rms <= stats.max.max(-stats.min));
+        }
+
+        assert!(matches!(wav.analyze::<1>(), Err(Error::FormatMismatch)));
+    }
+
+    #[test]
+    fn should_report_levels_that_never_cross_zero() {
+        let mut bytes = b"RIFF\x2c\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        bytes.extend_from_slice(&[1, 0, 1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 1, 0, 2, 0, 16, 0]);
+        bytes.extend_from_slice(b"data\x08\0\0\0");
+        bytes.extend(
+            [1_024i16, 4_096, 8_192, 2_048]
+                .iter()
+                .flat_map(|s| s.to_le_bytes()),
+        );
+
+        let analysis = Wav::from_bytes(&bytes).unwrap().analyze::<1>().unwrap();
+
+        assert_eq!(analysis.channels[0].min, 1_024.0 / 32_768.0);
+        assert_eq!(analysis.channels[0].max, 0.25);
+
+        // no samples at all
+        bytes[40..44].copy_from_slice(&[0; 4]);
+        let analysis = Wav::from_bytes(&bytes[..44])
+            .unwrap()
+            .analyze::<1>()
+            .unwrap();
+
+        assert_eq!(analysis.channels[0].min, 0.0);
+        assert_eq!(analysis.channels[0].max, 0.0);
+    }
+}
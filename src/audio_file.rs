@@ -0,0 +1,230 @@
+use crate::avi::AviStream;
+use crate::decoder::{Decoder, DecoderInfo};
+use crate::error::Error;
+use crate::flac::Flac;
+use crate::metadata::Metadata;
+use crate::mp3::{Mp3File, Mp3Header};
+use crate::ogg::OggReader;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+
+/// Number of leading bytes looked at to tell the formats apart, up to the form type of RIFF files
+const SNIFF_SIZE: usize = 12;
+
+/// Bytes kept of each metadata value of an [`AnyAudioFile`]
+pub const ANY_STRING_LEN: usize = 64;
+
+/// Container format of an [`AudioFile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// WAV, RF64 or AIFF file
+    Wav,
+    /// Native FLAC stream
+    Flac,
+    /// MPEG audio stream
+    Mp3,
+    /// Ogg container
+    Ogg,
+    /// Soundtrack of an AVI clip
+    Avi,
+}
+
+/// Audio file of any supported format, told apart by its first bytes rather than its extension
+// without an allocator the parsers can't be boxed, the enum is as large as a `Wav`
+#[allow(clippy::large_enum_variant)]
+pub enum AudioFile<S: AudioSource> {
+    /// WAV, RF64 or AIFF file
+    Wav(Wav<S>),
+    /// Native FLAC stream
+    Flac(Flac<S>),
+    /// MPEG audio stream, with or without a leading ID3v2 tag
+    Mp3(Mp3File<S>),
+    /// Ogg container, e.g. holding Vorbis or Opus
+    Ogg(OggReader<S>),
+    /// First audio stream of an AVI clip
+    Avi(Wav<AviStream<S>>),
+}
+
+impl<S: AudioSource> AudioFile<S> {
+    /// Sniff the first bytes of `source` and open it with the matching parser.
+    ///
+    /// Recognizes `RIFF`, `RF64` and `FORM` headers, AVI clips, `fLaC`, `OggS`, ID3v2 tags and
+    /// bare MPEG audio frames. Returns [`Error::UnknownFileFormat`] for anything else.
+    pub fn new_auto(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut magic = [0; SNIFF_SIZE];
+        source.seek(0).map_err(Error::Source)?;
+        let read = read_full(&mut source, &mut magic)?;
+        source.seek(0).map_err(Error::Source)?;
+
+        match &magic[..read] {
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'A', b'V', b'I', b' '] => {
+                Wav::from_avi(source).map(AudioFile::Avi)
+            }
+            [b'R', b'I', b'F', b'F', ..]
+            | [b'R', b'F', b'6', b'4', ..]
+            | [b'F', b'O', b'R', b'M', ..] => Wav::new(source).map(AudioFile::Wav),
+            [b'f', b'L', b'a', b'C', ..] => Flac::new(source).map(AudioFile::Flac),
+            [b'O', b'g', b'g', b'S', ..] => OggReader::new(source).map(AudioFile::Ogg),
+            [b'I', b'D', b'3', ..] => Mp3File::new(source).map(AudioFile::Mp3),
+            bytes if Mp3Header::parse(bytes).is_ok() => Mp3File::new(source).map(AudioFile::Mp3),
+            _ => Err(Error::UnknownFileFormat),
+        }
+    }
+
+    /// Format the file was opened as
+    pub fn format(&self) -> FileFormat {
+        match self {
+            AudioFile::Wav(_) => FileFormat::Wav,
+            AudioFile::Flac(_) => FileFormat::Flac,
+            AudioFile::Mp3(_) => FileFormat::Mp3,
+            AudioFile::Ogg(_) => FileFormat::Ogg,
+            AudioFile::Avi(_) => FileFormat::Avi,
+        }
+    }
+
+    /// The file as a [`Decoder`] handing out 16 bit PCM, `None` for formats that need an external
+    /// decoder such as a hardware MP3 chip
+    pub fn decoder(&mut self) -> Option<&mut dyn Decoder<Error = S::Error>> {
+        match self {
+            AudioFile::Wav(wav) => Some(wav),
+            AudioFile::Avi(avi) => Some(avi),
+            AudioFile::Flac(flac) => Some(flac),
+            AudioFile::Mp3(_) | AudioFile::Ogg(_) => None,
+        }
+    }
+
+    /// Destroy the [`AudioFile`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        match self {
+            AudioFile::Wav(wav) => wav.destroy(),
+            AudioFile::Flac(flac) => flac.destroy(),
+            AudioFile::Mp3(mp3) => mp3.destroy(),
+            AudioFile::Ogg(ogg) => ogg.destroy(),
+            AudioFile::Avi(avi) => avi.destroy().into_inner(),
+        }
+    }
+}
+
+/// [`AudioFile`] with its metadata read up front into buffers of a fixed size, so application
+/// structs and signatures only carry the source type
+///
+/// Metadata values hold up to [`ANY_STRING_LEN`] bytes.
+///
+/// ```
+/// use audio_parser::{AnyAudioFile, SliceSource};
+///
+/// struct Player<'a> {
+///     track: AnyAudioFile<SliceSource<'a>>,
+/// }
+///
+/// let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+/// let mut player = Player {
+///     track: AnyAudioFile::new(SliceSource::new(bytes)).unwrap(),
+/// };
+///
+/// let mut out = [0; 256];
+/// let decoder = player.track.decoder().unwrap();
+/// assert_eq!(decoder.decode(&mut out).unwrap(), 256);
+/// ```
+pub struct AnyAudioFile<S: AudioSource> {
+    file: AudioFile<S>,
+    metadata: Metadata<ANY_STRING_LEN>,
+}
+
+impl<S: AudioSource> AnyAudioFile<S> {
+    /// Open `source` with [`AudioFile::new_auto`] and read its metadata
+    pub fn new(source: S) -> Result<Self, Error<S::Error>> {
+        AudioFile::new_auto(source).and_then(Self::from_file)
+    }
+
+    /// Read the metadata of an already opened `file`, WAV and MP3 files carry any
+    pub fn from_file(mut file: AudioFile<S>) -> Result<Self, Error<S::Error>> {
+        let metadata = match &mut file {
+            AudioFile::Wav(wav) => wav.metadata()?,
+            AudioFile::Mp3(mp3) => mp3.metadata()?,
+            AudioFile::Flac(_) | AudioFile::Ogg(_) | AudioFile::Avi(_) => Metadata::default(),
+        };
+
+        Ok(AnyAudioFile { file, metadata })
+    }
+
+    /// Artist, title and other tags read when the file was opened
+    pub fn metadata(&self) -> &Metadata<ANY_STRING_LEN> {
+        &self.metadata
+    }
+
+    /// Sample rate, channel count and length of the stream, `None` for formats without a
+    /// [`Decoder`]
+    pub fn info(&mut self) -> Option<DecoderInfo> {
+        self.decoder().map(|decoder| decoder.info())
+    }
+
+    /// The file as a [`Decoder`], see [`AudioFile::decoder`]
+    pub fn decoder(&mut self) -> Option<&mut dyn Decoder<Error = S::Error>> {
+        self.file.decoder()
+    }
+
+    /// The wrapped file, e.g. to reach format specific functions
+    pub fn file_mut(&mut self) -> &mut AudioFile<S> {
+        &mut self.file
+    }
+
+    /// Destroy the [`AnyAudioFile`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.file.destroy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    fn open(bytes: &[u8]) -> Result<AudioFile<SliceSource<'_>>, Error> {
+        AudioFile::new_auto(SliceSource::new(bytes))
+    }
+
+    #[test]
+    fn should_dispatch_on_magic_bytes() {
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+        let flac = include_bytes!("../test_files/stereo_16_8000.flac");
+
+        assert!(matches!(open(wav), Ok(AudioFile::Wav(_))));
+        assert!(matches!(open(flac), Ok(AudioFile::Flac(_))));
+        assert_eq!(open(flac).unwrap().format(), FileFormat::Flac);
+        assert!(open(wav).unwrap().decoder().is_some());
+        assert!(open(flac).unwrap().decoder().is_some());
+        assert!(matches!(open(b"OggS\x00\x02"), Ok(AudioFile::Ogg(_))));
+        assert!(matches!(
+            open(b"ID3\x04\x00\x00\x00\x00\x00\x00"),
+            Ok(AudioFile::Mp3(_))
+        ));
+        assert!(matches!(
+            open(&[0xff, 0xfb, 0x90, 0x00]),
+            Ok(AudioFile::Mp3(_))
+        ));
+    }
+
+    #[test]
+    fn should_reject_unknown_files() {
+        assert!(matches!(open(b"PK\x03\x04"), Err(Error::UnknownFileFormat)));
+        assert!(matches!(open(b""), Err(Error::UnknownFileFormat)));
+    }
+
+    #[test]
+    fn should_read_metadata_up_front() {
+        let mut tag =
+            b"ID3\x03\x00\x00\x00\x00\x00\x13TIT2\x00\x00\x00\x09\x00\x00\x00Any song".to_vec();
+        tag.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+
+        let mut mp3 = AnyAudioFile::new(SliceSource::new(&tag)).unwrap();
+        assert_eq!(mp3.metadata().title(), Some("Any song"));
+        assert!(mp3.info().is_none());
+        assert!(matches!(mp3.file_mut(), AudioFile::Mp3(_)));
+
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = AnyAudioFile::new(SliceSource::new(wav)).unwrap();
+        assert_eq!(wav.info().unwrap().sample_rate, 48_000);
+        assert_eq!(wav.metadata().title(), None);
+    }
+}
@@ -0,0 +1,400 @@
+use crate::error::Error;
+use crate::fmt::{Fmt, FMT_MAX_LEN};
+use crate::riff::{Chunk, ChunkTag, ChunkWalker};
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use heapless::Vec;
+
+/// Stream header of an AVI stream, its type tells audio from video
+const STRH: [u8; 4] = *b"strh";
+/// Stream format, a `WAVEFORMATEX` for audio streams
+const STRF: [u8; 4] = *b"strf";
+/// Stream type of audio streams
+const AUDS: [u8; 4] = *b"auds";
+/// Chunk id suffix of audio data in the `movi` list, behind the two digit stream number
+const WB: [u8; 2] = *b"wb";
+
+/// Highest stream number that fits the two digits of a chunk id
+const MAX_STREAM: u8 = 99;
+
+/// Two digit number of an audio stream and its format
+type AudioStream = ([u8; 2], Fmt);
+
+/// [`AudioSource`] over the audio chunks of one stream of an AVI clip, read as if the samples
+/// were stored in one piece.
+///
+/// The audio chunks are interleaved with video in the `movi` list, reads and seeks walk their
+/// headers and skip everything else. Opened by [`Wav::from_avi`].
+pub struct AviStream<S: AudioSource> {
+    source: S,
+    /// Range of the chunks of the `movi` list
+    movi: (usize, usize),
+    /// Two digit number of the stream, the prefix of its chunk ids
+    stream: [u8; 2],
+    length: u32,
+    offset: u32,
+    walker: ChunkWalker,
+    /// Audio chunk holding the last byte read, with its offset in the stream
+    current: Option<(u32, Chunk)>,
+}
+
+impl<S: AudioSource> AviStream<S> {
+    /// Give back the underlying source
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    /// Walker at the first chunk of the `movi` list
+    fn rewind(&mut self) {
+        self.walker = ChunkWalker::range(self.movi.0, self.movi.1);
+        self.current = None;
+    }
+
+    /// Move on to the next audio chunk of the stream, `None` after the last one
+    fn next_audio(&mut self) -> Result<Option<Chunk>, S::Error> {
+        loop {
+            let chunk = match self.walker.next_chunk(&mut self.source) {
+                Ok(Some(chunk)) => chunk,
+                Err(Error::Source(e)) => return Err(e),
+                // a whole header always parses
+                Ok(None) | Err(_) => return Ok(None),
+            };
+
+            let id = chunk.id.to_bytes();
+
+            if chunk.id == ChunkTag::List {
+                // `rec ` lists group the chunks of one frame, their chunks are walked in line
+                self.walker.seek(chunk.start + 4);
+            } else if id[..2] == self.stream && id[2..] == WB {
+                let end = chunk.end.min(self.movi.1);
+
+                return Ok(Some(Chunk { end, ..chunk }));
+            }
+        }
+    }
+
+    /// Make the audio chunk holding stream offset `offset` current, `false` past the end
+    fn locate(&mut self, offset: u32) -> Result<bool, S::Error> {
+        let mut start = match self.current {
+            Some((start, chunk)) if start <= offset => {
+                if offset < start + (chunk.end - chunk.start) as u32 {
+                    return Ok(true);
+                }
+
+                start + (chunk.end - chunk.start) as u32
+            }
+            _ => {
+                self.rewind();
+                0
+            }
+        };
+
+        while let Some(chunk) = self.next_audio()? {
+            let len = (chunk.end - chunk.start) as u32;
+            self.current = Some((start, chunk));
+
+            if offset < start + len {
+                return Ok(true);
+            }
+
+            start += len;
+        }
+
+        Ok(false)
+    }
+}
+
+impl<S: AudioSource> AudioSource for AviStream<S> {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut read = 0;
+
+        while read < buf.len() && self.locate(self.offset)? {
+            let (start, chunk) = match self.current {
+                Some(current) => current,
+                None => break,
+            };
+
+            let skip = (self.offset - start) as usize;
+            let len = (buf.len() - read).min(chunk.end - chunk.start - skip);
+            self.source.seek((chunk.start + skip) as u32)?;
+
+            let n = self.source.read(&mut buf[read..read + len])?;
+            self.offset += n as u32;
+            read += n;
+
+            if n < len {
+                break;
+            }
+        }
+
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = offset.min(self.length);
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+impl<S: AudioSource> Wav<AviStream<S>> {
+    /// Open the first audio stream of the AVI clip in `source`, e.g. to play the soundtrack of a
+    /// recorded video.
+    ///
+    /// The format is read from the `strf` chunk of the stream, its samples from the `01wb` or
+    /// similar chunks of the `movi` list, which are walked once to add up their length. Returns
+    /// [`Error::NoFmtChunkFound`] if none of the first 100 streams is audio, later ones can't be
+    /// told apart by their chunk ids, and [`Error::NoDataChunkFound`] without a `movi` list.
+    pub fn from_avi(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut riff = [0; 12];
+        source.seek(0).map_err(Error::Source)?;
+
+        if read_full(&mut source, &mut riff)? != riff.len()
+            || &riff[..4] != b"RIFF"
+            || &riff[8..] != b"AVI "
+        {
+            return Err(Error::UnknownFileFormat);
+        }
+
+        let mut walker = ChunkWalker::new(source.length() as usize);
+        let mut audio = None;
+        let mut movi = None;
+
+        while let Some(chunk) = walker.next_chunk(&mut source)? {
+            match list_type(&mut source, &chunk)?.as_ref() {
+                Some(b"hdrl") if audio.is_none() => audio = find_audio(&mut source, &chunk)?,
+                Some(b"movi") => movi = Some(chunk),
+                _ => {}
+            }
+        }
+
+        let (stream, fmt) = audio.ok_or(Error::NoFmtChunkFound)?;
+        let movi = movi.ok_or(Error::NoDataChunkFound)?;
+        let end = movi.end.min(source.length() as usize);
+
+        let mut stream = AviStream {
+            source,
+            movi: (movi.start + 4, end),
+            stream,
+            length: 0,
+            offset: 0,
+            walker: ChunkWalker::range(movi.start + 4, end),
+            current: None,
+        };
+
+        while let Some(chunk) = stream.next_audio().map_err(Error::Source)? {
+            stream.length += (chunk.end - chunk.start) as u32;
+        }
+
+        stream.rewind();
+
+        Ok(Wav {
+            data: Chunk {
+                id: ChunkTag::Data,
+                start: 0,
+                end: stream.length as usize,
+            },
+            source: stream,
+            fmt,
+            chunks: Vec::new(),
+        })
+    }
+}
+
+/// List type of `chunk` if it is a `LIST`, the read position is left at its first sub chunk
+fn list_type<S: AudioSource>(
+    source: &mut S,
+    chunk: &Chunk,
+) -> Result<Option<[u8; 4]>, Error<S::Error>> {
+    let mut list_type = [0; 4];
+
+    if chunk.id != ChunkTag::List || read_full(source, &mut list_type)? != list_type.len() {
+        return Ok(None);
+    }
+
+    Ok(Some(list_type))
+}
+
+/// Number and format of the first audio stream of the `hdrl` list `hdrl`
+fn find_audio<S: AudioSource>(
+    source: &mut S,
+    hdrl: &Chunk,
+) -> Result<Option<AudioStream>, Error<S::Error>> {
+    let mut lists = ChunkWalker::list(hdrl);
+    let mut number: u8 = 0;
+
+    while let Some(strl) = lists.next_chunk(source)? {
+        // chunk ids only hold two digits
+        if number > MAX_STREAM {
+            break;
+        }
+
+        if list_type(source, &strl)? != Some(*b"strl") {
+            continue;
+        }
+
+        let mut chunks = ChunkWalker::list(&strl);
+        let mut is_audio = false;
+
+        while let Some(chunk) = chunks.next_chunk(source)? {
+            let mut body = [0; FMT_MAX_LEN];
+            let len = body.len().min(chunk.end - chunk.start);
+            let read = read_full(source, &mut body[..len])?;
+
+            match chunk.id.to_bytes() {
+                STRH => is_audio = body[..read].starts_with(&AUDS),
+                STRF if is_audio => {
+                    let fmt = Fmt::from_chunk(&body[..read]).map_err(Error::widen)?;
+                    let stream = [b'0' + number / 10, b'0' + number % 10];
+
+                    return Ok(Some((stream, fmt)));
+                }
+                _ => {}
+            }
+        }
+
+        number += 1;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_file::{AudioFile, FileFormat};
+    use crate::source::SliceSource;
+    use crate::wav::DataBulk;
+
+    fn chunk(tag: &[u8; 4], body: &[u8]) -> std::vec::Vec<u8> {
+        let mut bytes = tag.to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+
+        bytes
+    }
+
+    fn list(list_type: &[u8; 4], chunks: &[std::vec::Vec<u8>]) -> std::vec::Vec<u8> {
+        let mut body = list_type.to_vec();
+        chunks.iter().for_each(|c| body.extend_from_slice(c));
+
+        chunk(b"LIST", &body)
+    }
+
+    fn samples(samples: &[i16]) -> std::vec::Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    /// A video stream and a mono 16 bit 8 kHz audio stream, one frame grouped in a `rec ` list
+    fn clip() -> std::vec::Vec<u8> {
+        let strh = |kind: &[u8; 4]| chunk(b"strh", &[&kind[..], &[0; 52]].concat());
+        let fmt = [
+            1, 0, 1, 0, 0x40, 0x1f, 0, 0, 0x80, 0x3e, 0, 0, 2, 0, 16, 0, 0, 0,
+        ];
+
+        let hdrl = list(
+            b"hdrl",
+            &[
+                chunk(b"avih", &[0; 56]),
+                list(b"strl", &[strh(b"vids"), chunk(b"strf", &[0; 40])]),
+                list(b"strl", &[strh(b"auds"), chunk(b"strf", &fmt)]),
+            ],
+        );
+        let movi = list(
+            b"movi",
+            &[
+                chunk(b"00dc", &[0xaa; 5]),
+                chunk(b"01wb", &samples(&[1, 2, 3, 4])),
+                list(
+                    b"rec ",
+                    &[
+                        chunk(b"00dc", &[0xbb; 8]),
+                        chunk(b"01wb", &samples(&[5, 6])),
+                    ],
+                ),
+                chunk(b"01wb", &samples(&[7])),
+            ],
+        );
+
+        let body = [b"AVI ".to_vec(), hdrl, movi, chunk(b"idx1", &[0; 16])].concat();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn should_read_the_soundtrack_of_a_clip() {
+        let bytes = clip();
+        let mut wav = Wav::from_avi(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!((wav.fmt.sample_rate, wav.fmt.num_channels), (8_000, 1));
+        assert_eq!(wav.total_samples().unwrap(), 7);
+
+        match wav.next_n::<16>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [1, 2, 3, 4, 5, 6, 7]),
+            _ => unreachable!(),
+        }
+
+        wav.seek_to_sample(5).unwrap();
+        match wav.next_n::<16>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [6, 7]),
+            _ => unreachable!(),
+        }
+
+        wav.seek_to_sample(1).unwrap();
+        match wav.next_n::<4>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [2, 3, 4, 5]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_open_clips_as_audio_files() {
+        let bytes = clip();
+        let mut file = AudioFile::new_auto(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(file.format(), FileFormat::Avi);
+
+        let mut out = [0; 8];
+        assert_eq!(file.decoder().unwrap().decode(&mut out).unwrap(), 7);
+        assert_eq!(out[..7], [1, 2, 3, 4, 5, 6, 7]);
+
+        let mut silent = clip();
+        let auds = silent.windows(4).position(|w| w == b"auds").unwrap();
+        silent[auds..auds + 4].copy_from_slice(b"txts");
+
+        assert!(matches!(
+            Wav::from_avi(SliceSource::new(&silent)),
+            Err(Error::NoFmtChunkFound)
+        ));
+    }
+
+    #[test]
+    fn should_only_look_at_the_first_100_streams() {
+        let strh = |kind: &[u8; 4]| chunk(b"strh", &[&kind[..], &[0; 52]].concat());
+        let fmt = [1, 0, 1, 0, 0x40, 0x1f, 0, 0, 0x80, 0x3e, 0, 0, 2, 0, 16, 0];
+        let strl = |kind: &[u8; 4]| list(b"strl", &[strh(kind), chunk(b"strf", &fmt)]);
+
+        // stream 300 is audio, the ones before it video
+        let mut lists = vec![strl(b"vids"); 300];
+        lists.push(strl(b"auds"));
+
+        let body = [b"AVI ".to_vec(), list(b"hdrl", &lists), list(b"movi", &[])].concat();
+        let bytes = chunk(b"RIFF", &body);
+
+        assert!(matches!(
+            Wav::from_avi(SliceSource::new(&bytes)),
+            Err(Error::NoFmtChunkFound)
+        ));
+    }
+}
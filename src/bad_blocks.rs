@@ -0,0 +1,191 @@
+use crate::source::AudioSource;
+use heapless::Vec;
+
+/// Size of an SD card block, the granularity bad ranges are tracked with
+pub const BLOCK_SIZE: u32 = 512;
+
+#[derive(Debug, Clone, Copy)]
+struct BadBlock {
+    block: u32,
+    failures: u8,
+}
+
+/// [`AudioSource`] that remembers blocks failing to read and skips them on later passes.
+///
+/// Every failed read is counted against the block it started in. Once a block failed
+/// `threshold` times it is no longer read, silence is served in its place instead. This keeps
+/// long running installations looping over a partially damaged card from stalling on the
+/// same bad blocks every time. Up to `N` blocks are tracked, further failures are not
+/// remembered.
+#[derive(Debug)]
+pub struct BadBlocks<S: AudioSource, const N: usize> {
+    inner: S,
+    threshold: u8,
+    blocks: Vec<BadBlock, N>,
+    skipped: u32,
+}
+
+impl<S: AudioSource, const N: usize> BadBlocks<S, N> {
+    /// Wrap `inner`, skipping blocks once they failed to read `threshold` times
+    pub fn new(inner: S, threshold: u8) -> Self {
+        BadBlocks {
+            inner,
+            threshold: threshold.max(1),
+            blocks: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Number of bytes served as silence instead of being read
+    pub fn skipped_bytes(&self) -> u32 {
+        self.skipped
+    }
+
+    /// Byte ranges that are skipped
+    pub fn bad_ranges(&self) -> impl Iterator<Item = core::ops::Range<u32>> + '_ {
+        self.blocks
+            .iter()
+            .filter(move |b| b.failures >= self.threshold)
+            .map(|b| b.block * BLOCK_SIZE..(b.block + 1) * BLOCK_SIZE)
+    }
+
+    /// Give back the inner source
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn is_bad(&self, block: u32) -> bool {
+        self.blocks
+            .iter()
+            .any(|b| b.block == block && b.failures >= self.threshold)
+    }
+
+    fn record_failure(&mut self, block: u32) {
+        match self.blocks.iter_mut().find(|b| b.block == block) {
+            Some(bad) => bad.failures = bad.failures.saturating_add(1),
+            None => {
+                let _ = self.blocks.push(BadBlock { block, failures: 1 });
+            }
+        }
+    }
+}
+
+impl<S: AudioSource, const N: usize> AudioSource for BadBlocks<S, N> {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let length = self.inner.length();
+        let mut read = 0;
+
+        while read < buf.len() {
+            let offset = self.inner.offset();
+
+            if offset >= length {
+                break;
+            }
+
+            // never read across a block boundary so failures are attributed to one block
+            let block = offset / BLOCK_SIZE;
+            let block_end = ((block + 1) * BLOCK_SIZE).min(length);
+            let len = (buf.len() - read).min((block_end - offset) as usize);
+
+            if self.is_bad(block) {
+                buf[read..read + len].fill(0);
+                self.inner.seek(offset + len as u32)?;
+                self.skipped = self.skipped.saturating_add(len as u32);
+                read += len;
+                continue;
+            }
+
+            match self.inner.read(&mut buf[read..read + len]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) => {
+                    self.record_failure(block);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.inner.seek(offset)
+    }
+
+    fn offset(&self) -> u32 {
+        self.inner.offset()
+    }
+
+    fn length(&self) -> u32 {
+        self.inner.length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    /// Source failing every read that touches the second block
+    struct Damaged<'b> {
+        inner: SliceSource<'b>,
+        reads: u32,
+    }
+
+    impl<'b> AudioSource for Damaged<'b> {
+        type Error = ();
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            self.reads += 1;
+
+            if (BLOCK_SIZE..BLOCK_SIZE * 2).contains(&self.inner.offset()) {
+                return Err(());
+            }
+
+            Ok(self.inner.read(buf).unwrap())
+        }
+
+        fn seek(&mut self, offset: u32) -> Result<(), ()> {
+            self.inner.seek(offset).map_err(|_| ())
+        }
+
+        fn offset(&self) -> u32 {
+            self.inner.offset()
+        }
+
+        fn length(&self) -> u32 {
+            self.inner.length()
+        }
+    }
+
+    #[test]
+    fn should_skip_block_after_repeated_failures() {
+        let bytes = [1; BLOCK_SIZE as usize * 3];
+        let damaged = Damaged {
+            inner: SliceSource::new(&bytes),
+            reads: 0,
+        };
+
+        let mut source: BadBlocks<_, 4> = BadBlocks::new(damaged, 2);
+        let mut buf = [0; BLOCK_SIZE as usize * 2];
+
+        // two passes over the file fail on the second block
+        for _ in 0..2 {
+            source.seek(0).unwrap();
+            assert!(source.read(&mut buf).is_err());
+        }
+
+        assert_eq!(source.bad_ranges().next(), Some(BLOCK_SIZE..BLOCK_SIZE * 2));
+
+        source.seek(0).unwrap();
+        assert_eq!(source.read(&mut buf), Ok(buf.len()));
+        assert!(buf[..BLOCK_SIZE as usize].iter().all(|b| *b == 1));
+        assert!(buf[BLOCK_SIZE as usize..].iter().all(|b| *b == 0));
+        assert_eq!(source.skipped_bytes(), BLOCK_SIZE);
+
+        // the bad block was never read again
+        assert_eq!(source.into_inner().reads, 5);
+    }
+}
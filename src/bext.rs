@@ -0,0 +1,190 @@
+use crate::error::Error;
+use crate::metadata::to_string;
+use crate::riff::ChunkTag;
+use crate::source::AudioSource;
+use crate::timestamp::Timestamp;
+use crate::wav::{read_full, Wav};
+use heapless::String;
+
+/// Bytes of the `bext` chunk up to and including the time reference
+const BEXT_LEN: usize = 346;
+/// Offset of the origination date within the `bext` chunk
+const DATE_OFFSET: usize = 320;
+/// Offset of the time reference within the `bext` chunk
+const TIME_REFERENCE_OFFSET: usize = 338;
+
+/// Broadcast WAV (BWF) extension of the `bext` chunk, written by field recorders
+///
+/// Text values hold at most `MAX_STRING_LEN` bytes, longer values are cut short.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BroadcastExtension<const MAX_STRING_LEN: usize> {
+    description: String<MAX_STRING_LEN>,
+    originator: String<MAX_STRING_LEN>,
+    originator_reference: String<MAX_STRING_LEN>,
+    origination_date: String<10>,
+    origination_time: String<8>,
+    time_reference: u64,
+}
+
+impl<const MAX_STRING_LEN: usize> BroadcastExtension<MAX_STRING_LEN> {
+    fn from_bytes(bytes: &[u8; BEXT_LEN]) -> Self {
+        let mut time_reference = [0; 8];
+        time_reference.copy_from_slice(&bytes[TIME_REFERENCE_OFFSET..]);
+
+        BroadcastExtension {
+            description: to_string(&bytes[..256]),
+            originator: to_string(&bytes[256..288]),
+            originator_reference: to_string(&bytes[288..DATE_OFFSET]),
+            origination_date: to_string(&bytes[DATE_OFFSET..DATE_OFFSET + 10]),
+            origination_time: to_string(&bytes[DATE_OFFSET + 10..TIME_REFERENCE_OFFSET]),
+            time_reference: u64::from_le_bytes(time_reference),
+        }
+    }
+
+    /// Free text description of the recording
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Name of the device or organization that made the recording
+    pub fn originator(&self) -> &str {
+        &self.originator
+    }
+
+    /// Unique reference of the recording given by the originator
+    pub fn originator_reference(&self) -> &str {
+        &self.originator_reference
+    }
+
+    /// Date the recording was made, formatted as `YYYY-MM-DD`
+    pub fn origination_date(&self) -> &str {
+        &self.origination_date
+    }
+
+    /// Time of day the recording was made, formatted as `HH:MM:SS`, some writers use `-` as separator
+    pub fn origination_time(&self) -> &str {
+        &self.origination_time
+    }
+
+    /// Frames since midnight at which the recording started
+    pub fn time_reference(&self) -> u64 {
+        self.time_reference
+    }
+
+    /// Time of day at which the recording started, from the time reference at `sample_rate`
+    pub fn start_of_recording(&self, sample_rate: u32) -> Timestamp {
+        Timestamp::from_frames(self.time_reference, sample_rate)
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Read the `bext` chunk of a Broadcast WAV file, `None` if the file has none.
+    ///
+    /// The read position is left unchanged.
+    pub fn broadcast_extension<const MAX_STRING_LEN: usize>(
+        &mut self,
+    ) -> Result<Option<BroadcastExtension<MAX_STRING_LEN>>, Error<S::Error>> {
+        let chunk = match self.find_chunk(ChunkTag::Bext)? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        if chunk.end - chunk.start < BEXT_LEN {
+            return Err(Error::CantParseChunk(ChunkTag::Bext));
+        }
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let mut bytes = [0; BEXT_LEN];
+        let read = read_full(&mut self.source, &mut bytes);
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        if read? != BEXT_LEN {
+            return Err(Error::CantParseChunk(ChunkTag::Bext));
+        }
+
+        Ok(Some(BroadcastExtension::from_bytes(&bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mono 16 bit 48 kHz file with a `bext` chunk and 4 silent frames
+    fn recording(bext_len: usize) -> std::vec::Vec<u8> {
+        let mut bext = std::vec![0; bext_len];
+        bext[..12].copy_from_slice(b"Scene 4 take");
+        bext[256..266].copy_from_slice(b"FieldRec 2");
+        bext[288..293].copy_from_slice(b"AB123");
+        bext[DATE_OFFSET..TIME_REFERENCE_OFFSET].copy_from_slice(b"2024-05-1714:30:05");
+
+        // 14:30:05 at 48 kHz
+        let time_reference = 52_205u64 * 48_000;
+        if bext_len >= BEXT_LEN {
+            bext[TIME_REFERENCE_OFFSET..BEXT_LEN].copy_from_slice(&time_reference.to_le_bytes());
+        }
+
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        bytes.extend_from_slice(&[1, 0, 1, 0, 0x80, 0xbb, 0, 0, 0, 0x77, 1, 0, 2, 0, 16, 0]);
+        bytes.extend_from_slice(b"bext");
+        bytes.extend_from_slice(&(bext_len as u32).to_le_bytes());
+        bytes.extend_from_slice(&bext);
+        bytes.extend_from_slice(b"data\x08\0\0\0");
+        bytes.extend_from_slice(&[0; 8]);
+
+        let riff_len = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn should_read_broadcast_extension() {
+        let bytes = recording(602);
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+        let bext = wav.broadcast_extension::<32>().unwrap().unwrap();
+
+        assert_eq!(bext.description(), "Scene 4 take");
+        assert_eq!(bext.originator(), "FieldRec 2");
+        assert_eq!(bext.originator_reference(), "AB123");
+        assert_eq!(bext.origination_date(), "2024-05-17");
+        assert_eq!(bext.origination_time(), "14:30:05");
+        assert_eq!(bext.time_reference(), 52_205 * 48_000);
+        assert_eq!(bext.start_of_recording(48_000).micros, 52_205_000_000);
+
+        let short = wav.broadcast_extension::<5>().unwrap().unwrap();
+        assert_eq!(short.description(), "Scene");
+        assert_eq!(wav.timestamp().frames, 0);
+    }
+
+    #[test]
+    fn should_reject_truncated_bext() {
+        let bytes = recording(340);
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            wav.broadcast_extension::<8>(),
+            Err(Error::CantParseChunk(ChunkTag::Bext))
+        );
+    }
+
+    #[test]
+    fn should_read_bext_of_reaper_render() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let bext = Wav::from_bytes(bytes)
+            .unwrap()
+            .broadcast_extension::<8>()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(bext.description(), "");
+        assert_eq!(bext.originator(), "REAPER");
+        assert_eq!(bext.origination_date(), "2024-11-03");
+        assert_eq!(bext.origination_time(), "08-39-31");
+        assert_eq!(bext.time_reference(), 0);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::error::Error;
+use crate::fmt::Fmt;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Ring buffer of the raw sample data of a [`Wav`] that is refilled in large bursts, so the card
+/// and its bus can be powered down in between, for battery powered players.
+///
+/// [`BurstReader::split`] gives a [`BurstProducer`] for the main loop and a [`BurstConsumer`]
+/// for the audio ISR. Nothing is read while more than `low` bytes are buffered. Once playback
+/// drains the buffer to `low`, the next [`BurstProducer::poll`] reads until `high` bytes are
+/// buffered, in as few reads as the ring allows. The wider the gap between the watermarks, the
+/// longer the card idles; `low` has to cover the time the card takes to wake up.
+/// [`BurstProducer::idle_frames`] tells how long it may sleep.
+pub struct BurstReader<S: AudioSource, const N: usize> {
+    wav: Wav<S>,
+    ring: Ring<N>,
+    low: usize,
+    high: usize,
+}
+
+/// Ring shared by the two halves of a [`BurstReader`]
+struct Ring<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    /// Index the consumer reads from next, only changed by the consumer
+    head: AtomicUsize,
+    /// Index the producer writes to next, only changed by the producer
+    tail: AtomicUsize,
+    /// Bytes buffered, added to by the producer and taken from by the consumer
+    len: AtomicUsize,
+    /// Set by the producer once the end of the data chunk was read
+    end: AtomicBool,
+}
+
+// SAFETY: the producer only writes the free part of the ring and the consumer only reads the
+// filled part, the length is handed over with release and acquire ordering
+unsafe impl<const N: usize> Sync for Ring<N> {}
+
+impl<S: AudioSource, const N: usize> BurstReader<S, N> {
+    /// Buffer the sample data of `wav` from its current position, refilling from `low` to `high`
+    /// bytes. `high` is capped to the `N` bytes of the buffer and `low` to `high`.
+    pub fn new(wav: Wav<S>, low: usize, high: usize) -> Self {
+        let high = high.min(N);
+        let end = wav.is_end();
+
+        BurstReader {
+            wav,
+            ring: Ring {
+                buffer: UnsafeCell::new([0; N]),
+                head: AtomicUsize::new(0),
+                tail: AtomicUsize::new(0),
+                len: AtomicUsize::new(0),
+                end: AtomicBool::new(end),
+            },
+            low: low.min(high),
+            high,
+        }
+    }
+
+    /// Format of the buffered samples
+    pub fn fmt(&self) -> &Fmt {
+        &self.wav.fmt
+    }
+
+    /// Split into the reading and the playing end
+    pub fn split(&mut self) -> (BurstProducer<'_, S, N>, BurstConsumer<'_, N>) {
+        let ring = &self.ring;
+        let block_align = self.wav.fmt.block_align().max(1);
+
+        (
+            BurstProducer {
+                wav: &mut self.wav,
+                ring,
+                low: self.low,
+                high: self.high,
+            },
+            BurstConsumer { ring, block_align },
+        )
+    }
+
+    /// Give back the [`Wav`], positioned after the last buffered byte
+    pub fn into_inner(self) -> Wav<S> {
+        self.wav
+    }
+}
+
+/// Reading end of a [`BurstReader`], refilling the ring from storage in bursts
+pub struct BurstProducer<'a, S: AudioSource, const N: usize> {
+    wav: &'a mut Wav<S>,
+    ring: &'a Ring<N>,
+    low: usize,
+    high: usize,
+}
+
+impl<'a, S: AudioSource, const N: usize> BurstProducer<'a, S, N> {
+    /// Bytes buffered
+    pub fn level(&self) -> usize {
+        self.ring.len.load(Ordering::Acquire)
+    }
+
+    /// True when the buffer has drained to the low watermark and data is left to read, the card
+    /// has to be woken up for the next [`BurstProducer::poll`]
+    pub fn needs_refill(&self) -> bool {
+        self.level() <= self.low && !self.wav.is_end()
+    }
+
+    /// Frames that can be played before the buffer drains to the low watermark, how long the
+    /// card may stay idle. Every frame is buffered already once the end of the data was read.
+    pub fn idle_frames(&self) -> usize {
+        let block_align = self.wav.fmt.block_align().max(1);
+
+        match self.wav.is_end() {
+            true => self.level() / block_align,
+            false => self.level().saturating_sub(self.low) / block_align,
+        }
+    }
+
+    /// Read a burst up to the high watermark if the buffer has drained to the low watermark,
+    /// returns the number of bytes read, `0` while the card may idle.
+    pub fn poll(&mut self) -> Result<usize, Error<S::Error>> {
+        let burst = match self.needs_refill() {
+            true => self.burst()?,
+            false => 0,
+        };
+
+        self.ring.end.store(self.wav.is_end(), Ordering::Release);
+
+        Ok(burst)
+    }
+
+    fn burst(&mut self) -> Result<usize, Error<S::Error>> {
+        let mut burst = 0;
+
+        // at most two reads, up to the end of the ring and from its start
+        loop {
+            let level = self.level();
+            let tail = self.ring.tail.load(Ordering::Relaxed);
+            let left = self
+                .wav
+                .data_end()
+                .saturating_sub(self.wav.source.offset() as usize);
+            let len = (N - tail).min(self.high.saturating_sub(level)).min(left);
+
+            if len == 0 {
+                break;
+            }
+
+            // SAFETY: the `len` bytes from `tail` on are free, the consumer doesn't touch them
+            // until the new length is published below
+            let free = unsafe {
+                core::slice::from_raw_parts_mut((self.ring.buffer.get() as *mut u8).add(tail), len)
+            };
+            let read = read_full(&mut self.wav.source, free)?;
+
+            self.ring.tail.store((tail + read) % N, Ordering::Relaxed);
+            self.ring.len.fetch_add(read, Ordering::Release);
+            burst += read;
+
+            if read < len {
+                break;
+            }
+        }
+
+        Ok(burst)
+    }
+}
+
+/// Playing end of a [`BurstReader`], taking whole frames out of the ring
+pub struct BurstConsumer<'a, const N: usize> {
+    ring: &'a Ring<N>,
+    block_align: usize,
+}
+
+impl<'a, const N: usize> BurstConsumer<'a, N> {
+    /// Bytes buffered
+    pub fn level(&self) -> usize {
+        self.ring.len.load(Ordering::Acquire)
+    }
+
+    /// Copy whole frames of buffered samples into `buf` in the format of the file, e.g. from the
+    /// audio ISR, returns the number of bytes copied.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.level()) / self.block_align * self.block_align;
+
+        if len == 0 {
+            return 0;
+        }
+
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let first = len.min(N - head);
+        let buffer = self.ring.buffer.get() as *const u8;
+
+        // SAFETY: the `len` bytes from `head` on were published by the producer, which doesn't
+        // touch them again until the new length is published below. The frames may wrap around
+        // the end of the ring.
+        unsafe {
+            ptr::copy_nonoverlapping(buffer.add(head), buf.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(buffer, buf[first..].as_mut_ptr(), len - first);
+        }
+
+        self.ring.head.store((head + len) % N, Ordering::Relaxed);
+        self.ring.len.fetch_sub(len, Ordering::Release);
+
+        len
+    }
+
+    /// True once every whole frame of the data chunk was read out of the buffer
+    pub fn is_end(&self) -> bool {
+        self.ring.end.load(Ordering::Acquire) && self.level() < self.block_align
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_in_bursts_between_the_watermarks() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let wav = Wav::from_bytes(bytes).unwrap();
+        let data = &bytes[wav.data.start..wav.data.end.min(bytes.len())];
+        let mut reader: BurstReader<_, 1000> = BurstReader::new(wav, 200, 900);
+        let (mut producer, mut consumer) = reader.split();
+
+        let mut played = std::vec::Vec::new();
+
+        while !consumer.is_end() {
+            match producer.poll().unwrap() {
+                // the card idles until the buffer drains to the low watermark
+                0 => assert!(producer.level() > 200 || producer.wav.is_end()),
+                _ if producer.wav.is_end() => {}
+                burst => {
+                    assert!(burst >= 700);
+                    assert_eq!(producer.idle_frames(), (900 - 200) / 6);
+                }
+            }
+
+            // playback takes 10 frames per period
+            let mut period = [0; 64];
+            let len = consumer.read(&mut period);
+            assert_eq!(len, 60.min(data.len() - played.len()) / 6 * 6);
+            played.extend_from_slice(&period[..len]);
+        }
+
+        assert_eq!(played, &data[..data.len() / 6 * 6]);
+        assert_eq!(producer.idle_frames(), 0);
+    }
+
+    #[test]
+    fn should_refill_while_the_other_thread_plays() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let wav = Wav::from_bytes(bytes).unwrap();
+        let data = &bytes[wav.data.start..wav.data.end.min(bytes.len())];
+        let mut reader: BurstReader<_, 128> = BurstReader::new(wav, 32, 120);
+        let (mut producer, mut consumer) = reader.split();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                while !producer.wav.is_end() {
+                    if producer.poll().unwrap() == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut played = std::vec::Vec::new();
+
+            while !consumer.is_end() {
+                let mut period = [0; 18];
+                let len = consumer.read(&mut period);
+                played.extend_from_slice(&period[..len]);
+
+                if len == 0 {
+                    std::thread::yield_now();
+                }
+            }
+
+            assert_eq!(played, &data[..data.len() / 6 * 6]);
+        });
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::Error;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+use heapless::Vec;
+
+/// Conversion of raw samples of one channel into physical units, e.g. volts or g
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelCalibration {
+    /// Units per LSB, for float files units per 1.0
+    pub scale: f32,
+    /// Units added after scaling, i.e. the value of a zero sample
+    pub offset: f32,
+}
+
+impl ChannelCalibration {
+    /// Calibration passing raw values through unchanged
+    pub const IDENTITY: ChannelCalibration = ChannelCalibration {
+        scale: 1.0,
+        offset: 0.0,
+    };
+
+    fn apply(&self, raw: f32) -> f32 {
+        raw * self.scale + self.offset
+    }
+}
+
+/// Per channel calibration of a measurement file with `CHANNELS` channels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration<const CHANNELS: usize> {
+    /// Calibration of every channel, in channel order
+    pub channels: [ChannelCalibration; CHANNELS],
+}
+
+impl<const CHANNELS: usize> Calibration<CHANNELS> {
+    /// Create a calibration from the scale and offset of every channel
+    pub const fn new(channels: [ChannelCalibration; CHANNELS]) -> Self {
+        Calibration { channels }
+    }
+
+    /// Convert interleaved raw samples into calibrated values.
+    ///
+    /// 8 bit samples are taken relative to their midpoint of 128, so a zero signal reads as the offset.
+    pub fn apply<const NUM: usize>(&self, bulk: &DataBulk<NUM>) -> Vec<f32, NUM> {
+        fn map<T: Copy, const NUM: usize>(
+            channels: &[ChannelCalibration],
+            samples: &Vec<T, NUM>,
+            raw: impl Fn(T) -> f32,
+        ) -> Vec<f32, NUM> {
+            samples
+                .iter()
+                .zip(channels.iter().cycle())
+                .map(|(sample, calibration)| calibration.apply(raw(*sample)))
+                .collect()
+        }
+
+        if CHANNELS == 0 {
+            return Vec::new();
+        }
+
+        match bulk {
+            DataBulk::BitDepth8(samples) => map(&self.channels, samples, |s| s as f32 - 128.0),
+            DataBulk::BitDepth16(samples) => map(&self.channels, samples, |s| s as f32),
+            DataBulk::BitDepth24(samples) => map(&self.channels, samples, |s| s as f32),
+            DataBulk::Float32(samples) => map(&self.channels, samples, |s| s),
+        }
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Same as [`Wav::next_n`], converting the samples into calibrated values
+    pub fn next_n_calibrated<const NUM: usize, const CHANNELS: usize>(
+        &mut self,
+        calibration: &Calibration<CHANNELS>,
+    ) -> Result<Vec<f32, NUM>, Error<S::Error>> {
+        if self.fmt.num_channels as usize != CHANNELS {
+            return Err(Error::FormatMismatch);
+        }
+
+        let bulk = self.next_n::<NUM>()?;
+
+        Ok(calibration.apply(&bulk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_calibrate_every_channel() {
+        let calibration = Calibration::new([
+            ChannelCalibration {
+                scale: 0.5,
+                offset: 0.0,
+            },
+            ChannelCalibration {
+                scale: 2.0,
+                offset: -1.0,
+            },
+        ]);
+        let bulk = DataBulk::<4>::BitDepth16(Vec::from_slice(&[10, 10, -4, 0]).unwrap());
+
+        assert_eq!(calibration.apply(&bulk), [5.0, 19.0, -2.0, -1.0]);
+    }
+
+    #[test]
+    fn should_center_8_bit_samples() {
+        let calibration = Calibration::new([ChannelCalibration::IDENTITY]);
+        let bulk = DataBulk::<2>::BitDepth8(Vec::from_slice(&[128, 0]).unwrap());
+
+        assert_eq!(calibration.apply(&bulk), [0.0, -128.0]);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::identity::{FNV_OFFSET, FNV_PRIME};
+use core::hash::{Hash, Hasher};
+use heapless::Vec;
+
+/// Point in a stream a decoder can resume from without decoding from the start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint<St> {
+    /// Byte offset in the file the decoder continues reading from
+    pub offset: u32,
+    /// Index of the first frame decoded from `offset`
+    pub frame: u64,
+    /// Decoder state at `offset`, restoring it makes decoding continue bit exact
+    pub state: St,
+    /// Hash of `state` taken when it was recorded
+    pub hash: u32,
+}
+
+impl<St: Hash> Checkpoint<St> {
+    /// True if `state` still hashes to `hash`, false for a checkpoint damaged while kept, e.g.
+    /// in backup RAM, which must not be restored
+    pub fn is_intact(&self) -> bool {
+        state_hash(&self.state) == self.hash
+    }
+}
+
+/// Fixed capacity list of [`Checkpoint`]s recorded while decoding a stream.
+///
+/// A checkpoint is kept every `interval` frames. When the list is full every other checkpoint
+/// is dropped and the interval doubles, so any stream length fits in bounded memory. `N` has to
+/// be at least `1`.
+#[derive(Debug, Clone)]
+pub struct Checkpoints<St, const N: usize> {
+    interval: u64,
+    points: Vec<Checkpoint<St>, N>,
+}
+
+impl<St: Copy + Hash, const N: usize> Checkpoints<St, N> {
+    /// Fails to build for `N == 0`, a full list couldn't be thinned out to make room
+    const NOT_EMPTY: () = assert!(N > 0, "Checkpoints need room for at least one checkpoint");
+
+    /// Create an empty list keeping a checkpoint every `interval_frames` frames
+    pub fn new(interval_frames: u64) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NOT_EMPTY;
+
+        Checkpoints {
+            interval: interval_frames.max(1),
+            points: Vec::new(),
+        }
+    }
+
+    /// Current number of frames between checkpoints
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Offer the decoder position and state, it is kept when due.
+    ///
+    /// Positions at or before the last checkpoint are ignored, so replaying a stream after a
+    /// loop restart doesn't record anything twice.
+    pub fn record(&mut self, offset: u32, frame: u64, state: St) {
+        loop {
+            if let Some(last) = self.points.last() {
+                if frame < last.frame.saturating_add(self.interval) {
+                    return;
+                }
+            }
+
+            if !self.points.is_full() {
+                break;
+            }
+
+            // the interval doubles, the position might not be due anymore
+            self.thin_out();
+        }
+
+        let _ = self.points.push(Checkpoint {
+            offset,
+            frame,
+            state,
+            hash: state_hash(&state),
+        });
+    }
+
+    /// Latest checkpoint at or before `frame`
+    pub fn before(&self, frame: u64) -> Option<&Checkpoint<St>> {
+        let index = self.points.partition_point(|p| p.frame <= frame);
+        index.checked_sub(1).map(|i| &self.points[i])
+    }
+
+    /// Recorded checkpoints, ordered by frame
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint<St>> {
+        self.points.iter()
+    }
+
+    /// Forget all checkpoints
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    fn thin_out(&mut self) {
+        let mut index = 0;
+        self.points.retain(|_| {
+            index += 1;
+            index % 2 == 1
+        });
+
+        self.interval = self.interval.saturating_mul(2);
+    }
+}
+
+/// FNV-1a hasher, 32 bit
+struct Fnv(u32);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u32).wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+/// Hash of a decoder state
+fn state_hash<St: Hash>(state: &St) -> u32 {
+    let mut hasher = Fnv(FNV_OFFSET);
+    state.hash(&mut hasher);
+
+    hasher.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_record_at_interval() {
+        let mut checkpoints: Checkpoints<u8, 8> = Checkpoints::new(100);
+
+        for frame in (0..500).step_by(50) {
+            checkpoints.record(frame as u32 * 4, frame, 0);
+        }
+
+        let frames: Vec<u64, 8> = checkpoints.iter().map(|c| c.frame).collect();
+        assert_eq!(frames, [0, 100, 200, 300, 400]);
+
+        assert_eq!(checkpoints.before(250).unwrap().offset, 800);
+        assert_eq!(checkpoints.before(400).unwrap().frame, 400);
+    }
+
+    #[test]
+    fn should_thin_out_when_full() {
+        let mut checkpoints: Checkpoints<u64, 4> = Checkpoints::new(10);
+
+        for frame in (0..100).step_by(10) {
+            checkpoints.record(frame as u32, frame, frame);
+        }
+
+        let frames: Vec<u64, 4> = checkpoints.iter().map(|c| c.frame).collect();
+        assert_eq!(frames, [0, 40, 80]);
+        assert_eq!(checkpoints.interval(), 40);
+        assert_eq!(checkpoints.before(79).unwrap().state, 40);
+    }
+
+    #[test]
+    fn should_ignore_replayed_positions() {
+        let mut checkpoints: Checkpoints<(), 4> = Checkpoints::new(10);
+
+        checkpoints.record(0, 0, ());
+        checkpoints.record(10, 10, ());
+        checkpoints.record(0, 0, ());
+
+        assert_eq!(checkpoints.iter().count(), 2);
+    }
+
+    #[test]
+    fn should_detect_damaged_checkpoints() {
+        let mut checkpoints: Checkpoints<u64, 4> = Checkpoints::new(10);
+
+        checkpoints.record(0, 0, 7);
+        checkpoints.record(10, u64::MAX - 5, 8);
+        checkpoints.record(20, u64::MAX, 9);
+
+        let mut checkpoint = *checkpoints.before(u64::MAX).unwrap();
+        assert_eq!(checkpoint.state, 9);
+        assert!(checkpoint.is_intact());
+
+        checkpoint.state = 8;
+        assert!(!checkpoint.is_intact());
+    }
+}
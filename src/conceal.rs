@@ -0,0 +1,168 @@
+use crate::error::Error;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+
+/// What to deliver in place of a buffer that failed to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concealment {
+    /// Deliver a buffer of silence
+    Silence,
+    /// Deliver the last buffer that was read successfully again, silence if there is none
+    RepeatLast,
+}
+
+/// Error tolerant reader that conceals failed reads instead of aborting playback.
+///
+/// When the source fails mid-stream a concealment buffer is delivered, the read position skips
+/// to the first whole frame after the failed buffer and the error counter goes up. Errors that
+/// can't be recovered from, such as an unsupported bit depth, are still returned.
+#[derive(Debug, Clone)]
+pub struct Tolerant<const NUM: usize> {
+    concealment: Concealment,
+    last: Option<DataBulk<NUM>>,
+    errors: u32,
+}
+
+impl<const NUM: usize> Tolerant<NUM> {
+    /// Create a tolerant reader using the given concealment
+    pub fn new(concealment: Concealment) -> Self {
+        Tolerant {
+            concealment,
+            last: None,
+            errors: 0,
+        }
+    }
+
+    /// Number of reads that failed and were concealed
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Same as [`Wav::next_n`], concealing read errors
+    pub fn next_n<S: AudioSource>(
+        &mut self,
+        wav: &mut Wav<S>,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        let offset = wav.data_offset();
+
+        match wav.next_n() {
+            Ok(bulk) => {
+                if self.concealment == Concealment::RepeatLast {
+                    self.last = Some(bulk.clone());
+                }
+
+                Ok(bulk)
+            }
+            Err(Error::Source(_)) => {
+                self.errors = self.errors.saturating_add(1);
+
+                // resync on the first whole frame after the failed buffer
+                let block_align = wav.fmt.block_align().max(1);
+                let skip = NUM / (wav.fmt.num_channels as usize).max(1) * block_align;
+                let resync = (offset + skip).div_ceil(block_align) * block_align;
+
+                // a failing seek is retried by the next read
+                let _ = wav.seek_data(resync);
+
+                match (&self.last, self.concealment) {
+                    (Some(last), Concealment::RepeatLast) => Ok(last.clone()),
+                    _ => DataBulk::silence(&wav.fmt).map_err(Error::widen),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    const WAV: [u8; 60] = [
+        0x52, 0x49, 0x46, 0x46, // RIFF
+        0x34, 0x00, 0x00, 0x00, // chunk size
+        0x57, 0x41, 0x56, 0x45, // WAVE
+        0x66, 0x6d, 0x74, 0x20, // fmt_
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, // audio format
+        0x01, 0x00, // num channels
+        0x22, 0x56, 0x00, 0x00, // sample rate
+        0x44, 0xac, 0x00, 0x00, // byte rate
+        0x02, 0x00, // block align
+        0x10, 0x00, // bits per sample
+        0x64, 0x61, 0x74, 0x61, // data
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, 0x02, 0x00, // samples 1 and 2
+        0x03, 0x00, 0x04, 0x00, // samples 3 and 4
+        0x05, 0x00, 0x06, 0x00, // samples 5 and 6
+        0x07, 0x00, 0x08, 0x00, // samples 7 and 8
+    ];
+
+    /// Source failing every read that starts inside a damaged byte range
+    struct Damaged<'b> {
+        inner: SliceSource<'b>,
+        damaged: core::ops::Range<u32>,
+    }
+
+    impl<'b> AudioSource for Damaged<'b> {
+        type Error = ();
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            if self.damaged.contains(&self.inner.offset()) {
+                return Err(());
+            }
+
+            Ok(self.inner.read(buf).unwrap())
+        }
+
+        fn seek(&mut self, offset: u32) -> Result<(), ()> {
+            self.inner.seek(offset).map_err(|_| ())
+        }
+
+        fn offset(&self) -> u32 {
+            self.inner.offset()
+        }
+
+        fn length(&self) -> u32 {
+            self.inner.length()
+        }
+    }
+
+    fn damaged_wav() -> Wav<Damaged<'static>> {
+        let source = Damaged {
+            inner: SliceSource::new(&WAV),
+            damaged: 48..52,
+        };
+
+        Wav::new(source).unwrap()
+    }
+
+    fn samples(bulk: DataBulk<2>) -> [i16; 2] {
+        match bulk {
+            DataBulk::BitDepth16(samples) => [samples[0], samples[1]],
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_conceal_with_silence_and_resync() {
+        let mut wav = damaged_wav();
+        let mut tolerant: Tolerant<2> = Tolerant::new(Concealment::Silence);
+
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [1, 2]);
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [0, 0]);
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [5, 6]);
+        assert_eq!(tolerant.errors(), 1);
+    }
+
+    #[test]
+    fn should_conceal_by_repeating_last_buffer() {
+        let mut wav = damaged_wav();
+        let mut tolerant: Tolerant<2> = Tolerant::new(Concealment::RepeatLast);
+
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [1, 2]);
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [1, 2]);
+        assert_eq!(samples(tolerant.next_n(&mut wav).unwrap()), [5, 6]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Conformance harness for checking decoder output against reference vectors.
+//!
+//! The encoded input is a WAV file, IMA ADPCM included, or a FLAC file that goes
+//! through the decoders of this crate. The reference is a WAV file holding the
+//! expected PCM samples at the same channel count, sample rate and bit depth.
+//! Comparing them sample by sample reports the largest deviation, which makes
+//! it easy to verify bit exactness when porting to a new target.
+//!
+//! ```no_run
+//! use audio_parser::conformance;
+//!
+//! let report = conformance::compare_files(
+//!     "./test_files/stereo_16_48000.wav",
+//!     "./test_files/stereo_16_48000.wav",
+//! )
+//! .unwrap();
+//!
+//! assert!(report.is_bit_exact());
+//! ```
+
+use crate::audio_file::AudioFile;
+use crate::error::Error;
+use crate::flac::Flac;
+use crate::fmt::AudioCodec;
+use crate::source::{AudioSource, SliceSource};
+use crate::wav::{Data, DataBulk, Wav};
+use core::convert::Infallible;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Samples decoded per read of a PCM file
+const BULK_LEN: usize = 256;
+
+/// Outcome of comparing decoded samples against a reference vector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    /// Number of samples that were compared
+    pub samples: usize,
+    /// Largest absolute difference between a decoded and a reference sample
+    pub max_deviation: u32,
+    /// Index of the first sample with the largest deviation, `None` if all samples match
+    pub max_deviation_at: Option<usize>,
+    /// Set when the decoded and the reference vector differ in length
+    pub length_mismatch: bool,
+}
+
+impl Report {
+    /// True when every sample matches and both vectors have the same length
+    pub fn is_bit_exact(&self) -> bool {
+        self.max_deviation == 0 && !self.length_mismatch
+    }
+}
+
+/// Samples of an in memory file, decoded by the decoders of this crate at full bit depth
+struct Samples {
+    num_channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    samples: Vec<i32>,
+}
+
+impl Samples {
+    fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        match AudioFile::new_auto(SliceSource::new(bytes))? {
+            AudioFile::Wav(wav) => Samples::decode_wav(wav),
+            AudioFile::Avi(avi) => Samples::decode_wav(avi),
+            AudioFile::Flac(flac) => Samples::decode_flac(flac),
+            // not decoded by this crate
+            AudioFile::Mp3(_) | AudioFile::Ogg(_) => Err(Error::UnknownFileFormat),
+        }
+    }
+
+    fn decode_wav<S: AudioSource<Error = Infallible>>(mut wav: Wav<S>) -> Result<Self, Error> {
+        let fmt = wav.fmt;
+        let mut samples = Vec::new();
+
+        let bits_per_sample = if fmt.codec == AudioCodec::ImaAdpcm {
+            let mut block = vec![0; fmt.frames_per_block() * fmt.num_channels as usize];
+
+            loop {
+                match wav.next_adpcm_block(&mut block)? {
+                    0 => break 16,
+                    len => samples.extend(block[..len].iter().map(|&s| s as i32)),
+                }
+            }
+        } else {
+            loop {
+                let bulk: DataBulk<BULK_LEN> = wav.next_frames(usize::MAX)?;
+
+                let bits = match &bulk {
+                    DataBulk::BitDepth8(bulk) => {
+                        samples.extend(bulk.iter().map(|&s| s as i32));
+                        8
+                    }
+                    DataBulk::BitDepth16(bulk) => {
+                        samples.extend(bulk.iter().map(|&s| s as i32));
+                        16
+                    }
+                    DataBulk::BitDepth24(bulk) => {
+                        samples.extend_from_slice(bulk);
+                        24
+                    }
+                    DataBulk::Float32(bulk) => {
+                        samples.extend(bulk.iter().map(|&s| Data::Float32(s).as_i32()));
+                        32
+                    }
+                };
+
+                if bulk.is_empty() {
+                    break bits;
+                }
+            }
+        };
+
+        Ok(Samples {
+            num_channels: fmt.num_channels,
+            sample_rate: fmt.sample_rate,
+            bits_per_sample,
+            samples,
+        })
+    }
+
+    fn decode_flac(mut flac: Flac<SliceSource<'_>>) -> Result<Self, Error> {
+        let info = flac.info;
+        let channels = info.num_channels as usize;
+        let mut block = vec![0; info.max_block_size as usize * channels];
+        let mut samples = Vec::new();
+
+        loop {
+            match flac.next_block(&mut block) {
+                Ok(0) | Err(Error::EndOfData) => break,
+                Ok(frames) => samples.extend_from_slice(&block[..frames * channels]),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(Samples {
+            num_channels: info.num_channels,
+            sample_rate: info.sample_rate,
+            bits_per_sample: info.bit_depth,
+            samples,
+        })
+    }
+}
+
+/// Decode the `encoded` WAV or FLAC file and compare it sample by sample against the `reference`
+/// WAV file.
+///
+/// Returns [`Error::FormatMismatch`] if they differ in channel count, sample rate or bits per
+/// decoded sample, e.g. 16 bit IMA ADPCM against a 24 bit reference.
+pub fn compare(encoded: &[u8], reference: &[u8]) -> Result<Report, Error> {
+    let encoded = Samples::decode(encoded)?;
+    let reference = Samples::decode(reference)?;
+
+    if encoded.num_channels != reference.num_channels
+        || encoded.sample_rate != reference.sample_rate
+        || encoded.bits_per_sample != reference.bits_per_sample
+    {
+        return Err(Error::FormatMismatch);
+    }
+
+    let mut report = Report {
+        samples: 0,
+        max_deviation: 0,
+        max_deviation_at: None,
+        length_mismatch: false,
+    };
+
+    for (&d, &e) in encoded.samples.iter().zip(&reference.samples) {
+        let deviation = (d as i64 - e as i64).unsigned_abs() as u32;
+
+        if deviation > report.max_deviation {
+            report.max_deviation = deviation;
+            report.max_deviation_at = Some(report.samples);
+        }
+
+        report.samples += 1;
+    }
+
+    report.length_mismatch = encoded.samples.len() != reference.samples.len();
+
+    Ok(report)
+}
+
+/// Same as [`compare`], reading both WAV files from disk
+pub fn compare_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    encoded: P,
+    reference: Q,
+) -> io::Result<Report> {
+    let encoded = fs::read(encoded)?;
+    let reference = fs::read(reference)?;
+
+    compare(&encoded, &reference)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adpcm::decode_ima_block;
+
+    /// 16 bit PCM WAV file holding `samples`
+    fn pcm_wav(num_channels: u16, sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let data_len = 2 * samples.len() as u32;
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&num_channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * 2 * num_channels as u32).to_le_bytes());
+        bytes.extend_from_slice(&(2 * num_channels).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        samples
+            .iter()
+            .for_each(|s| bytes.extend_from_slice(&s.to_le_bytes()));
+
+        bytes
+    }
+
+    #[test]
+    fn should_be_bit_exact_against_itself() {
+        let report = compare_files(
+            "./test_files/stereo_24_48000.wav",
+            "./test_files/stereo_24_48000.wav",
+        )
+        .unwrap();
+
+        assert!(report.samples > 0);
+        assert!(report.is_bit_exact());
+    }
+
+    #[test]
+    fn should_fail_on_different_channel_counts() {
+        let mono = fs::read("./test_files/mono_16_48000.wav").unwrap();
+        let stereo = fs::read("./test_files/stereo_16_48000.wav").unwrap();
+
+        assert_eq!(compare(&mono, &stereo).unwrap_err(), Error::FormatMismatch);
+    }
+
+    #[test]
+    fn should_decode_ima_adpcm() {
+        let blocks = [
+            0x00, 0x00, 0x00, 0x00, 0x10, 0x32, 0x98, 0xba, // block 1
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // block 2
+        ];
+        let mut adpcm = pcm_wav(1, 22_050, &[]);
+        adpcm[20..22].copy_from_slice(&0x11u16.to_le_bytes());
+        adpcm[32..36].copy_from_slice(&[0x08, 0x00, 0x04, 0x00]);
+        adpcm[40..44].copy_from_slice(&16u32.to_le_bytes());
+        adpcm.extend_from_slice(&blocks);
+
+        let mut expected = [0; 18];
+        decode_ima_block(1, &blocks[..8], &mut expected[..9]).unwrap();
+        decode_ima_block(1, &blocks[8..], &mut expected[9..]).unwrap();
+
+        let report = compare(&adpcm, &pcm_wav(1, 22_050, &expected)).unwrap();
+        assert_eq!(report.samples, 18);
+        assert!(report.is_bit_exact());
+
+        let report = compare(&adpcm, &pcm_wav(1, 22_050, &expected[..17])).unwrap();
+        assert!(report.length_mismatch);
+    }
+
+    #[test]
+    fn should_decode_flac() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut flac = Flac::new(SliceSource::new(bytes)).unwrap();
+        let mut block = vec![0; 2 * flac.info.max_block_size as usize];
+        let mut expected = Vec::new();
+
+        while let Ok(frames @ 1..) = flac.next_block(&mut block) {
+            expected.extend(block[..2 * frames].iter().map(|&s| s as i16));
+        }
+
+        let report = compare(bytes, &pcm_wav(2, 8_000, &expected)).unwrap();
+        assert_eq!(report.samples, expected.len());
+        assert!(report.is_bit_exact());
+
+        expected[5] = expected[5].wrapping_add(3);
+        let report = compare(bytes, &pcm_wav(2, 8_000, &expected)).unwrap();
+        assert_eq!(report.max_deviation, 3);
+        assert_eq!(report.max_deviation_at, Some(5));
+    }
+
+    #[test]
+    fn should_fail_on_different_bit_depths() {
+        let wav_16 = fs::read("./test_files/stereo_16_48000.wav").unwrap();
+        let wav_24 = fs::read("./test_files/stereo_24_48000.wav").unwrap();
+
+        assert_eq!(
+            compare(&wav_16, &wav_24).unwrap_err(),
+            Error::FormatMismatch
+        );
+    }
+}
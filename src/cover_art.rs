@@ -0,0 +1,300 @@
+use crate::error::Error;
+use crate::id3::for_each_id3_frame;
+use crate::metadata::to_string;
+use crate::mp3::Mp3File;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use heapless::String;
+
+/// Bytes of a MIME type kept, longer ones are cut short
+const MIME_LEN: usize = 32;
+/// Bytes read from the start of a picture frame to find where the image begins
+const PICTURE_HEADER_LEN: usize = 256;
+/// Picture type of the front cover
+const FRONT_COVER: u8 = 3;
+
+/// Picture embedded in an ID3v2 `APIC` frame, located within the file rather than read into RAM
+///
+/// The image is read piece by piece with [`Wav::read_cover_art`] or [`Mp3File::read_cover_art`],
+/// e.g. straight into a display driver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverArt {
+    /// Offset of the image data from the start of the file
+    pub offset: u32,
+    /// Length of the image data in bytes
+    pub len: u32,
+    /// MIME type of the image, e.g. `image/jpeg`
+    pub mime: String<MIME_LEN>,
+    /// Picture type of ID3v2, `3` is the front cover
+    pub picture_type: u8,
+}
+
+impl CoverArt {
+    /// Locate the image of a picture frame whose value lies between `start` and `end`
+    fn parse<S: AudioSource>(
+        source: &mut S,
+        id: &[u8],
+        start: u32,
+        end: u32,
+    ) -> Result<Option<Self>, Error<S::Error>> {
+        let mut bytes = [0; PICTURE_HEADER_LEN];
+        let len = ((end - start) as usize).min(PICTURE_HEADER_LEN);
+
+        source.seek(start).map_err(Error::Source)?;
+        let read = read_full(source, &mut bytes[..len])?;
+        let bytes = &bytes[..read];
+
+        let (encoding, rest) = match bytes.split_first() {
+            Some((encoding, rest)) => (*encoding, rest),
+            None => return Ok(None),
+        };
+
+        // version 2.2 names a three letter image format instead of a MIME type
+        let (mime, rest) = match id {
+            b"PIC" if rest.len() >= 3 => {
+                let mime = match &rest[..3] {
+                    b"PNG" => "image/png",
+                    b"JPG" => "image/jpeg",
+                    _ => "",
+                };
+                (to_string(mime.as_bytes()), &rest[3..])
+            }
+            _ => match rest.iter().position(|b| *b == 0) {
+                Some(nul) => (to_string(&rest[..nul]), &rest[nul + 1..]),
+                None => return Ok(None),
+            },
+        };
+
+        let (picture_type, description) = match rest.split_first() {
+            Some((picture_type, description)) => (*picture_type, description),
+            None => return Ok(None),
+        };
+
+        // the description ends in a NUL of the width of its encoding
+        let description_len = match encoding {
+            1 | 2 => description
+                .chunks_exact(2)
+                .position(|unit| unit == [0, 0])
+                .map(|units| units * 2 + 2),
+            _ => description.iter().position(|b| *b == 0).map(|nul| nul + 1),
+        };
+
+        let description_len = match description_len {
+            Some(description_len) => description_len,
+            None => return Ok(None),
+        };
+
+        let offset = start + (read - description.len() + description_len) as u32;
+
+        Ok(Some(CoverArt {
+            offset,
+            len: end - offset,
+            mime,
+            picture_type,
+        }))
+    }
+}
+
+/// Find the front cover in the ID3v2 tag at the read position of `source`, or else the first
+/// picture. Returns the total size of the tag next to it, `None` if there is no tag.
+fn find_cover_art<S: AudioSource>(
+    source: &mut S,
+    end: u32,
+    cover: &mut Option<CoverArt>,
+) -> Result<Option<u32>, Error<S::Error>> {
+    for_each_id3_frame(source, end, |source, id, start, end| {
+        if id != b"APIC" && id != b"PIC" {
+            return Ok(false);
+        }
+
+        match CoverArt::parse(source, id, start, end)? {
+            Some(art) if art.picture_type == FRONT_COVER => {
+                *cover = Some(art);
+                Ok(true)
+            }
+            Some(art) if cover.is_none() => {
+                *cover = Some(art);
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    })
+}
+
+/// Read up to `buf.len()` bytes of `art` starting `offset` bytes into the image, returns the
+/// number of bytes read, `0` past its end
+fn read_cover_art<S: AudioSource>(
+    source: &mut S,
+    art: &CoverArt,
+    offset: u32,
+    buf: &mut [u8],
+) -> Result<usize, Error<S::Error>> {
+    let len = (art.len.saturating_sub(offset) as usize).min(buf.len());
+
+    if len == 0 {
+        return Ok(0);
+    }
+
+    let position = source.offset();
+    source.seek(art.offset + offset).map_err(Error::Source)?;
+
+    let read = read_full(source, &mut buf[..len]);
+    source.seek(position).map_err(Error::Source)?;
+
+    read
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Locate the cover art in the ID3v2 tag of an `id3 ` chunk, the front cover if there is one.
+    ///
+    /// `None` if the file has no picture. The read position is left unchanged.
+    pub fn cover_art(&mut self) -> Result<Option<CoverArt>, Error<S::Error>> {
+        let chunk = match self.find_id3_chunk()? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let mut cover = None;
+        let found = find_cover_art(&mut self.source, chunk.end as u32, &mut cover);
+        self.source.seek(position).map_err(Error::Source)?;
+
+        found.map(|_| cover)
+    }
+
+    /// Read up to `buf.len()` bytes of the image of `art`, starting `offset` bytes into it.
+    ///
+    /// Returns the number of bytes read, `0` once the image is read completely. The read position
+    /// is left unchanged.
+    pub fn read_cover_art(
+        &mut self,
+        art: &CoverArt,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<usize, Error<S::Error>> {
+        read_cover_art(&mut self.source, art, offset, buf)
+    }
+}
+
+impl<S: AudioSource> Mp3File<S> {
+    /// Locate the cover art in the ID3v2 tags at the start of the file, the front cover if there
+    /// is one.
+    ///
+    /// `None` if the file has no picture. The read position is left unchanged.
+    pub fn cover_art(&mut self) -> Result<Option<CoverArt>, Error<S::Error>> {
+        let position = self.source.offset();
+        let mut cover = None;
+        let mut start = 0;
+
+        let result = loop {
+            if let Err(e) = self.source.seek(start).map_err(Error::Source) {
+                break Err(e);
+            }
+
+            match find_cover_art(&mut self.source, u32::MAX, &mut cover) {
+                Ok(Some(size)) => start = start.saturating_add(size),
+                Ok(None) => break Ok(cover),
+                Err(e) => break Err(e),
+            }
+
+            if matches!(&cover, Some(art) if art.picture_type == FRONT_COVER) {
+                break Ok(cover);
+            }
+        };
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        result
+    }
+
+    /// Read up to `buf.len()` bytes of the image of `art`, starting `offset` bytes into it.
+    ///
+    /// Returns the number of bytes read, `0` once the image is read completely. The read position
+    /// is left unchanged.
+    pub fn read_cover_art(
+        &mut self,
+        art: &CoverArt,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<usize, Error<S::Error>> {
+        read_cover_art(&mut self.source, art, offset, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    /// ID3v2.3 tag holding `frames`
+    fn tag(frames: &[u8]) -> std::vec::Vec<u8> {
+        let size = frames.len() as u32;
+        let mut bytes = b"ID3\x03\x00\x00".to_vec();
+        bytes.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+        bytes.extend_from_slice(frames);
+        bytes
+    }
+
+    fn apic(picture_type: u8, description: &[u8], image: &[u8]) -> std::vec::Vec<u8> {
+        let mut value = b"\x01image/png\x00".to_vec();
+        value.push(picture_type);
+        value.extend_from_slice(description);
+        value.extend_from_slice(image);
+
+        let mut bytes = b"APIC".to_vec();
+        bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(&value);
+        bytes
+    }
+
+    #[test]
+    fn should_prefer_the_front_cover() {
+        let mut frames = apic(4, b"\xff\xfeB\x00\x00\x00", b"back");
+        frames.extend(apic(3, b"\xff\xfe\x00\x00", b"\x89PNG front"));
+        let mut bytes = tag(&frames);
+        bytes.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+
+        let mut mp3 = Mp3File::new(SliceSource::new(&bytes)).unwrap();
+        let position = mp3.source.offset();
+        let art = mp3.cover_art().unwrap().unwrap();
+
+        assert_eq!(art.mime, "image/png");
+        assert_eq!(art.picture_type, 3);
+        assert_eq!(art.len, 10);
+        assert_eq!(mp3.source.offset(), position);
+
+        let mut image = [0; 4];
+        assert_eq!(mp3.read_cover_art(&art, 0, &mut image).unwrap(), 4);
+        assert_eq!(&image, b"\x89PNG");
+        assert_eq!(mp3.read_cover_art(&art, 8, &mut image).unwrap(), 2);
+        assert_eq!(&image[..2], b"nt");
+        assert_eq!(mp3.read_cover_art(&art, 10, &mut image).unwrap(), 0);
+    }
+
+    #[test]
+    fn should_find_picture_of_wav_id3_chunk() {
+        let id3 = tag(&apic(0, b"\x00\x00", b"JFIF"));
+        let mut bytes = include_bytes!("../test_files/stereo_16_48000.wav").to_vec();
+        bytes.extend_from_slice(b"id3 ");
+        bytes.extend_from_slice(&(id3.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&id3);
+
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+        let art = wav.cover_art().unwrap().unwrap();
+
+        assert_eq!(art.picture_type, 0);
+        assert_eq!(art.offset as usize, bytes.len() - 4);
+
+        let mut image = [0; 8];
+        assert_eq!(wav.read_cover_art(&art, 0, &mut image).unwrap(), 4);
+        assert_eq!(&image[..4], b"JFIF");
+
+        let plain = include_bytes!("../test_files/stereo_16_48000.wav");
+        assert_eq!(Wav::from_bytes(plain).unwrap().cover_art(), Ok(None));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::fixed::{apply_gain, saturate_i16, UNITY_GAIN};
+
+/// Longest delay of the opposite channel the delay line holds, 0.3 ms up to 96 kHz
+const MAX_DELAY_FRAMES: usize = 32;
+/// Delay of the opposite channel, about the time sound takes around the head
+const DELAY_MICROS: u32 = 300;
+/// 2 pi times the 700 Hz cutoff of the opposite channel low pass, above it the head shadows
+const CUTOFF_OMEGA: u32 = 4_398;
+/// Extra fractional bits of the low pass state
+const STATE_SHIFT: u32 = 8;
+
+/// Crossfeed for headphone listening, mixing a delayed and low passed copy of each channel into
+/// the other so hard panned stereo sounds less like it sits inside the head
+///
+/// Meant as an optional stage of a player, for stereo samples only. The direct channels are
+/// lowered by the crossfeed level, so mono content keeps its level and never clips.
+#[derive(Debug, Clone)]
+pub struct Crossfeed {
+    delay: [[i16; 2]; MAX_DELAY_FRAMES],
+    index: usize,
+    delay_frames: usize,
+    /// Q15 coefficient of the one pole low pass
+    alpha: i64,
+    lowpass: [i64; 2],
+    level: u16,
+}
+
+impl Crossfeed {
+    /// Mild crossfeed at about -10 dB for stereo samples at `sample_rate`
+    pub fn new(sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1) as u64;
+        let delay_frames = (sample_rate * DELAY_MICROS as u64 / 1_000_000) as usize;
+
+        Crossfeed {
+            delay: [[0; 2]; MAX_DELAY_FRAMES],
+            index: 0,
+            delay_frames: delay_frames.min(MAX_DELAY_FRAMES - 1),
+            alpha: (CUTOFF_OMEGA as i64 * UNITY_GAIN as i64)
+                / (sample_rate as i64 + CUTOFF_OMEGA as i64),
+            lowpass: [0; 2],
+            level: UNITY_GAIN / 10 * 3,
+        }
+    }
+
+    /// Q1.15 gain of the opposite channel
+    pub fn level(&self) -> u16 {
+        self.level
+    }
+
+    /// Set the Q1.15 gain of the opposite channel, up to half of [`UNITY_GAIN`]. `0` passes the
+    /// samples unchanged
+    pub fn set_level(&mut self, level: u16) {
+        self.level = level.min(UNITY_GAIN / 2);
+    }
+
+    /// Apply the crossfeed to interleaved stereo `samples` in place, a trailing half frame is left
+    /// as it is
+    pub fn process(&mut self, samples: &mut [i16]) {
+        for frame in samples.chunks_exact_mut(2) {
+            let [left, right] = self.process_frame([frame[0], frame[1]]);
+            frame[0] = left;
+            frame[1] = right;
+        }
+    }
+
+    fn process_frame(&mut self, frame: [i16; 2]) -> [i16; 2] {
+        self.delay[self.index] = frame;
+        let delayed =
+            self.delay[(self.index + MAX_DELAY_FRAMES - self.delay_frames) % MAX_DELAY_FRAMES];
+        self.index = (self.index + 1) % MAX_DELAY_FRAMES;
+
+        for (state, sample) in self.lowpass.iter_mut().zip(delayed) {
+            *state += ((((sample as i64) << STATE_SHIFT) - *state) * self.alpha) >> 15;
+        }
+
+        let direct = UNITY_GAIN - self.level;
+        let mix = |sample: i16, opposite: i64| {
+            let crossed = apply_gain((opposite >> STATE_SHIFT) as i16, self.level);
+            saturate_i16(apply_gain(sample, direct) + crossed)
+        };
+
+        [
+            mix(frame[0], self.lowpass[1]),
+            mix(frame[1], self.lowpass[0]),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_feed_delayed_low_passed_opposite_channel() {
+        let mut crossfeed = Crossfeed::new(48_000);
+        let mut samples = [0; 64];
+        samples.chunks_exact_mut(2).for_each(|f| f[0] = 10_000);
+        crossfeed.process(&mut samples);
+
+        let direct = apply_gain(10_000, UNITY_GAIN - crossfeed.level()) as i16;
+        assert!(samples.chunks_exact(2).all(|f| f[0] == direct));
+
+        // 14 frames of delay at 48 kHz, then the right channel slowly rises
+        assert!(samples[..28].chunks_exact(2).all(|f| f[1] == 0));
+        assert!(samples[28..].chunks_exact(2).all(|f| f[1] > 0));
+        assert!(samples[31] < samples[63]);
+        assert!(samples[63] < apply_gain(10_000, crossfeed.level()) as i16);
+    }
+
+    #[test]
+    fn should_keep_mono_level() {
+        let mut crossfeed = Crossfeed::new(44_100);
+        crossfeed.set_level(UNITY_GAIN);
+        assert_eq!(crossfeed.level(), UNITY_GAIN / 2);
+
+        let mut samples = [i16::MAX; 2000];
+        crossfeed.process(&mut samples);
+
+        assert!((samples[1998] as i32 - i16::MAX as i32).abs() < 4);
+        assert!((samples[1999] as i32 - i16::MAX as i32).abs() < 4);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::error::Error;
+use crate::metadata::to_string;
+use crate::riff::{ChunkTag, ChunkWalker};
+use crate::source::AudioSource;
+use crate::timestamp::Timestamp;
+use crate::wav::{read_full, Wav};
+use core::convert::TryInto;
+use heapless::{String, Vec};
+
+/// Size in bytes of a single cue point entry in the `cue ` chunk
+pub(crate) const CUE_POINT_SIZE: usize = 24;
+/// List type of the `LIST` chunk holding the labels of cue points
+const ADTL: [u8; 4] = *b"adtl";
+/// Entry of the `adtl` list labeling a cue point
+const LABL: &[u8; 4] = b"labl";
+
+/// Marker stored in the `cue ` chunk of a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuePoint {
+    /// Unique identifier, referenced by labels in the `adtl` list
+    pub id: u32,
+    /// Position in playback order
+    pub position: u32,
+    /// Frame within the data chunk the marker points at
+    pub sample_offset: u32,
+}
+
+impl CuePoint {
+    pub(crate) fn from_bytes(bytes: &[u8; CUE_POINT_SIZE]) -> Self {
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        CuePoint {
+            id: field(0),
+            position: field(4),
+            sample_offset: field(20),
+        }
+    }
+
+    /// Serialize into an entry of the `cue ` chunk, pointing into the `data` chunk
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    pub(crate) fn to_bytes(self) -> [u8; CUE_POINT_SIZE] {
+        let mut bytes = [0; CUE_POINT_SIZE];
+        bytes[0..4].copy_from_slice(&self.id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.position.to_le_bytes());
+        bytes[8..12].copy_from_slice(b"data");
+        bytes[20..24].copy_from_slice(&self.sample_offset.to_le_bytes());
+
+        bytes
+    }
+}
+
+/// Cue point together with its `labl` text from the `adtl` list, e.g. a chapter of an audiobook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue<const LABEL_LEN: usize> {
+    /// Id and frame of the cue point
+    pub point: CuePoint,
+    /// Label of the cue point, cut short to `LABEL_LEN` bytes, `None` if it has none
+    pub label: Option<String<LABEL_LEN>>,
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Call `f` with every entry of the `cue ` chunk, if the file has one, stopping at the first
+    /// error. The read position is left unchanged
+    pub(crate) fn for_each_cue_point<F>(&mut self, mut f: F) -> Result<(), Error<S::Error>>
+    where
+        F: FnMut(CuePoint) -> Result<(), Error<S::Error>>,
+    {
+        let chunk = match self.find_chunk(ChunkTag::Cue)? {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let mut count = [0; 4];
+        let count = match self.source.read(&mut count).map_err(Error::Source)? {
+            4 => u32::from_le_bytes(count) as usize,
+            _ => 0,
+        };
+
+        // never trust the count beyond what the chunk can hold
+        let count = count.min((chunk.end - chunk.start).saturating_sub(4) / CUE_POINT_SIZE);
+        let mut result = Ok(());
+
+        for _ in 0..count {
+            let mut bytes = [0; CUE_POINT_SIZE];
+
+            if self.source.read(&mut bytes).map_err(Error::Source)? != CUE_POINT_SIZE {
+                break;
+            }
+
+            result = f(CuePoint::from_bytes(&bytes));
+
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        result
+    }
+
+    /// Read the cue points of the file in frame order, labeled from the `LIST` `adtl` chunk.
+    ///
+    /// Returns [`Error::TooManyCuePoints`] if the file has more than `N`. The read position is
+    /// left unchanged.
+    pub fn cues<const N: usize, const LABEL_LEN: usize>(
+        &mut self,
+    ) -> Result<Vec<Cue<LABEL_LEN>, N>, Error<S::Error>> {
+        let mut cues: Vec<Cue<LABEL_LEN>, N> = Vec::new();
+
+        self.for_each_cue_point(|point| {
+            let index = cues.partition_point(|c| c.point.sample_offset <= point.sample_offset);
+
+            cues.insert(index, Cue { point, label: None })
+                .map_err(|_| Error::TooManyCuePoints)
+        })?;
+
+        if !cues.is_empty() {
+            self.read_cue_labels(&mut cues)?;
+        }
+
+        Ok(cues)
+    }
+
+    /// Fill in the labels of `cues` from the `labl` entries of the `adtl` list
+    fn read_cue_labels<const LABEL_LEN: usize>(
+        &mut self,
+        cues: &mut [Cue<LABEL_LEN>],
+    ) -> Result<(), Error<S::Error>> {
+        let list = match self.find_list(ADTL)? {
+            Some(list) => list,
+            None => return Ok(()),
+        };
+
+        let position = self.source.offset();
+        let end = list.end.min(self.source.length() as usize);
+
+        // skip the list type
+        let mut walker = ChunkWalker::range(list.start + 4, end);
+
+        while let Some(entry) = walker.next_chunk(&mut self.source)? {
+            let mut id = [0; 4];
+
+            if read_full(&mut self.source, &mut id)? != id.len() {
+                break;
+            }
+
+            let id = u32::from_le_bytes(id);
+            let cue = cues.iter_mut().find(|c| c.point.id == id);
+
+            if let (true, Some(cue)) = (entry.id.to_bytes() == *LABL, cue) {
+                let mut label = [0; LABEL_LEN];
+                let len = (entry.end.min(end).saturating_sub(entry.start + 4)).min(LABEL_LEN);
+                let read = self.source.read(&mut label[..len]).map_err(Error::Source)?;
+
+                cue.label = Some(to_string(&label[..read]));
+            }
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(())
+    }
+
+    /// Move the read position to the frame of cue point `id`, e.g. to jump to a chapter.
+    ///
+    /// Returns [`Error::UnknownCuePoint`] if the file has no cue point with that id.
+    pub fn seek_to_cue(&mut self, id: u32) -> Result<Timestamp, Error<S::Error>> {
+        let mut frame = None;
+
+        self.for_each_cue_point(|point| {
+            if point.id == id && frame.is_none() {
+                frame = Some(point.sample_offset);
+            }
+
+            Ok(())
+        })?;
+
+        match frame {
+            Some(frame) => self.seek_to_sample(frame as u64),
+            None => Err(Error::UnknownCuePoint(id)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_cue_point() {
+        let bytes: [u8; CUE_POINT_SIZE] = [
+            0x01, 0x00, 0x00, 0x00, // id
+            0x00, 0x00, 0x00, 0x00, // position
+            0x64, 0x61, 0x74, 0x61, // data
+            0x00, 0x00, 0x00, 0x00, // chunk start
+            0x00, 0x00, 0x00, 0x00, // block start
+            0x88, 0x58, 0x01, 0x00, // sample offset
+        ];
+
+        let cue = CuePoint::from_bytes(&bytes);
+
+        assert_eq!(cue.id, 1);
+        assert_eq!(cue.position, 0);
+        assert_eq!(cue.sample_offset, 88_200);
+        assert_eq!(cue.to_bytes(), bytes);
+    }
+
+    /// Mono 16 bit file with 20 frames, cue points 7 at frame 12 and 3 at frame 4, and labels
+    fn audiobook() -> std::vec::Vec<u8> {
+        fn chunk(bytes: &mut std::vec::Vec<u8>, id: &[u8], body: &[u8]) {
+            bytes.extend_from_slice(id);
+            bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(body);
+
+            if body.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+
+        // PCM, mono, 8 kHz, 16 bit
+        let mut fmt = [1u16.to_le_bytes(), 1u16.to_le_bytes()].concat();
+        fmt.extend_from_slice(&8_000u32.to_le_bytes());
+        fmt.extend_from_slice(&16_000u32.to_le_bytes());
+        fmt.extend_from_slice(&2u16.to_le_bytes());
+        fmt.extend_from_slice(&16u16.to_le_bytes());
+
+        let mut cue = 2u32.to_le_bytes().to_vec();
+        for (position, (id, frame)) in [(7u32, 12u32), (3, 4)].iter().enumerate() {
+            let point = CuePoint {
+                id: *id,
+                position: position as u32,
+                sample_offset: *frame,
+            };
+            cue.extend_from_slice(&point.to_bytes());
+        }
+
+        let mut adtl = b"adtl".to_vec();
+        chunk(&mut adtl, b"labl", b"\x03\x00\x00\x00Chapter 1\0");
+        chunk(&mut adtl, b"note", b"\x07\x00\x00\x00ignored\0");
+        chunk(
+            &mut adtl,
+            b"labl",
+            b"\x07\x00\x00\x00Chapter 2, the long one\0",
+        );
+
+        let mut body = b"WAVE".to_vec();
+        chunk(&mut body, b"fmt ", &fmt);
+        chunk(&mut body, b"data", &[0; 40]);
+        chunk(&mut body, b"cue ", &cue);
+        chunk(&mut body, b"LIST", &adtl);
+
+        let mut bytes = std::vec::Vec::new();
+        chunk(&mut bytes, b"RIFF", &body);
+        bytes
+    }
+
+    #[test]
+    fn should_read_labeled_cues_in_frame_order() {
+        let bytes = audiobook();
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+
+        let cues = wav.cues::<4, 9>().unwrap();
+        let found: std::vec::Vec<_> = cues
+            .iter()
+            .map(|c| (c.point.id, c.point.sample_offset, c.label.as_deref()))
+            .collect();
+
+        assert_eq!(
+            found,
+            [(3, 4, Some("Chapter 1")), (7, 12, Some("Chapter 2"))]
+        );
+        assert!(matches!(wav.cues::<1, 9>(), Err(Error::TooManyCuePoints)));
+        assert_eq!(wav.timestamp().frames, 0);
+    }
+
+    #[test]
+    fn should_seek_to_cue() {
+        let bytes = audiobook();
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+
+        assert_eq!(wav.seek_to_cue(7).unwrap().frames, 12);
+        assert_eq!(wav.seek_to_cue(3).unwrap().frames, 4);
+        assert_eq!(wav.seek_to_cue(5), Err(Error::UnknownCuePoint(5)));
+        assert_eq!(wav.timestamp().frames, 4);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::error::Error;
+use crate::flac::Flac;
+use crate::fmt::AudioCodec;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+
+/// Stream parameters a [`Decoder`] reports before any audio is decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderInfo {
+    /// sample rate, typical values are `44_100` or `48_000`
+    pub sample_rate: u32,
+    /// number of interleaved channels of the decoded audio
+    pub num_channels: u16,
+    /// Length of the stream in frames, if the container tells
+    pub total_frames: Option<u64>,
+}
+
+/// Common front end of every codec, turning a stream into interleaved 16 bit PCM
+///
+/// Implemented by [`Wav`] for all the formats it reads and by [`Flac`]. Future software codecs
+/// and decoders driving a hardware chip implement it too, so a player handles them all the same
+/// way, see [`AudioFile::decoder`](crate::AudioFile::decoder).
+pub trait Decoder {
+    /// Error of the underlying source
+    type Error;
+
+    /// Sample rate, channel count and length of the stream
+    fn info(&self) -> DecoderInfo;
+
+    /// Decode the next samples into `out`, returning the number of samples written.
+    ///
+    /// Only whole frames are written, `0` means the stream ended. Codecs decoding whole blocks
+    /// return [`Error::BufferTooSmall`] if `out` can't hold one.
+    fn decode(&mut self, out: &mut [i16]) -> Result<usize, Error<Self::Error>>;
+}
+
+impl<S: AudioSource> Decoder for Wav<S> {
+    type Error = S::Error;
+
+    fn info(&self) -> DecoderInfo {
+        let blocks =
+            self.data_end().saturating_sub(self.data.start) / self.fmt.block_align().max(1);
+
+        DecoderInfo {
+            sample_rate: self.fmt.sample_rate,
+            num_channels: self.fmt.num_channels,
+            total_frames: Some((blocks * self.fmt.frames_per_block()) as u64),
+        }
+    }
+
+    fn decode(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        if self.fmt.codec == AudioCodec::ImaAdpcm {
+            return self.next_adpcm_block(out);
+        }
+
+        let channels = (self.fmt.num_channels as usize).max(1);
+        let frames = out.len() / channels;
+
+        if frames == 0 {
+            return Err(Error::BufferTooSmall(channels * 2));
+        }
+
+        let mut written = 0;
+
+        while written < frames * channels {
+            let bulk: DataBulk<96> = self.next_frames(frames - written / channels)?;
+
+            if bulk.is_empty() {
+                break;
+            }
+
+            bulk.for_each_i16(|sample| {
+                out[written] = sample;
+                written += 1;
+            });
+        }
+
+        Ok(written)
+    }
+}
+
+/// Blocks are decoded whole, `out` has to hold twice [`StreamInfo::max_block_size`] frames: the
+/// block is decoded at full precision into the whole buffer, then scaled to 16 bit in place
+///
+/// [`StreamInfo::max_block_size`]: crate::StreamInfo::max_block_size
+impl<S: AudioSource> Decoder for Flac<S> {
+    type Error = S::Error;
+
+    fn info(&self) -> DecoderInfo {
+        DecoderInfo {
+            sample_rate: self.info.sample_rate,
+            num_channels: self.info.num_channels,
+            total_frames: Some(self.info.total_frames).filter(|&frames| frames > 0),
+        }
+    }
+
+    fn decode(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        match self.next_block_q15(out) {
+            Err(Error::EndOfData) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    #[test]
+    fn should_decode_wav_through_the_trait() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+
+        let info = wav.info();
+        assert_eq!(info.sample_rate, 48_000);
+        assert_eq!(info.num_channels, 2);
+        assert_eq!(
+            info.total_frames,
+            Some(reference.duration().unwrap().frames)
+        );
+
+        let decoder: &mut dyn Decoder<Error = _> = &mut wav;
+        let mut out = [0; 301];
+        assert_eq!(decoder.decode(&mut out).unwrap(), 300);
+
+        match reference.next_n::<300>().unwrap() {
+            DataBulk::BitDepth24(samples) => {
+                assert!(samples
+                    .iter()
+                    .zip(&out)
+                    .all(|(&s, &o)| (s >> 8) as i16 == o))
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(matches!(
+            decoder.decode(&mut [0; 1]),
+            Err(Error::BufferTooSmall(4))
+        ));
+    }
+
+    #[test]
+    fn should_decode_flac_through_the_trait() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut flac = Flac::new(SliceSource::new(bytes)).unwrap();
+        let mut reference = Flac::new(SliceSource::new(bytes)).unwrap();
+
+        let info = flac.info();
+        assert_eq!((info.sample_rate, info.num_channels), (8_000, 2));
+
+        let needed = 2 * reference.info.max_block_size as usize * 2;
+        let mut out = std::vec![0; needed];
+        let mut block = std::vec![0; needed / 2];
+
+        let decoder: &mut dyn Decoder<Error = _> = &mut flac;
+        let mut decoded = 0;
+
+        while let Ok(frames @ 1..) = reference.next_block(&mut block) {
+            let len = decoder.decode(&mut out).unwrap();
+            assert_eq!(len, frames * 2);
+            assert!(out[..len].iter().zip(&block).all(|(&o, &b)| o as i32 == b));
+            decoded += len;
+        }
+
+        assert!(decoded > 0);
+        assert_eq!(decoder.decode(&mut out).unwrap(), 0);
+        assert!(matches!(
+            decoder.decode(&mut out[..needed - 1]),
+            Err(Error::BufferTooSmall(n)) if n == needed
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::fixed::saturate_i16;
+use crate::simd::narrow_24_to_16;
+
+/// Bits dropped going from 24 to 16 bit samples
+const SHIFT_24_TO_16: u32 = 8;
+/// Bits dropped going from 16 to 8 bit samples
+const SHIFT_16_TO_8: u32 = 8;
+
+/// How the bits dropped by a bit depth reduction are handled, from cheapest to best sounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain truncation, no extra cycles but the error correlates with the signal
+    Off,
+    /// Triangular noise of +-1 LSB before rounding, the error becomes a steady noise floor
+    Tpdf,
+    /// TPDF with first order error feedback, pushing the noise floor towards high frequencies
+    Shaped,
+}
+
+/// Output stage reducing the bit depth of interleaved samples with `CHANNELS` channels, e.g.
+/// right before a 16 bit DAC or an 8 bit PWM output
+///
+/// Conversions elsewhere in the crate truncate, so dithering costs cycles only where this stage is
+/// put in the chain.
+#[derive(Debug, Clone)]
+pub struct Ditherer<const CHANNELS: usize> {
+    dither: Dither,
+    seed: u32,
+    /// Quantization error of the previous sample of every channel, for noise shaping
+    error: [i32; CHANNELS],
+}
+
+impl<const CHANNELS: usize> Ditherer<CHANNELS> {
+    /// Create an output stage with the given dithering
+    pub fn new(dither: Dither) -> Self {
+        Ditherer {
+            dither,
+            seed: 0x2545_f491,
+            error: [0; CHANNELS],
+        }
+    }
+
+    /// Dithering currently applied
+    pub fn dither(&self) -> Dither {
+        self.dither
+    }
+
+    /// Change the dithering, e.g. when the device enters a low power mode
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+        self.error = [0; CHANNELS];
+    }
+
+    /// Reduce 24 bit samples in `input` to 16 bit samples in `output`, as many as both hold
+    pub fn to_i16(&mut self, input: &[i32], output: &mut [i16]) {
+        if self.dither == Dither::Off {
+            return narrow_24_to_16(input, output);
+        }
+
+        for (i, (out, &sample)) in output.iter_mut().zip(input).enumerate() {
+            let reduced = self.quantize(i, sample, SHIFT_24_TO_16);
+            *out = saturate_i16(reduced);
+        }
+    }
+
+    /// Reduce 16 bit samples in `input` to unsigned 8 bit samples in `output`, as many as both hold
+    pub fn to_u8(&mut self, input: &[i16], output: &mut [u8]) {
+        for (i, (out, &sample)) in output.iter_mut().zip(input).enumerate() {
+            let reduced = self.quantize(i, sample as i32, SHIFT_16_TO_8);
+            *out = (reduced.clamp(i8::MIN as i32, i8::MAX as i32) + 128) as u8;
+        }
+    }
+
+    /// Drop the lowest `shift` bits of the sample at interleaved `index`
+    fn quantize(&mut self, index: usize, sample: i32, shift: u32) -> i32 {
+        let step = 1 << shift;
+
+        match self.dither {
+            Dither::Off => sample >> shift,
+            Dither::Tpdf => (sample + self.tpdf(shift) + step / 2) >> shift,
+            Dither::Shaped => {
+                let noise = self.tpdf(shift);
+                let error = match self.error.get_mut(index % CHANNELS.max(1)) {
+                    Some(error) => error,
+                    None => return sample >> shift,
+                };
+
+                let shaped = sample - *error;
+                let reduced = (shaped + noise + step / 2) >> shift;
+
+                // bounded, so clipping can't wind the feedback up
+                *error = ((reduced << shift) - shaped).clamp(-2 * step, 2 * step);
+
+                reduced
+            }
+        }
+    }
+
+    /// Triangular noise in `(-2^shift, 2^shift)`, the difference of two uniform values
+    fn tpdf(&mut self, shift: u32) -> i32 {
+        self.random(shift) - self.random(shift)
+    }
+
+    /// Uniform value in `[0, 2^shift)` from a linear congruential generator
+    fn random(&mut self, shift: u32) -> i32 {
+        self.seed = self
+            .seed
+            .wrapping_mul(1_664_525)
+            .wrapping_add(1_013_904_223);
+
+        (self.seed >> (32 - shift)) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_truncate_without_dither() {
+        let mut ditherer = Ditherer::<2>::new(Dither::Off);
+        let mut out = [0; 3];
+        ditherer.to_i16(&[0x1ff, -1, 0x7f_ffff], &mut out);
+
+        assert_eq!(out, [1, -1, i16::MAX]);
+
+        let mut out = [0; 2];
+        ditherer.to_u8(&[i16::MIN, 0x7f], &mut out);
+
+        assert_eq!(out, [0, 128]);
+    }
+
+    #[test]
+    fn should_keep_fractional_level_with_tpdf() {
+        let mut ditherer = Ditherer::<1>::new(Dither::Tpdf);
+        let mut out = [0; 4096];
+        ditherer.to_i16(&[0x40; 4096], &mut out);
+
+        // a quarter LSB truncates to silence, dithered it averages out
+        let sum: i32 = out.iter().map(|&s| s as i32).sum();
+        assert!((sum - 1024).abs() < 128, "sum {}", sum);
+        assert!(out.iter().all(|&s| (-1..=2).contains(&s)));
+    }
+
+    #[test]
+    fn should_bound_accumulated_error_when_shaped() {
+        let mut ditherer = Ditherer::<2>::new(Dither::Shaped);
+        let input: [i32; 1000] = core::array::from_fn(|i| (i as i32 * 7919) % 100_000 - 50_000);
+        let mut out = [0; 1000];
+        ditherer.to_i16(&input, &mut out);
+
+        // first order shaping makes the error a difference, so per channel it telescopes
+        for channel in 0..2 {
+            let error: i32 = input
+                .iter()
+                .zip(&out)
+                .skip(channel)
+                .step_by(2)
+                .map(|(&i, &o)| ((o as i32) << 8) - i)
+                .sum();
+
+            assert!(error.abs() <= 512, "error {}", error);
+        }
+
+        ditherer.set_dither(Dither::Off);
+        assert_eq!(ditherer.dither(), Dither::Off);
+    }
+}
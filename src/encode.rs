@@ -0,0 +1,228 @@
+use crate::error::Error;
+use crate::sink::AudioSink;
+use core::convert::TryFrom;
+use heapless::Vec;
+
+/// Encoder turning fixed size frames of 16 bit PCM into compressed packets, e.g. Opus for voice
+/// notes uploaded over the network
+///
+/// Implemented by codecs behind a feature or in other crates, so they plug into an
+/// [`EncodingSink`] and any [`PacketSink`].
+pub trait PacketEncoder {
+    /// Sample rate of the PCM fed to the encoder
+    fn sample_rate(&self) -> u32;
+
+    /// Number of interleaved channels of the PCM fed to the encoder
+    fn num_channels(&self) -> u16;
+
+    /// Number of frames every packet is encoded from
+    fn frame_size(&self) -> usize;
+
+    /// Rate the granule positions of the packets count in, Opus encoders return `48_000`
+    fn granule_rate(&self) -> u32 {
+        self.sample_rate()
+    }
+
+    /// Number of frames at the granule rate the decoder drops at the start
+    fn pre_skip(&self) -> u16 {
+        0
+    }
+
+    /// Encode [`PacketEncoder::frame_size`] interleaved frames from `pcm` into `out`, returning
+    /// the length of the packet
+    fn encode(&mut self, pcm: &[i16], out: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// Destination of encoded packets, framing them for storage or the network
+pub trait PacketSink {
+    /// Write one whole packet, `granule_position` counts the frames up to its end
+    fn write_packet(&mut self, packet: &[u8], granule_position: u64) -> Result<(), Error>;
+
+    /// Mark the end of the stream after the last packet
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// [`PacketSink`] writing every packet behind its length as big endian `u16`, for transports
+/// that don't frame packets themselves
+#[derive(Debug)]
+pub struct RawPacketWriter<K> {
+    inner: K,
+}
+
+impl<K: AudioSink> RawPacketWriter<K> {
+    /// Wrap `inner`
+    pub fn new(inner: K) -> Self {
+        RawPacketWriter { inner }
+    }
+
+    /// Hand back the wrapped sink
+    pub fn into_inner(self) -> K {
+        self.inner
+    }
+}
+
+impl<K: AudioSink> PacketSink for RawPacketWriter<K> {
+    fn write_packet(&mut self, packet: &[u8], _granule_position: u64) -> Result<(), Error> {
+        let len = u16::try_from(packet.len()).map_err(|_| Error::InvalidFrame)?;
+
+        self.inner
+            .write(&len.to_be_bytes())
+            .map_err(|_| Error::Io)?;
+        self.inner.write(packet).map_err(|_| Error::Io)
+    }
+}
+
+/// Capture pipeline stage collecting PCM of any length into frames for a [`PacketEncoder`] and
+/// handing the packets to a [`PacketSink`]
+///
+/// `PCM` is the number of samples buffered and has to hold one frame of every channel of a
+/// packet, `PACKET` is the largest packet the encoder may produce in bytes.
+pub struct EncodingSink<E, P, const PCM: usize, const PACKET: usize> {
+    encoder: E,
+    packets: P,
+    pcm: Vec<i16, PCM>,
+    frames: u64,
+}
+
+impl<E: PacketEncoder, P: PacketSink, const PCM: usize, const PACKET: usize>
+    EncodingSink<E, P, PCM, PACKET>
+{
+    /// Feed `encoder` and write its packets to `packets`.
+    ///
+    /// Returns [`Error::BufferTooSmall`] if `PCM` doesn't hold one packet worth of samples.
+    pub fn new(encoder: E, packets: P) -> Result<Self, Error> {
+        let needed = encoder.frame_size() * encoder.num_channels() as usize;
+
+        if needed == 0 {
+            return Err(Error::InvalidEncoderConfig);
+        }
+
+        if needed > PCM {
+            return Err(Error::BufferTooSmall(needed));
+        }
+
+        Ok(EncodingSink {
+            encoder,
+            packets,
+            pcm: Vec::new(),
+            frames: 0,
+        })
+    }
+
+    /// Number of frames encoded into packets so far
+    pub fn frames_encoded(&self) -> u64 {
+        self.frames
+    }
+
+    /// Add interleaved samples, encoding a packet whenever a whole one is buffered
+    pub fn write_samples(&mut self, mut pcm: &[i16]) -> Result<(), Error> {
+        let needed = self.packet_samples();
+
+        while !pcm.is_empty() {
+            let take = (needed - self.pcm.len()).min(pcm.len());
+            // `new` made sure a whole packet fits
+            let _ = self.pcm.extend_from_slice(&pcm[..take]);
+            pcm = &pcm[take..];
+
+            if self.pcm.len() == needed {
+                self.encode()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encode what is buffered padded with silence, end the stream and hand back the encoder and
+    /// the packet sink
+    pub fn finish(mut self) -> Result<(E, P), Error> {
+        if !self.pcm.is_empty() {
+            let _ = self.pcm.resize(self.packet_samples(), 0);
+            self.encode()?;
+        }
+
+        self.packets.finish()?;
+
+        Ok((self.encoder, self.packets))
+    }
+
+    fn packet_samples(&self) -> usize {
+        self.encoder.frame_size() * self.encoder.num_channels() as usize
+    }
+
+    fn encode(&mut self) -> Result<(), Error> {
+        let mut packet = [0; PACKET];
+        let len = self.encoder.encode(&self.pcm, &mut packet)?;
+
+        self.pcm.clear();
+        self.frames += self.encoder.frame_size() as u64;
+
+        let granule_position =
+            self.frames * self.encoder.granule_rate() as u64 / self.encoder.sample_rate() as u64;
+
+        self.packets.write_packet(&packet[..len], granule_position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+
+    /// Encodes every frame as the high byte of its first sample
+    struct Decimator;
+
+    impl PacketEncoder for Decimator {
+        fn sample_rate(&self) -> u32 {
+            16_000
+        }
+
+        fn num_channels(&self) -> u16 {
+            2
+        }
+
+        fn frame_size(&self) -> usize {
+            4
+        }
+
+        fn encode(&mut self, pcm: &[i16], out: &mut [u8]) -> Result<usize, Error> {
+            for (frame, byte) in pcm.chunks(2).zip(out.iter_mut()) {
+                *byte = (frame[0] >> 8) as u8;
+            }
+
+            Ok(4)
+        }
+    }
+
+    #[test]
+    fn should_encode_packets_across_writes() {
+        let mut bytes = [0; 32];
+        let packets = RawPacketWriter::new(SliceSink::new(&mut bytes));
+        let mut sink = EncodingSink::<_, _, 8, 16>::new(Decimator, packets).unwrap();
+
+        let pcm: std::vec::Vec<i16> = (0..20).map(|i| (i << 8) as i16).collect();
+        sink.write_samples(&pcm[..5]).unwrap();
+        sink.write_samples(&pcm[5..]).unwrap();
+
+        assert_eq!(sink.frames_encoded(), 8);
+
+        let (_, packets) = sink.finish().unwrap();
+
+        assert_eq!(
+            packets.into_inner().written(),
+            [0, 4, 0, 2, 4, 6, 0, 4, 8, 10, 12, 14, 0, 4, 16, 18, 0, 0]
+        );
+    }
+
+    #[test]
+    fn should_need_room_for_a_packet() {
+        let mut bytes = [0; 32];
+        let packets = RawPacketWriter::new(SliceSink::new(&mut bytes));
+
+        assert!(matches!(
+            EncodingSink::<_, _, 4, 16>::new(Decimator, packets),
+            Err(Error::BufferTooSmall(8))
+        ));
+    }
+}
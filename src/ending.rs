@@ -0,0 +1,224 @@
+use crate::error::Error;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+use heapless::Vec;
+
+/// What a [`TrackEnd`] delivers once the sample data of a file runs out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndBehavior<F> {
+    /// Deliver the final short buffer, then report [`Error::EndOfData`]
+    Stop,
+    /// Repeat the last frame indefinitely
+    HoldLast,
+    /// Deliver silence indefinitely
+    Silence,
+    /// Call the callback to move the [`Wav`] on, e.g. to the next track, and keep reading from it.
+    /// Returning `false` stops like [`EndBehavior::Stop`]
+    Advance(F),
+}
+
+/// Reader applying an [`EndBehavior`] at the end of the sample data.
+///
+/// Every buffer holds the full `NUM` samples rounded down to whole frames, unless the behavior
+/// is to stop, so consumers don't have to special case the final short buffer.
+#[derive(Debug, Clone)]
+pub struct TrackEnd<const NUM: usize, F> {
+    behavior: EndBehavior<F>,
+    last: Option<DataBulk<NUM>>,
+}
+
+/// Append as many samples of `other` as fit, both buffers must hold the same bit depth
+fn append<const NUM: usize>(bulk: &mut DataBulk<NUM>, other: &DataBulk<NUM>) -> Result<(), Error> {
+    fn extend<T: Copy, const NUM: usize>(samples: &mut Vec<T, NUM>, other: &[T]) {
+        let len = other.len().min(NUM - samples.len());
+        // can't fail, bounded by the remaining capacity
+        let _ = samples.extend_from_slice(&other[..len]);
+    }
+
+    match (bulk, other) {
+        (DataBulk::BitDepth8(s), DataBulk::BitDepth8(o)) => extend(s, o),
+        (DataBulk::BitDepth16(s), DataBulk::BitDepth16(o)) => extend(s, o),
+        (DataBulk::BitDepth24(s), DataBulk::BitDepth24(o)) => extend(s, o),
+        (DataBulk::Float32(s), DataBulk::Float32(o)) => extend(s, o),
+        _ => return Err(Error::FormatMismatch),
+    }
+
+    Ok(())
+}
+
+/// Repeat the last `channels` samples of `frame` until `bulk` holds `len` samples
+fn hold<const NUM: usize>(
+    bulk: &mut DataBulk<NUM>,
+    frame: &DataBulk<NUM>,
+    channels: usize,
+    len: usize,
+) {
+    fn repeat<T: Copy, const NUM: usize>(
+        samples: &mut Vec<T, NUM>,
+        frame: &[T],
+        channels: usize,
+        len: usize,
+    ) {
+        if let Some(frame) = frame
+            .len()
+            .checked_sub(channels)
+            .map(|start| &frame[start..])
+        {
+            while samples.len() + channels <= len.min(NUM) {
+                // can't fail, bounded by the capacity
+                let _ = samples.extend_from_slice(frame);
+            }
+        }
+    }
+
+    match (bulk, frame) {
+        (DataBulk::BitDepth8(s), DataBulk::BitDepth8(f)) => repeat(s, f, channels, len),
+        (DataBulk::BitDepth16(s), DataBulk::BitDepth16(f)) => repeat(s, f, channels, len),
+        (DataBulk::BitDepth24(s), DataBulk::BitDepth24(f)) => repeat(s, f, channels, len),
+        (DataBulk::Float32(s), DataBulk::Float32(f)) => repeat(s, f, channels, len),
+        _ => {}
+    }
+}
+
+impl<const NUM: usize, F> TrackEnd<NUM, F> {
+    /// Create a reader using the given end behavior
+    pub fn new(behavior: EndBehavior<F>) -> Self {
+        TrackEnd {
+            behavior,
+            last: None,
+        }
+    }
+
+    /// Same as [`Wav::next_n`], applying the end behavior once the sample data runs out
+    pub fn next_n<S: AudioSource>(
+        &mut self,
+        wav: &mut Wav<S>,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>>
+    where
+        F: FnMut(&mut Wav<S>) -> bool,
+    {
+        let channels = (wav.fmt.num_channels as usize).max(1);
+        let len = NUM / channels * channels;
+
+        let mut bulk = wav.next_frames::<NUM>(wav.frames_left())?;
+
+        while bulk.len() < len {
+            match &mut self.behavior {
+                EndBehavior::Stop => break,
+                EndBehavior::Silence => {
+                    let silence = DataBulk::silence(&wav.fmt).map_err(Error::widen)?;
+                    append(&mut bulk, &silence).map_err(Error::widen)?;
+                }
+                EndBehavior::HoldLast => {
+                    let last = if bulk.len() >= channels {
+                        bulk.clone()
+                    } else {
+                        match &self.last {
+                            Some(last) => last.clone(),
+                            None => DataBulk::silence(&wav.fmt).map_err(Error::widen)?,
+                        }
+                    };
+
+                    hold(&mut bulk, &last, channels, len);
+                }
+                EndBehavior::Advance(advance) => {
+                    if !advance(wav) {
+                        break;
+                    }
+
+                    let next = wav.next_frames::<NUM>((len - bulk.len()) / channels)?;
+
+                    if next.is_empty() {
+                        break;
+                    }
+
+                    append(&mut bulk, &next).map_err(Error::widen)?;
+                }
+            }
+        }
+
+        if bulk.is_empty() {
+            return Err(Error::EndOfData);
+        }
+
+        if matches!(self.behavior, EndBehavior::HoldLast) {
+            self.last = Some(bulk.clone());
+        }
+
+        Ok(bulk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    const WAV: [u8; 52] = [
+        0x52, 0x49, 0x46, 0x46, // RIFF
+        0x2c, 0x00, 0x00, 0x00, // chunk size
+        0x57, 0x41, 0x56, 0x45, // WAVE
+        0x66, 0x6d, 0x74, 0x20, // fmt_
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, // audio format
+        0x01, 0x00, // num channels
+        0x44, 0xac, 0x00, 0x00, // sample rate
+        0x88, 0x58, 0x01, 0x00, // byte rate
+        0x02, 0x00, // block align
+        0x10, 0x00, // bits per sample
+        0x64, 0x61, 0x74, 0x61, // data
+        0x06, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, 0x02, 0x00, 0x03, 0x00, // samples 1, 2, 3
+        0xaa, 0xaa, // trailing bytes that aren't samples
+    ];
+
+    type NoAdvance = fn(&mut Wav<SliceSource<'static>>) -> bool;
+
+    fn samples(bulk: DataBulk<4>) -> Vec<i16, 4> {
+        match bulk {
+            DataBulk::BitDepth16(samples) => samples,
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_stop_at_data_end() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let mut end: TrackEnd<4, NoAdvance> = TrackEnd::new(EndBehavior::Stop);
+
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [1, 2, 3]);
+        assert!(matches!(end.next_n(&mut wav), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_pad_with_silence_or_last_sample() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let mut end: TrackEnd<4, NoAdvance> = TrackEnd::new(EndBehavior::Silence);
+
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [1, 2, 3, 0]);
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [0; 4]);
+
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let mut end: TrackEnd<4, NoAdvance> = TrackEnd::new(EndBehavior::HoldLast);
+
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [1, 2, 3, 3]);
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [3; 4]);
+    }
+
+    #[test]
+    fn should_advance_to_next_track() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let mut tracks = 1;
+        let mut end = TrackEnd::new(EndBehavior::Advance(|wav: &mut Wav<SliceSource>| {
+            tracks -= 1;
+            tracks >= 0
+                && Wav::new(SliceSource::new(&WAV))
+                    .map(|next| *wav = next)
+                    .is_ok()
+        }));
+
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [1, 2, 3, 1]);
+        assert_eq!(samples(end.next_n(&mut wav).unwrap()), [2, 3]);
+        assert!(matches!(end.next_n(&mut wav), Err(Error::EndOfData)));
+    }
+}
@@ -1,8 +1,16 @@
-use crate::chunk::ChunkTag;
+use crate::riff::ChunkTag;
+use core::convert::Infallible;
 
 /// Error type for different parsing failures
+///
+/// `E` is the error of the [`AudioSource`](crate::AudioSource) being read, functions that do no
+/// IO use the default and never return [`Error::Source`].
 #[derive(Debug, PartialEq)]
-pub enum Error {
+pub enum Error<E = Infallible> {
+    /// Reading from or seeking in the audio source failed, holds the error of the source
+    Source(E),
+    /// Writing to an audio sink failed
+    Io,
     /// Unknown or unsupported Chunk ID
     UnknownChunkID([u8; 4]),
     /// Failed parsing slice into specific bytes
@@ -11,6 +19,8 @@ pub enum Error {
     CantParseChunk(ChunkTag),
     /// No WAVE tag found
     NoWaveTagFound,
+    /// No fLaC tag found at the start of a FLAC stream
+    NoFlacTagFound,
     /// No riff chunk found
     NoRiffChunkFound,
     /// No data chunk found
@@ -21,4 +31,73 @@ pub enum Error {
     UnsupportedBitDepth(u16),
     /// Unsupported format
     UnsupportedFormat(u16),
+    /// AIFF-C file compressed with a codec that isn't supported, holds its compression type
+    UnsupportedCompression([u8; 4]),
+    /// More chunks than fit in the chunk list
+    TooManyChunks,
+    /// More cue points than fit in the trigger list
+    TooManyCuePoints,
+    /// The file has no cue point with the given id
+    UnknownCuePoint(u32),
+    /// Caller supplied buffer is too small, holds the number of bytes needed
+    BufferTooSmall(usize),
+    /// File has more channels than the caller supplied frame holds, holds the channel count
+    TooManyChannels(u16),
+    /// Two files that are expected to share a format differ in channel count or sample rate
+    FormatMismatch,
+    /// The end of the sample data was reached
+    EndOfData,
+    /// A compressed frame has no valid header or is shorter than its header says
+    InvalidFrame,
+    /// Encoder settings outside of what the codec allows
+    InvalidEncoderConfig,
+    /// The first bytes of a file match none of the supported formats
+    UnknownFileFormat,
+    /// A chunk reaches past the end of the file, holds the offset it should end at and the
+    /// length of the file
+    Truncated {
+        /// Byte offset the chunk body ends at according to its size field
+        expected: usize,
+        /// Length of the file
+        found: usize,
+    },
+    /// The file a resume point was recorded for was modified or replaced since
+    TrackChanged,
+    /// Sample data reaches past 4 GiB, beyond the 32 bit offsets of an
+    /// [`AudioSource`](crate::AudioSource) and the size fields of a RIFF file
+    TooLarge,
+}
+
+impl Error {
+    /// Carry a parsing error over into the error of a method reading from an audio source
+    pub(crate) fn widen<E>(self) -> Error<E> {
+        match self {
+            Error::Source(never) => match never {},
+            Error::Io => Error::Io,
+            Error::UnknownChunkID(id) => Error::UnknownChunkID(id),
+            Error::CantParseSliceInto => Error::CantParseSliceInto,
+            Error::CantParseChunk(tag) => Error::CantParseChunk(tag),
+            Error::NoWaveTagFound => Error::NoWaveTagFound,
+            Error::NoFlacTagFound => Error::NoFlacTagFound,
+            Error::NoRiffChunkFound => Error::NoRiffChunkFound,
+            Error::NoDataChunkFound => Error::NoDataChunkFound,
+            Error::NoFmtChunkFound => Error::NoFmtChunkFound,
+            Error::UnsupportedBitDepth(bit_depth) => Error::UnsupportedBitDepth(bit_depth),
+            Error::UnsupportedFormat(format) => Error::UnsupportedFormat(format),
+            Error::UnsupportedCompression(kind) => Error::UnsupportedCompression(kind),
+            Error::TooManyChunks => Error::TooManyChunks,
+            Error::TooManyCuePoints => Error::TooManyCuePoints,
+            Error::UnknownCuePoint(id) => Error::UnknownCuePoint(id),
+            Error::BufferTooSmall(needed) => Error::BufferTooSmall(needed),
+            Error::TooManyChannels(channels) => Error::TooManyChannels(channels),
+            Error::FormatMismatch => Error::FormatMismatch,
+            Error::EndOfData => Error::EndOfData,
+            Error::InvalidFrame => Error::InvalidFrame,
+            Error::InvalidEncoderConfig => Error::InvalidEncoderConfig,
+            Error::UnknownFileFormat => Error::UnknownFileFormat,
+            Error::Truncated { expected, found } => Error::Truncated { expected, found },
+            Error::TrackChanged => Error::TrackChanged,
+            Error::TooLarge => Error::TooLarge,
+        }
+    }
 }
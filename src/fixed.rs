@@ -0,0 +1,117 @@
+//! Fixed point conventions shared by every gain, mix, filter and conversion stage.
+//!
+//! - Samples are signed integers scaled to their bit depth: Q15 in an `i16`, 24 bit samples in the
+//!   low bits of an `i32`, Q31 when left justified for I2S. 8 bit samples are unsigned and centered
+//!   on 128 until they enter a stage.
+//! - Gains are unsigned Q1.15 in a `u16`, [`UNITY_GAIN`] passes a sample unchanged and
+//!   `u16::MAX` is just below 2.0. Mixing matrix coefficients are signed Q15 in an `i32`.
+//! - Pitch ratios are unsigned Q16.16 in a `u32`, see [`UNITY_PITCH`](crate::UNITY_PITCH).
+//! - Stages compute in `i32` or `i64`, wide enough that products and sums can't overflow, and
+//!   saturate exactly once when narrowing back to the sample width. Nothing wraps around: a
+//!   result beyond full scale is clipped to the largest or smallest sample.
+//!
+//! ```
+//! use audio_parser::fixed::{saturate_i16, scale_q15};
+//! use audio_parser::UNITY_GAIN;
+//!
+//! assert_eq!(scale_q15(i16::MAX, u16::MAX), i16::MAX);
+//! assert_eq!(scale_q15(-1000, UNITY_GAIN / 2), -500);
+//! assert_eq!(saturate_i16(i16::MIN as i32 - 1), i16::MIN);
+//! ```
+//!
+//! Saturation compiles to `SSAT` on Cortex-M cores with the DSP extension.
+
+use crate::simd;
+
+/// Gain of 1.0, gains are unsigned Q1.15 fixed point numbers
+pub const UNITY_GAIN: u16 = 1 << 15;
+
+/// Smallest 24 bit sample
+const I24_MIN: i64 = -(1 << 23);
+/// Largest 24 bit sample
+const I24_MAX: i64 = (1 << 23) - 1;
+
+/// Scale a sample by a Q1.15 gain without narrowing, the result always fits an `i32`
+pub(crate) fn apply_gain(sample: i16, gain: u16) -> i32 {
+    (sample as i32 * gain as i32) >> 15
+}
+
+/// Narrow a wide result to a 16 bit sample, clipping instead of wrapping around
+#[inline]
+pub fn saturate_i16(value: i32) -> i16 {
+    simd::saturate_i16(value)
+}
+
+/// Narrow a wide result to a 24 bit sample held in an `i32`, clipping instead of wrapping around
+pub fn saturate_i24(value: i64) -> i32 {
+    value.clamp(I24_MIN, I24_MAX) as i32
+}
+
+/// Scale a 16 bit sample by a Q1.15 gain, saturating for gains above unity
+pub fn scale_q15(sample: i16, gain: u16) -> i16 {
+    saturate_i16(apply_gain(sample, gain))
+}
+
+/// Scale a sample of `bit_depth` bits to Q15, dropping the low bits of deeper samples. Values
+/// beyond the bit depth, such as the side channel of FLAC, saturate.
+pub fn to_q15(sample: i32, bit_depth: u16) -> i16 {
+    match bit_depth {
+        0..=16 => saturate_i16(sample << (16 - bit_depth.max(1))),
+        _ => saturate_i16(sample >> (bit_depth as u32 - 16).min(31)),
+    }
+}
+
+/// Scale a float sample relative to full scale to Q15, `-1.0` and `1.0` map to `i16::MIN` and
+/// `i16::MAX` and anything beyond saturates
+pub fn f32_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32_768.0) as i16
+}
+
+/// Add `addend` onto a 16 bit sample, saturating at full scale
+pub fn mix_i16(sample: i16, addend: i32) -> i16 {
+    saturate_i16(sample as i32 + addend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_saturate_instead_of_wrapping() {
+        assert_eq!(saturate_i16(40_000), i16::MAX);
+        assert_eq!(saturate_i16(-40_000), i16::MIN);
+        assert_eq!(saturate_i16(-123), -123);
+        assert_eq!(saturate_i24(1 << 40), (1 << 23) - 1);
+        assert_eq!(saturate_i24(-(1 << 40)), -(1 << 23));
+
+        assert_eq!(mix_i16(i16::MAX, 1), i16::MAX);
+        assert_eq!(mix_i16(i16::MIN, -1), i16::MIN);
+        assert_eq!(mix_i16(i16::MIN, apply_gain(i16::MIN, u16::MAX)), i16::MIN);
+    }
+
+    #[test]
+    fn should_scale_by_q15_gains() {
+        assert_eq!(scale_q15(i16::MIN, UNITY_GAIN), i16::MIN);
+        assert_eq!(scale_q15(i16::MAX, UNITY_GAIN), i16::MAX);
+        assert_eq!(scale_q15(i16::MIN, u16::MAX), i16::MIN);
+        assert_eq!(scale_q15(20_000, u16::MAX), i16::MAX);
+        assert_eq!(scale_q15(1000, 0), 0);
+
+        assert_eq!(to_q15(-0x80_0000, 24), i16::MIN);
+        assert_eq!(to_q15(0x7f_ffff, 24), i16::MAX);
+        assert_eq!(to_q15(-128, 8), i16::MIN);
+        assert_eq!(to_q15(1234, 16), 1234);
+
+        // every product of a sample and a gain fits the wide type
+        assert_eq!(apply_gain(i16::MIN, u16::MAX), -65_535);
+    }
+
+    #[test]
+    fn should_scale_floats_to_q15() {
+        assert_eq!(f32_to_i16(0.5), 16_384);
+        assert_eq!(f32_to_i16(-1.0), i16::MIN);
+        assert_eq!(f32_to_i16(1.0), i16::MAX);
+        assert_eq!(f32_to_i16(-3.0), i16::MIN);
+        assert_eq!(f32_to_i16(f32::NAN), 0);
+    }
+}
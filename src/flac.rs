@@ -0,0 +1,753 @@
+use crate::checkpoint::{Checkpoint, Checkpoints};
+use crate::error::Error;
+use crate::fixed::to_q15;
+use crate::source::AudioSource;
+
+/// Identifier at the start of every FLAC stream
+const FLAC_TAG: [u8; 4] = *b"fLaC";
+/// Metadata block type of the mandatory STREAMINFO block
+const STREAMINFO: u8 = 0;
+/// Highest order of an LPC subframe
+const MAX_LPC_ORDER: usize = 32;
+/// Predictor coefficients of the fixed subframes, indexed by order
+const FIXED_COEFFICIENTS: [&[i64]; 5] = [&[], &[1], &[2, -1], &[3, -3, 1], &[4, -6, 4, -1]];
+
+/// Contents of the STREAMINFO metadata block, describing the whole stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamInfo {
+    /// Smallest number of frames in a block, except for the last block
+    pub min_block_size: u16,
+    /// Largest number of frames in a block
+    pub max_block_size: u16,
+    /// sample rate, typical values are `44_100`, `48_000` or `96_000`
+    pub sample_rate: u32,
+    /// number of audio channels, decoded blocks are interleaved
+    pub num_channels: u16,
+    /// bit depth for each sample, `8` up to `24`
+    pub bit_depth: u16,
+    /// Number of frames in the stream, `0` if unknown
+    pub total_frames: u64,
+}
+
+/// Channel decorrelation of a FLAC frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stereo {
+    Independent,
+    LeftSide,
+    SideRight,
+    MidSide,
+}
+
+/// Decoder for a FLAC stream read from an [`AudioSource`]
+///
+/// Decodes constant, verbatim, fixed and LPC subframes into interleaved samples right aligned at
+/// the bit depth of the stream. CRCs and the MD5 signature are not checked.
+pub struct Flac<S: AudioSource> {
+    source: S,
+    /// Contains data from the STREAMINFO block
+    pub info: StreamInfo,
+    buf: [u8; 64],
+    pos: usize,
+    len: usize,
+    bits: u64,
+    num_bits: u32,
+    frame: u64,
+}
+
+impl<S: AudioSource> Flac<S> {
+    /// Create new [`Flac`] instance from an [`AudioSource`], reading the metadata blocks up to the first frame
+    pub fn new(mut source: S) -> Result<Self, Error<S::Error>> {
+        source.seek(0).map_err(Error::Source)?;
+
+        let mut flac = Flac {
+            source,
+            info: StreamInfo {
+                min_block_size: 0,
+                max_block_size: 0,
+                sample_rate: 0,
+                num_channels: 0,
+                bit_depth: 0,
+                total_frames: 0,
+            },
+            buf: [0; 64],
+            pos: 0,
+            len: 0,
+            bits: 0,
+            num_bits: 0,
+            frame: 0,
+        };
+
+        for tag in FLAC_TAG {
+            if flac.read_byte().map_err(|_| Error::NoFlacTagFound)? != tag {
+                return Err(Error::NoFlacTagFound);
+            }
+        }
+
+        let mut found_info = false;
+
+        loop {
+            let is_last = flac.read_bits(1)? == 1;
+            let block_type = flac.read_bits(7)? as u8;
+            let len = flac.read_bits(24)? as usize;
+
+            if block_type == STREAMINFO && len == 34 {
+                flac.info = flac.read_stream_info()?;
+                found_info = true;
+            } else {
+                flac.skip_bytes(len)?;
+            }
+
+            if is_last {
+                break;
+            }
+        }
+
+        if !found_info {
+            return Err(Error::NoFmtChunkFound);
+        }
+
+        if flac.info.num_channels == 0 || flac.info.bit_depth > 24 || flac.info.bit_depth < 4 {
+            return Err(Error::UnsupportedBitDepth(flac.info.bit_depth));
+        }
+
+        Ok(flac)
+    }
+
+    /// Decode the next block into `out` as interleaved samples, returning the number of frames.
+    ///
+    /// `out` has to hold [`StreamInfo::max_block_size`] frames. Returns [`Error::EndOfData`] once
+    /// the stream ends, garbage in front of a frame is skipped.
+    pub fn next_block(&mut self, out: &mut [i32]) -> Result<usize, Error<S::Error>> {
+        let needed = self.info.max_block_size as usize * self.info.num_channels as usize;
+
+        if out.len() < needed {
+            return Err(Error::BufferTooSmall(needed));
+        }
+
+        let len = out.len();
+        self.decode_block(out, len)
+    }
+
+    /// Decode the next block into `out` as interleaved Q15 samples, returning the number of
+    /// samples. `out` has to hold twice [`StreamInfo::max_block_size`] frames, the block is decoded
+    /// at full precision into the whole buffer before it is narrowed.
+    pub(crate) fn next_block_q15(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        let needed = 2 * self.info.max_block_size as usize * self.info.num_channels as usize;
+
+        if out.len() < needed {
+            return Err(Error::BufferTooSmall(needed));
+        }
+
+        let len = out.len() / 2;
+        let samples = self.decode_block(&mut Halves(out), len)? * self.info.num_channels as usize;
+
+        // sample `i` is read from `out[2 * i..]` before `out[i]` is overwritten
+        for i in 0..samples {
+            let sample = Halves(out).get(i);
+            out[i] = to_q15(sample, self.info.bit_depth);
+        }
+
+        Ok(samples)
+    }
+
+    /// Decode the next block into `out`, which holds `len` samples
+    fn decode_block<B: BlockBuffer + ?Sized>(
+        &mut self,
+        out: &mut B,
+        len: usize,
+    ) -> Result<usize, Error<S::Error>> {
+        let channels = self.info.num_channels as usize;
+
+        self.sync()?;
+
+        let block_size_code = self.read_bits(4)?;
+        let sample_rate_code = self.read_bits(4)?;
+        let assignment = self.read_bits(4)?;
+        let bit_depth = match self.read_bits(3)? {
+            0 => self.info.bit_depth as u32,
+            1 => 8,
+            2 => 12,
+            4 => 16,
+            5 => 20,
+            6 => 24,
+            _ => return Err(Error::InvalidFrame),
+        };
+        self.read_bits(1)?;
+
+        // frame or sample number, UTF-8 coded
+        let first = self.read_bits(8)?;
+        for _ in 1..(first as u8).leading_ones() {
+            self.read_bits(8)?;
+        }
+
+        let block_size = match block_size_code {
+            1 => 192,
+            2..=5 => 576 << (block_size_code - 2),
+            6 => self.read_bits(8)? as usize + 1,
+            7 => self.read_bits(16)? as usize + 1,
+            8..=15 => 256 << (block_size_code - 8),
+            _ => return Err(Error::InvalidFrame),
+        };
+
+        match sample_rate_code {
+            12 => self.skip_bits(8)?,
+            13 | 14 => self.skip_bits(16)?,
+            15 => return Err(Error::InvalidFrame),
+            _ => (),
+        }
+
+        // header CRC
+        self.skip_bits(8)?;
+
+        let stereo = match assignment {
+            0..=7 if assignment as usize + 1 == channels => Stereo::Independent,
+            8 if channels == 2 => Stereo::LeftSide,
+            9 if channels == 2 => Stereo::SideRight,
+            10 if channels == 2 => Stereo::MidSide,
+            _ => return Err(Error::InvalidFrame),
+        };
+
+        if block_size * channels > len {
+            return Err(Error::BufferTooSmall(block_size * channels));
+        }
+
+        for channel in 0..channels {
+            let is_side = matches!(
+                (stereo, channel),
+                (Stereo::LeftSide, 1) | (Stereo::SideRight, 0) | (Stereo::MidSide, 1)
+            );
+            let bit_depth = bit_depth + is_side as u32;
+
+            self.read_subframe(out, channel, channels, block_size, bit_depth)?;
+        }
+
+        if stereo != Stereo::Independent {
+            for i in (0..block_size * channels).step_by(channels) {
+                let (a, b) = (out.get(i), out.get(i + 1));
+
+                let (left, right) = match stereo {
+                    Stereo::LeftSide => (a, a.wrapping_sub(b)),
+                    Stereo::SideRight => (a.wrapping_add(b), b),
+                    _ => {
+                        let mid = (a << 1) | (b & 1);
+                        (mid.wrapping_add(b) >> 1, mid.wrapping_sub(b) >> 1)
+                    }
+                };
+
+                out.set(i, left);
+                out.set(i + 1, right);
+            }
+        }
+
+        // padding up to the byte boundary and the frame CRC
+        self.num_bits -= self.num_bits % 8;
+        self.skip_bits(16)?;
+        self.frame += block_size as u64;
+
+        Ok(block_size)
+    }
+
+    /// Same as [`Flac::next_block`], offering the position in front of the block to
+    /// `checkpoints` first.
+    ///
+    /// Frames carry no state from one to the next, the state kept is the [`StreamInfo`] the
+    /// checkpoint belongs to.
+    pub fn next_block_checkpointed<const N: usize>(
+        &mut self,
+        out: &mut [i32],
+        checkpoints: &mut Checkpoints<StreamInfo, N>,
+    ) -> Result<usize, Error<S::Error>> {
+        checkpoints.record(self.stream_offset(), self.frame, self.info);
+        self.next_block(out)
+    }
+
+    /// Continue decoding from `checkpoint`, recorded by [`Flac::next_block_checkpointed`] for
+    /// this stream.
+    ///
+    /// Returns [`Error::FormatMismatch`] for a damaged checkpoint or one of another stream.
+    pub fn restore(&mut self, checkpoint: &Checkpoint<StreamInfo>) -> Result<(), Error<S::Error>> {
+        if !checkpoint.is_intact() || checkpoint.state != self.info {
+            return Err(Error::FormatMismatch);
+        }
+
+        self.seek_stream(checkpoint.offset)?;
+        self.frame = checkpoint.frame;
+
+        Ok(())
+    }
+
+    /// Index of the first frame of the next block, counted from the start of the stream or the
+    /// last restored checkpoint
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Destroy the [`Flac`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+
+    /// Byte offset in the stream of the first byte not decoded yet, the next frame between blocks
+    pub(crate) fn stream_offset(&self) -> u32 {
+        let buffered = (self.len - self.pos) as u32 + self.num_bits / 8;
+        self.source.offset().saturating_sub(buffered)
+    }
+
+    /// Drop what is buffered and continue decoding at byte offset `offset`, the next block is
+    /// read from the first frame found from there
+    pub(crate) fn seek_stream(&mut self, offset: u32) -> Result<(), Error<S::Error>> {
+        self.source.seek(offset).map_err(Error::Source)?;
+        self.pos = 0;
+        self.len = 0;
+        self.bits = 0;
+        self.num_bits = 0;
+
+        Ok(())
+    }
+
+    fn read_stream_info(&mut self) -> Result<StreamInfo, Error<S::Error>> {
+        let min_block_size = self.read_bits(16)? as u16;
+        let max_block_size = self.read_bits(16)? as u16;
+
+        // minimum and maximum frame size
+        self.skip_bits(48)?;
+
+        let sample_rate = self.read_bits(20)?;
+        let num_channels = self.read_bits(3)? as u16 + 1;
+        let bit_depth = self.read_bits(5)? as u16 + 1;
+        let total_frames = (self.read_bits(4)? as u64) << 32 | self.read_bits(32)? as u64;
+
+        // MD5 signature
+        self.skip_bytes(16)?;
+
+        Ok(StreamInfo {
+            min_block_size,
+            max_block_size,
+            sample_rate,
+            num_channels,
+            bit_depth,
+            total_frames,
+        })
+    }
+
+    /// Decode one channel into every `channels`th sample of `out`, starting at `channel`
+    fn read_subframe<B: BlockBuffer + ?Sized>(
+        &mut self,
+        out: &mut B,
+        channel: usize,
+        channels: usize,
+        block_size: usize,
+        bit_depth: u32,
+    ) -> Result<(), Error<S::Error>> {
+        if self.read_bits(1)? != 0 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let kind = self.read_bits(6)? as usize;
+        let wasted = match self.read_bits(1)? {
+            1 => self.read_unary()? + 1,
+            _ => 0,
+        };
+
+        if wasted >= bit_depth {
+            return Err(Error::InvalidFrame);
+        }
+
+        let bit_depth = bit_depth - wasted;
+        let index = |i: usize| i * channels + channel;
+
+        match kind {
+            0 => {
+                let value = self.read_signed(bit_depth)?;
+
+                for i in 0..block_size {
+                    out.set(index(i), value);
+                }
+            }
+            1 => {
+                for i in 0..block_size {
+                    out.set(index(i), self.read_signed(bit_depth)?);
+                }
+            }
+            8..=12 => {
+                let coefficients = FIXED_COEFFICIENTS[kind - 8];
+                let order = coefficients.len();
+
+                self.read_warm_up(out, &index, order, block_size, bit_depth)?;
+                self.read_residual(out, &index, order, block_size)?;
+                predict(out, &index, coefficients, 0, block_size);
+            }
+            32..=63 => {
+                let order = kind - 31;
+
+                self.read_warm_up(out, &index, order, block_size, bit_depth)?;
+
+                let precision = self.read_bits(4)? + 1;
+                let shift = self.read_signed(5)?;
+
+                if precision == 16 || shift < 0 {
+                    return Err(Error::InvalidFrame);
+                }
+
+                let mut coefficients = [0; MAX_LPC_ORDER];
+                for coefficient in coefficients[..order].iter_mut() {
+                    *coefficient = self.read_signed(precision)? as i64;
+                }
+
+                self.read_residual(out, &index, order, block_size)?;
+                predict(
+                    out,
+                    &index,
+                    &coefficients[..order],
+                    shift as u32,
+                    block_size,
+                );
+            }
+            _ => return Err(Error::InvalidFrame),
+        }
+
+        if wasted > 0 {
+            for i in 0..block_size {
+                out.set(index(i), out.get(index(i)) << wasted);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_warm_up<B: BlockBuffer + ?Sized>(
+        &mut self,
+        out: &mut B,
+        index: &impl Fn(usize) -> usize,
+        order: usize,
+        block_size: usize,
+        bit_depth: u32,
+    ) -> Result<(), Error<S::Error>> {
+        if order > block_size {
+            return Err(Error::InvalidFrame);
+        }
+
+        for i in 0..order {
+            out.set(index(i), self.read_signed(bit_depth)?);
+        }
+
+        Ok(())
+    }
+
+    /// Read the rice coded residual of the samples after the warm up samples
+    fn read_residual<B: BlockBuffer + ?Sized>(
+        &mut self,
+        out: &mut B,
+        index: &impl Fn(usize) -> usize,
+        order: usize,
+        block_size: usize,
+    ) -> Result<(), Error<S::Error>> {
+        let (parameter_bits, escape) = match self.read_bits(2)? {
+            0 => (4, 15),
+            1 => (5, 31),
+            _ => return Err(Error::InvalidFrame),
+        };
+
+        let partition_order = self.read_bits(4)?;
+        let partition_len = block_size >> partition_order;
+
+        if partition_len << partition_order != block_size || partition_len < order {
+            return Err(Error::InvalidFrame);
+        }
+
+        let mut i = order;
+
+        for partition in 0..1usize << partition_order {
+            let end = (partition + 1) * partition_len;
+            let parameter = self.read_bits(parameter_bits)?;
+
+            if parameter == escape {
+                let bits = self.read_bits(5)?;
+
+                for i in i..end {
+                    out.set(index(i), self.read_signed(bits)?);
+                }
+            } else {
+                for i in i..end {
+                    let quotient = self.read_unary()?;
+                    let value = quotient << parameter | self.read_bits(parameter)?;
+
+                    out.set(index(i), (value >> 1) as i32 ^ -((value & 1) as i32));
+                }
+            }
+
+            i = end;
+        }
+
+        Ok(())
+    }
+
+    /// Find the next frame sync code and consume it
+    fn sync(&mut self) -> Result<(), Error<S::Error>> {
+        self.num_bits -= self.num_bits % 8;
+
+        let mut previous = 0;
+
+        loop {
+            let byte = self.read_bits(8)? as u8;
+
+            if previous == 0xff && byte & 0xfe == 0xf8 {
+                return Ok(());
+            }
+
+            previous = byte;
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error<S::Error>> {
+        if self.pos == self.len {
+            self.len = self.source.read(&mut self.buf).map_err(Error::Source)?;
+            self.pos = 0;
+
+            if self.len == 0 {
+                return Err(Error::EndOfData);
+            }
+        }
+
+        self.pos += 1;
+
+        Ok(self.buf[self.pos - 1])
+    }
+
+    /// Read up to 32 bits, most significant bit first
+    fn read_bits(&mut self, count: u32) -> Result<u32, Error<S::Error>> {
+        while self.num_bits < count {
+            self.bits = self.bits << 8 | self.read_byte()? as u64;
+            self.num_bits += 8;
+        }
+
+        self.num_bits -= count;
+
+        Ok((self.bits >> self.num_bits) as u32 & (((1u64 << count) - 1) as u32))
+    }
+
+    fn read_signed(&mut self, count: u32) -> Result<i32, Error<S::Error>> {
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let value = self.read_bits(count)?;
+        let shift = 32 - count;
+
+        Ok(((value << shift) as i32) >> shift)
+    }
+
+    /// Count the zero bits in front of the next one bit
+    fn read_unary(&mut self) -> Result<u32, Error<S::Error>> {
+        let mut count = 0;
+
+        while self.read_bits(1)? == 0 {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn skip_bits(&mut self, count: u32) -> Result<(), Error<S::Error>> {
+        let mut left = count;
+
+        while left > 0 {
+            let step = left.min(32);
+            self.read_bits(step)?;
+            left -= step;
+        }
+
+        Ok(())
+    }
+
+    /// Skip whole bytes, seeking past what isn't buffered
+    fn skip_bytes(&mut self, count: usize) -> Result<(), Error<S::Error>> {
+        let cached = (self.num_bits / 8) as usize;
+        let from_cache = cached.min(count);
+        self.num_bits -= from_cache as u32 * 8;
+
+        let from_buf = (self.len - self.pos).min(count - from_cache);
+        self.pos += from_buf;
+
+        let rest = count - from_cache - from_buf;
+
+        if rest > 0 {
+            let offset = self.source.offset() as usize + rest;
+
+            if offset > self.source.length() as usize {
+                return Err(Error::EndOfData);
+            }
+
+            self.source.seek(offset as u32).map_err(Error::Source)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Samples of a block while it is decoded, kept at full precision until the channels are
+/// decorrelated
+trait BlockBuffer {
+    fn get(&self, index: usize) -> i32;
+    fn set(&mut self, index: usize, value: i32);
+}
+
+impl BlockBuffer for [i32] {
+    fn get(&self, index: usize) -> i32 {
+        self[index]
+    }
+
+    fn set(&mut self, index: usize, value: i32) {
+        self[index] = value;
+    }
+}
+
+/// `i32` samples stored as two `i16` halves each, so [`Decoder::decode`] decodes a block in the
+/// buffer it is handed
+struct Halves<'a>(&'a mut [i16]);
+
+impl BlockBuffer for Halves<'_> {
+    fn get(&self, index: usize) -> i32 {
+        (self.0[2 * index] as u16 as u32 | (self.0[2 * index + 1] as u16 as u32) << 16) as i32
+    }
+
+    fn set(&mut self, index: usize, value: i32) {
+        self.0[2 * index] = value as i16;
+        self.0[2 * index + 1] = (value >> 16) as i16;
+    }
+}
+
+/// Turn the residual following the warm up samples into samples using the predictor `coefficients`
+fn predict<B: BlockBuffer + ?Sized>(
+    out: &mut B,
+    index: &impl Fn(usize) -> usize,
+    coefficients: &[i64],
+    shift: u32,
+    block_size: usize,
+) {
+    let order = coefficients.len();
+
+    for i in order..block_size {
+        let prediction: i64 = coefficients
+            .iter()
+            .enumerate()
+            .map(|(j, c)| c * out.get(index(i - 1 - j)) as i64)
+            .sum();
+
+        let residual = out.get(index(i));
+        out.set(
+            index(i),
+            residual.wrapping_add((prediction >> shift) as i32),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    fn left(i: i32) -> i32 {
+        match i {
+            0..=15 => 123,
+            _ => (i * i * 37) % 2001 - 1000,
+        }
+    }
+
+    fn right(i: i32) -> i32 {
+        match i {
+            48..=63 => 4 * ((i * 7) % 50 - 25),
+            _ => (left(i) >> 1) + 3 * i - 50,
+        }
+    }
+
+    #[test]
+    fn should_decode_every_subframe_type() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut flac = Flac::new(SliceSource::new(bytes)).unwrap();
+
+        assert_eq!(flac.info.sample_rate, 8_000);
+        assert_eq!(flac.info.num_channels, 2);
+        assert_eq!(flac.info.bit_depth, 16);
+        assert_eq!(flac.info.total_frames, 74);
+
+        let mut out = [0; 32];
+        let mut frame = 0;
+
+        loop {
+            let frames = match flac.next_block(&mut out) {
+                Ok(frames) => frames,
+                Err(Error::EndOfData) => break,
+                Err(e) => panic!("{:?}", e),
+            };
+
+            for samples in out[..2 * frames].chunks_exact(2) {
+                assert_eq!(samples, [left(frame), right(frame)], "frame {}", frame);
+                frame += 1;
+            }
+        }
+
+        assert_eq!(frame, 74);
+    }
+
+    #[test]
+    fn should_fail_on_non_flac_files() {
+        let bytes = include_bytes!("../test_files/mono_16_48000.wav");
+
+        assert!(matches!(
+            Flac::new(SliceSource::new(bytes)),
+            Err(Error::NoFlacTagFound)
+        ));
+
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut flac = Flac::new(SliceSource::new(bytes)).unwrap();
+
+        assert!(matches!(
+            flac.next_block(&mut [0; 8]),
+            Err(Error::BufferTooSmall(32))
+        ));
+    }
+
+    #[test]
+    fn should_not_panic_on_corrupted_frames() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+
+        // the frames start after the STREAMINFO and padding blocks
+        for i in 56..bytes.len() {
+            let mut corrupted = *bytes;
+            corrupted[i] ^= 0x5a;
+
+            let mut flac = Flac::new(SliceSource::new(&corrupted)).unwrap();
+            let mut out = [0; 32];
+
+            while flac.next_block(&mut out).is_ok() {}
+        }
+    }
+
+    #[test]
+    fn should_restart_from_checkpoints() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut flac = Flac::new(SliceSource::new(bytes)).unwrap();
+        let mut checkpoints: Checkpoints<StreamInfo, 4> = Checkpoints::new(32);
+
+        let mut blocks = [[0; 32]; 5];
+        for block in blocks.iter_mut() {
+            flac.next_block_checkpointed(block, &mut checkpoints)
+                .unwrap();
+        }
+        assert_eq!(flac.frame(), 74);
+
+        let checkpoint = *checkpoints.before(40).unwrap();
+        assert_eq!(checkpoint.frame, 32);
+        flac.restore(&checkpoint).unwrap();
+
+        let mut out = [0; 32];
+        flac.next_block(&mut out).unwrap();
+        assert_eq!(out, blocks[2]);
+        assert_eq!(flac.frame(), 48);
+
+        let mut damaged = checkpoint;
+        damaged.state.sample_rate = 44_100;
+        assert!(matches!(flac.restore(&damaged), Err(Error::FormatMismatch)));
+    }
+}
@@ -1,50 +1,193 @@
+use crate::adpcm::{self, IMA_ADPCM, MAX_ADPCM_CHANNELS};
 use crate::error::Error;
-use core::convert::TryInto;
+use crate::g711::{A_LAW, MU_LAW};
+use crate::riff::ChunkTag;
+use core::convert::{TryFrom, TryInto};
+use heapless::Vec;
+
+/// Encoding of the samples in the data chunk, taken from the format code of the fmt chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// Integer PCM, format code `1`
+    Pcm,
+    /// 32 bit IEEE float, format code `3`
+    IeeeFloat,
+    /// 8 bit A-law companded PCM, format code `6`, expanded to 16 bit PCM
+    ALaw,
+    /// 8 bit µ-law companded PCM, format code `7`, expanded to 16 bit PCM
+    MuLaw,
+    /// 4 bit IMA ADPCM, format code `0x11`, decoded to 16 bit PCM
+    ImaAdpcm,
+    /// Big endian integer PCM of AIFF files, 8 bit samples are signed and read as unsigned
+    PcmBigEndian,
+}
 
 /// Struct representing the `fmt_` section of a WAV file
 ///
 /// for more information see [`here`]
 ///
 /// [`here`]: http://soundfile.sapp.org/doc/WaveFormat/
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Fmt {
+    /// encoding of the samples
+    pub codec: AudioCodec,
     /// sample rate, typical values are `44_100`, `48_000` or `96_000`
     pub sample_rate: u32,
     /// number of audio channels in the sample data, channels are interleaved
     pub num_channels: u16,
     /// bit depth for each sample, typical values are `16` or `24`
     pub bit_depth: u16,
+    /// size in bytes of a block as stored in the fmt chunk, a frame for PCM or a compressed block for ADPCM
+    pub block_size: u16,
 }
 
+/// Format code of `WAVE_FORMAT_EXTENSIBLE`, the actual format code is the start of its sub format
+const EXTENSIBLE: u16 = 0xfffe;
+/// Bytes of the fmt chunk before `cbSize`, all a plain PCM fmt chunk holds
+const FMT_BASE_LEN: usize = 16;
+/// Bytes of a fmt chunk with `cbSize` and no extension
+pub(crate) const FMT_EXTENDED_LEN: usize = FMT_BASE_LEN + 2;
+/// Bytes of the `WAVE_FORMAT_EXTENSIBLE` extension behind `cbSize`
+const EXTENSION_LEN: usize = 22;
+/// Bytes of a fmt chunk that are read, the extension of other formats isn't needed
+pub(crate) const FMT_MAX_LEN: usize = FMT_EXTENDED_LEN + EXTENSION_LEN;
+/// Sub format GUID of `WAVE_FORMAT_EXTENSIBLE` behind its two byte format code
+const SUB_FORMAT_GUID: [u8; 14] = [
+    0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71,
+];
+
 impl Fmt {
+    /// Parse the body of a fmt chunk, or its first [`FMT_MAX_LEN`] bytes.
+    ///
+    /// The 16 byte fields can be followed by `cbSize` and that many bytes of extension, which is
+    /// only read for `WAVE_FORMAT_EXTENSIBLE`. Chunks too short for the fields, cut off in
+    /// `cbSize` or shorter than the extension it announces are rejected.
     pub(crate) fn from_chunk(bytes: &[u8]) -> Result<Self, Error> {
-        let format = bytes[0..2]
-            .try_into()
-            .map_err(|_| Error::CantParseSliceInto)
-            .map(|b| u16::from_le_bytes(b))?;
+        if bytes.len() < FMT_BASE_LEN || bytes.len() == FMT_BASE_LEN + 1 {
+            return Err(Error::CantParseChunk(ChunkTag::Fmt));
+        }
 
-        if format != 1 {
-            return Err(Error::UnsupportedFormat(format));
+        let field = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let extension_len = match bytes.len() > FMT_BASE_LEN {
+            true => field(FMT_BASE_LEN) as usize,
+            false => 0,
+        };
+
+        // only the start of a longer extension may have been read
+        if bytes.len() > FMT_BASE_LEN
+            && FMT_BASE_LEN + 2 + extension_len.min(EXTENSION_LEN) > bytes.len()
+        {
+            return Err(Error::CantParseChunk(ChunkTag::Fmt));
         }
 
-        let num_channels = bytes[2..4]
-            .try_into()
-            .map_err(|_| Error::CantParseSliceInto)
-            .map(|b| u16::from_le_bytes(b))?;
+        let format = match field(0) {
+            EXTENSIBLE if extension_len < EXTENSION_LEN => {
+                return Err(Error::CantParseChunk(ChunkTag::Fmt))
+            }
+            EXTENSIBLE if bytes[26..40] != SUB_FORMAT_GUID => {
+                return Err(Error::UnsupportedFormat(EXTENSIBLE))
+            }
+            EXTENSIBLE => field(24),
+            format => format,
+        };
+
+        let codec = match format {
+            1 => AudioCodec::Pcm,
+            3 => AudioCodec::IeeeFloat,
+            A_LAW => AudioCodec::ALaw,
+            MU_LAW => AudioCodec::MuLaw,
+            IMA_ADPCM => AudioCodec::ImaAdpcm,
+            _ => return Err(Error::UnsupportedFormat(format)),
+        };
 
-        let sample_rate = bytes[4..8]
-            .try_into()
-            .map_err(|_| Error::CantParseSliceInto)
-            .map(|b| u32::from_le_bytes(b))?;
+        let num_channels = field(2);
+        let sample_rate = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let block_size = field(12);
+        let bit_depth = field(14);
 
-        let bit_depth = bytes[14..16]
-            .try_into()
-            .map_err(|_| Error::CantParseSliceInto)
-            .map(|b| u16::from_le_bytes(b))?;
+        if codec == AudioCodec::IeeeFloat && bit_depth != 32 {
+            return Err(Error::UnsupportedBitDepth(bit_depth));
+        }
+
+        if matches!(codec, AudioCodec::ALaw | AudioCodec::MuLaw) && bit_depth != 8 {
+            return Err(Error::UnsupportedBitDepth(bit_depth));
+        }
+
+        if codec == AudioCodec::ImaAdpcm {
+            if bit_depth != 4 {
+                return Err(Error::UnsupportedBitDepth(bit_depth));
+            }
+
+            if num_channels == 0 || num_channels as usize > MAX_ADPCM_CHANNELS {
+                return Err(Error::UnsupportedFormat(format));
+            }
+
+            if adpcm::frames_per_block(block_size as usize, num_channels as usize) == 0 {
+                return Err(Error::CantParseChunk(ChunkTag::Fmt));
+            }
+        }
 
         Ok(Fmt {
+            codec,
             num_channels,
             sample_rate,
             bit_depth,
+            block_size,
         })
     }
+
+    /// Serialize into the body of a fmt chunk, 16 bytes for PCM and 18 with an empty `cbSize` for
+    /// the other formats. Compressed formats needing extra fields are rejected, as are sample
+    /// rates and frame sizes that overflow their fields.
+    pub(crate) fn to_chunk(self) -> Result<Vec<u8, FMT_EXTENDED_LEN>, Error> {
+        let format = match self.codec {
+            AudioCodec::Pcm => 1,
+            AudioCodec::IeeeFloat => 3,
+            AudioCodec::ALaw => A_LAW,
+            AudioCodec::MuLaw => MU_LAW,
+            AudioCodec::ImaAdpcm => return Err(Error::UnsupportedFormat(IMA_ADPCM)),
+            AudioCodec::PcmBigEndian => return Err(Error::FormatMismatch),
+        };
+
+        let block_align =
+            u16::try_from(self.block_align()).map_err(|_| Error::CantParseChunk(ChunkTag::Fmt))?;
+        let byte_rate = self
+            .sample_rate
+            .checked_mul(block_align as u32)
+            .ok_or(Error::CantParseChunk(ChunkTag::Fmt))?;
+
+        let mut bytes = [0; FMT_EXTENDED_LEN];
+        bytes[0..2].copy_from_slice(&format.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.num_channels.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.sample_rate.to_le_bytes());
+        bytes[8..12].copy_from_slice(&byte_rate.to_le_bytes());
+        bytes[12..14].copy_from_slice(&block_align.to_le_bytes());
+        bytes[14..16].copy_from_slice(&self.bit_depth.to_le_bytes());
+
+        // the cbSize of 0 stays
+        let len = match self.codec {
+            AudioCodec::Pcm => FMT_BASE_LEN,
+            _ => FMT_EXTENDED_LEN,
+        };
+
+        Ok(Vec::from_slice(&bytes[..len]).unwrap())
+    }
+
+    /// Number of bytes in one block, i.e. one sample for every channel or one compressed block
+    pub fn block_align(&self) -> usize {
+        match self.codec {
+            AudioCodec::ImaAdpcm => self.block_size as usize,
+            _ => self.num_channels as usize * (self.bit_depth as usize).div_ceil(8),
+        }
+    }
+
+    /// Number of frames in one block, `1` for uncompressed formats
+    pub fn frames_per_block(&self) -> usize {
+        match self.codec {
+            AudioCodec::ImaAdpcm => {
+                adpcm::frames_per_block(self.block_size as usize, self.num_channels as usize)
+            }
+            _ => 1,
+        }
+    }
 }
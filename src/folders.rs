@@ -0,0 +1,311 @@
+use crate::audio_file::AudioFile;
+use crate::error::Error;
+use crate::index::INDEX_NAME_LEN;
+use crate::sd_card::{SdAudioFile, SdCard};
+use core::convert::TryInto;
+use core::fmt::Write;
+use embedded_sdmmc::{BlockDevice, Directory, Mode, RawFile, TimeSource};
+use heapless::String;
+
+/// Error of reading the folders of an [`SdCard`] on block device `D`
+type CardError<D> = Error<embedded_sdmmc::Error<<D as BlockDevice>::Error>>;
+
+/// Order the tracks of a folder, and the folders themselves, are played in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderOrder {
+    /// Order of the directory entries on the card, usually the order the files were copied in
+    OnCard,
+    /// Order of the 8.3 names
+    Name,
+}
+
+/// Position of a playback that treats every folder as an album, like most MP3 players do.
+///
+/// The files right inside the root directory are played first, then those of each of its
+/// subdirectories. Only the current folder and file names are kept, every step walks the
+/// directory once, so folders of any size are played without a list in RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderPlayback<'r> {
+    root: &'r str,
+    order: FolderOrder,
+    folder: Option<String<INDEX_NAME_LEN>>,
+    track: Option<String<INDEX_NAME_LEN>>,
+}
+
+impl<'r> FolderPlayback<'r> {
+    /// Play the folders of the directory at `root`, e.g. `MUSIC`, or of the root directory if it
+    /// is empty
+    pub fn new(root: &'r str, order: FolderOrder) -> Self {
+        FolderPlayback {
+            root,
+            order,
+            folder: None,
+            track: None,
+        }
+    }
+
+    /// Name of the folder being played, `None` for the files right inside the root
+    pub fn folder(&self) -> Option<&str> {
+        self.folder.as_deref()
+    }
+
+    /// Name of the last track handed out in the current folder
+    pub fn track(&self) -> Option<&str> {
+        self.track.as_deref()
+    }
+
+    /// Start over at the first track of the root
+    pub fn rewind(&mut self) {
+        self.folder = None;
+        self.track = None;
+    }
+}
+
+/// Picks the entry following `after` in `order` out of the names offered one at a time
+struct NextName<'a> {
+    order: FolderOrder,
+    after: Option<&'a str>,
+    passed: bool,
+    found: Option<String<INDEX_NAME_LEN>>,
+}
+
+impl<'a> NextName<'a> {
+    fn new(order: FolderOrder, after: Option<&'a str>) -> Self {
+        NextName {
+            order,
+            after,
+            passed: after.is_none(),
+            found: None,
+        }
+    }
+
+    fn offer(&mut self, name: &str) {
+        match self.order {
+            FolderOrder::OnCard if self.found.is_none() => {
+                if self.passed {
+                    self.found = name.try_into().ok();
+                } else {
+                    self.passed = Some(name) == self.after;
+                }
+            }
+            FolderOrder::OnCard => {}
+            FolderOrder::Name => {
+                let later = match self.after {
+                    Some(after) => name > after,
+                    None => true,
+                };
+                let earlier = match &self.found {
+                    Some(found) => name < found.as_str(),
+                    None => true,
+                };
+
+                if later && earlier {
+                    self.found = name.try_into().ok();
+                }
+            }
+        }
+    }
+}
+
+impl<
+        D: BlockDevice,
+        T: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > SdCard<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    /// Open the track following the current one of `playback`, moving on to the first track of
+    /// the next folder at the end of a folder. Returns `None` after the last track of the last
+    /// folder, until [`FolderPlayback::rewind`].
+    ///
+    /// Files that aren't audio are skipped. Failing to read the card is reported as
+    /// [`Error::Source`].
+    pub fn next_track(
+        &mut self,
+        playback: &mut FolderPlayback,
+    ) -> Result<Option<SdAudioFile<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>, CardError<D>> {
+        // sniffed a second time, a file returned from within the loop of `next_file` would keep
+        // the card borrowed for the following iterations
+        match self.next_file(playback)? {
+            Some(file) => AudioFile::new_auto(file.to_file(self.volume_mgr())).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Skip the rest of the current folder, the following [`SdCard::next_track`] opens the first
+    /// track of the next one. Returns `false` if the current folder is the last one.
+    pub fn next_folder(&mut self, playback: &mut FolderPlayback) -> Result<bool, CardError<D>> {
+        let mut root = self
+            .open_dir(playback.root.split('/'))
+            .map_err(Error::Source)?;
+        let folder = next_name(&mut root, playback.folder.as_deref(), playback.order, true)
+            .map_err(Error::Source)?;
+
+        match folder {
+            Some(folder) => {
+                playback.folder = Some(folder);
+                playback.track = None;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Open the audio file following the current track of `playback`, see
+    /// [`SdCard::next_track`]
+    fn next_file(
+        &mut self,
+        playback: &mut FolderPlayback,
+    ) -> Result<Option<RawFile>, CardError<D>> {
+        loop {
+            // the directory is closed before the file is sniffed, or the next folder looked up
+            let next = {
+                let folder = playback.folder.as_deref();
+                let mut dir = self
+                    .open_dir(playback.root.split('/').chain(folder))
+                    .map_err(Error::Source)?;
+
+                match next_name(&mut dir, playback.track.as_deref(), playback.order, false)
+                    .map_err(Error::Source)?
+                {
+                    Some(name) => {
+                        let dir = dir.to_raw_directory();
+                        let file =
+                            self.volume_mgr()
+                                .open_file_in_dir(dir, name.as_str(), Mode::ReadOnly);
+                        self.volume_mgr().close_dir(dir).map_err(Error::Source)?;
+
+                        Some((name, file.map_err(Error::Source)?))
+                    }
+                    None => None,
+                }
+            };
+
+            let file = match next {
+                Some((name, file)) => {
+                    playback.track = Some(name);
+                    file
+                }
+                None => match self.next_folder(playback)? {
+                    true => continue,
+                    false => return Ok(None),
+                },
+            };
+
+            match AudioFile::new_auto(file.to_file(self.volume_mgr())) {
+                Ok(file) => return Ok(Some(file.destroy().to_raw_file())),
+                Err(Error::Source(e)) => return Err(Error::Source(e)),
+                // not audio, or damaged
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// Name of the file or subdirectory of `dir` following `after` in `order`, the first one if
+/// `after` is `None`. `.` and `..` are skipped.
+fn next_name<
+    D: BlockDevice,
+    T: TimeSource,
+    const DIRS: usize,
+    const FILES: usize,
+    const VOLUMES: usize,
+>(
+    dir: &mut Directory<'_, D, T, DIRS, FILES, VOLUMES>,
+    after: Option<&str>,
+    order: FolderOrder,
+    directories: bool,
+) -> Result<Option<String<INDEX_NAME_LEN>>, embedded_sdmmc::Error<D::Error>> {
+    let mut next = NextName::new(order, after);
+
+    dir.iterate_dir(|entry| {
+        if entry.attributes.is_volume() || entry.attributes.is_directory() != directories {
+            return;
+        }
+
+        let mut name: String<INDEX_NAME_LEN> = String::new();
+        let _ = write!(name, "{}", entry.name);
+
+        if !name.starts_with('.') {
+            next.offer(&name);
+        }
+    })?;
+
+    Ok(next.found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sd_card::tests::{card, TestCard};
+
+    const NAMES: [&str; 4] = ["B.WAV", "C.MP3", "A.WAV", "D.OGG"];
+
+    fn walk(order: FolderOrder) -> std::vec::Vec<std::string::String> {
+        let mut names = std::vec::Vec::new();
+        let mut after: Option<std::string::String> = None;
+
+        loop {
+            let mut next = NextName::new(order, after.as_deref());
+            NAMES.iter().for_each(|name| next.offer(name));
+
+            match next.found {
+                Some(found) => after = Some(found.as_str().into()),
+                None => return names,
+            }
+
+            names.extend(after.clone());
+        }
+    }
+
+    #[test]
+    fn should_step_through_names_in_order() {
+        assert_eq!(walk(FolderOrder::OnCard), NAMES);
+        assert_eq!(
+            walk(FolderOrder::Name),
+            ["A.WAV", "B.WAV", "C.MP3", "D.OGG"]
+        );
+    }
+
+    fn play(card: &mut TestCard, order: FolderOrder) -> std::vec::Vec<std::string::String> {
+        let mut playback = FolderPlayback::new("", order);
+        let mut played = std::vec::Vec::new();
+
+        while let Some(file) = card.next_track(&mut playback).unwrap() {
+            drop(file);
+            played.push(format!(
+                "{}/{}",
+                playback.folder().unwrap_or_default(),
+                playback.track().unwrap()
+            ));
+        }
+
+        played
+    }
+
+    #[test]
+    fn should_play_the_folders_of_a_card() {
+        let wav = include_bytes!("../test_files/mono_16_48000.wav");
+        let flac = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut card = card(&[
+            ("INTRO.WAV", wav),
+            ("B/TWO.FLA", flac),
+            ("B/ONE.FLA", flac),
+            ("A/NOTES.TXT", b"not audio"),
+            ("A/SONG.WAV", wav),
+        ]);
+
+        assert_eq!(
+            play(&mut card, FolderOrder::OnCard),
+            ["/INTRO.WAV", "B/TWO.FLA", "B/ONE.FLA", "A/SONG.WAV"]
+        );
+        assert_eq!(
+            play(&mut card, FolderOrder::Name),
+            ["/INTRO.WAV", "A/SONG.WAV", "B/ONE.FLA", "B/TWO.FLA"]
+        );
+
+        card.close().unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+/// Format code of A-law companded PCM in the fmt chunk
+pub(crate) const A_LAW: u16 = 6;
+/// Format code of µ-law companded PCM in the fmt chunk
+pub(crate) const MU_LAW: u16 = 7;
+
+/// 16 bit PCM value of every A-law code
+pub(crate) const A_LAW_TABLE: [i16; 256] = table(A_LAW);
+/// 16 bit PCM value of every µ-law code
+pub(crate) const MU_LAW_TABLE: [i16; 256] = table(MU_LAW);
+
+/// Expand all 256 codes of the given format code at compile time
+const fn table(format: u16) -> [i16; 256] {
+    let mut table = [0; 256];
+    let mut code = 0;
+
+    while code < 256 {
+        table[code] = match format {
+            A_LAW => a_law_to_linear(code as u8),
+            _ => mu_law_to_linear(code as u8),
+        };
+        code += 1;
+    }
+
+    table
+}
+
+const fn a_law_to_linear(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let segment = (code & 0x70) >> 4;
+    let mut value = ((code & 0x0f) as i16) << 4;
+
+    match segment {
+        0 => value += 8,
+        1 => value += 0x108,
+        _ => value = (value + 0x108) << (segment - 1),
+    }
+
+    if code & 0x80 != 0 {
+        value
+    } else {
+        -value
+    }
+}
+
+const fn mu_law_to_linear(code: u8) -> i16 {
+    let code = !code;
+    let value = ((((code & 0x0f) as i16) << 3) + 0x84) << ((code & 0x70) >> 4);
+
+    if code & 0x80 != 0 {
+        0x84 - value
+    } else {
+        value - 0x84
+    }
+}
+
+/// Compress a 16 bit PCM sample into its A-law code
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
+pub(crate) fn linear_to_a_law(sample: i16) -> u8 {
+    // A-law works on 13 bit samples, negative values are stored in ones' complement
+    let (value, mask) = match sample >> 3 {
+        value if value >= 0 => (value, 0xd5),
+        value => (-value - 1, 0x55),
+    };
+
+    let segment = match value {
+        0..=0x1f => 0,
+        _ => 27 - (value as u32).leading_zeros(),
+    };
+
+    let code = match segment {
+        0 | 1 => (segment << 4) as u8 | ((value >> 1) & 0x0f) as u8,
+        2..=7 => (segment << 4) as u8 | ((value >> segment) & 0x0f) as u8,
+        _ => 0x7f,
+    };
+
+    code ^ mask
+}
+
+/// Compress a 16 bit PCM sample into its µ-law code
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
+pub(crate) fn linear_to_mu_law(sample: i16) -> u8 {
+    let (value, sign) = match sample as i32 {
+        value if value < 0 => (-value, 0x80),
+        value => (value, 0x00),
+    };
+
+    let biased = value.min(32_635) + 0x84;
+    let exponent = 31 - ((biased >> 7) as u32).leading_zeros();
+    let mantissa = (biased >> (exponent + 3)) & 0x0f;
+
+    !(sign | (exponent << 4) as u8 | mantissa as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_expand_reference_codes() {
+        assert_eq!(A_LAW_TABLE[0xd5], 8);
+        assert_eq!(A_LAW_TABLE[0x55], -8);
+        assert_eq!(A_LAW_TABLE[0xaa], 32_256);
+        assert_eq!(A_LAW_TABLE[0x2a], -32_256);
+
+        assert_eq!(MU_LAW_TABLE[0xff], 0);
+        assert_eq!(MU_LAW_TABLE[0x7f], 0);
+        assert_eq!(MU_LAW_TABLE[0x80], 32_124);
+        assert_eq!(MU_LAW_TABLE[0x00], -32_124);
+    }
+
+    #[test]
+    fn should_compress_back_to_the_same_codes() {
+        for code in 0..=255u8 {
+            assert_eq!(linear_to_a_law(A_LAW_TABLE[code as usize]), code);
+
+            // both zero codes expand to 0, which compresses to the positive one
+            if code != 0x7f {
+                assert_eq!(linear_to_mu_law(MU_LAW_TABLE[code as usize]), code);
+            }
+        }
+
+        assert_eq!(linear_to_a_law(i16::MIN), 0x2a);
+        assert_eq!(linear_to_mu_law(i16::MIN), 0x00);
+        assert_eq!(linear_to_mu_law(i16::MAX), 0x80);
+    }
+}
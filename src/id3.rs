@@ -0,0 +1,432 @@
+use crate::error::Error;
+use crate::metadata::{to_string, ListChunkTag, Metadata};
+use crate::riff::{Chunk, ChunkTag};
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use heapless::String;
+
+/// Size of the header of an ID3v2 tag, and of a frame header from version 2.3 on
+pub(crate) const ID3_HEADER_SIZE: usize = 10;
+/// Bytes of a text frame read at most, longer values are cut short
+const MAX_TEXT_LEN: usize = 256;
+
+/// Genres of ID3v1, which `TCON` may refer to by number
+const GENRES: [&str; 80] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "AlternRock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+/// Metadata field filled by a text frame, by its id in version 2.2 or 2.3 and later
+fn frame_tag(id: &[u8]) -> Option<ListChunkTag> {
+    match id {
+        b"TIT2" | b"TT2" => Some(ListChunkTag::Title),
+        b"TPE1" | b"TP1" => Some(ListChunkTag::Artist),
+        b"TALB" | b"TAL" => Some(ListChunkTag::Product),
+        b"TCON" | b"TCO" => Some(ListChunkTag::Genre),
+        b"TDRC" | b"TYER" | b"TYE" => Some(ListChunkTag::CreationDate),
+        _ => None,
+    }
+}
+
+/// Size stored in 7 bits per byte, so it never contains a sync pattern
+pub(crate) fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(0, |size, b| size << 7 | (b & 0x7f) as u32)
+}
+
+/// Big endian size of a frame header of version 2.2 or 2.3
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |size, b| size << 8 | *b as u32)
+}
+
+/// Decode the first value of a text frame, whose first byte names the encoding
+fn decode_text<const N: usize>(bytes: &[u8]) -> String<N> {
+    let mut string = String::new();
+
+    let (encoding, text) = match bytes.split_first() {
+        Some((encoding, text)) => (*encoding, text),
+        None => return string,
+    };
+
+    let mut push = |c: char| string.push(c).is_ok();
+
+    match encoding {
+        // ISO-8859-1 maps straight onto the first code points
+        0 => {
+            let _ = text
+                .iter()
+                .take_while(|b| **b != 0)
+                .all(|b| push(*b as char));
+        }
+        1 | 2 => {
+            let (text, little_endian) = match text {
+                [0xff, 0xfe, text @ ..] if encoding == 1 => (text, true),
+                [0xfe, 0xff, text @ ..] => (text, false),
+                _ => (text, false),
+            };
+
+            let units = text.chunks_exact(2).map(|u| match little_endian {
+                true => u16::from_le_bytes([u[0], u[1]]),
+                false => u16::from_be_bytes([u[0], u[1]]),
+            });
+
+            let _ = core::char::decode_utf16(units.take_while(|u| *u != 0))
+                .all(|c| push(c.unwrap_or(core::char::REPLACEMENT_CHARACTER)));
+        }
+        3 => return to_string(text),
+        _ => {}
+    }
+
+    string
+}
+
+/// Name of an ID3v1 genre referenced as `(17)` or `17`, `None` for any other value
+fn genre_name(value: &str) -> Option<&'static str> {
+    let number = value
+        .strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .unwrap_or(value);
+
+    GENRES.get(number.parse::<usize>().ok()?).copied()
+}
+
+/// Call `f` with the id and the value range of each frame of the ID3v2 tag at the read position
+/// of `source`, ending by `end` at the latest, until it returns `true`.
+///
+/// Compressed, encrypted and unsynchronised frames are skipped. Returns the total size of the tag,
+/// `None` if there is no tag at the read position. The read position is left anywhere within the
+/// tag.
+pub(crate) fn for_each_id3_frame<S: AudioSource>(
+    source: &mut S,
+    end: u32,
+    mut f: impl FnMut(&mut S, &[u8], u32, u32) -> Result<bool, Error<S::Error>>,
+) -> Result<Option<u32>, Error<S::Error>> {
+    let start = source.offset();
+    let mut header = [0; ID3_HEADER_SIZE];
+
+    if read_full(source, &mut header)? != header.len() || &header[..3] != b"ID3" {
+        return Ok(None);
+    }
+
+    let version = header[3];
+    let flags = header[5];
+    let size = syncsafe(&header[6..10]);
+    let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+    let total = ID3_HEADER_SIZE as u32 + size + footer;
+    let body_end = start.saturating_add(ID3_HEADER_SIZE as u32 + size).min(end);
+
+    // unsynchronised tags and versions beyond 2.4 are skipped as a whole
+    if flags & 0x80 != 0 || !(2..=4).contains(&version) {
+        return Ok(Some(total));
+    }
+
+    let (id_len, frame_header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut index = start.saturating_add(ID3_HEADER_SIZE as u32);
+
+    if flags & 0x40 != 0 && version >= 3 {
+        let mut extended = [0; 4];
+        read_full(source, &mut extended)?;
+
+        let extended_len = match version {
+            3 => big_endian(&extended).saturating_add(4),
+            _ => syncsafe(&extended),
+        };
+
+        index = index
+            .checked_add(extended_len)
+            .filter(|&index| index <= body_end)
+            .ok_or(Error::CantParseChunk(ChunkTag::Unknown(*b"id3 ")))?;
+    }
+
+    while index.saturating_add(frame_header_len) <= body_end {
+        let mut frame = [0; ID3_HEADER_SIZE];
+        let frame = &mut frame[..frame_header_len as usize];
+        source.seek(index).map_err(Error::Source)?;
+
+        if read_full(source, frame)? != frame.len() || frame[0] == 0 {
+            // padding
+            break;
+        }
+
+        let (id, rest) = frame.split_at(id_len);
+        let size = match version {
+            2 => big_endian(&rest[..3]),
+            3 => big_endian(&rest[..4]),
+            _ => syncsafe(&rest[..4]),
+        };
+        let format = if version >= 3 { rest[5] } else { 0 };
+        let value_start = index + frame_header_len;
+        let value_end = value_start.saturating_add(size).min(body_end);
+
+        let readable = match version {
+            3 => format & 0xc0 == 0,
+            4 => format & 0x0e == 0,
+            _ => true,
+        };
+        // a data length indicator in front of the value
+        let skip = if version == 4 && format & 0x01 != 0 {
+            4
+        } else {
+            0
+        };
+
+        if readable && f(source, id, (value_start + skip).min(value_end), value_end)? {
+            break;
+        }
+
+        index = value_start.saturating_add(size);
+    }
+
+    Ok(Some(total))
+}
+
+/// Read the ID3v2 tag at the read position of `source`, ending by `end` at the latest, into
+/// `metadata`. Fields already set are kept.
+///
+/// Returns the total size of the tag, `None` if there is no tag at the read position. The read
+/// position is left anywhere within the tag.
+pub(crate) fn read_id3v2<S: AudioSource, const MAX_STRING_LEN: usize>(
+    source: &mut S,
+    end: u32,
+    metadata: &mut Metadata<MAX_STRING_LEN>,
+) -> Result<Option<u32>, Error<S::Error>> {
+    for_each_id3_frame(source, end, |source, id, start, end| {
+        let field = frame_tag(id).and_then(|tag| Some((tag, metadata.field_mut(tag)?)));
+
+        if let Some((tag, field @ None)) = field {
+            let mut value = [0; MAX_TEXT_LEN];
+            let len = (end - start) as usize;
+
+            source.seek(start).map_err(Error::Source)?;
+            let read = read_full(source, &mut value[..len.min(MAX_TEXT_LEN)])?;
+            let text: String<MAX_STRING_LEN> = decode_text(&value[..read]);
+
+            *field = match genre_name(&text) {
+                Some(genre) if tag == ListChunkTag::Genre => Some(to_string(genre.as_bytes())),
+                _ => Some(text),
+            };
+        }
+
+        Ok(false)
+    })
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Fill the fields of `metadata` that are still missing from the ID3v2 tag of an `id3 `
+    /// chunk, if the file has one. The read position is left unchanged
+    pub(crate) fn read_id3_chunk<const MAX_STRING_LEN: usize>(
+        &mut self,
+        metadata: &mut Metadata<MAX_STRING_LEN>,
+    ) -> Result<(), Error<S::Error>> {
+        let chunk = match self.find_id3_chunk()? {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let read = read_id3v2(&mut self.source, chunk.end as u32, metadata);
+        self.source.seek(position).map_err(Error::Source)?;
+
+        read.map(|_| ())
+    }
+
+    /// The `id3 ` chunk holding an ID3v2 tag, also found spelled `ID3 `
+    pub(crate) fn find_id3_chunk(&mut self) -> Result<Option<Chunk>, Error<S::Error>> {
+        self.find_chunk_by(|_, chunk| match chunk.id {
+            ChunkTag::Unknown(id) => id.eq_ignore_ascii_case(b"id3 "),
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    /// ID3v2 tag of the given version holding `frames`
+    fn tag(version: u8, frames: &[u8]) -> std::vec::Vec<u8> {
+        let size = frames.len() as u32 + 4;
+        let mut bytes = b"ID3".to_vec();
+        bytes.extend_from_slice(&[version, 0, 0]);
+        bytes.extend((0..4).rev().map(|i| ((size >> (7 * i)) & 0x7f) as u8));
+        bytes.extend_from_slice(frames);
+        // padding
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    fn frame(id: &[u8], value: &[u8]) -> std::vec::Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    #[test]
+    fn should_read_text_frames_in_every_encoding() {
+        let mut frames = frame(b"TIT2", b"\x00Caf\xe9");
+        frames.extend(frame(
+            b"TPE1",
+            b"\x01\xff\xfeB\x00j\x00\xf6\x00r\x00k\x00\x00\x00",
+        ));
+        frames.extend(frame(b"TALB", b"\x03Homogenic\x00Other"));
+        frames.extend(frame(b"TCON", b"\x00(26)"));
+        frames.extend(frame(b"TYER", b"\x001997"));
+        frames.extend(frame(b"TXXX", b"\x00skipped"));
+
+        let bytes = tag(3, &frames);
+        let mut source = SliceSource::new(&bytes);
+        let mut metadata = Metadata::<16>::default();
+
+        let size = read_id3v2(&mut source, u32::MAX, &mut metadata).unwrap();
+
+        assert_eq!(size, Some(bytes.len() as u32));
+        assert_eq!(metadata.title(), Some("Café"));
+        assert_eq!(metadata.artist(), Some("Björk"));
+        assert_eq!(metadata.product(), Some("Homogenic"));
+        assert_eq!(metadata.genre(), Some("Ambient"));
+        assert_eq!(metadata.creation_date(), Some("1997"));
+    }
+
+    #[test]
+    fn should_read_version_4_and_keep_set_fields() {
+        let mut frames = frame(b"TIT2", b"\x02\x00H\x00i");
+        frames.extend(frame(b"TDRC", b"\x032024-05-17"));
+        frames.extend(frame(b"TCON", b"\x03Shoegaze"));
+        let bytes = tag(4, &frames);
+
+        let mut metadata = Metadata::<4>::default();
+        metadata.set(ListChunkTag::Title, b"Kept");
+
+        let mut source = SliceSource::new(&bytes);
+        read_id3v2(&mut source, u32::MAX, &mut metadata).unwrap();
+
+        assert_eq!(metadata.title(), Some("Kept"));
+        assert_eq!(metadata.creation_date(), Some("2024"));
+        assert_eq!(metadata.genre(), Some("Shoe"));
+
+        let mut source = SliceSource::new(b"RIFF");
+        assert_eq!(read_id3v2(&mut source, 4, &mut metadata), Ok(None));
+    }
+
+    #[test]
+    fn should_reject_extended_headers_past_the_tag() {
+        let mut metadata = Metadata::<4>::default();
+
+        for (version, size) in [(3, [0xff; 4]), (4, [0x7f; 4]), (3, [0, 0, 1, 0])].iter() {
+            let mut bytes = tag(*version, &frame(b"TIT2", b"\x00Lost"));
+            bytes[5] = 0x40;
+            bytes.splice(10..10, size.iter().copied());
+
+            let mut source = SliceSource::new(&bytes);
+            assert_eq!(
+                read_id3v2(&mut source, u32::MAX, &mut metadata),
+                Err(Error::CantParseChunk(ChunkTag::Unknown(*b"id3 ")))
+            );
+        }
+
+        assert_eq!(metadata.title(), None);
+    }
+
+    #[test]
+    fn should_fill_wav_metadata_from_id3_chunk() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let id3 = tag(3, &frame(b"TPE1", b"\x00Air"));
+
+        let mut bytes = bytes.to_vec();
+        bytes.extend_from_slice(b"id3 ");
+        bytes.extend_from_slice(&(id3.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&id3);
+
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+        let metadata: Metadata<16> = wav.metadata().unwrap();
+
+        assert_eq!(metadata.artist(), Some("Air"));
+        assert_eq!(wav.timestamp().frames, 0);
+    }
+}
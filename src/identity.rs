@@ -0,0 +1,156 @@
+use core::convert::TryInto;
+
+/// Bytes of a [`TrackIdentity`] as stored in a file
+pub const TRACK_IDENTITY_LEN: usize = 12;
+
+/// FNV-1a offset basis and prime, 32 bit
+pub(crate) const FNV_OFFSET: u32 = 0x811c_9dc5;
+pub(crate) const FNV_PRIME: u32 = 0x0100_0193;
+
+/// Identity of a file that keeps resolving wherever its path is written down, e.g. for resume
+/// points, bookmarks or playlists built on a desktop and read on the device.
+///
+/// Made of a hash of the path, the size and the modification time of the file. Paths are
+/// compared ignoring ASCII case, with `\` read as `/` and leading or repeated separators ignored,
+/// so `Music\Track01.wav` and `/MUSIC/TRACK01.WAV` are the same. The modification time is packed
+/// like a FAT directory entry, which every driver reads the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackIdentity {
+    path_hash: u32,
+    size: u32,
+    modified: u32,
+}
+
+impl TrackIdentity {
+    /// Identity of the file at `path` of `size` bytes, last modified at `modified`, a FAT date in
+    /// the top 16 bits and a FAT time in the bottom 16 bits
+    pub fn new(path: &str, size: u32, modified: u32) -> Self {
+        TrackIdentity {
+            path_hash: path_hash(path),
+            size,
+            modified,
+        }
+    }
+
+    /// Hash of the normalized path
+    pub fn path_hash(&self) -> u32 {
+        self.path_hash
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Modification time of the file, FAT date and time packed into a `u32`
+    pub fn modified(&self) -> u32 {
+        self.modified
+    }
+
+    /// True if `other` has the same size and modification time, whatever its path.
+    ///
+    /// Resolves files whose names differ entirely between drivers, such as a long name on the
+    /// desktop and its 8.3 alias on the device, or files that were moved. Try an exact match
+    /// first, two different files can share both.
+    pub fn same_content(&self, other: &TrackIdentity) -> bool {
+        self.size == other.size && self.modified == other.modified
+    }
+
+    /// The identity as stored in a file
+    pub fn to_bytes(&self) -> [u8; TRACK_IDENTITY_LEN] {
+        let mut bytes = [0; TRACK_IDENTITY_LEN];
+
+        bytes[..4].copy_from_slice(&self.path_hash.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.modified.to_le_bytes());
+
+        bytes
+    }
+
+    /// Identity read back from a file
+    pub fn from_bytes(bytes: &[u8; TRACK_IDENTITY_LEN]) -> Self {
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        TrackIdentity {
+            path_hash: field(0),
+            size: field(4),
+            modified: field(8),
+        }
+    }
+}
+
+/// Pack a date and time into the layout of a FAT directory entry, with a resolution of two
+/// seconds: the years since 1980, month and day in the top 16 bits, the hours, minutes and
+/// halved seconds in the bottom 16 bits
+pub fn fat_timestamp(year: u16, month: u8, day: u8, hours: u8, minutes: u8, seconds: u8) -> u32 {
+    let date = (year.saturating_sub(1980) as u32) << 9 | (month as u32) << 5 | day as u32;
+    let time = (hours as u32) << 11 | (minutes as u32) << 5 | (seconds / 2) as u32;
+
+    date << 16 | time
+}
+
+/// FNV-1a hash of the normalized `path`
+pub(crate) fn path_hash(path: &str) -> u32 {
+    let mut hash = FNV_OFFSET;
+    let mut separator = false;
+    let mut started = false;
+
+    for byte in path.bytes() {
+        let byte = match byte {
+            b'\\' | b'/' => {
+                separator = true;
+                continue;
+            }
+            byte => byte.to_ascii_uppercase(),
+        };
+
+        // one separator between components, none in front of the first
+        if separator && started {
+            hash = (hash ^ b'/' as u32).wrapping_mul(FNV_PRIME);
+        }
+
+        separator = false;
+        started = true;
+        hash = (hash ^ byte as u32).wrapping_mul(FNV_PRIME);
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_ignore_how_the_path_is_written() {
+        let modified = fat_timestamp(2024, 5, 17, 21, 4, 31);
+        let device = TrackIdentity::new("MUSIC/TRACK01.WAV", 1_234_567, modified);
+
+        for path in [
+            "/Music/Track01.wav",
+            "music\\track01.WAV",
+            "MUSIC//TRACK01.WAV",
+        ]
+        .iter()
+        {
+            assert_eq!(TrackIdentity::new(path, 1_234_567, modified), device);
+        }
+
+        let other = TrackIdentity::new("MUSIC/TRACK02.WAV", 1_234_567, modified);
+        assert_ne!(other, device);
+        assert!(other.same_content(&device));
+
+        let long_name = TrackIdentity::new("Music/01 - Opening.wav", 1_234_567, modified);
+        assert!(long_name.same_content(&device));
+        assert_eq!(TrackIdentity::from_bytes(&device.to_bytes()), device);
+    }
+
+    #[test]
+    fn should_pack_fat_timestamps() {
+        // 2024-05-17 21:04:30, seconds are stored halved
+        let packed = fat_timestamp(2024, 5, 17, 21, 4, 31);
+
+        assert_eq!(packed >> 16, 44 << 9 | 5 << 5 | 17);
+        assert_eq!(packed & 0xffff, 21 << 11 | 4 << 5 | 15);
+    }
+}
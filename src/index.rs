@@ -0,0 +1,407 @@
+use crate::audio_file::{AudioFile, FileFormat};
+use crate::error::Error;
+use crate::metadata::{to_string, Metadata};
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::timestamp::Timestamp;
+use crate::wav::read_full;
+use core::convert::TryInto;
+use heapless::String;
+
+/// Bytes at the start of an index file
+const INDEX_MAGIC: &[u8; 4] = b"APIX";
+/// Layout version of the entries, bumped whenever it changes
+const INDEX_VERSION: u8 = 1;
+/// Bytes in front of the first entry
+const INDEX_HEADER_LEN: usize = 8;
+/// Bytes of the file name of an entry, an 8.3 name with its dot
+pub const INDEX_NAME_LEN: usize = 12;
+/// Bytes kept of each tag of an entry, longer values are cut short
+pub const INDEX_TAG_LEN: usize = 32;
+/// Bytes of the name, format, channel count, sample rate and length of an entry
+const FIELDS_LEN: usize = INDEX_NAME_LEN + 10;
+/// Bytes of one entry of an index file
+pub const INDEX_ENTRY_LEN: usize = FIELDS_LEN + 5 * INDEX_TAG_LEN;
+
+/// Format, length and tags of one audio file, probed once and stored in an index file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub(crate) name: String<INDEX_NAME_LEN>,
+    pub(crate) format: FileFormat,
+    pub(crate) num_channels: u8,
+    pub(crate) sample_rate: u32,
+    pub(crate) total_frames: u32,
+    pub(crate) title: String<INDEX_TAG_LEN>,
+    pub(crate) artist: String<INDEX_TAG_LEN>,
+    pub(crate) album: String<INDEX_TAG_LEN>,
+    pub(crate) genre: String<INDEX_TAG_LEN>,
+    pub(crate) date: String<INDEX_TAG_LEN>,
+}
+
+impl IndexEntry {
+    /// Read the format, length and tags of `file`, stored under `name`.
+    ///
+    /// The length of MP3 files is estimated from the bitrate of the first frame, it is `0` for Ogg
+    /// files. The read position of `file` is left unchanged.
+    pub fn probe<S: AudioSource>(
+        name: &str,
+        file: &mut AudioFile<S>,
+    ) -> Result<Self, Error<S::Error>> {
+        let format = file.format();
+
+        let (num_channels, sample_rate, total_frames, metadata) = match file {
+            AudioFile::Wav(wav) => (
+                wav.fmt.num_channels,
+                wav.fmt.sample_rate,
+                wav.total_samples()?,
+                wav.metadata::<INDEX_TAG_LEN>()?,
+            ),
+            AudioFile::Flac(flac) => (
+                flac.info.num_channels,
+                flac.info.sample_rate,
+                flac.info.total_frames,
+                Metadata::default(),
+            ),
+            AudioFile::Mp3(mp3) => {
+                let header = mp3.peek_header()?;
+
+                (
+                    header.num_channels,
+                    header.sample_rate,
+                    mp3.estimated_duration()?.frames,
+                    mp3.metadata()?,
+                )
+            }
+            AudioFile::Ogg(_) => (0, 0, 0, Metadata::default()),
+            AudioFile::Avi(avi) => (
+                avi.fmt.num_channels,
+                avi.fmt.sample_rate,
+                avi.total_samples()?,
+                Metadata::default(),
+            ),
+        };
+
+        let tag = |value: Option<&str>| to_string(value.unwrap_or_default().as_bytes());
+
+        Ok(IndexEntry {
+            name: to_string(name.as_bytes()),
+            format,
+            num_channels: num_channels.min(u8::MAX as u16) as u8,
+            sample_rate,
+            total_frames: total_frames.min(u32::MAX as u64) as u32,
+            title: tag(metadata.title()),
+            artist: tag(metadata.artist()),
+            album: tag(metadata.product()),
+            genre: tag(metadata.genre()),
+            date: tag(metadata.creation_date()),
+        })
+    }
+
+    /// Name of the file within the scanned directory
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Format the file was opened as
+    pub fn format(&self) -> FileFormat {
+        self.format
+    }
+
+    /// Number of interleaved channels, `0` if unknown
+    pub fn num_channels(&self) -> u16 {
+        self.num_channels as u16
+    }
+
+    /// Sample rate, `0` if unknown
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Length of the file, `0` if unknown
+    pub fn duration(&self) -> Timestamp {
+        Timestamp::from_frames(self.total_frames as u64, self.sample_rate)
+    }
+
+    /// Title of the track
+    pub fn title(&self) -> Option<&str> {
+        non_empty(&self.title)
+    }
+
+    /// Artist of the track
+    pub fn artist(&self) -> Option<&str> {
+        non_empty(&self.artist)
+    }
+
+    /// Album of the track
+    pub fn album(&self) -> Option<&str> {
+        non_empty(&self.album)
+    }
+
+    /// Genre of the track
+    pub fn genre(&self) -> Option<&str> {
+        non_empty(&self.genre)
+    }
+
+    /// Year or date the track was recorded
+    pub fn date(&self) -> Option<&str> {
+        non_empty(&self.date)
+    }
+
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    fn to_bytes(&self) -> [u8; INDEX_ENTRY_LEN] {
+        let mut bytes = [0; INDEX_ENTRY_LEN];
+
+        bytes[..self.name.len()].copy_from_slice(self.name.as_bytes());
+        bytes[INDEX_NAME_LEN] = match self.format {
+            FileFormat::Wav => 0,
+            FileFormat::Flac => 1,
+            FileFormat::Mp3 => 2,
+            FileFormat::Ogg => 3,
+            FileFormat::Avi => 4,
+        };
+        bytes[INDEX_NAME_LEN + 1] = self.num_channels;
+        bytes[INDEX_NAME_LEN + 2..INDEX_NAME_LEN + 6]
+            .copy_from_slice(&self.sample_rate.to_le_bytes());
+        bytes[INDEX_NAME_LEN + 6..FIELDS_LEN].copy_from_slice(&self.total_frames.to_le_bytes());
+
+        let tags = [
+            &self.title,
+            &self.artist,
+            &self.album,
+            &self.genre,
+            &self.date,
+        ];
+
+        for (field, tag) in bytes[FIELDS_LEN..]
+            .chunks_exact_mut(INDEX_TAG_LEN)
+            .zip(tags.iter())
+        {
+            field[..tag.len()].copy_from_slice(tag.as_bytes());
+        }
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; INDEX_ENTRY_LEN]) -> Option<Self> {
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let tag = |i: usize| to_string(&bytes[FIELDS_LEN + i * INDEX_TAG_LEN..][..INDEX_TAG_LEN]);
+
+        let format = match bytes[INDEX_NAME_LEN] {
+            0 => FileFormat::Wav,
+            1 => FileFormat::Flac,
+            2 => FileFormat::Mp3,
+            3 => FileFormat::Ogg,
+            4 => FileFormat::Avi,
+            _ => return None,
+        };
+
+        Some(IndexEntry {
+            name: to_string(&bytes[..INDEX_NAME_LEN]),
+            format,
+            num_channels: bytes[INDEX_NAME_LEN + 1],
+            sample_rate: field(INDEX_NAME_LEN + 2),
+            total_frames: field(INDEX_NAME_LEN + 6),
+            title: tag(0),
+            artist: tag(1),
+            album: tag(2),
+            genre: tag(3),
+            date: tag(4),
+        })
+    }
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    match value {
+        "" => None,
+        value => Some(value),
+    }
+}
+
+/// Writes an index file entry by entry to an [`AudioSink`], nothing is kept in RAM
+///
+/// Sink errors are reported as [`Error::Io`].
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
+pub struct IndexWriter<K: AudioSink> {
+    sink: K,
+    len: u32,
+}
+
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
+impl<K: AudioSink> IndexWriter<K> {
+    /// Start an index file at the position of `sink`
+    pub fn new(mut sink: K) -> Result<Self, Error> {
+        let mut header = [0; INDEX_HEADER_LEN];
+        header[..4].copy_from_slice(INDEX_MAGIC);
+        header[4] = INDEX_VERSION;
+
+        sink.write(&header).map_err(|_| Error::Io)?;
+
+        Ok(IndexWriter { sink, len: 0 })
+    }
+
+    /// Continue an index file whose header and first `len` entries were already written, at the
+    /// position of `sink`
+    #[cfg(feature = "write")]
+    pub(crate) fn append(sink: K, len: u32) -> Self {
+        IndexWriter { sink, len }
+    }
+
+    /// Append `entry`
+    pub fn push(&mut self, entry: &IndexEntry) -> Result<(), Error> {
+        self.sink.write(&entry.to_bytes()).map_err(|_| Error::Io)?;
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Number of entries written
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// True before the first entry
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Destroy the [`IndexWriter`] instance and get the underlying sink
+    pub fn into_inner(self) -> K {
+        self.sink
+    }
+}
+
+/// Index file written by an [`IndexWriter`], reading entries on demand
+pub struct Index<S: AudioSource> {
+    source: S,
+    len: u32,
+}
+
+impl<S: AudioSource> Index<S> {
+    /// Check the header of the index file in `source`.
+    ///
+    /// Returns [`Error::UnknownFileFormat`] if it isn't an index file of this version, the index
+    /// should then be built again.
+    pub fn new(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut header = [0; INDEX_HEADER_LEN];
+        source.seek(0).map_err(Error::Source)?;
+
+        if read_full(&mut source, &mut header)? != INDEX_HEADER_LEN
+            || &header[..4] != INDEX_MAGIC
+            || header[4] != INDEX_VERSION
+        {
+            return Err(Error::UnknownFileFormat);
+        }
+
+        let len = (source.length() as usize).saturating_sub(INDEX_HEADER_LEN) / INDEX_ENTRY_LEN;
+
+        Ok(Index {
+            source,
+            len: len as u32,
+        })
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// True if the index holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read the entry at `index`, `None` past the last one
+    pub fn get(&mut self, index: u32) -> Result<Option<IndexEntry>, Error<S::Error>> {
+        if index >= self.len {
+            return Ok(None);
+        }
+
+        let offset = INDEX_HEADER_LEN as u32 + index * INDEX_ENTRY_LEN as u32;
+        self.source.seek(offset).map_err(Error::Source)?;
+
+        let mut bytes = [0; INDEX_ENTRY_LEN];
+
+        if read_full(&mut self.source, &mut bytes)? != INDEX_ENTRY_LEN {
+            return Err(Error::EndOfData);
+        }
+
+        IndexEntry::from_bytes(&bytes)
+            .map(Some)
+            .ok_or(Error::UnknownFileFormat)
+    }
+
+    /// Destroy the [`Index`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+    use crate::wav::Wav;
+
+    fn open(bytes: &[u8]) -> AudioFile<SliceSource<'_>> {
+        AudioFile::new_auto(SliceSource::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn should_probe_and_read_back_entries() {
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+        let flac = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut mp3 =
+            b"ID3\x03\x00\x00\x00\x00\x00\x13TIT2\x00\x00\x00\x09\x00\x00\x00Any song".to_vec();
+        mp3.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        mp3.extend_from_slice(&[0; 413]);
+
+        let entries = [
+            IndexEntry::probe("STEREO.WAV", &mut open(wav)).unwrap(),
+            IndexEntry::probe("STEREO.FLA", &mut open(flac)).unwrap(),
+            IndexEntry::probe("SONG.MP3", &mut open(&mp3)).unwrap(),
+        ];
+
+        let mut out = [0; 1024];
+        let mut writer = IndexWriter::new(SliceSink::new(&mut out)).unwrap();
+
+        for entry in &entries {
+            writer.push(entry).unwrap();
+        }
+
+        assert_eq!(writer.len(), 3);
+        let len = writer.into_inner().written().len();
+        assert_eq!(len, INDEX_HEADER_LEN + 3 * INDEX_ENTRY_LEN);
+
+        let mut index = Index::new(SliceSource::new(&out[..len])).unwrap();
+        assert_eq!(index.len(), 3);
+
+        for (i, entry) in entries.iter().enumerate() {
+            assert_eq!(index.get(i as u32).unwrap().as_ref(), Some(entry));
+        }
+
+        assert_eq!(index.get(3), Ok(None));
+
+        let wav_entry = index.get(0).unwrap().unwrap();
+        let mut reference = Wav::from_bytes(wav).unwrap();
+        assert_eq!(wav_entry.format(), FileFormat::Wav);
+        assert_eq!(wav_entry.num_channels(), 2);
+        assert_eq!(wav_entry.duration(), reference.duration().unwrap());
+
+        let mp3_entry = index.get(2).unwrap().unwrap();
+        assert_eq!(mp3_entry.name(), "SONG.MP3");
+        assert_eq!(mp3_entry.title(), Some("Any song"));
+        assert_eq!(mp3_entry.artist(), None);
+        assert_eq!(mp3_entry.duration().frames, 417 * 8 * 44_100 / 128_000);
+    }
+
+    #[test]
+    fn should_reject_foreign_files() {
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+
+        assert!(matches!(
+            Index::new(SliceSource::new(wav)),
+            Err(Error::UnknownFileFormat)
+        ));
+    }
+}
@@ -1,54 +1,177 @@
-//! Basic `no_std` library for parsing and creating WAV files.
+//! Basic `no_std` library for parsing and playing WAV files.
 //!
-//! Reading a WAV file:
+//! Reading a WAV file baked into the firmware:
 //! ```
-//! use std::fs;
-//! use std::path::Path;
-//! use wavv::{Wav, Data};
+//! use audio_parser::{DataBulk, Wav};
 //!
-//! fn main() {
-//!     let bytes = fs::read(Path::new("./test_files/stereo_16_48000.wav")).unwrap();
-//! 	let wav = Wav::from_bytes(&bytes).unwrap();
+//! let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+//! let mut wav = Wav::from_bytes(bytes).unwrap();
 //!
-//!     assert_eq!(wav.fmt.num_channels, 2);
-//!     assert_eq!(wav.fmt.bit_depth, 16);
-//!     assert_eq!(wav.fmt.sample_rate, 48_000);
+//! assert_eq!(wav.fmt.num_channels, 2);
+//! assert_eq!(wav.fmt.bit_depth, 16);
+//! assert_eq!(wav.fmt.sample_rate, 48_000);
 //!
-//!     match wav.data {
-//!         Data::BitDepth8(samples) => println!("{:?}", samples),
-//!         Data::BitDepth16(samples) => println!("{:?}", samples),
-//!         Data::BitDepth24(samples) => println!("{:?}", samples),
-//!     }
+//! match wav.next_n::<256>().unwrap() {
+//!     DataBulk::BitDepth16(samples) => println!("{:?}", samples),
+//!     _ => unreachable!(),
 //! }
 //! ```
 //!
-//! Writing a WAV file:
-//! ```
-//! use std::fs::File;
-//! use std::io::Write;
-//! use std::path::Path;
-//! use wavv::{Wav, Data};
-//!
-//! fn main() {
-//!     // Enjoy the silence
-//!     let data = Data::BitDepth16(vec![0; 480_000]);
-//! 	let wav = Wav::from_data(data, 48_000, 2);
-//!
-//!     let path = Path::new("output.wav");
-//!     let mut file = File::create(&path).unwrap();
-//!     file.write_all(&wav.to_bytes()).unwrap();
-//! }
-//! ```
+//! Files on an SD card are read the same way, [`Wav::new`] takes any [`AudioSource`] such as an
+//! embedded_sdmmc `File`.
 
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![warn(missing_docs)]
 
-mod chunk;
+mod adpcm;
+mod adts;
+mod aiff;
+mod analyze;
+mod audio_file;
+mod avi;
+mod bad_blocks;
+mod bext;
+mod burst;
+mod calibration;
+mod checkpoint;
+mod conceal;
+#[cfg(feature = "std")]
+pub mod conformance;
+mod cover_art;
+mod crossfeed;
+mod cue;
+mod decoder;
+mod dither;
+mod encode;
+mod ending;
 mod error;
+pub mod fixed;
+mod flac;
 mod fmt;
+mod folders;
+mod g711;
+mod id3;
+mod identity;
+mod index;
+mod library;
+mod looping;
+mod matrix;
+mod metadata;
+mod mixer;
+mod monitor;
+mod mp3;
+mod multicore;
+mod normalize;
+mod ogg;
+mod pipeline;
+#[cfg(feature = "write")]
+mod play_stats;
+mod prefetch;
+mod profile;
+mod remux;
+mod resume;
+#[cfg(feature = "write")]
+mod retag;
+pub mod riff;
+mod sampler;
+mod samples;
+#[cfg(feature = "sbc")]
+mod sbc;
+mod sd_card;
+mod self_test;
+mod sf2;
+mod sfx;
+mod simd;
+mod sink;
+mod source;
+mod split;
+mod sync;
+mod timestamp;
+mod tone;
+mod trigger;
+mod vad;
+mod volumes;
 mod wav;
+#[cfg(feature = "write")]
+mod writer;
+mod zero_crossing;
 
-pub use chunk::{Chunk, ChunkTag};
+pub use adpcm::decode_ima_block;
+pub use adts::{AdtsConfig, AdtsHeader, AdtsMode, AdtsSink};
+pub use analyze::{Analysis, ChannelStats};
+pub use audio_file::{AnyAudioFile, AudioFile, FileFormat, ANY_STRING_LEN};
+pub use avi::AviStream;
+pub use bad_blocks::{BadBlocks, BLOCK_SIZE};
+pub use bext::BroadcastExtension;
+pub use burst::{BurstConsumer, BurstProducer, BurstReader};
+pub use calibration::{Calibration, ChannelCalibration};
+pub use checkpoint::{Checkpoint, Checkpoints};
+pub use conceal::{Concealment, Tolerant};
+pub use cover_art::CoverArt;
+pub use crossfeed::Crossfeed;
+pub use cue::{Cue, CuePoint};
+pub use decoder::{Decoder, DecoderInfo};
+pub use dither::{Dither, Ditherer};
+pub use encode::{EncodingSink, PacketEncoder, PacketSink, RawPacketWriter};
+pub use ending::{EndBehavior, TrackEnd};
 pub use error::Error;
-pub use fmt::Fmt;
-pub use wav::{Data, DataBulk, Wav};
+pub use fixed::UNITY_GAIN;
+pub use flac::{Flac, StreamInfo};
+pub use fmt::{AudioCodec, Fmt};
+pub use folders::{FolderOrder, FolderPlayback};
+pub use identity::{fat_timestamp, TrackIdentity, TRACK_IDENTITY_LEN};
+#[cfg(feature = "write")]
+pub use index::IndexWriter;
+pub use index::{Index, IndexEntry, INDEX_ENTRY_LEN, INDEX_NAME_LEN, INDEX_TAG_LEN};
+pub use library::{Catalog, Page, Query, Search, SearchMode, SortKey};
+pub use matrix::ChannelMatrix;
+pub use metadata::{ListChunkTag, Metadata, MAX_OTHER_TAGS};
+pub use mixer::{mix_into, Ducking, PriorityMixer};
+pub use monitor::Monitor;
+pub use mp3::{Mp3File, Mp3Header, MpegVersion};
+pub use multicore::{DecodeRunner, FifoConsumer, FifoProducer, SampleFifo, StageRunner};
+pub use normalize::Normalization;
+pub use ogg::{OggCodec, OggPacket, OggPage, OggReader, OggStream, OggWriter};
+pub use pipeline::{Chain, DynPipeline, Gain, Passthrough, Pipeline, Stage};
+#[cfg(feature = "write")]
+pub use play_stats::{PlayRecord, PlayStats, PLAY_RECORD_LEN};
+pub use prefetch::{BufferedAudioFile, PrefetchConsumer, PrefetchProducer};
+pub use profile::{CycleCounter, CycleStats, Profiled};
+#[cfg(feature = "write")]
+pub use remux::{concat, extract, remux};
+pub use resume::{ResumeToken, RESUME_PATH_LEN, RESUME_TOKEN_LEN};
+pub use riff::{Chunk, ChunkTag, ChunkWalker};
+pub use sampler::{LoopKind, SampleLoop, SamplerInfo};
+pub use samples::{Sample, Samples};
+#[cfg(feature = "sbc")]
+pub use sbc::{SbcAllocation, SbcChannelMode, SbcConfig, SbcEncoder};
+#[cfg(feature = "write")]
+pub use sd_card::SdPlayStats;
+pub use sd_card::{SdAudioFile, SdCard, SdIndex};
+pub use self_test::{self_test, Loopback, SelfTestReport};
+pub use sf2::{
+    Generator, InstrumentHeader, PresetHeader, SampleHeader, SoundFont, Zone, MAX_ZONE_GENERATORS,
+    SF2_NAME_LEN,
+};
+pub use sfx::{ClipId, SfxBank, UNITY_PITCH};
+pub use sink::{AudioSink, SliceSink};
+pub use source::{
+    AudioSource, BufferedSource, ByteStream, EitherError, EitherSource, HybridSource, SliceSource,
+    StreamError, StreamSource, CHUNK_LEN,
+};
+pub use sync::{Clock, OpenTiming, SyncStart};
+pub use timestamp::{Stamped, Timestamp};
+pub use tone::ToneGenerator;
+pub use trigger::{Trigger, Triggers};
+pub use vad::{VoiceLog, VoiceSegment};
+pub use volumes::{Library, TrackId, MAX_VOLUME_TRACKS};
+pub use wav::{decode_block, parse_header_bytes, Data, DataBulk, Header, Wav, MAX_CHUNKS};
+#[cfg(feature = "write")]
+pub use writer::{DualWriter, WavWriter};
+
+/// True if the crate was built without the `write` feature.
+///
+/// Such a build has no writer, no retagging or in place repairs, and card files don't implement
+/// [`AudioSink`], so nothing in it can modify the card. Products that must never do so can have
+/// the compiler check it: `const _: () = assert!(audio_parser::READ_ONLY);`
+pub const READ_ONLY: bool = cfg!(not(feature = "write"));
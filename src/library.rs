@@ -0,0 +1,506 @@
+use crate::error::Error;
+use crate::index::{Index, IndexEntry};
+use crate::source::AudioSource;
+use core::cmp::Ordering;
+use heapless::Vec;
+
+/// Field of an [`IndexEntry`] a library is sorted or filtered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// File name
+    Name,
+    /// Title of the track
+    Title,
+    /// Artist of the track
+    Artist,
+    /// Album of the track
+    Album,
+    /// Genre of the track
+    Genre,
+    /// Year or date the track was recorded
+    Date,
+}
+
+impl SortKey {
+    fn value(self, entry: &IndexEntry) -> &str {
+        match self {
+            SortKey::Name => &entry.name,
+            SortKey::Title => &entry.title,
+            SortKey::Artist => &entry.artist,
+            SortKey::Album => &entry.album,
+            SortKey::Genre => &entry.genre,
+            SortKey::Date => &entry.date,
+        }
+    }
+
+    fn slot(self) -> usize {
+        self as usize
+    }
+}
+
+/// Order and selection of the entries of an [`Index`] to browse, e.g. the albums of one artist
+///
+/// Values are compared ignoring ASCII case, entries with equal values keep their order in the
+/// index. Missing tags compare as empty and sort first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query<'q> {
+    sort: SortKey,
+    filters: [Option<&'q str>; 6],
+}
+
+impl<'q> Query<'q> {
+    /// All entries sorted by `sort`
+    pub fn new(sort: SortKey) -> Self {
+        Query {
+            sort,
+            filters: [None; 6],
+        }
+    }
+
+    /// Only keep entries whose `key` equals `value`, replacing an earlier filter on `key`
+    pub fn filter(mut self, key: SortKey, value: &'q str) -> Self {
+        self.filters[key.slot()] = Some(value);
+        self
+    }
+
+    /// True if `entry` passes every filter
+    pub fn matches(&self, entry: &IndexEntry) -> bool {
+        let keys = [
+            SortKey::Name,
+            SortKey::Title,
+            SortKey::Artist,
+            SortKey::Album,
+            SortKey::Genre,
+            SortKey::Date,
+        ];
+
+        keys.iter().all(|key| match self.filters[key.slot()] {
+            Some(value) => key.value(entry).eq_ignore_ascii_case(value),
+            None => true,
+        })
+    }
+
+    /// Order of two entries found at keys `a.0` and `b.0`
+    fn compare<K: Ord>(&self, a: (K, &IndexEntry), b: (K, &IndexEntry)) -> Ordering {
+        let lowercase = |entry| {
+            self.sort
+                .value(entry)
+                .bytes()
+                .map(|b| b.to_ascii_lowercase())
+        };
+
+        lowercase(a.1).cmp(lowercase(b.1)).then(a.0.cmp(&b.0))
+    }
+}
+
+/// How the text of a [`Search`] is matched against titles and artists, ignoring ASCII case
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// The title or artist starts with the text
+    Prefix,
+    /// The text appears anywhere in the title or artist
+    Substring,
+}
+
+impl SearchMode {
+    fn matches(self, value: &str, text: &str) -> bool {
+        let (value, text) = (value.as_bytes(), text.as_bytes());
+
+        match self {
+            SearchMode::Prefix => {
+                value.len() >= text.len() && value[..text.len()].eq_ignore_ascii_case(text)
+            }
+            SearchMode::Substring => {
+                text.is_empty()
+                    || value
+                        .windows(text.len())
+                        .any(|window| window.eq_ignore_ascii_case(text))
+            }
+        }
+    }
+}
+
+/// Entries browsed with a [`Query`] or a [`Search`], such as an [`Index`] or the indexes of
+/// several volumes in a [`Library`](crate::Library)
+pub trait Catalog {
+    /// Address of an entry, also ordering entries whose values are equal
+    type Key: Copy + Ord;
+    /// Error reading an entry
+    type Error;
+
+    /// Read the entry at `key`, `None` if there is none
+    fn entry(&mut self, key: Self::Key) -> Result<Option<IndexEntry>, Self::Error>;
+
+    /// Key of the entry following `key`, the first entry if `key` is `None`, so that every entry
+    /// is visited once
+    fn next_key(&mut self, key: Option<Self::Key>) -> Result<Option<Self::Key>, Self::Error>;
+}
+
+impl<S: AudioSource> Catalog for Index<S> {
+    type Key = u32;
+    type Error = Error<S::Error>;
+
+    fn entry(&mut self, key: u32) -> Result<Option<IndexEntry>, Self::Error> {
+        self.get(key)
+    }
+
+    fn next_key(&mut self, key: Option<u32>) -> Result<Option<u32>, Self::Error> {
+        let next = key.map_or(0, |key| key + 1);
+
+        match next < self.len() {
+            true => Ok(Some(next)),
+            false => Ok(None),
+        }
+    }
+}
+
+/// Iterator over the entries of a [`Catalog`] whose title or artist match a text, in the order
+/// of their keys, created by [`Index::search`] or [`Library::search`](crate::Library::search).
+///
+/// Stops after `limit` matches, the last entry, or after yielding the first error.
+pub struct Search<'c, 't, C: Catalog> {
+    catalog: &'c mut C,
+    text: &'t str,
+    mode: SearchMode,
+    key: Option<C::Key>,
+    left: usize,
+}
+
+impl<'c, 't, C: Catalog> Search<'c, 't, C> {
+    pub(crate) fn new(catalog: &'c mut C, text: &'t str, mode: SearchMode, limit: usize) -> Self {
+        Search {
+            catalog,
+            text,
+            mode,
+            key: None,
+            left: limit,
+        }
+    }
+}
+
+impl<'c, 't, C: Catalog> Iterator for Search<'c, 't, C> {
+    type Item = Result<(C::Key, IndexEntry), C::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.left > 0 {
+            let key = match self.catalog.next_key(self.key) {
+                Ok(Some(key)) => key,
+                Ok(None) => break,
+                Err(e) => {
+                    self.left = 0;
+                    return Some(Err(e));
+                }
+            };
+
+            self.key = Some(key);
+
+            let entry = match self.catalog.entry(key) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.left = 0;
+                    return Some(Err(e));
+                }
+            };
+
+            if self.mode.matches(&entry.title, self.text)
+                || self.mode.matches(&entry.artist, self.text)
+            {
+                self.left -= 1;
+                return Some(Ok((key, entry)));
+            }
+        }
+
+        self.left = 0;
+        None
+    }
+}
+
+/// Page of browsed entries with their key, the position in the index for an [`Index`], which
+/// serves as the cursor for the neighbouring pages
+pub type Page<const N: usize, K = u32> = Vec<(K, IndexEntry), N>;
+
+impl<S: AudioSource> Index<S> {
+    /// Number of entries passing the filters of `query`
+    pub fn count(&mut self, query: &Query) -> Result<u32, Error<S::Error>> {
+        count(self, query)
+    }
+
+    /// Up to `N` entries matching `query` that follow the entry at position `after` in its order,
+    /// the first page if `after` is `None`.
+    ///
+    /// Only one page is held in RAM, each call reads the whole index once.
+    pub fn page_after<const N: usize>(
+        &mut self,
+        query: &Query,
+        after: Option<u32>,
+    ) -> Result<Page<N>, Error<S::Error>> {
+        collect_page(self, query, after, Ordering::Greater)
+    }
+
+    /// Up to `N` entries matching `query` that precede the entry at position `before` in its
+    /// order, the last page if `before` is `None`.
+    ///
+    /// Only one page is held in RAM, each call reads the whole index once.
+    pub fn page_before<const N: usize>(
+        &mut self,
+        query: &Query,
+        before: Option<u32>,
+    ) -> Result<Page<N>, Error<S::Error>> {
+        collect_page(self, query, before, Ordering::Less)
+    }
+
+    /// Page number `page` of `N` entries matching `query`, counting from `0`.
+    ///
+    /// Reads the whole index once per page up to the requested one, stepping with
+    /// [`Index::page_after`] is cheaper when the pages are shown in order.
+    pub fn page<const N: usize>(
+        &mut self,
+        query: &Query,
+        page: u32,
+    ) -> Result<Page<N>, Error<S::Error>> {
+        nth_page(self, query, page)
+    }
+
+    /// Up to `limit` entries whose title or artist match `text` ignoring ASCII case, e.g. the
+    /// letters entered so far with a jog dial.
+    ///
+    /// Entries are read one at a time in index order, so no more than one is held in RAM.
+    pub fn search<'i, 't>(
+        &'i mut self,
+        text: &'t str,
+        mode: SearchMode,
+        limit: usize,
+    ) -> Search<'i, 't, Self> {
+        Search::new(self, text, mode, limit)
+    }
+}
+
+/// Number of entries of `catalog` passing the filters of `query`
+pub(crate) fn count<C: Catalog>(catalog: &mut C, query: &Query) -> Result<u32, C::Error> {
+    let mut count = 0;
+    let mut key = catalog.next_key(None)?;
+
+    while let Some(at) = key {
+        if let Some(entry) = catalog.entry(at)? {
+            count += query.matches(&entry) as u32;
+        }
+
+        key = catalog.next_key(Some(at))?;
+    }
+
+    Ok(count)
+}
+
+/// Page number `page` of `N` entries of `catalog` matching `query`
+pub(crate) fn nth_page<C: Catalog, const N: usize>(
+    catalog: &mut C,
+    query: &Query,
+    page: u32,
+) -> Result<Page<N, C::Key>, C::Error> {
+    let mut entries = collect_page(catalog, query, None, Ordering::Greater)?;
+
+    for _ in 0..page {
+        let last = match entries.last() {
+            Some((key, _)) if entries.is_full() => *key,
+            _ => return Ok(Vec::new()),
+        };
+
+        entries = collect_page(catalog, query, Some(last), Ordering::Greater)?;
+    }
+
+    Ok(entries)
+}
+
+/// Gather the `N` entries of `catalog` matching `query` closest to the entry at `anchor` on the
+/// `side` of it, sorted in the order of `query`
+pub(crate) fn collect_page<C: Catalog, const N: usize>(
+    catalog: &mut C,
+    query: &Query,
+    anchor: Option<C::Key>,
+    side: Ordering,
+) -> Result<Page<N, C::Key>, C::Error> {
+    let mut page: Page<N, C::Key> = Vec::new();
+
+    let anchor = match anchor {
+        Some(key) => match catalog.entry(key)? {
+            Some(entry) => Some((key, entry)),
+            None => return Ok(page),
+        },
+        None => None,
+    };
+
+    let mut next = catalog.next_key(None)?;
+
+    while let Some(key) = next {
+        next = catalog.next_key(Some(key))?;
+
+        let entry = match catalog.entry(key)? {
+            Some(entry) if query.matches(&entry) => entry,
+            _ => continue,
+        };
+
+        if let Some((anchor, anchor_entry)) = &anchor {
+            if query.compare((key, &entry), (*anchor, anchor_entry)) != side {
+                continue;
+            }
+        }
+
+        let at = page
+            .iter()
+            .position(|(other, other_entry)| {
+                query.compare((key, &entry), (*other, other_entry)) == Ordering::Less
+            })
+            .unwrap_or(page.len());
+
+        // following pages keep the smallest entries, preceding pages the largest
+        let at = match (page.is_full(), side) {
+            (false, _) => at,
+            (true, Ordering::Less) if at == 0 => continue,
+            (true, Ordering::Less) => {
+                page.remove(0);
+                at - 1
+            }
+            (true, _) if at == N => continue,
+            (true, _) => {
+                page.pop();
+                at
+            }
+        };
+
+        let _ = page.insert(at, (key, entry));
+    }
+
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_file::FileFormat;
+    use crate::index::IndexWriter;
+    use crate::metadata::to_string;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+
+    const TRACKS: [(&str, &str, &str, &str); 7] = [
+        ("A.WAV", "So What", "Miles Davis", "Kind of Blue"),
+        ("B.WAV", "Blue Train", "john coltrane", "Blue Train"),
+        (
+            "C.WAV",
+            "Miles Runs the Voodoo Down",
+            "Miles Davis",
+            "Bitches Brew",
+        ),
+        ("D.WAV", "", "", ""),
+        (
+            "E.WAV",
+            "Gloria's Step",
+            "Bill Evans",
+            "Sunday at the Village Vanguard",
+        ),
+        ("F.WAV", "Blue in Green", "miles davis", "Kind of Blue"),
+        (
+            "G.WAV",
+            "Acknowledgement",
+            "John Coltrane",
+            "A Love Supreme",
+        ),
+    ];
+
+    fn index(out: &mut [u8]) -> Index<SliceSource<'_>> {
+        let mut writer = IndexWriter::new(SliceSink::new(out)).unwrap();
+
+        for (name, title, artist, album) in TRACKS.iter() {
+            let entry = IndexEntry {
+                name: to_string(name.as_bytes()),
+                format: FileFormat::Wav,
+                num_channels: 2,
+                sample_rate: 48_000,
+                total_frames: 0,
+                title: to_string(title.as_bytes()),
+                artist: to_string(artist.as_bytes()),
+                album: to_string(album.as_bytes()),
+                genre: to_string(b"Jazz"),
+                date: to_string(b""),
+            };
+
+            writer.push(&entry).unwrap();
+        }
+
+        let len = writer.into_inner().written().len();
+        Index::new(SliceSource::new(&out[..len])).unwrap()
+    }
+
+    fn names<const N: usize>(page: &Page<N>) -> std::vec::Vec<&str> {
+        page.iter().map(|(_, entry)| entry.name()).collect()
+    }
+
+    #[test]
+    fn should_sort_stably_ignoring_case() {
+        let mut out = [0; 2048];
+        let mut index = index(&mut out);
+        let query = Query::new(SortKey::Artist);
+
+        let first = index.page_after::<3>(&query, None).unwrap();
+        assert_eq!(names(&first), ["D.WAV", "E.WAV", "B.WAV"]);
+
+        let second = index.page_after::<3>(&query, Some(first[2].0)).unwrap();
+        assert_eq!(names(&second), ["G.WAV", "A.WAV", "C.WAV"]);
+
+        let third = index.page::<3>(&query, 2).unwrap();
+        assert_eq!(names(&third), ["F.WAV"]);
+        assert!(index.page::<3>(&query, 3).unwrap().is_empty());
+
+        let back = index.page_before::<3>(&query, Some(second[0].0)).unwrap();
+        assert_eq!(back, first);
+
+        let last = index.page_before::<2>(&query, None).unwrap();
+        assert_eq!(names(&last), ["C.WAV", "F.WAV"]);
+    }
+
+    #[test]
+    fn should_filter_by_several_keys() {
+        let mut out = [0; 2048];
+        let mut index = index(&mut out);
+
+        let query = Query::new(SortKey::Album).filter(SortKey::Artist, "MILES DAVIS");
+        assert_eq!(index.count(&query).unwrap(), 3);
+
+        let page = index.page_after::<8>(&query, None).unwrap();
+        assert_eq!(names(&page), ["C.WAV", "A.WAV", "F.WAV"]);
+
+        let query = query.filter(SortKey::Album, "kind of blue");
+        assert_eq!(index.count(&query).unwrap(), 2);
+
+        let query = query.filter(SortKey::Genre, "Rock");
+        assert!(index.page_after::<8>(&query, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_search_titles_and_artists() {
+        let mut out = [0; 2048];
+        let mut index = index(&mut out);
+
+        let search = |index: &mut Index<_>, text, mode, limit| {
+            index
+                .search(text, mode, limit)
+                .map(|found| found.unwrap().1.name)
+                .collect::<std::vec::Vec<_>>()
+        };
+
+        let found = search(&mut index, "MILES", SearchMode::Prefix, 8);
+        assert_eq!(found, ["A.WAV", "C.WAV", "F.WAV"]);
+
+        let found = search(&mut index, "blue", SearchMode::Prefix, 8);
+        assert_eq!(found, ["B.WAV", "F.WAV"]);
+
+        let found = search(&mut index, "Blue", SearchMode::Substring, 8);
+        assert_eq!(found, ["B.WAV", "F.WAV"]);
+
+        let found = search(&mut index, "o", SearchMode::Substring, 3);
+        assert_eq!(found, ["A.WAV", "B.WAV", "C.WAV"]);
+
+        assert!(search(&mut index, "vanguard", SearchMode::Substring, 8).is_empty());
+    }
+}
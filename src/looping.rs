@@ -0,0 +1,157 @@
+use crate::error::Error;
+use crate::fixed::UNITY_GAIN;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+
+/// Blend `head` into `tail` frame by frame, `seam` is the index of the first frame within a
+/// crossfade of `fade` frames
+fn crossfade<const NUM: usize>(
+    tail: &mut DataBulk<NUM>,
+    head: &DataBulk<NUM>,
+    channels: usize,
+    seam: usize,
+    fade: usize,
+) {
+    let unity = UNITY_GAIN as i64;
+
+    // the head gain rises from just above zero to just below unity, so neither side is skipped
+    let weight = |index: usize| ((seam + index / channels + 1) as i64 * unity) / (fade as i64 + 1);
+    let blend = |index: usize, t: i64, h: i64| {
+        let w = weight(index);
+        (t * (unity - w) + h * w) / unity
+    };
+
+    match (tail, head) {
+        (DataBulk::BitDepth8(t), DataBulk::BitDepth8(h)) => {
+            for (i, (t, h)) in t.iter_mut().zip(h).enumerate() {
+                *t = (blend(i, *t as i64 - 128, *h as i64 - 128) + 128) as u8;
+            }
+        }
+        (DataBulk::BitDepth16(t), DataBulk::BitDepth16(h)) => {
+            for (i, (t, h)) in t.iter_mut().zip(h).enumerate() {
+                *t = blend(i, *t as i64, *h as i64) as i16;
+            }
+        }
+        (DataBulk::BitDepth24(t), DataBulk::BitDepth24(h)) => {
+            for (i, (t, h)) in t.iter_mut().zip(h).enumerate() {
+                *t = blend(i, *t as i64, *h as i64) as i32;
+            }
+        }
+        (DataBulk::Float32(t), DataBulk::Float32(h)) => {
+            for (i, (t, h)) in t.iter_mut().zip(h).enumerate() {
+                let w = weight(i) as f32 / unity as f32;
+                *t = *t * (1.0 - w) + *h * w;
+            }
+        }
+        _ => {}
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Same as [`Wav::next_n`], playing the file as an endless loop with a crossfaded seam.
+    ///
+    /// The last `fade_millis` of the file are blended into its first `fade_millis`, so a loop that
+    /// wasn't cut on a perfect boundary doesn't click every cycle. After the first cycle playback
+    /// continues right after the faded-in start. Buffers come back shorter when they reach the
+    /// start or the end of the seam, the fade is capped at half the file.
+    pub fn next_n_looped<const NUM: usize>(
+        &mut self,
+        fade_millis: u32,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        let block_align = self.fmt.block_align().max(1);
+        let channels = (self.fmt.num_channels as usize).max(1);
+
+        let data_end = self.data_end();
+        let total = data_end.saturating_sub(self.data.start) / block_align;
+        let fade = (self.fmt.sample_rate as u64 * fade_millis as u64 / 1000) as usize;
+        let fade = fade.min(total / 2);
+
+        if total == 0 {
+            return Err(Error::EndOfData);
+        }
+
+        let mut frame = self.data_offset() / block_align;
+
+        if frame >= total {
+            self.seek_data(fade * block_align)?;
+            frame = fade;
+        }
+
+        let seam_start = total - fade;
+
+        if frame < seam_start {
+            return self.next_frames(seam_start - frame);
+        }
+
+        let seam = frame - seam_start;
+        let mut tail = self.next_frames(total - frame)?;
+        let after = self.data_offset();
+
+        self.seek_data(seam * block_align)?;
+        let head = self.next_frames(tail.len() / channels)?;
+        self.seek_data(after)?;
+
+        crossfade(&mut tail, &head, channels, seam, fade);
+
+        Ok(tail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+
+    const WAV: [u8; 60] = [
+        0x52, 0x49, 0x46, 0x46, // RIFF
+        0x34, 0x00, 0x00, 0x00, // chunk size
+        0x57, 0x41, 0x56, 0x45, // WAVE
+        0x66, 0x6d, 0x74, 0x20, // fmt_
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, // audio format
+        0x01, 0x00, // num channels
+        0xe8, 0x03, 0x00, 0x00, // sample rate
+        0xd0, 0x07, 0x00, 0x00, // byte rate
+        0x02, 0x00, // block align
+        0x10, 0x00, // bits per sample
+        0x64, 0x61, 0x74, 0x61, // data
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x00, 0x00, 0xe8, 0x03, // samples 0, 1000
+        0xd0, 0x07, 0xb8, 0x0b, // samples 2000, 3000
+        0xa0, 0x0f, 0x88, 0x13, // samples 4000, 5000
+        0x70, 0x17, 0x58, 0x1b, // samples 6000, 7000
+    ];
+
+    fn samples(bulk: DataBulk<16>) -> heapless::Vec<i16, 16> {
+        match bulk {
+            DataBulk::BitDepth16(samples) => samples,
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_crossfade_loop_seam() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+
+        assert_eq!(
+            samples(wav.next_n_looped(2).unwrap()),
+            [0, 1000, 2000, 3000, 4000, 5000]
+        );
+        assert_eq!(samples(wav.next_n_looped(2).unwrap()), [4000, 3000]);
+
+        // the faded in start isn't played again
+        assert_eq!(
+            samples(wav.next_n_looped(2).unwrap()),
+            [2000, 3000, 4000, 5000]
+        );
+        assert_eq!(samples(wav.next_n_looped(2).unwrap()), [4000, 3000]);
+    }
+
+    #[test]
+    fn should_loop_without_fade() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+
+        assert_eq!(samples(wav.next_n_looped(0).unwrap()).len(), 8);
+        assert_eq!(samples(wav.next_n_looped(0).unwrap())[..2], [0, 1000]);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::error::Error;
+use crate::fixed::{saturate_i16, UNITY_GAIN};
+use crate::simd::dual_mac;
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+use heapless::Vec;
+
+/// Mixing matrix mapping `IN` input channels onto `OUT` output channels.
+///
+/// Output channel `o` is the sum of every input channel `i` scaled by `coefficients[o][i]`.
+/// Coefficients are Q15 fixed point like gains, [`UNITY_GAIN`] passes a channel unchanged and
+/// negative values invert it, so downmixing, upmixing and channel selection are all one matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMatrix<const IN: usize, const OUT: usize> {
+    /// Q15 coefficient of every input channel, for every output channel
+    pub coefficients: [[i32; IN]; OUT],
+}
+
+impl<const IN: usize, const OUT: usize> ChannelMatrix<IN, OUT> {
+    /// Create a matrix from Q15 coefficients, indexed as `[output][input]`
+    pub const fn new(coefficients: [[i32; IN]; OUT]) -> Self {
+        ChannelMatrix { coefficients }
+    }
+
+    /// Matrix routing output channel `o` from input channel `map[o]` at unity gain
+    pub fn select(map: [usize; OUT]) -> Self {
+        let mut coefficients = [[0; IN]; OUT];
+
+        for (row, input) in coefficients.iter_mut().zip(map) {
+            if let Some(coefficient) = row.get_mut(input) {
+                *coefficient = UNITY_GAIN as i32;
+            }
+        }
+
+        ChannelMatrix { coefficients }
+    }
+
+    /// Mix the interleaved frames of `input` into a new buffer of `OUT` channel frames.
+    ///
+    /// Results saturate at the limits of the bit depth, frames that don't fit in `NUM` are dropped.
+    pub fn apply<const NUM: usize>(&self, input: &DataBulk<NUM>) -> DataBulk<NUM> {
+        fn map<T: Copy, const IN: usize, const OUT: usize, const NUM: usize>(
+            coefficients: &[[i32; IN]; OUT],
+            input: &Vec<T, NUM>,
+            mix: impl Fn(&[T], &[i32; IN]) -> T,
+        ) -> Vec<T, NUM> {
+            let mut output = Vec::new();
+
+            if IN == 0 {
+                return output;
+            }
+
+            for frame in input.chunks_exact(IN).take(NUM / OUT.max(1)) {
+                for row in coefficients {
+                    // can't fail, the number of frames is bounded by the capacity
+                    let _ = output.push(mix(frame, row));
+                }
+            }
+
+            output
+        }
+
+        fn sum(samples: impl Iterator<Item = i64>, row: &[i32], min: i64, max: i64) -> i64 {
+            let mixed: i64 = samples.zip(row).map(|(s, c)| s * *c as i64).sum();
+            (mixed >> 15).clamp(min, max)
+        }
+
+        let c = &self.coefficients;
+
+        match input {
+            DataBulk::BitDepth8(samples) => DataBulk::BitDepth8(map(c, samples, |f, row| {
+                let centered = f.iter().map(|s| *s as i64 - 128);
+                (sum(centered, row, -128, 127) + 128) as u8
+            })),
+            DataBulk::BitDepth16(samples) => DataBulk::BitDepth16(map(c, samples, |f, row| {
+                // stereo rows below unity fit one dual multiply-accumulate without overflow
+                if let (&[left, right], &[l, r]) = (f, &row[..]) {
+                    let fits = |c: i32| (-(i16::MAX as i32)..=i16::MAX as i32).contains(&c);
+
+                    if fits(l) && fits(r) {
+                        return saturate_i16(
+                            dual_mac([left, right], [l as i16, r as i16], 0) >> 15,
+                        );
+                    }
+                }
+
+                let samples = f.iter().map(|s| *s as i64);
+                sum(samples, row, i16::MIN as i64, i16::MAX as i64) as i16
+            })),
+            DataBulk::BitDepth24(samples) => DataBulk::BitDepth24(map(c, samples, |f, row| {
+                let samples = f.iter().map(|s| *s as i64);
+                sum(samples, row, -(1 << 23), (1 << 23) - 1) as i32
+            })),
+            DataBulk::Float32(samples) => DataBulk::Float32(map(c, samples, |f, row| {
+                f.iter()
+                    .zip(row)
+                    .map(|(s, c)| s * (*c as f32 / UNITY_GAIN as f32))
+                    .sum()
+            })),
+        }
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Same as [`Wav::next_n`], passing the frames through `matrix` to change the channel layout.
+    ///
+    /// Only as many frames are read as fit in `NUM` after mixing, so nothing is dropped.
+    pub fn next_n_mapped<const NUM: usize, const IN: usize, const OUT: usize>(
+        &mut self,
+        matrix: &ChannelMatrix<IN, OUT>,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        if self.fmt.num_channels as usize != IN {
+            return Err(Error::FormatMismatch);
+        }
+
+        let bulk = self.next_frames::<NUM>(NUM / OUT.max(1))?;
+
+        Ok(matrix.apply(&bulk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF: i32 = UNITY_GAIN as i32 / 2;
+
+    #[test]
+    fn should_downmix_stereo_to_mono() {
+        let matrix = ChannelMatrix::new([[HALF, HALF]]);
+        let input =
+            DataBulk::<8>::BitDepth16(Vec::from_slice(&[100, 300, i16::MAX, i16::MAX]).unwrap());
+
+        assert_eq!(
+            matrix.apply(&input),
+            DataBulk::BitDepth16(Vec::from_slice(&[200, i16::MAX]).unwrap())
+        );
+    }
+
+    #[test]
+    fn should_upmix_and_saturate() {
+        let unity = UNITY_GAIN as i32;
+        let matrix = ChannelMatrix::new([[unity], [-unity], [2 * unity]]);
+        let input = DataBulk::<6>::BitDepth8(Vec::from_slice(&[138, 255, 0]).unwrap());
+
+        // only two frames fit after upmixing to three channels
+        assert_eq!(
+            matrix.apply(&input),
+            DataBulk::BitDepth8(Vec::from_slice(&[138, 118, 148, 255, 1, 255]).unwrap())
+        );
+    }
+
+    #[test]
+    fn should_select_channels() {
+        let matrix: ChannelMatrix<3, 2> = ChannelMatrix::select([2, 0]);
+        let input = DataBulk::<6>::BitDepth24(Vec::from_slice(&[1, 2, 3, 4, 5, 6]).unwrap());
+
+        assert_eq!(
+            matrix.apply(&input),
+            DataBulk::BitDepth24(Vec::from_slice(&[3, 1, 6, 4]).unwrap())
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use heapless::{String, Vec};
+
+/// List type of the `LIST` chunk holding metadata
+pub(crate) const INFO: [u8; 4] = [b'I', b'N', b'F', b'O'];
+/// Tags with a named field in [`Metadata`]
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
+const NAMED_TAGS: [ListChunkTag; 6] = [
+    ListChunkTag::Artist,
+    ListChunkTag::Title,
+    ListChunkTag::Product,
+    ListChunkTag::Genre,
+    ListChunkTag::Keywords,
+    ListChunkTag::CreationDate,
+];
+/// Number of tags without a named field kept by [`Metadata`], further ones are dropped
+pub const MAX_OTHER_TAGS: usize = 8;
+
+/// Sub chunk tags of a `LIST` `INFO` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListChunkTag {
+    /// `IART`, artist of the original subject
+    Artist,
+    /// `INAM`, title of the subject
+    Title,
+    /// `IPRD`, product the file was made for, commonly the album
+    Product,
+    /// `IGNR`, genre
+    Genre,
+    /// `IKEY`, keywords
+    Keywords,
+    /// `ICRD`, creation date
+    CreationDate,
+    /// Any other tag, e.g. `ITRK` or `ILOC`, listed by [`Metadata::other_tags`]
+    Unknown([u8; 4]),
+}
+
+impl ListChunkTag {
+    pub(crate) fn from_bytes(bytes: &[u8; 4]) -> Self {
+        match bytes {
+            [b'I', b'A', b'R', b'T'] => ListChunkTag::Artist,
+            [b'I', b'N', b'A', b'M'] => ListChunkTag::Title,
+            [b'I', b'P', b'R', b'D'] => ListChunkTag::Product,
+            [b'I', b'G', b'N', b'R'] => ListChunkTag::Genre,
+            [b'I', b'K', b'E', b'Y'] => ListChunkTag::Keywords,
+            [b'I', b'C', b'R', b'D'] => ListChunkTag::CreationDate,
+            _ => ListChunkTag::Unknown(*bytes),
+        }
+    }
+
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    pub(crate) fn to_bytes(self) -> [u8; 4] {
+        match self {
+            ListChunkTag::Artist => *b"IART",
+            ListChunkTag::Title => *b"INAM",
+            ListChunkTag::Product => *b"IPRD",
+            ListChunkTag::Genre => *b"IGNR",
+            ListChunkTag::Keywords => *b"IKEY",
+            ListChunkTag::CreationDate => *b"ICRD",
+            ListChunkTag::Unknown(bytes) => bytes,
+        }
+    }
+}
+
+/// Metadata from the `LIST` `INFO` chunk of a WAV file or an ID3v2 tag
+///
+/// Each value holds at most `MAX_STRING_LEN` bytes, longer values are cut short. Up to
+/// [`MAX_OTHER_TAGS`] tags without a named field, such as `ITRK`, are kept as well.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata<const MAX_STRING_LEN: usize> {
+    artist: Option<String<MAX_STRING_LEN>>,
+    title: Option<String<MAX_STRING_LEN>>,
+    product: Option<String<MAX_STRING_LEN>>,
+    genre: Option<String<MAX_STRING_LEN>>,
+    keywords: Option<String<MAX_STRING_LEN>>,
+    creation_date: Option<String<MAX_STRING_LEN>>,
+    others: Vec<([u8; 4], String<MAX_STRING_LEN>), MAX_OTHER_TAGS>,
+}
+
+impl<const MAX_STRING_LEN: usize> Metadata<MAX_STRING_LEN> {
+    /// Artist of the original subject
+    pub fn artist(&self) -> Option<&str> {
+        self.artist.as_deref()
+    }
+
+    /// Title of the subject
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Product the file was made for, commonly the album
+    pub fn product(&self) -> Option<&str> {
+        self.product.as_deref()
+    }
+
+    /// Genre
+    pub fn genre(&self) -> Option<&str> {
+        self.genre.as_deref()
+    }
+
+    /// Keywords
+    pub fn keywords(&self) -> Option<&str> {
+        self.keywords.as_deref()
+    }
+
+    /// Creation date, commonly formatted as `YYYY-MM-DD`
+    pub fn creation_date(&self) -> Option<&str> {
+        self.creation_date.as_deref()
+    }
+
+    /// Value of `tag`, whether it has a named field or not
+    pub fn get(&self, tag: ListChunkTag) -> Option<&str> {
+        match tag {
+            ListChunkTag::Artist => self.artist(),
+            ListChunkTag::Title => self.title(),
+            ListChunkTag::Product => self.product(),
+            ListChunkTag::Genre => self.genre(),
+            ListChunkTag::Keywords => self.keywords(),
+            ListChunkTag::CreationDate => self.creation_date(),
+            ListChunkTag::Unknown(id) => self
+                .others
+                .iter()
+                .find(|(other, _)| *other == id)
+                .map(|(_, value)| value.as_str()),
+        }
+    }
+
+    /// Tags without a named field in the order they were found, e.g. the track number of `ITRK`
+    pub fn other_tags(&self) -> impl Iterator<Item = (ListChunkTag, &str)> {
+        self.others
+            .iter()
+            .map(|(id, value)| (ListChunkTag::Unknown(*id), value.as_str()))
+    }
+
+    /// Set the value of `tag`, cut short to `MAX_STRING_LEN` bytes.
+    ///
+    /// Returns `false` if `tag` has no named field and [`MAX_OTHER_TAGS`] others are kept already.
+    pub fn set_tag(&mut self, tag: ListChunkTag, value: &str) -> bool {
+        let value = to_string(value.as_bytes());
+
+        match (tag, self.field_mut(tag)) {
+            (_, Some(field)) => *field = Some(value),
+            (ListChunkTag::Unknown(id), None) => {
+                match self.others.iter_mut().find(|(other, _)| *other == id) {
+                    Some((_, other)) => *other = value,
+                    None => return self.others.push((id, value)).is_ok(),
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Remove the value of `tag`
+    pub fn remove_tag(&mut self, tag: ListChunkTag) {
+        match (tag, self.field_mut(tag)) {
+            (_, Some(field)) => *field = None,
+            (ListChunkTag::Unknown(id), None) => self.others.retain(|(other, _)| *other != id),
+            _ => {}
+        }
+    }
+
+    /// Every tag that has a value, the named ones first
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    pub(crate) fn tags(&self) -> impl Iterator<Item = (ListChunkTag, &str)> {
+        NAMED_TAGS
+            .iter()
+            .filter_map(move |tag| Some((*tag, self.get(*tag)?)))
+            .chain(self.other_tags())
+    }
+
+    /// Store the raw value of a sub chunk, trailing NUL bytes are dropped
+    pub(crate) fn set(&mut self, tag: ListChunkTag, bytes: &[u8]) {
+        match (tag, self.field_mut(tag)) {
+            (_, Some(field)) => *field = Some(to_string(bytes)),
+            (ListChunkTag::Unknown(id), None) => {
+                let _ = self.others.push((id, to_string(bytes)));
+            }
+            _ => {}
+        }
+    }
+
+    /// Value stored for `tag`, `None` for unknown tags
+    pub(crate) fn field_mut(
+        &mut self,
+        tag: ListChunkTag,
+    ) -> Option<&mut Option<String<MAX_STRING_LEN>>> {
+        match tag {
+            ListChunkTag::Artist => Some(&mut self.artist),
+            ListChunkTag::Title => Some(&mut self.title),
+            ListChunkTag::Product => Some(&mut self.product),
+            ListChunkTag::Genre => Some(&mut self.genre),
+            ListChunkTag::Keywords => Some(&mut self.keywords),
+            ListChunkTag::CreationDate => Some(&mut self.creation_date),
+            ListChunkTag::Unknown(_) => None,
+        }
+    }
+}
+
+/// Convert a NUL terminated value to a string, keeping the valid UTF-8 prefix of the bytes
+pub(crate) fn to_string<const N: usize>(bytes: &[u8]) -> String<N> {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+
+    let valid = match core::str::from_utf8(bytes) {
+        Ok(valid) => valid,
+        Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    };
+
+    let mut string = String::new();
+
+    for c in valid.chars() {
+        if string.push(c).is_err() {
+            break;
+        }
+    }
+
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_strip_nul_and_truncate() {
+        let string: String<4> = to_string(b"Abbey Road\0");
+        assert_eq!(string, "Abbe");
+
+        let string: String<16> = to_string(b"Help\0\0");
+        assert_eq!(string, "Help");
+    }
+
+    #[test]
+    fn should_keep_valid_utf8_prefix() {
+        let string: String<16> = to_string(&[b'o', b'k', 0xff, b'x']);
+        assert_eq!(string, "ok");
+
+        // never split a multi byte character
+        let string: String<2> = to_string("aé".as_bytes());
+        assert_eq!(string, "a");
+    }
+
+    #[test]
+    fn should_list_unknown_tags_apart() {
+        let tag = ListChunkTag::from_bytes(b"ILOC");
+        assert_eq!(tag, ListChunkTag::Unknown(*b"ILOC"));
+
+        let mut metadata: Metadata<8> = Metadata::default();
+        metadata.set(tag, b"Studio\0");
+        metadata.set(ListChunkTag::Title, b"Help");
+
+        assert_eq!(metadata.title(), Some("Help"));
+        assert_eq!(metadata.get(ListChunkTag::Title), Some("Help"));
+        assert_eq!(metadata.get(tag), Some("Studio"));
+        assert_eq!(metadata.get(ListChunkTag::Unknown(*b"ITRK")), None);
+
+        let others: std::vec::Vec<_> = metadata.other_tags().collect();
+        assert_eq!(others, [(tag, "Studio")]);
+
+        for _ in 0..MAX_OTHER_TAGS {
+            metadata.set(ListChunkTag::Unknown(*b"ICMT"), b"x");
+        }
+
+        assert_eq!(metadata.other_tags().count(), MAX_OTHER_TAGS);
+    }
+
+    #[test]
+    fn should_set_and_remove_tags() {
+        let mut metadata: Metadata<8> = Metadata::default();
+        let track = ListChunkTag::Unknown(*b"ITRK");
+
+        assert!(metadata.set_tag(ListChunkTag::Title, "Take 12 final"));
+        assert!(metadata.set_tag(track, "1"));
+        assert!(metadata.set_tag(track, "2"));
+
+        assert_eq!(metadata.title(), Some("Take 12 "));
+        assert_eq!(metadata.get(track), Some("2"));
+        assert_eq!(metadata.other_tags().count(), 1);
+
+        metadata.remove_tag(track);
+        metadata.remove_tag(ListChunkTag::Title);
+        assert_eq!(metadata, Metadata::default());
+    }
+}
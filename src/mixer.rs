@@ -0,0 +1,129 @@
+use crate::fixed::{apply_gain, mix_i16, scale_q15, UNITY_GAIN};
+
+/// Add `input` scaled by the Q1.15 `gain` onto `output`, saturating at the 16 bit limits
+pub fn mix_into(output: &mut [i16], input: &[i16], gain: u16) {
+    for (out, sample) in output.iter_mut().zip(input) {
+        *out = mix_i16(*out, apply_gain(*sample, gain));
+    }
+}
+
+/// How far and how fast the music source is ducked while a priority source plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ducking {
+    /// Q1.15 gain applied to the music source while fully ducked
+    pub level: u16,
+    /// Frames it takes to go from unity gain down to `level`
+    pub attack_frames: u32,
+    /// Frames it takes to go from `level` back up to unity gain
+    pub release_frames: u32,
+}
+
+/// Mixes a music source with an optional priority source, e.g. a navigation prompt.
+///
+/// While the priority source plays the music is smoothly ducked, once it stops the
+/// music smoothly returns to unity gain.
+#[derive(Debug, Clone)]
+pub struct PriorityMixer {
+    ducking: Ducking,
+    /// Current music gain in Q1.31, the extra precision keeps long ramps smooth
+    gain: u32,
+}
+
+impl PriorityMixer {
+    /// Create a mixer with the music source at unity gain
+    pub fn new(ducking: Ducking) -> Self {
+        PriorityMixer {
+            ducking,
+            gain: (UNITY_GAIN as u32) << 16,
+        }
+    }
+
+    /// Current Q1.15 gain of the music source
+    pub fn music_gain(&self) -> u16 {
+        (self.gain >> 16) as u16
+    }
+
+    /// Mix interleaved `music` and `priority` samples into `output`.
+    ///
+    /// Pass `None` as priority when the priority source is silent or finished. Both inputs are
+    /// expected to have the same channel count, `output` is overwritten.
+    pub fn mix(
+        &mut self,
+        output: &mut [i16],
+        music: &[i16],
+        priority: Option<&[i16]>,
+        num_channels: u16,
+    ) {
+        let channels = (num_channels as usize).max(1);
+        let level = (self.ducking.level.min(UNITY_GAIN) as u32) << 16;
+        let unity = (UNITY_GAIN as u32) << 16;
+        let range = unity - level;
+
+        let (target, step) = match priority {
+            Some(_) => (level, range / self.ducking.attack_frames.max(1)),
+            None => (unity, range / self.ducking.release_frames.max(1)),
+        };
+
+        for (out, frame) in output.chunks_mut(channels).zip(music.chunks(channels)) {
+            self.gain = if self.gain > target {
+                self.gain.saturating_sub(step.max(1)).max(target)
+            } else {
+                self.gain.saturating_add(step.max(1)).min(target)
+            };
+
+            let gain = self.music_gain();
+
+            for (o, sample) in out.iter_mut().zip(frame) {
+                *o = scale_q15(*sample, gain);
+            }
+        }
+
+        if let Some(priority) = priority {
+            mix_into(output, priority, UNITY_GAIN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUCKING: Ducking = Ducking {
+        level: UNITY_GAIN / 4,
+        attack_frames: 4,
+        release_frames: 8,
+    };
+
+    #[test]
+    fn should_saturate_when_mixing() {
+        let mut output = [i16::MAX - 10, i16::MIN + 10, 100];
+        mix_into(&mut output, &[100, -100, 100], UNITY_GAIN);
+
+        assert_eq!(output, [i16::MAX, i16::MIN, 200]);
+    }
+
+    #[test]
+    fn should_duck_and_release_music() {
+        let mut mixer = PriorityMixer::new(DUCKING);
+        let music = [1000; 8];
+        let mut output = [0; 8];
+
+        mixer.mix(&mut output, &music, Some(&[0; 8]), 2);
+        assert_eq!(mixer.music_gain(), UNITY_GAIN / 4);
+        assert_eq!(output[6..], [250, 250]);
+
+        mixer.mix(&mut output, &music, None, 1);
+        assert_eq!(mixer.music_gain(), UNITY_GAIN);
+        assert_eq!(output[7], 1000);
+    }
+
+    #[test]
+    fn should_add_priority_source() {
+        let mut mixer = PriorityMixer::new(DUCKING);
+        let mut output = [0; 2];
+
+        mixer.mix(&mut output, &[0, 0], Some(&[500, -500]), 2);
+
+        assert_eq!(output, [500, -500]);
+    }
+}
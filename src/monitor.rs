@@ -0,0 +1,121 @@
+use crate::error::Error;
+use crate::fixed::{scale_q15, UNITY_GAIN};
+
+/// Headphone monitoring branch of the record path, delaying and scaling the incoming samples
+/// before they go to the playback output
+///
+/// The delay line holds `N` samples, so the latency can be set up to `(N - 1) / num_channels`
+/// frames, e.g. to line the monitor up with a delayed playback path.
+#[derive(Debug, Clone)]
+pub struct Monitor<const N: usize> {
+    delay: [i16; N],
+    index: usize,
+    latency: usize,
+    num_channels: u16,
+    gain: u16,
+}
+
+impl<const N: usize> Monitor<N> {
+    /// Create a monitor for interleaved samples with `num_channels` channels, at unity gain and
+    /// without added latency
+    pub fn new(num_channels: u16) -> Self {
+        Monitor {
+            delay: [0; N],
+            index: 0,
+            latency: 0,
+            num_channels: num_channels.max(1),
+            gain: UNITY_GAIN,
+        }
+    }
+
+    /// Q1.15 gain applied to the monitored samples
+    pub fn gain(&self) -> u16 {
+        self.gain
+    }
+
+    /// Set the Q1.15 gain of the monitored samples, `0` mutes the monitor
+    pub fn set_gain(&mut self, gain: u16) {
+        self.gain = gain;
+    }
+
+    /// Latency added to the monitored samples in frames
+    pub fn latency_frames(&self) -> usize {
+        self.latency / self.num_channels as usize
+    }
+
+    /// Delay the monitored samples by `frames` frames, returns [`Error::BufferTooSmall`] holding
+    /// the needed capacity if the delay line is too short
+    pub fn set_latency_frames(&mut self, frames: usize) -> Result<(), Error> {
+        let latency = frames * self.num_channels as usize;
+
+        if latency >= N.max(1) {
+            return Err(Error::BufferTooSmall(latency + 1));
+        }
+
+        self.latency = latency;
+
+        Ok(())
+    }
+
+    /// Pass interleaved `input` through the monitor into `output`, which has to be as long
+    pub fn process(&mut self, input: &[i16], output: &mut [i16]) -> Result<(), Error> {
+        if output.len() < input.len() {
+            return Err(Error::BufferTooSmall(input.len()));
+        }
+
+        for (out, &sample) in output.iter_mut().zip(input) {
+            *out = self.process_sample(sample);
+        }
+
+        Ok(())
+    }
+
+    /// Push one sample into the delay line and return the delayed, scaled sample
+    pub(crate) fn process_sample(&mut self, sample: i16) -> i16 {
+        let delayed = if N == 0 {
+            sample
+        } else {
+            self.delay[self.index] = sample;
+            let delayed = self.delay[(self.index + N - self.latency) % N];
+            self.index = (self.index + 1) % N;
+            delayed
+        };
+
+        scale_q15(delayed, self.gain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_delay_and_scale_samples() {
+        let mut monitor = Monitor::<8>::new(2);
+        monitor.set_latency_frames(2).unwrap();
+        monitor.set_gain(UNITY_GAIN / 2);
+
+        let mut out = [0; 8];
+        monitor
+            .process(&[100, -100, 200, -200, 300, -300, 400, -400], &mut out)
+            .unwrap();
+
+        assert_eq!(out, [0, 0, 0, 0, 50, -50, 100, -100]);
+        assert_eq!(monitor.latency_frames(), 2);
+    }
+
+    #[test]
+    fn should_bound_latency_by_delay_line() {
+        let mut monitor = Monitor::<8>::new(2);
+
+        assert_eq!(monitor.set_latency_frames(4), Err(Error::BufferTooSmall(9)));
+        assert!(monitor.set_latency_frames(3).is_ok());
+
+        monitor.set_gain(u16::MAX);
+        let mut out = [0; 8];
+        monitor.set_latency_frames(0).unwrap();
+        monitor.process(&[i16::MAX; 2], &mut out).unwrap();
+
+        assert_eq!(out[..2], [i16::MAX; 2]);
+    }
+}
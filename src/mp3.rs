@@ -0,0 +1,373 @@
+use crate::error::Error;
+use crate::id3::{read_id3v2, syncsafe, ID3_HEADER_SIZE};
+use crate::metadata::Metadata;
+use crate::source::AudioSource;
+use crate::timestamp::Timestamp;
+use crate::wav::read_full;
+
+/// Size of an MPEG audio frame header
+const HEADER_SIZE: usize = 4;
+
+/// Bitrates in kbit/s of MPEG 1 layer I, II and III, indexed by the bitrate index minus one
+const BITRATES_V1: [[u16; 14]; 3] = [
+    [
+        32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+];
+/// Bitrates in kbit/s of MPEG 2 and 2.5 layer I and layer II/III, indexed by the bitrate index minus one
+const BITRATES_V2: [[u16; 14]; 2] = [
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Version of the MPEG audio standard a frame follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MpegVersion {
+    /// MPEG 1, 32 kHz up to 48 kHz
+    Mpeg1,
+    /// MPEG 2, 16 kHz up to 24 kHz
+    Mpeg2,
+    /// MPEG 2.5, 8 kHz up to 12 kHz
+    Mpeg25,
+}
+
+/// Header of an MPEG audio frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mp3Header {
+    /// MPEG version of the frame
+    pub version: MpegVersion,
+    /// Layer of the frame, `3` for MP3
+    pub layer: u8,
+    /// Bitrate in bit/s
+    pub bitrate: u32,
+    /// sample rate, typical values are `44_100` or `48_000`
+    pub sample_rate: u32,
+    /// `1` for mono frames, `2` otherwise
+    pub num_channels: u16,
+    /// Length of the whole frame in bytes, header included
+    pub frame_len: usize,
+    /// Number of frames of decoded audio the frame holds
+    pub samples_per_frame: u16,
+}
+
+impl Mp3Header {
+    /// Parse the header at the start of `bytes`, free format frames are not supported
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let b = bytes.get(..HEADER_SIZE).ok_or(Error::InvalidFrame)?;
+
+        if b[0] != 0xff || b[1] & 0xe0 != 0xe0 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let version = match (b[1] >> 3) & 0x03 {
+            0 => MpegVersion::Mpeg25,
+            2 => MpegVersion::Mpeg2,
+            3 => MpegVersion::Mpeg1,
+            _ => return Err(Error::InvalidFrame),
+        };
+
+        let layer = match (b[1] >> 1) & 0x03 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return Err(Error::InvalidFrame),
+        };
+
+        let bitrate_index = (b[2] >> 4) as usize;
+        let sample_rate_index = ((b[2] >> 2) & 0x03) as usize;
+        let padding = ((b[2] >> 1) & 0x01) as usize;
+
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return Err(Error::InvalidFrame);
+        }
+
+        let kbits = match (version, layer) {
+            (MpegVersion::Mpeg1, _) => BITRATES_V1[layer as usize - 1][bitrate_index - 1],
+            (_, 1) => BITRATES_V2[0][bitrate_index - 1],
+            _ => BITRATES_V2[1][bitrate_index - 1],
+        };
+        let bitrate = kbits as u32 * 1000;
+
+        let sample_rate = [44_100, 48_000, 32_000][sample_rate_index]
+            >> match version {
+                MpegVersion::Mpeg1 => 0,
+                MpegVersion::Mpeg2 => 1,
+                MpegVersion::Mpeg25 => 2,
+            };
+
+        let samples_per_frame = match (version, layer) {
+            (_, 1) => 384,
+            (MpegVersion::Mpeg1, _) | (_, 2) => 1152,
+            _ => 576,
+        };
+
+        let frame_len = match layer {
+            1 => (12 * bitrate as usize / sample_rate as usize + padding) * 4,
+            _ => samples_per_frame as usize / 8 * bitrate as usize / sample_rate as usize + padding,
+        };
+
+        Ok(Mp3Header {
+            version,
+            layer,
+            bitrate,
+            sample_rate,
+            num_channels: if b[3] >> 6 == 3 { 1 } else { 2 },
+            frame_len,
+            samples_per_frame,
+        })
+    }
+}
+
+/// MPEG audio stream read from an [`AudioSource`], split into whole frames for a hardware decoder
+///
+/// Leading ID3v2 tags and anything between frames that doesn't parse as a frame header are skipped.
+pub struct Mp3File<S: AudioSource> {
+    pub(crate) source: S,
+}
+
+impl<S: AudioSource> Mp3File<S> {
+    /// Create new [`Mp3File`] instance from an [`AudioSource`], skipping an ID3v2 tag at the start
+    pub fn new(mut source: S) -> Result<Self, Error<S::Error>> {
+        source.seek(0).map_err(Error::Source)?;
+
+        let mut mp3 = Mp3File { source };
+        mp3.skip_id3()?;
+
+        Ok(mp3)
+    }
+
+    /// Read the next whole frame into `buf`, returning its header.
+    ///
+    /// Returns [`Error::BufferTooSmall`] without consuming the frame if it doesn't fit in `buf`,
+    /// and [`Error::EndOfData`] once no whole frame is left.
+    pub fn next_frame(&mut self, buf: &mut [u8]) -> Result<Mp3Header, Error<S::Error>> {
+        let header = self.sync()?;
+
+        if header.frame_len > buf.len() {
+            return Err(Error::BufferTooSmall(header.frame_len));
+        }
+
+        let frame = &mut buf[..header.frame_len];
+
+        if read_full(&mut self.source, frame)? != frame.len() {
+            return Err(Error::EndOfData);
+        }
+
+        Ok(header)
+    }
+
+    /// Header of the next frame, the read position is left unchanged
+    pub fn peek_header(&mut self) -> Result<Mp3Header, Error<S::Error>> {
+        let position = self.source.offset();
+        let header = self.sync();
+        self.source.seek(position).map_err(Error::Source)?;
+
+        header
+    }
+
+    /// Length of the stream from the next frame on, assuming every frame has the bitrate of the
+    /// next one, which holds for constant bitrate files. The read position is left unchanged.
+    pub fn estimated_duration(&mut self) -> Result<Timestamp, Error<S::Error>> {
+        let position = self.source.offset();
+        let header = self.sync();
+        let start = self.source.offset();
+        self.source.seek(position).map_err(Error::Source)?;
+        let header = header?;
+
+        let bits = (self.source.length().saturating_sub(start) as u64) * 8;
+        let frames = match header.bitrate {
+            0 => 0,
+            bitrate => bits * header.sample_rate as u64 / bitrate as u64,
+        };
+
+        Ok(Timestamp::from_frames(frames, header.sample_rate))
+    }
+
+    /// Read the title, artist, album, genre and year of the ID3v2 tags at the start of the file.
+    ///
+    /// Values longer than `MAX_STRING_LEN` bytes are cut short. The read position is left unchanged.
+    pub fn metadata<const MAX_STRING_LEN: usize>(
+        &mut self,
+    ) -> Result<Metadata<MAX_STRING_LEN>, Error<S::Error>> {
+        let position = self.source.offset();
+        let mut metadata = Metadata::default();
+        let mut start = 0;
+
+        let result = loop {
+            if let Err(e) = self.source.seek(start).map_err(Error::Source) {
+                break Err(e);
+            }
+
+            match read_id3v2(&mut self.source, u32::MAX, &mut metadata) {
+                Ok(Some(size)) => start = start.saturating_add(size),
+                Ok(None) => break Ok(metadata),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        result
+    }
+
+    /// Destroy the [`Mp3File`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+
+    /// Skip ID3v2 tags at the read position
+    fn skip_id3(&mut self) -> Result<(), Error<S::Error>> {
+        loop {
+            let start = self.source.offset();
+            let mut tag = [0; ID3_HEADER_SIZE];
+
+            if read_full(&mut self.source, &mut tag)? != tag.len() || &tag[..3] != b"ID3" {
+                return self.source.seek(start).map_err(Error::Source);
+            }
+
+            // a footer repeats the header
+            let size = syncsafe(&tag[6..10]);
+            let footer = if tag[5] & 0x10 != 0 { 10 } else { 0 };
+
+            self.source
+                .seek(start.saturating_add(ID3_HEADER_SIZE as u32 + size + footer))
+                .map_err(Error::Source)?;
+        }
+    }
+
+    /// Move the read position to the next frame header
+    fn sync(&mut self) -> Result<Mp3Header, Error<S::Error>> {
+        let mut scan = [0; 64];
+
+        loop {
+            let start = self.source.offset();
+            let read = read_full(&mut self.source, &mut scan)?;
+
+            if read < HEADER_SIZE {
+                return Err(Error::EndOfData);
+            }
+
+            let found = (0..=read - HEADER_SIZE).find_map(|i| {
+                Mp3Header::parse(&scan[i..read])
+                    .ok()
+                    .map(|header| (i, header))
+            });
+
+            if let Some((i, header)) = found {
+                self.source.seek(start + i as u32).map_err(Error::Source)?;
+
+                return Ok(header);
+            }
+
+            // the last bytes might hold the start of a header
+            self.source
+                .seek(start + (read - HEADER_SIZE + 1) as u32)
+                .map_err(Error::Source)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+    use std::vec::Vec;
+
+    fn frame(padding: bool) -> Vec<u8> {
+        let mut frame = std::vec![0x55; 417 + padding as usize];
+        frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90 | (padding as u8) << 1, 0x00]);
+        frame
+    }
+
+    #[test]
+    fn should_parse_headers() {
+        let header = Mp3Header::parse(&[0xff, 0xfb, 0x90, 0x00]).unwrap();
+
+        assert_eq!(header.version, MpegVersion::Mpeg1);
+        assert_eq!(header.layer, 3);
+        assert_eq!(header.bitrate, 128_000);
+        assert_eq!(header.sample_rate, 44_100);
+        assert_eq!(header.num_channels, 2);
+        assert_eq!(header.frame_len, 417);
+
+        let header = Mp3Header::parse(&[0xff, 0xf3, 0x84, 0xc0]).unwrap();
+
+        assert_eq!(header.version, MpegVersion::Mpeg2);
+        assert_eq!(header.sample_rate, 24_000);
+        assert_eq!(header.num_channels, 1);
+        assert_eq!(header.samples_per_frame, 576);
+        assert_eq!(header.frame_len, 72 * 64_000 / 24_000);
+
+        assert!(Mp3Header::parse(&[0xff, 0xfb, 0xf0, 0x00]).is_err());
+    }
+
+    #[test]
+    fn should_estimate_constant_bitrate_duration() {
+        let mut bytes = b"ID3\x04\x00\x00\x00\x00\x00\x10".to_vec();
+        bytes.extend_from_slice(&[0; 16]);
+
+        // 1.1 s of padded and unpadded frames, 1152 frames of audio each
+        for i in 0..42 {
+            bytes.extend_from_slice(&frame(i % 3 != 0));
+        }
+
+        let mut mp3 = Mp3File::new(SliceSource::new(&bytes)).unwrap();
+        let position = mp3.source.offset();
+
+        assert_eq!(mp3.peek_header().unwrap().bitrate, 128_000);
+        let duration = mp3.estimated_duration().unwrap();
+        assert!((duration.frames as i64 - 42 * 1152).abs() < 100);
+        assert_eq!(mp3.source.offset(), position);
+    }
+
+    #[test]
+    fn should_skip_id3_tag_and_garbage() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"ID3\x04\x00\x00\x00\x00\x01\x00");
+        bytes.extend_from_slice(&[0xaa; 128]);
+        bytes.extend_from_slice(&frame(false));
+        bytes.extend_from_slice(&[0x00, 0xff, 0x12]);
+        bytes.extend_from_slice(&frame(true));
+        bytes.extend_from_slice(&frame(false)[..100]);
+
+        let mut mp3 = Mp3File::new(SliceSource::new(&bytes)).unwrap();
+        let mut buf = [0; 512];
+
+        assert_eq!(mp3.next_frame(&mut buf).unwrap().frame_len, 417);
+        assert_eq!(buf[..417], frame(false)[..]);
+
+        assert!(matches!(
+            mp3.next_frame(&mut [0; 16]),
+            Err(Error::BufferTooSmall(418))
+        ));
+        assert_eq!(mp3.next_frame(&mut buf).unwrap().frame_len, 418);
+        assert_eq!(buf[..418], frame(true)[..]);
+
+        assert!(matches!(mp3.next_frame(&mut buf), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_read_id3_metadata() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"ID3\x03\x00\x00\x00\x00\x00\x13");
+        bytes.extend_from_slice(b"TIT2\x00\x00\x00\x09\x00\x00\x00Teardrop");
+        bytes.extend_from_slice(&frame(false));
+
+        let mut mp3 = Mp3File::new(SliceSource::new(&bytes)).unwrap();
+        let metadata = mp3.metadata::<16>().unwrap();
+
+        assert_eq!(metadata.title(), Some("Teardrop"));
+        assert_eq!(metadata.artist(), None);
+
+        let mut buf = [0; 512];
+        assert_eq!(mp3.next_frame(&mut buf).unwrap().frame_len, 417);
+    }
+}
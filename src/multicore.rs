@@ -0,0 +1,451 @@
+use crate::decoder::Decoder;
+use crate::error::Error;
+use crate::pipeline::{write_samples, Stage};
+use crate::sink::AudioSink;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Lock-free single producer, single consumer queue of samples, handing decoded audio from one
+/// core to another
+///
+/// Holds up to `N - 1` samples. [`SampleFifo::split`] gives one [`FifoProducer`] and one
+/// [`FifoConsumer`] that can be moved to different cores or interrupt priorities, neither side
+/// ever blocks or disables interrupts.
+///
+/// A FIFO in a `static` is split once with [`SampleFifo::take_split`], which hands out halves
+/// that live as long as the program and can be given to the task of each core:
+///
+/// ```
+/// use audio_parser::SampleFifo;
+///
+/// static FIFO: SampleFifo<256> = SampleFifo::new();
+///
+/// let (mut producer, mut consumer) = FIFO.take_split().unwrap();
+/// assert!(FIFO.take_split().is_none());
+///
+/// producer.push(&[1, 2, 3]);
+/// assert_eq!(consumer.available(), 3);
+/// ```
+pub struct SampleFifo<const N: usize> {
+    buffer: UnsafeCell<[i16; N]>,
+    /// Index the producer writes to next
+    write: AtomicUsize,
+    /// Index the consumer reads from next
+    read: AtomicUsize,
+    closed: AtomicBool,
+    /// Set once [`SampleFifo::take_split`] handed out the halves
+    taken: AtomicBool,
+}
+
+// SAFETY: the producer only writes the free part of the buffer and the consumer only reads the
+// filled part, the indices are handed over with release and acquire ordering
+unsafe impl<const N: usize> Sync for SampleFifo<N> {}
+
+impl<const N: usize> SampleFifo<N> {
+    /// Create an empty FIFO, usable in a `static`
+    pub const fn new() -> Self {
+        SampleFifo {
+            buffer: UnsafeCell::new([0; N]),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            taken: AtomicBool::new(false),
+        }
+    }
+
+    /// Number of samples the FIFO holds at most
+    pub const fn capacity(&self) -> usize {
+        N.saturating_sub(1)
+    }
+
+    /// Number of samples waiting to be consumed
+    pub fn len(&self) -> usize {
+        filled::<N>(
+            self.write.load(Ordering::Acquire),
+            self.read.load(Ordering::Acquire),
+        )
+    }
+
+    /// True when no samples wait to be consumed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Split into the producing and the consuming end
+    pub fn split(&mut self) -> (FifoProducer<'_, N>, FifoConsumer<'_, N>) {
+        let fifo = &*self;
+
+        (FifoProducer { fifo }, FifoConsumer { fifo })
+    }
+
+    /// Split a shared FIFO, e.g. one in a `static`, into the producing and the consuming end.
+    ///
+    /// Only the first call gets the halves, every later one `None`, so there is never more than
+    /// one of each.
+    pub fn take_split(&self) -> Option<(FifoProducer<'_, N>, FifoConsumer<'_, N>)> {
+        match self.taken.swap(true, Ordering::AcqRel) {
+            false => Some((FifoProducer { fifo: self }, FifoConsumer { fifo: self })),
+            true => None,
+        }
+    }
+}
+
+impl<const N: usize> Default for SampleFifo<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Samples between `read` and `write`
+fn filled<const N: usize>(write: usize, read: usize) -> usize {
+    match N {
+        0 => 0,
+        _ => (write + N - read) % N,
+    }
+}
+
+/// Writing end of a [`SampleFifo`]
+pub struct FifoProducer<'a, const N: usize> {
+    fifo: &'a SampleFifo<N>,
+}
+
+impl<'a, const N: usize> FifoProducer<'a, N> {
+    /// Number of samples that can be pushed right now
+    pub fn free(&self) -> usize {
+        let write = self.fifo.write.load(Ordering::Relaxed);
+        let read = self.fifo.read.load(Ordering::Acquire);
+
+        self.fifo.capacity() - filled::<N>(write, read)
+    }
+
+    /// Append as many of `samples` as fit, returns the number pushed
+    pub fn push(&mut self, samples: &[i16]) -> usize {
+        let count = samples.len().min(self.free());
+
+        if count == 0 {
+            return 0;
+        }
+
+        let write = self.fifo.write.load(Ordering::Relaxed);
+        let first = count.min(N - write);
+        let buffer = self.fifo.buffer.get() as *mut i16;
+
+        // SAFETY: the `count` slots from `write` on are free, the consumer doesn't touch them
+        // until the new index is published below
+        unsafe {
+            ptr::copy_nonoverlapping(samples.as_ptr(), buffer.add(write), first);
+            ptr::copy_nonoverlapping(samples[first..].as_ptr(), buffer, count - first);
+        }
+
+        self.fifo
+            .write
+            .store((write + count) % N, Ordering::Release);
+
+        count
+    }
+
+    /// Tell the consumer no more samples will follow
+    pub fn close(&mut self) {
+        self.fifo.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Reading end of a [`SampleFifo`]
+pub struct FifoConsumer<'a, const N: usize> {
+    fifo: &'a SampleFifo<N>,
+}
+
+impl<'a, const N: usize> FifoConsumer<'a, N> {
+    /// Number of samples that can be popped right now
+    pub fn available(&self) -> usize {
+        filled::<N>(
+            self.fifo.write.load(Ordering::Acquire),
+            self.fifo.read.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Move up to `out.len()` samples into `out`, returns the number popped
+    pub fn pop(&mut self, out: &mut [i16]) -> usize {
+        let count = out.len().min(self.available());
+
+        if count == 0 {
+            return 0;
+        }
+
+        let read = self.fifo.read.load(Ordering::Relaxed);
+        let first = count.min(N - read);
+        let buffer = self.fifo.buffer.get() as *const i16;
+
+        // SAFETY: the `count` slots from `read` on were published by the producer, which doesn't
+        // touch them again until the new index is published below
+        unsafe {
+            ptr::copy_nonoverlapping(buffer.add(read), out.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(buffer, out[first..].as_mut_ptr(), count - first);
+        }
+
+        self.fifo.read.store((read + count) % N, Ordering::Release);
+
+        count
+    }
+
+    /// True once the producer closed the FIFO and every sample was popped
+    pub fn is_finished(&self) -> bool {
+        self.fifo.closed.load(Ordering::Acquire) && self.available() == 0
+    }
+}
+
+/// Decoding half of a playback chain split across two cores, filling a [`SampleFifo`]
+///
+/// Runs on the core that owns the storage, its counterpart [`StageRunner`] on the core driving
+/// the output.
+pub struct DecodeRunner<'a, D, const FIFO_LEN: usize> {
+    decoder: D,
+    producer: FifoProducer<'a, FIFO_LEN>,
+    channels: usize,
+    finished: bool,
+}
+
+impl<'a, D: Decoder, const FIFO_LEN: usize> DecodeRunner<'a, D, FIFO_LEN> {
+    /// Decode `decoder` into `producer`
+    pub fn new(decoder: D, producer: FifoProducer<'a, FIFO_LEN>) -> Self {
+        let channels = (decoder.info().num_channels as usize).max(1);
+
+        DecodeRunner {
+            decoder,
+            producer,
+            channels,
+            finished: false,
+        }
+    }
+
+    /// Decode up to `N` samples of whole frames into the FIFO, as many as it has room for.
+    ///
+    /// Returns the number of samples pushed, `0` while the FIFO has no room for the next frame or
+    /// block, or once the decoder ran out, which also closes the FIFO. Codecs decoding whole
+    /// blocks return [`Error::BufferTooSmall`] if a block doesn't fit in `N` samples or the FIFO
+    /// even when it is empty.
+    pub fn run<const N: usize>(&mut self) -> Result<usize, Error<D::Error>> {
+        let most = self.producer.fifo.capacity().min(N) / self.channels * self.channels;
+        let wanted = self.producer.free().min(most);
+
+        if wanted == 0 || self.finished {
+            return Ok(0);
+        }
+
+        let mut samples = [0; N];
+
+        let decoded = match self.decoder.decode(&mut samples[..wanted]) {
+            // the block fits once the consumer made room
+            Err(Error::BufferTooSmall(_)) if wanted < most => return Ok(0),
+            decoded => decoded?,
+        };
+
+        if decoded == 0 {
+            self.finished = true;
+            self.producer.close();
+        }
+
+        Ok(self.producer.push(&samples[..decoded]))
+    }
+
+    /// True once the decoder ran out
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Take the runner apart into its decoder and producer
+    pub fn into_parts(self) -> (D, FifoProducer<'a, FIFO_LEN>) {
+        (self.decoder, self.producer)
+    }
+}
+
+/// Processing half of a playback chain split across two cores, running [`Stage`]s on the samples
+/// of a [`SampleFifo`] and writing them to an [`AudioSink`]
+///
+/// The sink receives little endian 16 bit PCM like a [`Pipeline`](crate::Pipeline).
+pub struct StageRunner<'a, P, K, const FIFO_LEN: usize> {
+    consumer: FifoConsumer<'a, FIFO_LEN>,
+    stages: P,
+    sink: K,
+    channels: usize,
+}
+
+impl<'a, P: Stage, K: AudioSink, const FIFO_LEN: usize> StageRunner<'a, P, K, FIFO_LEN> {
+    /// Process frames of `channels` interleaved samples from `consumer` with `stages` into `sink`
+    pub fn new(consumer: FifoConsumer<'a, FIFO_LEN>, stages: P, sink: K, channels: u16) -> Self {
+        StageRunner {
+            consumer,
+            stages,
+            sink,
+            channels: (channels as usize).max(1),
+        }
+    }
+
+    /// Pop up to `N` samples of whole frames, run them through the stages and write them to the
+    /// sink, returns the number of samples written, `0` while the FIFO is empty.
+    ///
+    /// Sink errors are reported as [`Error::Io`].
+    pub fn run<const N: usize>(&mut self) -> Result<usize, Error> {
+        let wanted = self.consumer.available().min(N) / self.channels * self.channels;
+        let mut samples = [0; N];
+        let popped = self.consumer.pop(&mut samples[..wanted]);
+        let samples = &mut samples[..popped];
+
+        if popped > 0 {
+            self.stages.process(samples);
+            write_samples(&mut self.sink, samples)?;
+        }
+
+        Ok(popped)
+    }
+
+    /// True once the decoding side finished and every sample was written
+    pub fn is_finished(&self) -> bool {
+        self.consumer.is_finished()
+    }
+
+    /// Stages, e.g. to change their settings between runs
+    pub fn stages_mut(&mut self) -> &mut P {
+        &mut self.stages
+    }
+
+    /// Take the runner apart into its consumer, stages and sink
+    pub fn into_parts(self) -> (FifoConsumer<'a, FIFO_LEN>, P, K) {
+        (self.consumer, self.stages, self.sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Gain;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+    use crate::wav::Wav;
+    use crate::UNITY_GAIN;
+
+    #[test]
+    fn should_wrap_around_the_buffer() {
+        let mut fifo = SampleFifo::<8>::new();
+        let (mut producer, mut consumer) = fifo.split();
+        let mut out = [0; 8];
+
+        assert_eq!(producer.push(&[1, 2, 3, 4, 5, 6, 7, 8, 9]), 7);
+        assert_eq!(producer.free(), 0);
+        assert_eq!(consumer.pop(&mut out[..5]), 5);
+        assert_eq!(out[..5], [1, 2, 3, 4, 5]);
+
+        assert_eq!(producer.push(&[10, 11, 12, 13]), 4);
+        assert_eq!(consumer.available(), 6);
+        assert_eq!(consumer.pop(&mut out), 6);
+        assert_eq!(out[..6], [6, 7, 10, 11, 12, 13]);
+
+        assert!(!consumer.is_finished());
+        producer.close();
+        assert!(consumer.is_finished());
+        assert!(fifo.is_empty());
+    }
+
+    #[test]
+    fn should_hand_samples_between_threads() {
+        let mut fifo = SampleFifo::<33>::new();
+        let (mut producer, mut consumer) = fifo.split();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                let mut next = 0i16;
+
+                while next < 10_000 {
+                    let chunk: [i16; 7] = core::array::from_fn(|i| next + i as i16);
+                    next += producer.push(&chunk) as i16;
+                }
+
+                producer.close();
+            });
+
+            let mut expected = 0;
+
+            while !consumer.is_finished() {
+                let mut out = [0; 5];
+                let popped = consumer.pop(&mut out);
+
+                for &sample in &out[..popped] {
+                    assert_eq!(sample, expected);
+                    expected += 1;
+                }
+            }
+
+            assert!(expected >= 10_000);
+        });
+    }
+
+    #[test]
+    fn should_split_decode_and_stages() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+        let mut expected = [0; 300];
+        reference.decode(&mut expected).unwrap();
+
+        let mut fifo = SampleFifo::<129>::new();
+        let (producer, consumer) = fifo.split();
+        let mut out = [0; 800];
+
+        let mut decode = DecodeRunner::new(Wav::from_bytes(bytes).unwrap(), producer);
+        let mut output =
+            StageRunner::new(consumer, Gain(UNITY_GAIN / 2), SliceSink::new(&mut out), 2);
+
+        let mut written = 0;
+
+        while written < 300 {
+            decode.run::<64>().unwrap();
+            written += output.run::<51>().unwrap();
+        }
+
+        assert!(!decode.is_finished());
+        assert!(!output.is_finished());
+
+        let (_, _, sink) = output.into_parts();
+
+        for (bytes, &sample) in sink.written().chunks_exact(2).zip(&expected) {
+            assert_eq!(i16::from_le_bytes([bytes[0], bytes[1]]), sample >> 1);
+        }
+    }
+
+    #[test]
+    fn should_wait_for_room_for_a_whole_block() {
+        // mono IMA ADPCM, two 8 byte blocks of 9 frames each
+        let bytes: [u8; 60] = [
+            0x52, 0x49, 0x46, 0x46, // RIFF
+            0x34, 0x00, 0x00, 0x00, // chunk size
+            0x57, 0x41, 0x56, 0x45, // WAVE
+            0x66, 0x6d, 0x74, 0x20, // fmt_
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x11, 0x00, // audio format
+            0x01, 0x00, // num channels
+            0x22, 0x56, 0x00, 0x00, // sample rate
+            0x88, 0x58, 0x01, 0x00, // byte rate
+            0x08, 0x00, // block align
+            0x04, 0x00, // bits per sample
+            0x64, 0x61, 0x74, 0x61, // data
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x00, 0x00, 0x00, 0x00, 0x10, 0x32, 0x98, 0xba, // block 1
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // block 2
+        ];
+        let mut fifo = SampleFifo::<13>::new();
+        let (producer, mut consumer) = fifo.split();
+        let mut decode = DecodeRunner::new(Wav::new(SliceSource::new(&bytes)).unwrap(), producer);
+        let mut out = [0; 9];
+
+        assert_eq!(decode.run::<16>().unwrap(), 9);
+        assert_eq!(decode.run::<16>().unwrap(), 0);
+        assert_eq!(consumer.pop(&mut out[..6]), 6);
+
+        assert_eq!(decode.run::<16>().unwrap(), 9);
+        assert_eq!(consumer.pop(&mut out), 9);
+        assert_eq!(out[3..], [16; 6]);
+
+        // a block never fits in 8 samples
+        assert!(matches!(decode.run::<8>(), Err(Error::BufferTooSmall(18))));
+    }
+}
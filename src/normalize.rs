@@ -0,0 +1,166 @@
+use crate::analyze::normalize;
+use crate::error::Error;
+use crate::fixed::{apply_gain, saturate_i16, saturate_i24, UNITY_GAIN};
+use crate::source::AudioSource;
+use crate::wav::{DataBulk, Wav};
+use core::f32::consts::LN_2;
+
+/// `log2(10) / 20`, turns decibels into a power of two
+const DB_TO_LOG2: f32 = 0.166_096_4;
+
+/// `2^x` for `-126 <= x < 128`, `core` has no float exponent functions
+fn exp2(x: f32) -> f32 {
+    let x = x.clamp(-126.0, 127.0);
+
+    let mut whole = x as i32;
+    if whole as f32 > x {
+        whole -= 1;
+    }
+
+    let f = x - whole as f32;
+    let fraction = 1.0 + f * (LN_2 + f * (0.240_226_5 + f * (0.055_504_1 + f * 0.009_618_1)));
+
+    fraction * f32::from_bits(((whole + 127) as u32) << 23)
+}
+
+/// Linear amplitude ratio of a level in decibels
+fn db_to_linear(db: f32) -> f32 {
+    exp2(db * DB_TO_LOG2)
+}
+
+/// Gain that brings the peak of a file to a target level, e.g. to even out a folder of prompts.
+///
+/// Found with [`Wav::normalization`] in a first pass over the file, then applied to every buffer
+/// during playback or transcoding. The gain is Q1.15 like that of every other stage, so quiet
+/// files are boosted by up to just below 2.0, about +6 dB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalization {
+    gain: u16,
+}
+
+impl Normalization {
+    /// Gain taking a `peak` relative to full scale to `target_dbfs`, a silent file keeps unity gain
+    pub fn new(peak: f32, target_dbfs: f32) -> Self {
+        let gain = if peak > 0.0 {
+            db_to_linear(target_dbfs.min(0.0)) / peak
+        } else {
+            1.0
+        };
+
+        Normalization {
+            gain: (gain as f64 * UNITY_GAIN as f64).min(u16::MAX as f64) as u16,
+        }
+    }
+
+    /// Q1.15 gain applied to every sample
+    pub fn gain(&self) -> u16 {
+        self.gain
+    }
+
+    /// Scale every sample in `bulk`, saturating at the limits of the bit depth
+    pub fn apply<const NUM: usize>(&self, bulk: &mut DataBulk<NUM>) {
+        let gain = self.gain;
+
+        match bulk {
+            DataBulk::BitDepth8(samples) => samples.iter_mut().for_each(|s| {
+                *s = (apply_gain(*s as i16 - 128, gain).clamp(-128, 127) + 128) as u8
+            }),
+            DataBulk::BitDepth16(samples) => samples
+                .iter_mut()
+                .for_each(|s| *s = saturate_i16(apply_gain(*s, gain))),
+            DataBulk::BitDepth24(samples) => samples
+                .iter_mut()
+                .for_each(|s| *s = saturate_i24((*s as i64 * gain as i64) >> 15)),
+            DataBulk::Float32(samples) => {
+                let gain = gain as f32 / UNITY_GAIN as f32;
+                samples.iter_mut().for_each(|s| *s *= gain)
+            }
+        }
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Scan the whole sample data for the highest absolute sample value relative to full scale.
+    ///
+    /// The read position is left unchanged.
+    pub fn peak(&mut self) -> Result<f32, Error<S::Error>> {
+        let data_end = self.data_end();
+        let total = data_end.saturating_sub(self.data.start) / self.fmt.block_align().max(1);
+
+        let position = self.data_offset();
+        self.seek_data(0)?;
+
+        let mut peak = 0f32;
+        let mut frames = 0;
+
+        while frames < total {
+            let bulk = self.next_frames::<256>(total - frames)?;
+            let read = bulk.len() / (self.fmt.num_channels as usize).max(1);
+
+            if read == 0 {
+                break;
+            }
+
+            for index in 0..bulk.len() {
+                if let Some((sample, _)) = normalize(&bulk, index) {
+                    peak = peak.max(sample.abs());
+                }
+            }
+
+            frames += read;
+        }
+
+        self.seek_data(position)?;
+
+        Ok(peak)
+    }
+
+    /// First pass of peak normalization, finds the gain that brings the peak to `target_dbfs`
+    pub fn normalization(&mut self, target_dbfs: f32) -> Result<Normalization, Error<S::Error>> {
+        Ok(Normalization::new(self.peak()?, target_dbfs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::Vec;
+
+    #[test]
+    fn should_convert_decibels() {
+        assert!((db_to_linear(0.0) - 1.0).abs() < 1e-4);
+        assert!((db_to_linear(-6.0206) - 0.5).abs() < 1e-4);
+        assert!((db_to_linear(-20.0) - 0.1).abs() < 1e-4);
+        assert!((db_to_linear(6.0206) - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn should_bring_peak_to_target() {
+        let normalization = Normalization::new(0.5, -6.0206);
+        assert!((normalization.gain() as i32 - UNITY_GAIN as i32).abs() < 16);
+
+        // boosts stop just below 2.0
+        assert_eq!(Normalization::new(0.25, 0.0).gain(), u16::MAX);
+
+        let mut bulk =
+            DataBulk::<3>::BitDepth16(Vec::from_slice(&[8_192, -8_192, 30_000]).unwrap());
+        Normalization::new(0.8, 0.0).apply(&mut bulk);
+
+        assert_eq!(
+            bulk,
+            DataBulk::BitDepth16(Vec::from_slice(&[10_240, -10_240, i16::MAX]).unwrap())
+        );
+    }
+
+    #[test]
+    fn should_find_file_peak() {
+        let bytes = include_bytes!("../test_files/mono_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+
+        assert_eq!(wav.peak().unwrap(), 28_556.0 / 32_768.0);
+
+        let mut bulk = wav.next_n::<1024>().unwrap();
+        wav.normalization(-1.0).unwrap().apply(&mut bulk);
+        assert_eq!(wav.timestamp().frames, 1024);
+    }
+}
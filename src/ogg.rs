@@ -0,0 +1,705 @@
+use crate::encode::{PacketEncoder, PacketSink};
+use crate::error::Error;
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::wav::read_full;
+use heapless::Vec;
+
+/// Capture pattern starting every Ogg page
+const CAPTURE_PATTERN: [u8; 4] = *b"OggS";
+/// Size of the fixed part of a page header, up to and including the segment count
+const PAGE_HEADER_SIZE: usize = 27;
+/// Lacing value of a segment continuing in the next one
+const FULL_SEGMENT: u8 = 255;
+/// Most segments a page holds
+const MAX_SEGMENTS: usize = 255;
+/// Granule position of a page on which no packet ends
+const NO_GRANULE: u64 = u64::MAX;
+/// Vendor string written into the Opus comment header
+const OPUS_VENDOR: &[u8] = b"audio_parser";
+
+/// Header type flag of a page continuing a packet of the previous page
+const CONTINUED: u8 = 0x01;
+/// Header type flag of the first page of a logical stream
+const BEGIN_OF_STREAM: u8 = 0x02;
+/// Header type flag of the last page of a logical stream
+const END_OF_STREAM: u8 = 0x04;
+
+/// Codec of a logical stream, told apart by its first packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OggCodec {
+    /// Opus, first packet starts with `OpusHead`
+    Opus,
+    /// Vorbis, first packet starts with `\x01vorbis`
+    Vorbis,
+    /// FLAC mapped into Ogg, first packet starts with `\x7fFLAC`
+    Flac,
+    /// Anything else
+    Unknown,
+}
+
+impl OggCodec {
+    fn from_packet(packet: &[u8]) -> Self {
+        if packet.starts_with(b"OpusHead") {
+            OggCodec::Opus
+        } else if packet.starts_with(b"\x01vorbis") {
+            OggCodec::Vorbis
+        } else if packet.starts_with(b"\x7fFLAC") {
+            OggCodec::Flac
+        } else {
+            OggCodec::Unknown
+        }
+    }
+}
+
+/// Logical stream multiplexed into an Ogg file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggStream {
+    /// Serial number tagging every page of the stream
+    pub serial: u32,
+    /// Codec of the stream
+    pub codec: OggCodec,
+}
+
+/// Header of an Ogg page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggPage {
+    /// Header type flags
+    pub header_type: u8,
+    /// Codec defined position of the last packet completed on this page, e.g. the sample count
+    pub granule_position: u64,
+    /// Serial number of the logical stream
+    pub serial: u32,
+    /// Sequence number of the page within its stream
+    pub sequence: u32,
+    /// Number of bytes of packet data following the header
+    pub body_len: usize,
+}
+
+impl OggPage {
+    /// True when the page starts with the continuation of a packet of the previous page
+    pub fn is_continued(&self) -> bool {
+        self.header_type & CONTINUED != 0
+    }
+
+    /// True for the first page of a logical stream
+    pub fn is_begin_of_stream(&self) -> bool {
+        self.header_type & BEGIN_OF_STREAM != 0
+    }
+
+    /// True for the last page of a logical stream
+    pub fn is_end_of_stream(&self) -> bool {
+        self.header_type & END_OF_STREAM != 0
+    }
+}
+
+/// Packet reassembled from the segments of one or more pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OggPacket {
+    /// Serial number of the logical stream
+    pub serial: u32,
+    /// Length of the packet in bytes
+    pub len: usize,
+    /// Granule position of the page, set for the last packet completed on a page
+    pub granule_position: Option<u64>,
+    /// True for the last packet of the logical stream
+    pub is_last: bool,
+}
+
+/// Ogg container read from an [`AudioSource`], handing out the packets of one logical stream
+///
+/// Packets are meant for a software or hardware Vorbis or Opus decoder. Page CRCs are not checked,
+/// garbage between pages is skipped.
+pub struct OggReader<S: AudioSource> {
+    source: S,
+    serial: Option<u32>,
+    page: Option<OggPage>,
+    lacing: [u8; 255],
+    segments: usize,
+    segment: usize,
+}
+
+impl<S: AudioSource> OggReader<S> {
+    /// Create new [`OggReader`] instance from an [`AudioSource`], reading the first stream found
+    pub fn new(mut source: S) -> Result<Self, Error<S::Error>> {
+        source.seek(0).map_err(Error::Source)?;
+
+        Ok(OggReader {
+            source,
+            serial: None,
+            page: None,
+            lacing: [0; 255],
+            segments: 0,
+            segment: 0,
+        })
+    }
+
+    /// List the logical streams of the file, which all start on the first pages.
+    ///
+    /// Returns [`Error::TooManyChunks`] if there are more than `N` streams. Reading starts over
+    /// from the beginning afterwards.
+    pub fn streams<const N: usize>(&mut self) -> Result<Vec<OggStream, N>, Error<S::Error>> {
+        self.rewind()?;
+
+        let mut streams = Vec::new();
+
+        loop {
+            let page = match self.next_page() {
+                Ok(page) if page.is_begin_of_stream() => page,
+                Ok(_) | Err(Error::EndOfData) => break,
+                Err(e) => return Err(e),
+            };
+
+            let mut magic = [0; 8];
+            let len = page.body_len.min(magic.len());
+            read_full(&mut self.source, &mut magic[..len])?;
+
+            let stream = OggStream {
+                serial: page.serial,
+                codec: OggCodec::from_packet(&magic[..len]),
+            };
+
+            streams.push(stream).map_err(|_| Error::TooManyChunks)?;
+            self.skip_body(len)?;
+        }
+
+        self.rewind()?;
+
+        Ok(streams)
+    }
+
+    /// Hand out the packets of the stream with the given serial number only
+    pub fn select_stream(&mut self, serial: u32) {
+        self.serial = Some(serial);
+    }
+
+    /// Read the next packet of the selected stream into `buf`.
+    ///
+    /// A packet that doesn't fit is skipped and reported as [`Error::BufferTooSmall`] holding its
+    /// length. Returns [`Error::EndOfData`] once the file ends.
+    pub fn next_packet(&mut self, buf: &mut [u8]) -> Result<OggPacket, Error<S::Error>> {
+        let mut len = 0;
+
+        loop {
+            if self.segment == self.segments {
+                let page = self.next_selected_page()?;
+
+                // the tail of a packet whose start was never read is dropped
+                if page.is_continued() && len == 0 {
+                    self.skip_continuation()?;
+                }
+
+                continue;
+            }
+
+            let lace = self.lacing[self.segment] as usize;
+            self.segment += 1;
+
+            match buf.get_mut(len..len + lace) {
+                Some(segment) => {
+                    if read_full(&mut self.source, segment)? != lace {
+                        return Err(Error::EndOfData);
+                    }
+                }
+                None => self.skip_bytes(lace)?,
+            }
+
+            len += lace;
+
+            if lace < FULL_SEGMENT as usize {
+                break;
+            }
+        }
+
+        if len > buf.len() {
+            return Err(Error::BufferTooSmall(len));
+        }
+
+        let page = self.page.ok_or(Error::EndOfData)?;
+        let completes_page = !self.lacing[self.segment..self.segments]
+            .iter()
+            .any(|&lace| lace < FULL_SEGMENT);
+
+        Ok(OggPacket {
+            serial: page.serial,
+            len,
+            granule_position: completes_page.then_some(page.granule_position),
+            is_last: completes_page && page.is_end_of_stream(),
+        })
+    }
+
+    /// Destroy the [`OggReader`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+
+    /// Byte offset of the page being read, or of the next one between pages
+    pub(crate) fn stream_offset(&self) -> u32 {
+        if self.page.is_none() || self.segment == self.segments {
+            return self.source.offset();
+        }
+
+        let read: usize = self.lacing[..self.segment]
+            .iter()
+            .map(|&l| l as usize)
+            .sum();
+        let consumed = PAGE_HEADER_SIZE + self.segments + read;
+
+        self.source.offset().saturating_sub(consumed as u32)
+    }
+
+    /// Continue reading at the page at or after byte offset `offset`, packets continued from an
+    /// earlier page are dropped
+    pub(crate) fn seek_stream(&mut self, offset: u32) -> Result<(), Error<S::Error>> {
+        self.source.seek(offset).map_err(Error::Source)?;
+        self.page = None;
+        self.segments = 0;
+        self.segment = 0;
+
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), Error<S::Error>> {
+        self.page = None;
+        self.segments = 0;
+        self.segment = 0;
+
+        self.source.seek(0).map_err(Error::Source)
+    }
+
+    /// Read the header of the next page of the selected stream, skipping pages of other streams
+    fn next_selected_page(&mut self) -> Result<OggPage, Error<S::Error>> {
+        loop {
+            let page = self.next_page()?;
+
+            match self.serial {
+                Some(serial) if serial != page.serial => self.skip_body(0)?,
+                _ => {
+                    self.serial = Some(page.serial);
+                    return Ok(page);
+                }
+            }
+        }
+    }
+
+    /// Find the next capture pattern and read the page header and lacing values behind it
+    fn next_page(&mut self) -> Result<OggPage, Error<S::Error>> {
+        let mut header = [0; PAGE_HEADER_SIZE];
+
+        loop {
+            let start = self.source.offset();
+
+            if read_full(&mut self.source, &mut header)? != header.len() {
+                return Err(Error::EndOfData);
+            }
+
+            if header[..4] == CAPTURE_PATTERN && header[4] == 0 {
+                break;
+            }
+
+            // resume right after the first byte that might start a capture pattern
+            let skip = header[1..]
+                .iter()
+                .position(|&b| b == CAPTURE_PATTERN[0])
+                .map_or(header.len(), |i| i + 1);
+
+            self.source
+                .seek(start + skip as u32)
+                .map_err(Error::Source)?;
+        }
+
+        let segments = header[26] as usize;
+
+        if read_full(&mut self.source, &mut self.lacing[..segments])? != segments {
+            return Err(Error::EndOfData);
+        }
+
+        let page = OggPage {
+            header_type: header[5],
+            granule_position: u64::from_le_bytes([
+                header[6], header[7], header[8], header[9], header[10], header[11], header[12],
+                header[13],
+            ]),
+            serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+            sequence: u32::from_le_bytes([header[18], header[19], header[20], header[21]]),
+            body_len: self.lacing[..segments]
+                .iter()
+                .map(|&lace| lace as usize)
+                .sum(),
+        };
+
+        self.page = Some(page);
+        self.segments = segments;
+        self.segment = 0;
+
+        Ok(page)
+    }
+
+    /// Skip the segments continuing a packet from the previous page
+    fn skip_continuation(&mut self) -> Result<(), Error<S::Error>> {
+        while self.segment < self.segments {
+            let lace = self.lacing[self.segment];
+            self.segment += 1;
+            self.skip_bytes(lace as usize)?;
+
+            if lace < FULL_SEGMENT {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Skip what is left of the body of the current page, of which `read` bytes were read already
+    fn skip_body(&mut self, read: usize) -> Result<(), Error<S::Error>> {
+        let body_len = self.page.map_or(0, |page| page.body_len);
+        self.segment = self.segments;
+
+        self.skip_bytes(body_len.saturating_sub(read))
+    }
+
+    fn skip_bytes(&mut self, count: usize) -> Result<(), Error<S::Error>> {
+        let offset = self.source.offset() as usize + count;
+
+        if offset > self.source.length() as usize {
+            return Err(Error::EndOfData);
+        }
+
+        self.source.seek(offset as u32).map_err(Error::Source)
+    }
+}
+
+/// [`PacketSink`] writing packets into Ogg pages of one logical stream
+///
+/// Every packet starts a new page, packets longer than a page continue on the following ones.
+#[derive(Debug)]
+pub struct OggWriter<K> {
+    inner: K,
+    serial: u32,
+    sequence: u32,
+}
+
+impl<K: AudioSink> OggWriter<K> {
+    /// Wrap `inner`, tagging every page with `serial`
+    pub fn new(inner: K, serial: u32) -> Self {
+        OggWriter {
+            inner,
+            serial,
+            sequence: 0,
+        }
+    }
+
+    /// Write the identification and comment headers of an Ogg Opus stream, which have to come
+    /// before the first audio packet
+    pub fn write_opus_headers(&mut self, encoder: &impl PacketEncoder) -> Result<(), Error> {
+        let mut head = [0; 19];
+        head[..8].copy_from_slice(b"OpusHead");
+        head[8] = 1;
+        head[9] = encoder.num_channels() as u8;
+        head[10..12].copy_from_slice(&encoder.pre_skip().to_le_bytes());
+        head[12..16].copy_from_slice(&encoder.sample_rate().to_le_bytes());
+
+        let mut tags = [0; 16 + OPUS_VENDOR.len()];
+        tags[..8].copy_from_slice(b"OpusTags");
+        tags[8..12].copy_from_slice(&(OPUS_VENDOR.len() as u32).to_le_bytes());
+        tags[12..12 + OPUS_VENDOR.len()].copy_from_slice(OPUS_VENDOR);
+
+        self.write_packet(&head, 0)?;
+        self.write_packet(&tags, 0)
+    }
+
+    /// Hand back the wrapped sink
+    pub fn into_inner(self) -> K {
+        self.inner
+    }
+
+    fn write_page(
+        &mut self,
+        header_type: u8,
+        granule_position: u64,
+        lacing: &[u8],
+        body: &[u8],
+    ) -> Result<(), Error> {
+        let mut header = [0; PAGE_HEADER_SIZE];
+        header[..4].copy_from_slice(&CAPTURE_PATTERN);
+        header[5] = header_type;
+        header[6..14].copy_from_slice(&granule_position.to_le_bytes());
+        header[14..18].copy_from_slice(&self.serial.to_le_bytes());
+        header[18..22].copy_from_slice(&self.sequence.to_le_bytes());
+        header[26] = lacing.len() as u8;
+
+        let crc = [&header[..], lacing, body]
+            .iter()
+            .fold(0, |crc, bytes| crc32(crc, bytes));
+        header[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        for bytes in [&header[..], lacing, body] {
+            self.inner.write(bytes).map_err(|_| Error::Io)?;
+        }
+
+        self.sequence += 1;
+
+        Ok(())
+    }
+}
+
+impl<K: AudioSink> PacketSink for OggWriter<K> {
+    fn write_packet(&mut self, packet: &[u8], granule_position: u64) -> Result<(), Error> {
+        let mut rest = packet;
+        let mut header_type = 0;
+
+        loop {
+            if self.sequence == 0 {
+                header_type |= BEGIN_OF_STREAM;
+            }
+
+            let mut lacing = [FULL_SEGMENT; MAX_SEGMENTS];
+            let full = rest.len() / FULL_SEGMENT as usize;
+
+            // a packet ends with the first segment shorter than 255 bytes, even an empty one
+            let (segments, len, ends) = if full < MAX_SEGMENTS {
+                lacing[full] = (rest.len() % FULL_SEGMENT as usize) as u8;
+                (full + 1, rest.len(), true)
+            } else {
+                (MAX_SEGMENTS, MAX_SEGMENTS * FULL_SEGMENT as usize, false)
+            };
+
+            let granule = if ends { granule_position } else { NO_GRANULE };
+            self.write_page(header_type, granule, &lacing[..segments], &rest[..len])?;
+
+            rest = &rest[len..];
+            header_type = CONTINUED;
+
+            if ends {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Write an empty page flagged as the end of the stream
+    fn finish(&mut self) -> Result<(), Error> {
+        self.write_page(END_OF_STREAM, NO_GRANULE, &[], &[])
+    }
+}
+
+/// CRC-32 of Ogg pages, polynomial `0x04c11db7` without reflection or final xor
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= (byte as u32) << 24;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                crc << 1 ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+    use std::vec::Vec as StdVec;
+
+    /// Build a page holding `packets`, the last one continuing on the next page if `open`
+    fn page(
+        header_type: u8,
+        serial: u32,
+        granule: u64,
+        packets: &[&[u8]],
+        open: bool,
+    ) -> StdVec<u8> {
+        let mut lacing = StdVec::new();
+        let mut body = StdVec::new();
+
+        for (i, packet) in packets.iter().enumerate() {
+            let full = packet.len() / 255;
+            lacing.extend(std::iter::repeat_n(255, full));
+
+            if !(open && i == packets.len() - 1) {
+                lacing.push((packet.len() % 255) as u8);
+            }
+
+            body.extend_from_slice(packet);
+        }
+
+        let mut page = StdVec::new();
+        page.extend_from_slice(b"OggS\x00");
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&0u32.to_le_bytes());
+        page.extend_from_slice(&0u32.to_le_bytes());
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(&body);
+        page
+    }
+
+    fn file() -> StdVec<u8> {
+        let long: StdVec<u8> = (0..600).map(|i| i as u8).collect();
+
+        let mut bytes = StdVec::new();
+        bytes.extend(page(BEGIN_OF_STREAM, 7, 0, &[b"OpusHead\x01\x02"], false));
+        bytes.extend(page(BEGIN_OF_STREAM, 9, 0, &[b"\x01vorbis\x00"], false));
+        bytes.extend_from_slice(b"junk");
+        bytes.extend(page(0, 7, 0, &[b"OpusTags", &long[..510]], true));
+        bytes.extend(page(0, 9, 0, &[b"vorbis audio"], false));
+        bytes.extend(page(
+            CONTINUED | END_OF_STREAM,
+            7,
+            960,
+            &[&long[510..]],
+            false,
+        ));
+        bytes
+    }
+
+    #[test]
+    fn should_list_streams() {
+        let bytes = file();
+        let mut ogg = OggReader::new(SliceSource::new(&bytes)).unwrap();
+
+        let streams = ogg.streams::<4>().unwrap();
+
+        assert_eq!(
+            streams,
+            [
+                OggStream {
+                    serial: 7,
+                    codec: OggCodec::Opus
+                },
+                OggStream {
+                    serial: 9,
+                    codec: OggCodec::Vorbis
+                },
+            ]
+        );
+        assert!(matches!(ogg.streams::<1>(), Err(Error::TooManyChunks)));
+    }
+
+    #[test]
+    fn should_reassemble_packets_across_pages() {
+        let bytes = file();
+        let mut ogg = OggReader::new(SliceSource::new(&bytes)).unwrap();
+        ogg.select_stream(7);
+
+        let mut buf = [0; 1024];
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(&buf[..packet.len], b"OpusHead\x01\x02");
+        assert_eq!(packet.granule_position, Some(0));
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(&buf[..packet.len], b"OpusTags");
+        assert_eq!(packet.granule_position, Some(0));
+
+        assert!(matches!(
+            ogg.next_packet(&mut [0; 16]),
+            Err(Error::BufferTooSmall(600))
+        ));
+
+        let mut ogg = OggReader::new(SliceSource::new(&bytes)).unwrap();
+        ogg.select_stream(7);
+        ogg.next_packet(&mut buf).unwrap();
+        ogg.next_packet(&mut buf).unwrap();
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(packet.len, 600);
+        assert!(buf[..600].iter().enumerate().all(|(i, &b)| b == i as u8));
+        assert_eq!(packet.granule_position, Some(960));
+        assert!(packet.is_last);
+
+        assert!(matches!(ogg.next_packet(&mut buf), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_follow_first_stream_without_selection() {
+        let bytes = file();
+        let mut ogg = OggReader::new(SliceSource::new(&bytes)).unwrap();
+        let mut buf = [0; 1024];
+
+        assert_eq!(ogg.next_packet(&mut buf).unwrap().serial, 7);
+        assert_eq!(ogg.next_packet(&mut buf).unwrap().serial, 7);
+    }
+
+    struct Opus;
+
+    impl PacketEncoder for Opus {
+        fn sample_rate(&self) -> u32 {
+            16_000
+        }
+
+        fn num_channels(&self) -> u16 {
+            1
+        }
+
+        fn frame_size(&self) -> usize {
+            320
+        }
+
+        fn granule_rate(&self) -> u32 {
+            48_000
+        }
+
+        fn pre_skip(&self) -> u16 {
+            312
+        }
+
+        fn encode(&mut self, _pcm: &[i16], _out: &mut [u8]) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn should_compute_page_crc() {
+        assert_eq!(crc32(0, b"123456789"), 0x89a1_897f);
+    }
+
+    #[test]
+    fn should_write_pages_the_reader_parses() {
+        let mut bytes = std::vec![0; 2048];
+        let mut ogg = OggWriter::new(SliceSink::new(&mut bytes), 42);
+
+        let long: StdVec<u8> = (0..1000).map(|i| i as u8).collect();
+        ogg.write_opus_headers(&Opus).unwrap();
+        ogg.write_packet(&long, 960).unwrap();
+        ogg.finish().unwrap();
+
+        let len = ogg.into_inner().written().len();
+        let mut ogg = OggReader::new(SliceSource::new(&bytes[..len])).unwrap();
+
+        assert_eq!(
+            ogg.streams::<1>().unwrap(),
+            [OggStream {
+                serial: 42,
+                codec: OggCodec::Opus
+            }]
+        );
+
+        let mut buf = [0; 1024];
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..packet.len],
+            b"OpusHead\x01\x01\x38\x01\x80\x3e\x00\x00\x00\x00\x00"
+        );
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..packet.len],
+            b"OpusTags\x0c\x00\x00\x00audio_parser\x00\x00\x00\x00"
+        );
+
+        let packet = ogg.next_packet(&mut buf).unwrap();
+        assert_eq!(buf[..packet.len], long[..]);
+        assert_eq!(packet.granule_position, Some(960));
+
+        assert!(matches!(ogg.next_packet(&mut buf), Err(Error::EndOfData)));
+    }
+}
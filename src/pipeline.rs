@@ -0,0 +1,401 @@
+use crate::crossfeed::Crossfeed;
+use crate::decoder::Decoder;
+use crate::error::Error;
+use crate::fixed::scale_q15;
+use crate::monitor::Monitor;
+use crate::sink::AudioSink;
+use heapless::Vec;
+
+/// Bytes of 16 bit samples handed to the sink at a time
+const SINK_CHUNK_LEN: usize = 64;
+
+/// Processing stage of a [`Pipeline`], working in place on interleaved 16 bit samples
+///
+/// Implemented for the crate's sample processors and for any `FnMut(&mut [i16])` closure.
+pub trait Stage {
+    /// Process `samples` in place
+    fn process(&mut self, samples: &mut [i16]);
+}
+
+impl<F: FnMut(&mut [i16])> Stage for F {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        self(samples)
+    }
+}
+
+/// Stage leaving the samples as they are, where every [`Pipeline`] starts out
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Passthrough;
+
+impl Stage for Passthrough {
+    #[inline]
+    fn process(&mut self, _samples: &mut [i16]) {}
+}
+
+/// Two stages run one after the other, [`Pipeline::stage`] nests them into one type
+#[derive(Debug, Clone)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    /// Stages run first, e.g. to read a [`Profiled`](crate::Profiled) stage after
+    /// [`Pipeline::into_parts`]
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    /// Stage run last
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+}
+
+impl<A: Stage, B: Stage> Stage for Chain<A, B> {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        self.first.process(samples);
+        self.second.process(samples);
+    }
+}
+
+/// Stage scaling every sample by a Q1.15 gain, saturating above unity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gain(pub u16);
+
+impl Stage for Gain {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        samples.iter_mut().for_each(|s| *s = scale_q15(*s, self.0));
+    }
+}
+
+impl Stage for Crossfeed {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        Crossfeed::process(self, samples)
+    }
+}
+
+impl<const N: usize> Stage for Monitor<N> {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        samples
+            .iter_mut()
+            .for_each(|s| *s = self.process_sample(*s));
+    }
+}
+
+/// Playback chain from a [`Decoder`] through [`Stage`]s into an [`AudioSink`], put together at
+/// compile time.
+///
+/// Every stage added with [`Pipeline::stage`] becomes part of the pipeline's type, so the
+/// compiler sees the whole chain and can inline it into one loop, without dynamic dispatch. The
+/// sink receives little endian 16 bit PCM.
+///
+/// ```
+/// use audio_parser::{Crossfeed, Gain, Pipeline, SliceSink, Wav, UNITY_GAIN};
+///
+/// let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+/// let mut out = [0; 4096];
+///
+/// let mut pipeline = Pipeline::new(Wav::from_bytes(bytes).unwrap(), SliceSink::new(&mut out))
+///     .stage(Gain(UNITY_GAIN / 2))
+///     .stage(Crossfeed::new(48_000));
+///
+/// assert_eq!(pipeline.run::<256>().unwrap(), 256);
+/// ```
+pub struct Pipeline<D, P, K> {
+    decoder: D,
+    stages: P,
+    sink: K,
+}
+
+impl<D: Decoder, K: AudioSink> Pipeline<D, Passthrough, K> {
+    /// Pipeline copying the decoded samples into `sink` unchanged, until stages are added
+    pub fn new(decoder: D, sink: K) -> Self {
+        Pipeline {
+            decoder,
+            stages: Passthrough,
+            sink,
+        }
+    }
+}
+
+impl<D: Decoder, P: Stage, K: AudioSink> Pipeline<D, P, K> {
+    /// Append `stage` after the stages added so far
+    pub fn stage<T: Stage>(self, stage: T) -> Pipeline<D, Chain<P, T>, K> {
+        Pipeline {
+            decoder: self.decoder,
+            stages: Chain {
+                first: self.stages,
+                second: stage,
+            },
+            sink: self.sink,
+        }
+    }
+
+    /// Decode up to `N` samples, run them through the stages and write them to the sink, returns
+    /// the number of samples written, `0` once the decoder ran out.
+    ///
+    /// Sink errors are reported as [`Error::Io`].
+    #[inline]
+    pub fn run<const N: usize>(&mut self) -> Result<usize, Error<D::Error>> {
+        let mut samples = [0; N];
+        let decoded = self.decoder.decode(&mut samples)?;
+        let samples = &mut samples[..decoded];
+
+        self.stages.process(samples);
+        write_samples(&mut self.sink, samples)?;
+
+        Ok(decoded)
+    }
+
+    /// Call [`Pipeline::run`] until the decoder runs out, returns the total number of samples
+    pub fn run_to_end<const N: usize>(&mut self) -> Result<usize, Error<D::Error>> {
+        let mut total = 0;
+
+        loop {
+            match self.run::<N>()? {
+                0 => return Ok(total),
+                n => total += n,
+            }
+        }
+    }
+
+    /// Take the pipeline apart into its decoder, stages and sink
+    pub fn into_parts(self) -> (D, P, K) {
+        (self.decoder, self.stages, self.sink)
+    }
+}
+
+/// Playback chain like [`Pipeline`] whose stages can be changed while it runs, e.g. from user
+/// settings, at the cost of an indirect call per stage and block.
+///
+/// Holds up to `MAX` borrowed stages in place, no allocator needed.
+///
+/// ```
+/// use audio_parser::{DynPipeline, Gain, SliceSink, Stage, Wav, UNITY_GAIN};
+///
+/// let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+/// let mut out = [0; 4096];
+/// let mut quiet = Gain(UNITY_GAIN / 4);
+///
+/// let mut pipeline: DynPipeline<_, _, 4> =
+///     DynPipeline::new(Wav::from_bytes(bytes).unwrap(), SliceSink::new(&mut out));
+/// pipeline.push(&mut quiet).ok().unwrap();
+/// assert_eq!(pipeline.run::<256>().unwrap(), 256);
+///
+/// pipeline.remove(0);
+/// assert_eq!(pipeline.run::<256>().unwrap(), 256);
+/// ```
+pub struct DynPipeline<'a, D, K, const MAX: usize> {
+    decoder: D,
+    stages: Vec<&'a mut dyn Stage, MAX>,
+    sink: K,
+}
+
+impl<'a, D: Decoder, K: AudioSink, const MAX: usize> DynPipeline<'a, D, K, MAX> {
+    /// Pipeline copying the decoded samples into `sink` unchanged, until stages are pushed
+    pub fn new(decoder: D, sink: K) -> Self {
+        DynPipeline {
+            decoder,
+            stages: Vec::new(),
+            sink,
+        }
+    }
+
+    /// Number of stages in the chain
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// True when samples pass through unchanged
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Append `stage` after the current stages, gives it back if `MAX` stages are in use
+    pub fn push(&mut self, stage: &'a mut dyn Stage) -> Result<(), &'a mut dyn Stage> {
+        self.stages.push(stage)
+    }
+
+    /// Put `stage` at `index`, shifting the later stages back, gives it back if `MAX` stages are
+    /// in use or `index` is past the end
+    pub fn insert(
+        &mut self,
+        index: usize,
+        stage: &'a mut dyn Stage,
+    ) -> Result<(), &'a mut dyn Stage> {
+        if index > self.stages.len() {
+            return Err(stage);
+        }
+
+        self.stages.insert(index, stage)
+    }
+
+    /// Take out the stage at `index`, `None` if there is none
+    pub fn remove(&mut self, index: usize) -> Option<&'a mut dyn Stage> {
+        if index < self.stages.len() {
+            Some(self.stages.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Drop every stage, samples pass through unchanged afterwards
+    pub fn clear(&mut self) {
+        self.stages.clear();
+    }
+
+    /// Decode up to `N` samples, run them through the stages and write them to the sink, returns
+    /// the number of samples written, `0` once the decoder ran out.
+    ///
+    /// Sink errors are reported as [`Error::Io`].
+    pub fn run<const N: usize>(&mut self) -> Result<usize, Error<D::Error>> {
+        let mut samples = [0; N];
+        let decoded = self.decoder.decode(&mut samples)?;
+        let samples = &mut samples[..decoded];
+
+        for stage in self.stages.iter_mut() {
+            stage.process(samples);
+        }
+
+        write_samples(&mut self.sink, samples)?;
+
+        Ok(decoded)
+    }
+
+    /// Call [`DynPipeline::run`] until the decoder runs out, returns the total number of samples
+    pub fn run_to_end<const N: usize>(&mut self) -> Result<usize, Error<D::Error>> {
+        let mut total = 0;
+
+        loop {
+            match self.run::<N>()? {
+                0 => return Ok(total),
+                n => total += n,
+            }
+        }
+    }
+
+    /// Take the pipeline apart into its decoder and sink, releasing the stages
+    pub fn into_parts(self) -> (D, K) {
+        (self.decoder, self.sink)
+    }
+}
+
+/// Write `samples` to `sink` as little endian 16 bit PCM
+pub(crate) fn write_samples<K: AudioSink, E>(
+    sink: &mut K,
+    samples: &[i16],
+) -> Result<(), Error<E>> {
+    let mut bytes = [0; SINK_CHUNK_LEN];
+
+    for chunk in samples.chunks(SINK_CHUNK_LEN / 2) {
+        for (b, s) in bytes.chunks_exact_mut(2).zip(chunk) {
+            b.copy_from_slice(&s.to_le_bytes());
+        }
+
+        sink.write(&bytes[..chunk.len() * 2])
+            .map_err(|_| Error::Io)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+    use crate::wav::Wav;
+
+    /// Sink dropping everything written to it
+    struct Discard;
+
+    impl AudioSink for Discard {
+        type Error = ();
+
+        fn write(&mut self, _bytes: &[u8]) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_run_stages_in_order() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+        let mut out = [0; 2000];
+
+        let mut pipeline = Pipeline::new(Wav::from_bytes(bytes).unwrap(), SliceSink::new(&mut out))
+            .stage(Gain(u16::MAX))
+            .stage(|samples: &mut [i16]| samples.iter_mut().for_each(|s| *s /= 2));
+
+        assert_eq!(pipeline.run::<1000>().unwrap(), 1000);
+
+        let (_, _, sink) = pipeline.into_parts();
+        let mut expected = [0; 1000];
+        reference.decode(&mut expected).unwrap();
+
+        for (written, &sample) in sink.written().chunks_exact(2).zip(&expected) {
+            let doubled = scale_q15(sample, u16::MAX);
+            assert_eq!(i16::from_le_bytes([written[0], written[1]]), doubled / 2);
+        }
+    }
+
+    #[test]
+    fn should_run_to_the_end_and_report_sink_errors() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let total = wav.duration().unwrap().frames as usize * 2;
+
+        let mut counted = 0;
+        let mut pipeline =
+            Pipeline::new(wav, Discard).stage(|samples: &mut [i16]| counted += samples.len());
+
+        assert_eq!(pipeline.run_to_end::<512>().unwrap(), total);
+        drop(pipeline);
+        assert_eq!(counted, total);
+
+        let mut full = Pipeline::new(Wav::from_bytes(bytes).unwrap(), SliceSink::new(&mut []));
+        assert!(matches!(full.run::<64>(), Err(Error::Io)));
+    }
+
+    #[test]
+    fn should_change_dynamic_stages_between_runs() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+        let mut expected = [0; 200];
+        reference.decode(&mut expected).unwrap();
+
+        let mut halve = |samples: &mut [i16]| samples.iter_mut().for_each(|s| *s /= 2);
+        let mut negate = |samples: &mut [i16]| samples.iter_mut().for_each(|s| *s = -*s);
+        let mut extra = Passthrough;
+        let mut out = [0; 400];
+
+        let mut pipeline: DynPipeline<_, _, 2> =
+            DynPipeline::new(Wav::from_bytes(bytes).unwrap(), SliceSink::new(&mut out));
+        assert!(pipeline.push(&mut halve).is_ok());
+        assert!(pipeline.insert(0, &mut negate).is_ok());
+        assert!(pipeline.push(&mut extra).is_err());
+        assert_eq!(pipeline.len(), 2);
+        assert_eq!(pipeline.run::<100>().unwrap(), 100);
+
+        assert!(pipeline.remove(0).is_some());
+        assert!(pipeline.remove(1).is_none());
+        assert_eq!(pipeline.run::<100>().unwrap(), 100);
+
+        let (_, sink) = pipeline.into_parts();
+        let written: std::vec::Vec<i16> = sink
+            .written()
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+
+        for (i, (&w, &e)) in written.iter().zip(&expected).enumerate() {
+            let e = if i < 100 { -e / 2 } else { e / 2 };
+            assert_eq!(w, e);
+        }
+    }
+}
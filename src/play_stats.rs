@@ -0,0 +1,416 @@
+use crate::error::Error;
+use crate::identity::{TrackIdentity, TRACK_IDENTITY_LEN};
+use crate::index::INDEX_NAME_LEN;
+use crate::metadata::to_string;
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::wav::read_full;
+use core::convert::TryInto;
+use heapless::{String, Vec};
+
+/// Bytes at the start of a play statistics file
+const STATS_MAGIC: &[u8; 4] = b"APPS";
+/// Layout version of the records, bumped whenever it changes
+const STATS_VERSION: u8 = 2;
+/// Bytes in front of the first record
+const STATS_HEADER_LEN: usize = 8;
+/// Bytes of one record of a play statistics file
+pub const PLAY_RECORD_LEN: usize = TRACK_IDENTITY_LEN + INDEX_NAME_LEN + 12;
+/// Offset of the name in a record
+const NAME: usize = TRACK_IDENTITY_LEN;
+/// Offset of the counters in a record
+const FIELDS: usize = NAME + INDEX_NAME_LEN;
+
+/// How often and when a track was played, and when it was first seen
+///
+/// Kept per [`TrackIdentity`], so tracks of the same name in different folders are counted
+/// apart. The name is only stored for showing the record. Times are whatever the caller counts
+/// in, e.g. seconds from an RTC, and are only compared with each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayRecord {
+    identity: TrackIdentity,
+    name: String<INDEX_NAME_LEN>,
+    plays: u32,
+    added: u32,
+    last_played: u32,
+}
+
+impl PlayRecord {
+    /// Identity of the track the record belongs to
+    pub fn identity(&self) -> &TrackIdentity {
+        &self.identity
+    }
+
+    /// Name of the track as given when it was recorded, e.g. its file name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of times the track was played
+    pub fn plays(&self) -> u32 {
+        self.plays
+    }
+
+    /// Time the track was first recorded
+    pub fn added(&self) -> u32 {
+        self.added
+    }
+
+    /// Time the track was last played, `None` if it never was
+    pub fn last_played(&self) -> Option<u32> {
+        match self.plays {
+            0 => None,
+            _ => Some(self.last_played),
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; PLAY_RECORD_LEN] {
+        let mut bytes = [0; PLAY_RECORD_LEN];
+
+        bytes[..NAME].copy_from_slice(&self.identity.to_bytes());
+        bytes[NAME..NAME + self.name.len()].copy_from_slice(self.name.as_bytes());
+        bytes[FIELDS..FIELDS + 4].copy_from_slice(&self.plays.to_le_bytes());
+        bytes[FIELDS + 4..FIELDS + 8].copy_from_slice(&self.added.to_le_bytes());
+        bytes[FIELDS + 8..].copy_from_slice(&self.last_played.to_le_bytes());
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; PLAY_RECORD_LEN]) -> Self {
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        PlayRecord {
+            identity: TrackIdentity::from_bytes(bytes[..NAME].try_into().unwrap()),
+            name: to_string(&bytes[NAME..FIELDS]),
+            plays: field(FIELDS),
+            added: field(FIELDS + 4),
+            last_played: field(FIELDS + 8),
+        }
+    }
+}
+
+/// Play counts and times of tracks kept in a small file next to the music, one fixed size record
+/// per track, for "most played" and "recently added" views.
+///
+/// Records are looked up by [`TrackIdentity`] and updated in place, nothing but the record at
+/// hand is kept in RAM. Sink errors are reported as [`Error::Io`].
+pub struct PlayStats<S: AudioSource + AudioSink> {
+    file: S,
+    len: u32,
+}
+
+impl<S: AudioSource + AudioSink> PlayStats<S> {
+    /// Open the play statistics in `file`, starting a new file if it is empty.
+    ///
+    /// Returns [`Error::UnknownFileFormat`] if it holds something else or records of another
+    /// version.
+    pub fn new(mut file: S) -> Result<Self, Error<<S as AudioSource>::Error>> {
+        let mut header = [0; STATS_HEADER_LEN];
+        file.seek(0).map_err(Error::Source)?;
+
+        if file.length() == 0 {
+            header[..4].copy_from_slice(STATS_MAGIC);
+            header[4] = STATS_VERSION;
+            AudioSink::write(&mut file, &header).map_err(|_| Error::Io)?;
+
+            return Ok(PlayStats { file, len: 0 });
+        }
+
+        if read_full(&mut file, &mut header)? != STATS_HEADER_LEN
+            || &header[..4] != STATS_MAGIC
+            || header[4] != STATS_VERSION
+        {
+            return Err(Error::UnknownFileFormat);
+        }
+
+        let len = (file.length() as usize).saturating_sub(STATS_HEADER_LEN) / PLAY_RECORD_LEN;
+
+        Ok(PlayStats {
+            file,
+            len: len as u32,
+        })
+    }
+
+    /// Number of tracks with a record
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// True if no track was recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read the record at `index`, `None` past the last one
+    pub fn get(
+        &mut self,
+        index: u32,
+    ) -> Result<Option<PlayRecord>, Error<<S as AudioSource>::Error>> {
+        if index >= self.len {
+            return Ok(None);
+        }
+
+        self.file.seek(offset(index)).map_err(Error::Source)?;
+        let mut bytes = [0; PLAY_RECORD_LEN];
+
+        if read_full(&mut self.file, &mut bytes)? != PLAY_RECORD_LEN {
+            return Err(Error::EndOfData);
+        }
+
+        Ok(Some(PlayRecord::from_bytes(&bytes)))
+    }
+
+    /// Record of the track `identity`, with its position
+    pub fn find(
+        &mut self,
+        identity: &TrackIdentity,
+    ) -> Result<Option<(u32, PlayRecord)>, Error<<S as AudioSource>::Error>> {
+        for index in 0..self.len {
+            match self.get(index)? {
+                Some(record) if record.identity == *identity => return Ok(Some((index, record))),
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Note that the track `identity` named `name` showed up at `now`, e.g. while building an
+    /// index, unless it already has a record
+    pub fn added(
+        &mut self,
+        identity: TrackIdentity,
+        name: &str,
+        now: u32,
+    ) -> Result<PlayRecord, Error<<S as AudioSource>::Error>> {
+        match self.find(&identity)? {
+            Some((_, record)) => Ok(record),
+            None => self.append(identity, name, now),
+        }
+    }
+
+    /// Count a play of the track `identity` at `now`, e.g. once a track is played to its end.
+    ///
+    /// Tracks without a record are added at `now` under `name`.
+    pub fn played(
+        &mut self,
+        identity: TrackIdentity,
+        name: &str,
+        now: u32,
+    ) -> Result<PlayRecord, Error<<S as AudioSource>::Error>> {
+        let (index, mut record) = match self.find(&identity)? {
+            Some(found) => found,
+            None => (self.len, self.append(identity, name, now)?),
+        };
+
+        record.plays = record.plays.saturating_add(1);
+        record.last_played = now;
+        self.write(index, &record)?;
+
+        Ok(record)
+    }
+
+    /// Up to `N` tracks that were played the most, most played first
+    pub fn most_played<const N: usize>(
+        &mut self,
+    ) -> Result<Vec<PlayRecord, N>, Error<<S as AudioSource>::Error>> {
+        self.top(|record| match record.plays {
+            0 => None,
+            plays => Some(plays),
+        })
+    }
+
+    /// Up to `N` tracks that were played last, the latest first
+    pub fn recently_played<const N: usize>(
+        &mut self,
+    ) -> Result<Vec<PlayRecord, N>, Error<<S as AudioSource>::Error>> {
+        self.top(PlayRecord::last_played)
+    }
+
+    /// Up to `N` tracks that were added last, the latest first
+    pub fn recently_added<const N: usize>(
+        &mut self,
+    ) -> Result<Vec<PlayRecord, N>, Error<<S as AudioSource>::Error>> {
+        self.top(|record| Some(record.added))
+    }
+
+    /// Destroy the [`PlayStats`] instance and get the underlying file
+    pub fn destroy(self) -> S {
+        self.file
+    }
+
+    /// The `N` records with the largest `key`, records without one are left out and ties keep
+    /// their order in the file
+    fn top<const N: usize>(
+        &mut self,
+        key: impl Fn(&PlayRecord) -> Option<u32>,
+    ) -> Result<Vec<PlayRecord, N>, Error<<S as AudioSource>::Error>> {
+        let mut top: Vec<PlayRecord, N> = Vec::new();
+
+        for index in 0..self.len {
+            let (record, value) = match self.get(index)? {
+                Some(record) => match key(&record) {
+                    Some(value) => (record, value),
+                    None => continue,
+                },
+                None => break,
+            };
+
+            let at = top
+                .iter()
+                .position(|other| key(other) < Some(value))
+                .unwrap_or(top.len());
+
+            if at == N {
+                continue;
+            }
+
+            if top.is_full() {
+                top.pop();
+            }
+
+            let _ = top.insert(at, record);
+        }
+
+        Ok(top)
+    }
+
+    fn append(
+        &mut self,
+        identity: TrackIdentity,
+        name: &str,
+        now: u32,
+    ) -> Result<PlayRecord, Error<<S as AudioSource>::Error>> {
+        let record = PlayRecord {
+            identity,
+            name: to_string(name.as_bytes()),
+            plays: 0,
+            added: now,
+            last_played: 0,
+        };
+
+        self.write(self.len, &record)?;
+        self.len += 1;
+
+        Ok(record)
+    }
+
+    fn write(
+        &mut self,
+        index: u32,
+        record: &PlayRecord,
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        self.file.seek(offset(index)).map_err(Error::Source)?;
+        AudioSink::write(&mut self.file, &record.to_bytes()).map_err(|_| Error::Io)
+    }
+}
+
+/// Byte offset of the record at `index`
+fn offset(index: u32) -> u32 {
+    STATS_HEADER_LEN as u32 + index * PLAY_RECORD_LEN as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::RamFile;
+
+    fn names<const N: usize>(records: &Vec<PlayRecord, N>) -> std::vec::Vec<&str> {
+        records.iter().map(PlayRecord::name).collect()
+    }
+
+    fn track(path: &str) -> TrackIdentity {
+        TrackIdentity::new(path, 1_000, 0)
+    }
+
+    #[test]
+    fn should_count_plays_across_reopening() {
+        let mut stats = PlayStats::new(RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        })
+        .unwrap();
+
+        stats.added(track("A.WAV"), "A.WAV", 10).unwrap();
+        stats.added(track("B.MP3"), "B.MP3", 20).unwrap();
+        stats.played(track("B.MP3"), "B.MP3", 30).unwrap();
+        stats.played(track("C.FLA"), "C.FLA", 40).unwrap();
+        stats.played(track("B.MP3"), "B.MP3", 50).unwrap();
+        assert_eq!(
+            stats.added(track("B.MP3"), "B.MP3", 60).unwrap().added(),
+            20
+        );
+
+        let mut stats = PlayStats::new(stats.destroy()).unwrap();
+        assert_eq!(stats.len(), 3);
+
+        let (_, record) = stats.find(&track("B.MP3")).unwrap().unwrap();
+        assert_eq!((record.plays(), record.last_played()), (2, Some(50)));
+        assert_eq!(*record.identity(), track("B.MP3"));
+        assert_eq!(
+            stats
+                .find(&track("A.WAV"))
+                .unwrap()
+                .unwrap()
+                .1
+                .last_played(),
+            None
+        );
+
+        assert_eq!(
+            names(&stats.most_played::<4>().unwrap()),
+            ["B.MP3", "C.FLA"]
+        );
+        assert_eq!(names(&stats.recently_played::<1>().unwrap()), ["B.MP3"]);
+        assert_eq!(
+            names(&stats.recently_added::<2>().unwrap()),
+            ["C.FLA", "B.MP3"]
+        );
+    }
+
+    #[test]
+    fn should_count_tracks_of_the_same_name_apart() {
+        let mut stats = PlayStats::new(RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        })
+        .unwrap();
+
+        stats
+            .played(track("ALBUM1/TRACK01.MP3"), "TRACK01.MP3", 10)
+            .unwrap();
+        stats
+            .played(track("ALBUM2/TRACK01.MP3"), "TRACK01.MP3", 20)
+            .unwrap();
+        stats
+            .played(track("ALBUM2/TRACK01.MP3"), "TRACK01.MP3", 30)
+            .unwrap();
+
+        assert_eq!(stats.len(), 2);
+
+        let (index, record) = stats.find(&track("album1\\track01.mp3")).unwrap().unwrap();
+        assert_eq!((index, record.plays()), (0, 1));
+        assert_eq!(
+            stats
+                .find(&track("ALBUM2/TRACK01.MP3"))
+                .unwrap()
+                .unwrap()
+                .1
+                .plays(),
+            2
+        );
+    }
+
+    #[test]
+    fn should_reject_other_files() {
+        let file = RamFile {
+            bytes: include_bytes!("../test_files/stereo_16_48000.wav").to_vec(),
+            offset: 0,
+        };
+
+        assert!(matches!(
+            PlayStats::new(file),
+            Err(Error::UnknownFileFormat)
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::Error;
+use crate::fmt::Fmt;
+use crate::source::{AudioSource, CHUNK_LEN};
+use crate::wav::{read_full, Wav};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Double buffered reader of the raw sample data of a [`Wav`], so the next block can be read
+/// from slow storage while the current one is played.
+///
+/// [`BufferedAudioFile::split`] gives a [`PrefetchProducer`] for the main loop, which calls
+/// [`PrefetchProducer::fill_next`] whenever the back buffer is free, and a [`PrefetchConsumer`]
+/// for the audio ISR or DMA, which plays [`PrefetchConsumer::current`] and calls
+/// [`PrefetchConsumer::swap`] once done with it. Buffers hold up to [`CHUNK_LEN`] bytes of whole
+/// frames in the format of the file.
+pub struct BufferedAudioFile<S: AudioSource> {
+    wav: Wav<S>,
+    buffers: Buffers,
+}
+
+/// Buffers shared by the two halves of a [`BufferedAudioFile`]
+struct Buffers {
+    data: [UnsafeCell<[u8; CHUNK_LEN]>; 2],
+    lens: [AtomicUsize; 2],
+    /// Index of the buffer being played, only changed by the consumer
+    front: AtomicUsize,
+    /// Set by the producer once the back buffer is filled, cleared by the consumer on a swap
+    next_ready: AtomicBool,
+}
+
+// SAFETY: the producer only writes the back buffer while `next_ready` is clear and the consumer
+// only reads the front buffer, they are handed over with release and acquire ordering
+unsafe impl Sync for Buffers {}
+
+impl<S: AudioSource> BufferedAudioFile<S> {
+    /// Prefetch the sample data of `wav` from its current position, nothing is buffered until
+    /// the first [`PrefetchProducer::fill_next`] and [`PrefetchConsumer::swap`]
+    pub fn new(wav: Wav<S>) -> Self {
+        BufferedAudioFile {
+            wav,
+            buffers: Buffers {
+                data: [
+                    UnsafeCell::new([0; CHUNK_LEN]),
+                    UnsafeCell::new([0; CHUNK_LEN]),
+                ],
+                lens: [AtomicUsize::new(0), AtomicUsize::new(0)],
+                front: AtomicUsize::new(0),
+                next_ready: AtomicBool::new(false),
+            },
+        }
+    }
+
+    /// Format of the buffered samples
+    pub fn fmt(&self) -> &Fmt {
+        &self.wav.fmt
+    }
+
+    /// Split into the reading and the playing end
+    pub fn split(&mut self) -> (PrefetchProducer<'_, S>, PrefetchConsumer<'_>) {
+        let buffers = &self.buffers;
+
+        (
+            PrefetchProducer {
+                wav: &mut self.wav,
+                buffers,
+            },
+            PrefetchConsumer { buffers },
+        )
+    }
+
+    /// Give back the [`Wav`], positioned after the last prefetched byte
+    pub fn into_inner(self) -> Wav<S> {
+        self.wav
+    }
+}
+
+/// Reading end of a [`BufferedAudioFile`], filling the back buffer from storage
+pub struct PrefetchProducer<'a, S: AudioSource> {
+    wav: &'a mut Wav<S>,
+    buffers: &'a Buffers,
+}
+
+impl<'a, S: AudioSource> PrefetchProducer<'a, S> {
+    /// Read the next block into the back buffer, returns its length in bytes, `0` at the end of
+    /// the data chunk. Does nothing while the back buffer still waits for a swap.
+    ///
+    /// Returns [`Error::BufferTooSmall`] if a single frame doesn't fit in [`CHUNK_LEN`].
+    pub fn fill_next(&mut self) -> Result<usize, Error<S::Error>> {
+        let ready = self.buffers.next_ready.load(Ordering::Acquire);
+        let back = 1 - self.buffers.front.load(Ordering::Relaxed);
+
+        if ready {
+            return Ok(self.buffers.lens[back].load(Ordering::Relaxed));
+        }
+
+        let block_align = self.wav.fmt.block_align().max(1);
+
+        if block_align > CHUNK_LEN {
+            return Err(Error::BufferTooSmall(block_align));
+        }
+
+        let len = (CHUNK_LEN / block_align).min(self.wav.frames_left()) * block_align;
+
+        // SAFETY: `next_ready` is clear, the consumer doesn't touch the back buffer until it is
+        // set below
+        let buffer = unsafe { &mut *self.buffers.data[back].get() };
+        let read = read_full(&mut self.wav.source, &mut buffer[..len])?;
+        let len = read / block_align * block_align;
+
+        self.buffers.lens[back].store(len, Ordering::Relaxed);
+        self.buffers.next_ready.store(true, Ordering::Release);
+
+        Ok(len)
+    }
+
+    /// True when the back buffer is filled and waits to be swapped in
+    pub fn is_next_ready(&self) -> bool {
+        self.buffers.next_ready.load(Ordering::Acquire)
+    }
+}
+
+/// Playing end of a [`BufferedAudioFile`], handing out the front buffer
+pub struct PrefetchConsumer<'a> {
+    buffers: &'a Buffers,
+}
+
+impl<'a> PrefetchConsumer<'a> {
+    /// True when the back buffer is filled and ready to be swapped in
+    pub fn is_next_ready(&self) -> bool {
+        self.buffers.next_ready.load(Ordering::Acquire)
+    }
+
+    /// Make the back buffer current, returns `false` if it wasn't filled yet, an underrun
+    pub fn swap(&mut self) -> bool {
+        if !self.buffers.next_ready.load(Ordering::Acquire) {
+            return false;
+        }
+
+        let front = self.buffers.front.load(Ordering::Relaxed);
+        self.buffers.front.store(1 - front, Ordering::Relaxed);
+        self.buffers.next_ready.store(false, Ordering::Release);
+
+        true
+    }
+
+    /// Bytes of the current buffer, empty before the first swap and at the end of the data
+    pub fn current(&self) -> &[u8] {
+        let front = self.buffers.front.load(Ordering::Relaxed);
+        let len = self.buffers.lens[front].load(Ordering::Relaxed);
+
+        // SAFETY: the producer doesn't write the front buffer, and it only becomes the back
+        // buffer through `swap`, which can't be called while the slice is borrowed
+        let buffer = unsafe { &*self.buffers.data[front].get() };
+        &buffer[..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_alternate_buffers_through_the_data() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let wav = Wav::from_bytes(bytes).unwrap();
+        let data = &bytes[wav.data.start..wav.data.end.min(bytes.len())];
+        let mut file = BufferedAudioFile::new(wav);
+        let (mut producer, mut consumer) = file.split();
+
+        assert!(consumer.current().is_empty());
+        assert!(!consumer.swap());
+
+        let mut played = 0;
+
+        loop {
+            let filled = producer.fill_next().unwrap();
+            assert_eq!(producer.fill_next().unwrap(), filled);
+            assert!(consumer.is_next_ready());
+            assert!(consumer.swap());
+
+            if filled == 0 {
+                break;
+            }
+
+            // whole 6 byte frames only
+            assert_eq!(consumer.current().len(), 510.min(data.len() - played));
+            assert_eq!(consumer.current(), &data[played..played + filled]);
+            played += filled;
+        }
+
+        assert_eq!(played, data.len() / 6 * 6);
+        assert!(file.into_inner().is_end());
+    }
+
+    #[test]
+    fn should_fill_while_the_other_thread_plays() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let wav = Wav::from_bytes(bytes).unwrap();
+        let data = &bytes[wav.data.start..wav.data.end.min(bytes.len())];
+        let mut file = BufferedAudioFile::new(wav);
+        let (mut producer, mut consumer) = file.split();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || loop {
+                match producer.is_next_ready() {
+                    true => std::thread::yield_now(),
+                    false if producer.fill_next().unwrap() == 0 => break,
+                    false => {}
+                }
+            });
+
+            let mut played = std::vec::Vec::new();
+
+            loop {
+                if !consumer.swap() {
+                    std::thread::yield_now();
+                    continue;
+                }
+
+                if consumer.current().is_empty() {
+                    break;
+                }
+
+                played.extend_from_slice(consumer.current());
+            }
+
+            assert_eq!(played, &data[..data.len() / 6 * 6]);
+        });
+    }
+}
@@ -0,0 +1,227 @@
+use crate::decoder::{Decoder, DecoderInfo};
+use crate::error::Error;
+use crate::pipeline::Stage;
+use crate::sink::AudioSink;
+
+/// Free running cycle counter supplied by the user, e.g. the DWT `CYCCNT` register of a
+/// Cortex-M, allowed to wrap around
+pub trait CycleCounter {
+    /// Current cycle count
+    fn cycles(&self) -> u32;
+}
+
+impl<C: CycleCounter + ?Sized> CycleCounter for &C {
+    fn cycles(&self) -> u32 {
+        (**self).cycles()
+    }
+}
+
+/// Cycles spent in one part of a [`Pipeline`](crate::Pipeline), gathered by [`Profiled`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    /// Number of measured calls
+    pub calls: u32,
+    /// Cycles of all calls together
+    pub total: u64,
+    /// Cycles of the slowest call
+    pub max: u32,
+}
+
+impl CycleStats {
+    /// Average cycles per call, `0` before the first call
+    pub fn average(&self) -> u32 {
+        match self.calls {
+            0 => 0,
+            calls => (self.total / calls as u64) as u32,
+        }
+    }
+
+    fn record(&mut self, cycles: u32) {
+        self.calls = self.calls.saturating_add(1);
+        self.total = self.total.saturating_add(cycles as u64);
+        self.max = self.max.max(cycles);
+    }
+}
+
+/// Wrapper counting the cycles spent in a [`Stage`], [`Decoder`] or [`AudioSink`], to see which
+/// part of a pipeline eats the CPU budget
+///
+/// Wrap only what should be measured, the rest of the pipeline runs as before. A measurement
+/// costs two reads of the counter per call.
+///
+/// ```
+/// use audio_parser::{CycleCounter, Gain, Pipeline, Profiled, SliceSink, Wav, UNITY_GAIN};
+/// use core::cell::Cell;
+///
+/// /// Stand-in for a hardware counter
+/// struct Ticks(Cell<u32>);
+///
+/// impl CycleCounter for Ticks {
+///     fn cycles(&self) -> u32 {
+///         self.0.set(self.0.get() + 10);
+///         self.0.get()
+///     }
+/// }
+///
+/// let ticks = Ticks(Cell::new(0));
+/// let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+/// let mut out = [0; 4096];
+///
+/// let decoder = Profiled::new(Wav::from_bytes(bytes).unwrap(), &ticks);
+/// let mut pipeline = Pipeline::new(decoder, SliceSink::new(&mut out))
+///     .stage(Profiled::new(Gain(UNITY_GAIN / 2), &ticks));
+/// pipeline.run::<256>().unwrap();
+///
+/// let (decoder, stages, _) = pipeline.into_parts();
+/// assert_eq!(decoder.stats().calls, 1);
+/// assert_eq!(stages.second().stats().calls, 1);
+/// ```
+pub struct Profiled<T, C> {
+    inner: T,
+    counter: C,
+    stats: CycleStats,
+}
+
+impl<T, C: CycleCounter> Profiled<T, C> {
+    /// Measure every call into `inner` with `counter`
+    pub fn new(inner: T, counter: C) -> Self {
+        Profiled {
+            inner,
+            counter,
+            stats: CycleStats::default(),
+        }
+    }
+
+    /// Cycles measured so far
+    pub fn stats(&self) -> CycleStats {
+        self.stats
+    }
+
+    /// Forget the measurements, e.g. once per second to watch the load change
+    pub fn reset(&mut self) {
+        self.stats = CycleStats::default();
+    }
+
+    /// The measured part, e.g. to change its settings
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Stop measuring and give back the measured part
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    #[inline]
+    fn measure<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        let start = self.counter.cycles();
+        let result = f(&mut self.inner);
+        let cycles = self.counter.cycles().wrapping_sub(start);
+
+        self.stats.record(cycles);
+
+        result
+    }
+}
+
+impl<T: Stage, C: CycleCounter> Stage for Profiled<T, C> {
+    #[inline]
+    fn process(&mut self, samples: &mut [i16]) {
+        self.measure(|stage| stage.process(samples))
+    }
+}
+
+impl<T: Decoder, C: CycleCounter> Decoder for Profiled<T, C> {
+    type Error = T::Error;
+
+    fn info(&self) -> DecoderInfo {
+        self.inner.info()
+    }
+
+    #[inline]
+    fn decode(&mut self, out: &mut [i16]) -> Result<usize, Error<Self::Error>> {
+        self.measure(|decoder| decoder.decode(out))
+    }
+}
+
+impl<T: AudioSink, C: CycleCounter> AudioSink for Profiled<T, C> {
+    type Error = T::Error;
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.measure(|sink| sink.write(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{Gain, Pipeline};
+    use crate::sink::SliceSink;
+    use crate::wav::Wav;
+    use core::cell::Cell;
+
+    /// Counter advancing by a fixed step on every read, starting close to wrapping
+    struct Steps {
+        now: Cell<u32>,
+        step: u32,
+    }
+
+    impl CycleCounter for Steps {
+        fn cycles(&self) -> u32 {
+            let now = self.now.get().wrapping_add(self.step);
+            self.now.set(now);
+            now
+        }
+    }
+
+    #[test]
+    fn should_measure_each_wrapped_part() {
+        let steps = Steps {
+            now: Cell::new(u32::MAX - 5),
+            step: 7,
+        };
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut out = [0; 4096];
+
+        let decoder = Profiled::new(Wav::from_bytes(bytes).unwrap(), &steps);
+        let sink = Profiled::new(SliceSink::new(&mut out), &steps);
+        let mut pipeline = Pipeline::new(decoder, sink)
+            .stage(Profiled::new(Gain(0), &steps))
+            .stage(|samples: &mut [i16]| assert!(samples.iter().all(|&s| s == 0)));
+
+        assert_eq!(pipeline.run::<128>().unwrap(), 128);
+        assert_eq!(pipeline.run::<128>().unwrap(), 128);
+
+        let (decoder, stages, sink) = pipeline.into_parts();
+        let expected = CycleStats {
+            calls: 2,
+            total: 14,
+            max: 7,
+        };
+
+        assert_eq!(decoder.stats(), expected);
+        assert_eq!(decoder.info().sample_rate, 48_000);
+        assert_eq!(stages.first().second().stats(), expected);
+        // 256 bytes per run go out in 64 byte chunks
+        assert_eq!(sink.stats().calls, 8);
+        assert_eq!(sink.stats().average(), 7);
+    }
+
+    #[test]
+    fn should_reset_stats() {
+        let steps = Steps {
+            now: Cell::new(0),
+            step: 3,
+        };
+        let mut gain = Profiled::new(Gain(0), &steps);
+
+        gain.process(&mut [1, 2]);
+        assert_eq!(gain.stats().max, 3);
+
+        gain.reset();
+        assert_eq!(gain.stats(), CycleStats::default());
+        assert_eq!(gain.stats().average(), 0);
+        assert_eq!(gain.into_inner(), Gain(0));
+    }
+}
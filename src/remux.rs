@@ -0,0 +1,416 @@
+use crate::error::Error;
+use crate::fmt::Fmt;
+#[cfg(feature = "write")]
+use crate::riff::ChunkTag;
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use core::convert::TryFrom;
+
+/// Size of a header holding only the RIFF, data and a PCM fmt chunk
+const CANONICAL_HEADER_SIZE: usize = 44;
+
+/// Write a canonical WAV header for `data_len` bytes of sample data, returns its size: 44 bytes,
+/// or 46 for formats whose fmt chunk carries a `cbSize`.
+///
+/// Returns [`Error::TooLarge`] if the sample data doesn't fit the 32 bit RIFF sizes.
+pub(crate) fn write_header<K: AudioSink, E>(
+    sink: &mut K,
+    fmt: &Fmt,
+    data_len: usize,
+) -> Result<u32, Error<E>> {
+    let fmt_chunk = fmt.to_chunk().map_err(Error::widen)?;
+    let fmt_end = 20 + fmt_chunk.len();
+    let header_len = fmt_end + 8;
+
+    let data_len = u32::try_from(data_len).map_err(|_| Error::TooLarge)?;
+    let riff_len = (header_len as u32 - 8 + (data_len & 1))
+        .checked_add(data_len)
+        .ok_or(Error::TooLarge)?;
+
+    let mut header = [0; CANONICAL_HEADER_SIZE + 2];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    header[8..12].copy_from_slice(b"WAVE");
+    header[12..16].copy_from_slice(b"fmt ");
+    header[16..20].copy_from_slice(&(fmt_chunk.len() as u32).to_le_bytes());
+    header[20..fmt_end].copy_from_slice(&fmt_chunk);
+    header[fmt_end..fmt_end + 4].copy_from_slice(b"data");
+    header[fmt_end + 4..header_len].copy_from_slice(&data_len.to_le_bytes());
+
+    sink.write(&header[..header_len]).map_err(|_| Error::Io)?;
+
+    Ok(header_len as u32)
+}
+
+/// Copy the sample data of `src` to `dst` behind a freshly written header using `fmt`.
+///
+/// Repairs files from recorders that write a wrong sample rate or bogus chunks: only the fmt and
+/// data chunks are written and the samples are copied unmodified. `fmt` has to keep the frame size
+/// of `src`, so the samples stay valid. Returns the number of sample bytes copied.
+#[cfg(feature = "write")]
+pub fn remux<S: AudioSource, K: AudioSink>(
+    src: &mut Wav<S>,
+    dst: &mut K,
+    fmt: Fmt,
+) -> Result<usize, Error<S::Error>> {
+    if fmt.block_align() != src.fmt.block_align() {
+        return Err(Error::FormatMismatch);
+    }
+
+    let len = src.data_end().saturating_sub(src.data.start);
+
+    write_header(dst, &fmt, len)?;
+    let copied = src.copy_data_to(dst)?;
+
+    if copied & 1 == 1 {
+        dst.write(&[0]).map_err(|_| Error::Io)?;
+    }
+
+    Ok(copied)
+}
+
+/// Join the sample data of `inputs` into a single file written to `dst`, in order.
+///
+/// Meant for merging segmented recordings, so every input has to share the fmt of the first one.
+/// Only whole frames of every input are copied to keep the channels aligned at the seams. Returns
+/// the number of sample bytes written, the read positions of the inputs are left unchanged.
+#[cfg(feature = "write")]
+pub fn concat<S: AudioSource, K: AudioSink>(
+    inputs: &mut [Wav<S>],
+    dst: &mut K,
+) -> Result<usize, Error<S::Error>> {
+    let fmt = inputs.first().ok_or(Error::NoFmtChunkFound)?.fmt;
+
+    if inputs.iter().any(|wav| wav.fmt != fmt) {
+        return Err(Error::FormatMismatch);
+    }
+
+    let block_align = fmt.block_align().max(1);
+    let whole_blocks = |wav: &Wav<S>| {
+        let len = wav.data_end().saturating_sub(wav.data.start);
+        len - len % block_align
+    };
+
+    let len: usize = inputs.iter().map(whole_blocks).sum();
+
+    write_header(dst, &fmt, len)?;
+
+    let mut copied = 0;
+
+    for wav in inputs.iter_mut() {
+        let len = whole_blocks(wav);
+        copied += wav.copy_data_bytes(dst, 0, len)?;
+    }
+
+    if copied & 1 == 1 {
+        dst.write(&[0]).map_err(|_| Error::Io)?;
+    }
+
+    Ok(copied)
+}
+
+/// Write the frames `start_frame..end_frame` of `src` to `dst` as a standalone WAV file.
+///
+/// Lets a device trim a recording without decoding it. The range is cut short to the frames in
+/// `src`, an empty range writes a file without samples. Returns the number of frames written, the
+/// read position of `src` is left unchanged.
+#[cfg(feature = "write")]
+pub fn extract<S: AudioSource, K: AudioSink>(
+    src: &mut Wav<S>,
+    start_frame: usize,
+    end_frame: usize,
+    dst: &mut K,
+) -> Result<usize, Error<S::Error>> {
+    let block_align = src.fmt.block_align().max(1);
+    let frames = src.data_end().saturating_sub(src.data.start) / block_align;
+
+    let end_frame = end_frame.min(frames);
+    let start_frame = start_frame.min(end_frame);
+    let len = (end_frame - start_frame) * block_align;
+
+    write_header(dst, &src.fmt, len)?;
+    let copied = src.copy_data_bytes(dst, start_frame * block_align, len)?;
+
+    if copied & 1 == 1 {
+        dst.write(&[0]).map_err(|_| Error::Io)?;
+    }
+
+    Ok(copied / block_align)
+}
+
+#[cfg(feature = "write")]
+impl<S: AudioSource + AudioSink> Wav<S> {
+    /// Overwrite the sample rate and the dependent byte rate in the fmt chunk of the file in place.
+    ///
+    /// For files recorded with a mislabeled rate, the sample data is not touched. The read position
+    /// is left unchanged.
+    pub fn set_sample_rate(
+        &mut self,
+        sample_rate: u32,
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        let fmt = self
+            .find_chunk(ChunkTag::Fmt)?
+            .ok_or(Error::NoFmtChunkFound)?;
+
+        let byte_rate = sample_rate as u64 * self.fmt.block_align() as u64
+            / self.fmt.frames_per_block().max(1) as u64;
+
+        let mut rates = [0; 8];
+        rates[0..4].copy_from_slice(&sample_rate.to_le_bytes());
+        rates[4..8].copy_from_slice(&(byte_rate as u32).to_le_bytes());
+
+        let position = self.source.offset();
+        self.source
+            .seek(fmt.start as u32 + 4)
+            .map_err(Error::Source)?;
+        AudioSink::write(&mut self.source, &rates).map_err(|_| Error::Io)?;
+        self.source.seek(position).map_err(Error::Source)?;
+
+        self.fmt.sample_rate = sample_rate;
+
+        Ok(())
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Stream the unmodified bytes of the data chunk to `sink`, returning the number of bytes copied.
+    ///
+    /// Nothing is decoded, so a file can be repaired or remuxed behind a new header quickly. The
+    /// read position is left unchanged.
+    pub fn copy_data_to<K: AudioSink>(&mut self, sink: &mut K) -> Result<usize, Error<S::Error>> {
+        let len = self.data_end().saturating_sub(self.data.start);
+
+        self.copy_data_bytes(sink, 0, len)
+    }
+
+    /// Copy `len` bytes of the data chunk starting `skip` bytes in to `sink`, leaving the read
+    /// position unchanged
+    fn copy_data_bytes<K: AudioSink>(
+        &mut self,
+        sink: &mut K,
+        skip: usize,
+        len: usize,
+    ) -> Result<usize, Error<S::Error>> {
+        let position = self.source.offset();
+        self.source
+            .seek((self.data.start + skip) as u32)
+            .map_err(Error::Source)?;
+
+        let mut buf = [0; 256];
+        let mut copied = 0;
+
+        while copied < len {
+            let wanted = buf.len().min(len - copied);
+            let read = read_full(&mut self.source, &mut buf[..wanted])?;
+
+            if read == 0 {
+                break;
+            }
+
+            sink.write(&buf[..read]).map_err(|_| Error::Io)?;
+            copied += read;
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(copied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fmt::AudioCodec;
+    use crate::riff::ChunkTag;
+    use crate::sink::SliceSink;
+    use core::convert::Infallible;
+
+    #[test]
+    fn should_copy_data_chunk_unmodified() {
+        let bytes = include_bytes!("../test_files/mono_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let (start, end) = (wav.data.start, wav.data.end);
+
+        let mut buf = std::vec![0; end - start];
+        let mut sink = SliceSink::new(&mut buf);
+
+        assert_eq!(wav.copy_data_to(&mut sink).unwrap(), end - start);
+        assert_eq!(sink.written(), &bytes[start..end]);
+        assert_eq!(wav.timestamp().frames, 0);
+
+        let mut small = [0; 16];
+        assert!(matches!(
+            wav.copy_data_to(&mut SliceSink::new(&mut small)),
+            Err(Error::Io)
+        ));
+    }
+
+    #[test]
+    fn should_write_headers_for_every_fmt() {
+        let mut fmt = Wav::from_bytes(include_bytes!("../test_files/mono_24_48000.wav"))
+            .unwrap()
+            .fmt;
+        let mut buf = [0; 64];
+
+        let len = write_header::<_, Infallible>(&mut SliceSink::new(&mut buf), &fmt, 6).unwrap();
+        assert_eq!(len, 44);
+
+        fmt.codec = AudioCodec::IeeeFloat;
+        fmt.bit_depth = 32;
+        fmt.block_size = 4;
+        let mut sink = SliceSink::new(&mut buf);
+        assert_eq!(
+            write_header::<_, Infallible>(&mut sink, &fmt, 8).unwrap(),
+            46
+        );
+        sink.write(&[0; 8]).unwrap();
+
+        let header = crate::wav::parse_header_bytes(sink.written()).unwrap();
+        assert_eq!((header.fmt, header.data.start), (fmt, 46));
+
+        // a hostile sample rate overflows the byte rate
+        fmt.sample_rate = u32::MAX;
+        assert_eq!(
+            write_header::<_, Infallible>(&mut SliceSink::new(&mut buf), &fmt, 0),
+            Err(Error::CantParseChunk(ChunkTag::Fmt))
+        );
+
+        // sample data past 4 GiB doesn't fit the RIFF sizes
+        fmt.sample_rate = 48_000;
+        assert_eq!(
+            write_header::<_, Infallible>(&mut SliceSink::new(&mut buf), &fmt, u32::MAX as usize),
+            Err(Error::TooLarge)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_remux_with_corrected_sample_rate() {
+        let bytes = include_bytes!("../test_files/mono_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let fmt = Fmt {
+            sample_rate: 44_100,
+            ..wav.fmt
+        };
+
+        let mut buf = std::vec![0; bytes.len()];
+        let mut sink = SliceSink::new(&mut buf);
+        let copied = remux(&mut wav, &mut sink, fmt).unwrap();
+
+        let fixed = Wav::from_bytes(sink.written()).unwrap();
+        assert_eq!(fixed.fmt.sample_rate, 44_100);
+        assert_eq!(fixed.data.start, 44);
+        assert_eq!(fixed.data.end - fixed.data.start, copied);
+        assert_eq!(sink.written().len(), 44 + copied + (copied & 1));
+
+        let stereo = Fmt {
+            num_channels: 2,
+            ..wav.fmt
+        };
+        assert!(matches!(
+            remux(&mut wav, &mut sink, stereo),
+            Err(Error::FormatMismatch)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_concat_matching_files() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut inputs = [
+            Wav::from_bytes(bytes).unwrap(),
+            Wav::from_bytes(bytes).unwrap(),
+        ];
+        let len = inputs[0].data.end - inputs[0].data.start;
+
+        let mut buf = std::vec![0; 2 * bytes.len()];
+        let mut sink = SliceSink::new(&mut buf);
+
+        assert_eq!(concat(&mut inputs, &mut sink).unwrap(), 2 * len);
+
+        let joined = Wav::from_bytes(sink.written()).unwrap();
+        let data = &sink.written()[joined.data.start..joined.data.end];
+
+        assert_eq!(joined.fmt, inputs[0].fmt);
+        assert_eq!(data.len(), 2 * len);
+        assert_eq!(
+            &data[..len],
+            &bytes[inputs[0].data.start..inputs[0].data.end]
+        );
+        assert_eq!(&data[len..], &data[..len]);
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_not_concat_different_formats() {
+        let mut inputs = [
+            Wav::from_bytes(include_bytes!("../test_files/stereo_16_48000.wav")).unwrap(),
+            Wav::from_bytes(include_bytes!("../test_files/stereo_24_48000.wav")).unwrap(),
+        ];
+        let mut buf = [0; 64];
+
+        assert!(matches!(
+            concat(&mut inputs, &mut SliceSink::new(&mut buf)),
+            Err(Error::FormatMismatch)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_extract_frame_range() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let data = &bytes[wav.data.start..];
+
+        let mut buf = [0; 64];
+        let mut sink = SliceSink::new(&mut buf);
+
+        assert_eq!(extract(&mut wav, 2, 5, &mut sink).unwrap(), 3);
+
+        let trimmed = Wav::from_bytes(sink.written()).unwrap();
+        assert_eq!(trimmed.fmt, wav.fmt);
+        assert_eq!(
+            &sink.written()[trimmed.data.start..trimmed.data.end],
+            &data[2 * 6..5 * 6]
+        );
+
+        let mut buf = [0; 64];
+        let mut sink = SliceSink::new(&mut buf);
+
+        assert_eq!(extract(&mut wav, 7, 3, &mut sink).unwrap(), 0);
+        assert_eq!(sink.written().len(), 44);
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_set_sample_rate_in_place() {
+        use crate::sink::RamFile;
+
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let file = RamFile {
+            bytes: bytes.to_vec(),
+            offset: 0,
+        };
+        let mut wav = Wav::new(file).unwrap();
+        let position = wav.source.offset();
+
+        wav.set_sample_rate(44_100).unwrap();
+
+        assert_eq!(wav.fmt.sample_rate, 44_100);
+        assert_eq!(wav.source.offset(), position);
+
+        let fixed = Wav::from_bytes(&wav.source.bytes).unwrap();
+        assert_eq!(fixed.fmt.sample_rate, 44_100);
+        assert_eq!(
+            &wav.source.bytes[fixed.data.start..],
+            &bytes[fixed.data.start..]
+        );
+
+        let fmt = wav.find_chunk(ChunkTag::Fmt).unwrap().unwrap();
+        assert_eq!(
+            wav.source.bytes[fmt.start + 8..fmt.start + 12],
+            (44_100u32 * 4).to_le_bytes()
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::audio_file::AudioFile;
+use crate::error::Error;
+use crate::identity::{TrackIdentity, TRACK_IDENTITY_LEN};
+use crate::source::AudioSource;
+use crate::wav::Wav;
+use core::convert::TryInto;
+use heapless::String;
+
+/// Longest path a [`ResumeToken`] holds
+pub const RESUME_PATH_LEN: usize = 64;
+
+/// Bytes of a [`ResumeToken`] as stored, e.g. in backup RAM kept powered during deep sleep
+pub const RESUME_TOKEN_LEN: usize = TRACK_IDENTITY_LEN + 4 + 1 + RESUME_PATH_LEN;
+
+/// Where playback of a closed file stands, to reopen it and continue after a deep sleep.
+///
+/// Recorded by [`SdCard::suspend`](crate::SdCard::suspend) and handed back to
+/// [`SdCard::resume`](crate::SdCard::resume), which refuses it if the file changed meanwhile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeToken {
+    path: String<RESUME_PATH_LEN>,
+    identity: TrackIdentity,
+    position: u32,
+}
+
+impl ResumeToken {
+    /// Token for the file at `path` with identity `identity`, at a position taken from
+    /// [`AudioFile::resume_position`]. `None` if the path is longer than [`RESUME_PATH_LEN`].
+    pub fn new(path: &str, identity: TrackIdentity, position: u32) -> Option<Self> {
+        let mut stored = String::new();
+        stored.push_str(path).ok()?;
+
+        Some(ResumeToken {
+            path: stored,
+            identity,
+            position,
+        })
+    }
+
+    /// Path the file was opened from
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Identity of the file when it was closed
+    pub fn identity(&self) -> &TrackIdentity {
+        &self.identity
+    }
+
+    /// Position to continue reading from, see [`AudioFile::resume_position`]
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// The token as stored
+    pub fn to_bytes(&self) -> [u8; RESUME_TOKEN_LEN] {
+        let mut bytes = [0; RESUME_TOKEN_LEN];
+        let path = TRACK_IDENTITY_LEN + 5;
+
+        bytes[..TRACK_IDENTITY_LEN].copy_from_slice(&self.identity.to_bytes());
+        bytes[TRACK_IDENTITY_LEN..path - 1].copy_from_slice(&self.position.to_le_bytes());
+        bytes[path - 1] = self.path.len() as u8;
+        bytes[path..path + self.path.len()].copy_from_slice(self.path.as_bytes());
+
+        bytes
+    }
+
+    /// Token read back, `None` if the bytes hold no valid path, e.g. uninitialized memory after
+    /// a cold boot
+    pub fn from_bytes(bytes: &[u8; RESUME_TOKEN_LEN]) -> Option<Self> {
+        let path = TRACK_IDENTITY_LEN + 5;
+        let identity = TrackIdentity::from_bytes(bytes[..TRACK_IDENTITY_LEN].try_into().unwrap());
+        let position = u32::from_le_bytes(bytes[TRACK_IDENTITY_LEN..path - 1].try_into().unwrap());
+        let len = bytes[path - 1] as usize;
+
+        let name = bytes.get(path..path + len)?;
+        let name = core::str::from_utf8(name).ok()?;
+
+        ResumeToken::new(name, identity, position)
+    }
+}
+
+impl<S: AudioSource> AudioFile<S> {
+    /// Position playback can continue from once the file is reopened, see
+    /// [`AudioFile::resume_at`].
+    ///
+    /// For WAV, AIFF and AVI the offset into the sample data, rounded down to a whole block. For
+    /// MP3 and FLAC the offset of the next frame in the file, for Ogg that of the page being
+    /// read.
+    pub fn resume_position(&self) -> u32 {
+        match self {
+            AudioFile::Wav(wav) => data_position(wav),
+            AudioFile::Avi(avi) => data_position(avi),
+            AudioFile::Flac(flac) => flac.stream_offset(),
+            AudioFile::Mp3(mp3) => mp3.source.offset(),
+            AudioFile::Ogg(ogg) => ogg.stream_offset(),
+        }
+    }
+
+    /// Continue reading from `position`, taken from [`AudioFile::resume_position`] of the same
+    /// file before it was closed.
+    ///
+    /// WAV, AIFF, AVI, MP3 and FLAC continue exactly where they stopped. Ogg restarts at the
+    /// page being read, packets of it that were read already are read again.
+    pub fn resume_at(&mut self, position: u32) -> Result<(), Error<S::Error>> {
+        match self {
+            AudioFile::Wav(wav) => wav.seek_data(position as usize),
+            AudioFile::Avi(avi) => avi.seek_data(position as usize),
+            AudioFile::Flac(flac) => flac.seek_stream(position),
+            AudioFile::Mp3(mp3) => mp3.source.seek(position).map_err(Error::Source),
+            AudioFile::Ogg(ogg) => ogg.seek_stream(position),
+        }
+    }
+}
+
+/// Read position of `wav` in its sample data, rounded down to a whole block
+fn data_position<S: AudioSource>(wav: &Wav<S>) -> u32 {
+    let block_align = wav.fmt.block_align().max(1);
+
+    (wav.data_offset() / block_align * block_align) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::fat_timestamp;
+    use crate::source::SliceSource;
+
+    fn open(bytes: &[u8]) -> AudioFile<SliceSource<'_>> {
+        AudioFile::new_auto(SliceSource::new(bytes)).unwrap()
+    }
+
+    fn next_block(file: &mut AudioFile<SliceSource<'_>>, out: &mut [i32]) -> usize {
+        match file {
+            AudioFile::Flac(flac) => flac.next_block(out).unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_store_resume_tokens() {
+        let identity = TrackIdentity::new(
+            "MUSIC/TRACK01.WAV",
+            4_096,
+            fat_timestamp(2024, 1, 2, 3, 4, 5),
+        );
+        let token = ResumeToken::new("MUSIC/TRACK01.WAV", identity, 1_234).unwrap();
+
+        assert_eq!(ResumeToken::from_bytes(&token.to_bytes()), Some(token));
+        assert_eq!(ResumeToken::from_bytes(&[0xff; RESUME_TOKEN_LEN]), None);
+        assert!(ResumeToken::new(&"A/".repeat(40), identity, 0).is_none());
+    }
+
+    #[test]
+    fn should_continue_where_playback_stopped() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut file = open(bytes);
+
+        let mut out = [0; 64];
+        file.decoder().unwrap().decode(&mut out).unwrap();
+        let position = file.resume_position();
+        file.decoder().unwrap().decode(&mut out).unwrap();
+
+        let mut resumed = open(bytes);
+        resumed.resume_at(position).unwrap();
+
+        let mut again = [0; 64];
+        resumed.decoder().unwrap().decode(&mut again).unwrap();
+        assert_eq!(again, out);
+
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let mut file = open(bytes);
+        let mut block = [0; 2 * 4_608];
+
+        next_block(&mut file, &mut block);
+        let position = file.resume_position();
+        let len = next_block(&mut file, &mut block);
+
+        let mut resumed = open(bytes);
+        resumed.resume_at(position).unwrap();
+
+        let mut again = [0; 2 * 4_608];
+        assert_eq!(next_block(&mut resumed, &mut again), len);
+        assert_eq!(again[..len * 2], block[..len * 2]);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::error::Error;
+use crate::metadata::{Metadata, INFO};
+use crate::riff::{Chunk, ChunkTag};
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+
+/// Id a replaced `LIST` `INFO` chunk is renamed to, so readers skip it
+const JUNK: [u8; 4] = *b"JUNK";
+
+/// Size of the `INFO` sub chunk holding `value`, with its NUL terminator but without padding
+fn entry_len(value: &str) -> u32 {
+    value.len() as u32 + 1
+}
+
+impl<S: AudioSource + AudioSink> Wav<S> {
+    /// Replace the `LIST` `INFO` chunk of the file with the tags of `metadata`, e.g. after renaming
+    /// a take on the device.
+    ///
+    /// The chunk is rewritten in place if the tags fit, otherwise the old chunk becomes a `JUNK`
+    /// chunk and a new one is appended to the end of the file, updating the RIFF size. The read
+    /// position is left unchanged. Only RIFF files are supported, others return
+    /// [`Error::NoRiffChunkFound`]. Sink errors are reported as [`Error::Io`].
+    pub fn write_metadata<const MAX_STRING_LEN: usize>(
+        &mut self,
+        metadata: &Metadata<MAX_STRING_LEN>,
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        let position = self.source.offset();
+        let result = self.replace_info(metadata);
+        self.source.seek(position).map_err(Error::Source)?;
+
+        result
+    }
+
+    fn replace_info<const MAX_STRING_LEN: usize>(
+        &mut self,
+        metadata: &Metadata<MAX_STRING_LEN>,
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        let mut header = [0; 12];
+        self.source.seek(0).map_err(Error::Source)?;
+
+        if read_full(&mut self.source, &mut header)? != header.len()
+            || &header[..4] != b"RIFF"
+            || &header[8..] != b"WAVE"
+        {
+            return Err(Error::NoRiffChunkFound);
+        }
+
+        // every entry is padded to an even size
+        let len = 4 + metadata
+            .tags()
+            .map(|(_, value)| 8 + entry_len(value).div_ceil(2) * 2)
+            .sum::<u32>();
+        let old = self.find_list(INFO)?;
+
+        if let Some(old) = old {
+            let old_len = (old.end - old.start) as u32;
+
+            if len <= old_len {
+                return match len {
+                    4 if old_len > 4 => self.rename(old, JUNK),
+                    _ => self.write_info(metadata, old.start as u32 - 8, old_len),
+                };
+            }
+
+            self.rename(old, JUNK)?;
+        }
+
+        if len == 4 {
+            return Ok(());
+        }
+
+        let riff_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let start = 8 + riff_len + (riff_len & 1);
+        self.write_info(metadata, start, len)?;
+
+        // everything up to the new chunk, its header and its body
+        let riff_len = start + len;
+        self.source.seek(4).map_err(Error::Source)?;
+        AudioSink::write(&mut self.source, &riff_len.to_le_bytes()).map_err(|_| Error::Io)?;
+
+        let _ = self.chunks.push(Chunk {
+            id: ChunkTag::List,
+            start: start as usize + 8,
+            end: (start + 8 + len) as usize,
+        });
+
+        Ok(())
+    }
+
+    /// Write a `LIST` `INFO` chunk of `len` bytes at `start`, the last entry is stretched with
+    /// NUL bytes to fill it
+    fn write_info<const MAX_STRING_LEN: usize>(
+        &mut self,
+        metadata: &Metadata<MAX_STRING_LEN>,
+        start: u32,
+        len: u32,
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        self.source.seek(start).map_err(Error::Source)?;
+
+        let mut write = |bytes: &[u8]| AudioSink::write(&mut self.source, bytes);
+        let mut left = len - 4;
+        let count = metadata.tags().count();
+
+        let mut header = [0; 12];
+        header[..4].copy_from_slice(b"LIST");
+        header[4..8].copy_from_slice(&len.to_le_bytes());
+        header[8..].copy_from_slice(&INFO);
+        write(&header).map_err(|_| Error::Io)?;
+
+        for (i, (tag, value)) in metadata.tags().enumerate() {
+            let size = match i + 1 == count {
+                true => left - 8,
+                false => entry_len(value),
+            };
+
+            let mut header = [0; 8];
+            header[..4].copy_from_slice(&tag.to_bytes());
+            header[4..].copy_from_slice(&size.to_le_bytes());
+            write(&header).map_err(|_| Error::Io)?;
+            write(value.as_bytes()).map_err(|_| Error::Io)?;
+
+            // NUL terminator, padding and stretch
+            let mut zeros = (size - value.len() as u32) + (size & 1);
+            left -= 8 + size + (size & 1);
+
+            while zeros > 0 {
+                let n = zeros.min(16);
+                write(&[0; 16][..n as usize]).map_err(|_| Error::Io)?;
+                zeros -= n;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the id of `chunk`, in the file and in [`Wav::chunks`]
+    fn rename(
+        &mut self,
+        chunk: Chunk,
+        id: [u8; 4],
+    ) -> Result<(), Error<<S as AudioSource>::Error>> {
+        self.source
+            .seek(chunk.start as u32 - 8)
+            .map_err(Error::Source)?;
+        AudioSink::write(&mut self.source, &id).map_err(|_| Error::Io)?;
+
+        if let Some(known) = self.chunks.iter_mut().find(|known| **known == chunk) {
+            known.id = ChunkTag::Unknown(id);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ListChunkTag;
+    use crate::sink::RamFile;
+
+    /// Test file with a `LIST` `INFO` chunk behind the samples holding `title`
+    fn tagged(title: &[u8]) -> RamFile {
+        let mut bytes = include_bytes!("../test_files/stereo_16_48000.wav").to_vec();
+        let size = 4 + 8 + title.len() as u32;
+
+        bytes.extend_from_slice(b"LIST");
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(b"INFOINAM");
+        bytes.extend_from_slice(&(title.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(title);
+
+        let riff_len = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+
+        RamFile { bytes, offset: 0 }
+    }
+
+    fn reopen(wav: Wav<RamFile>) -> (Wav<RamFile>, Metadata<32>) {
+        let mut file = wav.destroy();
+        file.offset = 0;
+
+        let mut wav = Wav::new(file).unwrap();
+        let metadata = wav.metadata().unwrap();
+
+        (wav, metadata)
+    }
+
+    #[test]
+    fn should_rewrite_info_in_place() {
+        let file = tagged(b"Take 1 of the morning session\0");
+        let len = file.bytes.len();
+        let mut wav = Wav::new(file).unwrap();
+        let position = wav.source.offset();
+
+        let mut metadata: Metadata<32> = wav.metadata().unwrap();
+        metadata.set_tag(ListChunkTag::Title, "Take 2");
+        metadata.set_tag(ListChunkTag::Artist, "Band");
+        wav.write_metadata(&metadata).unwrap();
+
+        assert_eq!(wav.source.offset(), position);
+        let (wav, read) = reopen(wav);
+
+        assert_eq!(read, metadata);
+        assert_eq!(wav.source.bytes.len(), len);
+    }
+
+    #[test]
+    fn should_append_info_that_outgrows_the_old_chunk() {
+        let original = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = Wav::new(tagged(b"T1")).unwrap();
+
+        let mut metadata: Metadata<32> = Metadata::default();
+        metadata.set_tag(ListChunkTag::Title, "A much longer title");
+        metadata.set_tag(ListChunkTag::Unknown(*b"ITRK"), "7");
+        wav.write_metadata(&metadata).unwrap();
+
+        let (mut wav, read) = reopen(wav);
+        assert_eq!(read, metadata);
+
+        let bytes = &wav.source.bytes;
+        let riff_len = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        assert_eq!(riff_len as usize, bytes.len() - 8);
+        assert_eq!(&bytes[original.len()..original.len() + 4], b"JUNK");
+        assert_eq!(bytes[8..original.len()], original[8..]);
+
+        // an empty list leaves nothing to read
+        wav.write_metadata(&Metadata::<32>::default()).unwrap();
+        let (_, read) = reopen(wav);
+        assert_eq!(read, Metadata::default());
+    }
+}
@@ -0,0 +1,476 @@
+//! Chunks of RIFF files, and of IFF files such as AIFF, and a walker over them.
+//!
+//! WAV is parsed with this module, other formats made of chunks, such as AVI audio, DLS or SF2
+//! banks, can be built on [`ChunkWalker`]: it reads one 8 byte header at a time from any
+//! [`AudioSource`] and leaves the bodies to the caller, so files of any size are walked without
+//! allocating.
+
+use crate::error::Error;
+use crate::source::{AudioSource, SliceSource};
+use crate::wav::read_full;
+use core::convert::TryInto;
+use heapless::Vec;
+
+use crate::wav::MAX_CHUNKS;
+
+/// RIFF chunks are tagged with 4 byte identifiers.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChunkTag {
+    /// Root level "chunk"
+    Riff,
+    /// Root level "chunk" of RF64 files, whose sizes don't fit in 32 bits and are kept in `ds64`
+    Rf64,
+    /// Table of the 64 bit RIFF and data chunk sizes of an RF64 file
+    Ds64,
+    /// Mandatory chunk for WAV files, contains data such as the sample rate, bit depth, and number of channels.
+    Fmt,
+    /// Mandatory chunk for WAV files, contains the (interleaved) samples.
+    Data,
+    /// File identifier, should be located right after the RIFF tag and chunk size
+    Wave,
+    /// Optional chunk with markers pointing at positions in the sample data
+    Cue,
+    /// Optional sampler chunk with the MIDI unity note and loop points
+    Smpl,
+    /// Broadcast WAV extension with the description, originator and recording time
+    Bext,
+    /// Optional list of sub chunks, e.g. `INFO` metadata or `adtl` cue labels
+    List,
+    /// Number of frames in the file, required for compressed formats
+    Fact,
+    /// Common chunk of AIFF files, the counterpart of `fmt `
+    Comm,
+    /// Sound data chunk of AIFF files, the counterpart of `data`
+    Ssnd,
+    /// Unkown/unhandled chunk tag, useful for parsing [`Chunk`] bytes.
+    Unknown([u8; 4]),
+}
+
+impl ChunkTag {
+    /// Tag of the 4 byte identifier `bytes`
+    pub fn from_bytes(bytes: &[u8; 4]) -> Self {
+        match bytes {
+            [b'R', b'I', b'F', b'F'] => ChunkTag::Riff,
+            [b'R', b'F', b'6', b'4'] => ChunkTag::Rf64,
+            [b'd', b's', b'6', b'4'] => ChunkTag::Ds64,
+            [b'f', b'm', b't', b' '] => ChunkTag::Fmt,
+            [b'd', b'a', b't', b'a'] => ChunkTag::Data,
+            [b'W', b'A', b'V', b'E'] => ChunkTag::Wave,
+            [b'c', b'u', b'e', b' '] => ChunkTag::Cue,
+            [b's', b'm', b'p', b'l'] => ChunkTag::Smpl,
+            [b'b', b'e', b'x', b't'] => ChunkTag::Bext,
+            [b'L', b'I', b'S', b'T'] => ChunkTag::List,
+            [b'f', b'a', b'c', b't'] => ChunkTag::Fact,
+            [b'C', b'O', b'M', b'M'] => ChunkTag::Comm,
+            [b'S', b'S', b'N', b'D'] => ChunkTag::Ssnd,
+            _ => ChunkTag::Unknown(*bytes),
+        }
+    }
+
+    /// The 4 byte identifier of the tag
+    pub fn to_bytes(self) -> [u8; 4] {
+        match self {
+            ChunkTag::Riff => [b'R', b'I', b'F', b'F'],
+            ChunkTag::Rf64 => [b'R', b'F', b'6', b'4'],
+            ChunkTag::Ds64 => [b'd', b's', b'6', b'4'],
+            ChunkTag::Fmt => [b'f', b'm', b't', b' '],
+            ChunkTag::Data => [b'd', b'a', b't', b'a'],
+            ChunkTag::Wave => [b'W', b'A', b'V', b'E'],
+            ChunkTag::Cue => [b'c', b'u', b'e', b' '],
+            ChunkTag::Smpl => [b's', b'm', b'p', b'l'],
+            ChunkTag::Bext => [b'b', b'e', b'x', b't'],
+            ChunkTag::List => [b'L', b'I', b'S', b'T'],
+            ChunkTag::Fact => [b'f', b'a', b'c', b't'],
+            ChunkTag::Comm => [b'C', b'O', b'M', b'M'],
+            ChunkTag::Ssnd => [b'S', b'S', b'N', b'D'],
+            ChunkTag::Unknown(bytes) => bytes,
+        }
+    }
+}
+
+/// Resource Interchange File Format (RIFF) tagged chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    /// Chunk tag
+    pub id: ChunkTag,
+    /// Byte offset of the chunk body from the start of the file
+    pub start: usize,
+    /// Byte offset right after the chunk body, excluding any padding byte
+    pub end: usize,
+}
+
+impl Chunk {
+    pub(crate) fn from_bytes(bytes: &[u8], offset: usize) -> Result<Self, Error> {
+        Self::parse(bytes, offset, u32::from_le_bytes)
+    }
+
+    fn parse(bytes: &[u8], offset: usize, read_size: fn([u8; 4]) -> u32) -> Result<Self, Error> {
+        let id = bytes
+            .get(0..4)
+            .and_then(|b| b.try_into().ok())
+            .map(ChunkTag::from_bytes)
+            .ok_or(Error::CantParseSliceInto)?;
+
+        let size = bytes
+            .get(4..8)
+            .and_then(|b| b.try_into().ok())
+            .map(read_size)
+            .ok_or(Error::CantParseSliceInto)?;
+
+        // start and end are absolute positions of the chunk body
+        let start = offset + 8;
+        let end = start.saturating_add(size as usize);
+
+        Ok(Chunk { id, start, end })
+    }
+
+    /// [`Error::Truncated`] unless the chunk body ends within a file of `length` bytes, a missing
+    /// padding byte is tolerated
+    pub(crate) fn check_within(&self, length: usize) -> Result<(), Error> {
+        match self.end > length {
+            true => Err(Error::Truncated {
+                expected: self.end,
+                found: length,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Byte offset of the header of the chunk that follows, behind the padding byte of odd sized
+    /// chunks
+    pub fn next_offset(&self) -> usize {
+        self.end.saturating_add((self.end - self.start) & 1)
+    }
+
+    /// Take the size of a `data` chunk from the `ds64` chunk if its `size_field` holds the RF64
+    /// placeholder size, [`Error::TooLarge`] if the data then ends past 4 GiB
+    pub(crate) fn with_ds64(self, size_field: u32, data_size: Option<u64>) -> Result<Self, Error> {
+        match data_size {
+            Some(size) if self.id == ChunkTag::Data && size_field == RF64_SIZE => {
+                let end = (self.start as u64)
+                    .checked_add(size)
+                    .filter(|&end| end <= u32::MAX as u64)
+                    .ok_or(Error::TooLarge)?;
+
+                Ok(Chunk {
+                    end: end as usize,
+                    ..self
+                })
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Size field of RF64 chunks whose real size is kept in the `ds64` chunk
+const RF64_SIZE: u32 = u32::MAX;
+
+/// Walks the chunks of a RIFF or IFF file, or the sub chunks of a `LIST`, one header at a time.
+///
+/// Only the 8 byte headers are read, each [`ChunkWalker::next_chunk`] leaves the source at the
+/// body of the chunk it returns, to be read or skipped by the caller. Chunk sizes aren't checked
+/// against the length of the source, see [`Chunk::end`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkWalker {
+    next: usize,
+    end: usize,
+    big_endian: bool,
+    size_field: u32,
+}
+
+impl ChunkWalker {
+    /// Walk the top level chunks of a file of `length` bytes, behind the 12 byte `RIFF` or `FORM`
+    /// header and form type
+    pub fn new(length: usize) -> Self {
+        Self::range(12, length)
+    }
+
+    /// Walk the sub chunks of the `LIST` chunk `list`, behind its list type
+    pub fn list(list: &Chunk) -> Self {
+        Self::range(list.start.saturating_add(4), list.end)
+    }
+
+    /// Walk the chunks from byte offset `start` up to byte offset `end`
+    pub fn range(start: usize, end: usize) -> Self {
+        ChunkWalker {
+            next: start,
+            end,
+            big_endian: false,
+            size_field: 0,
+        }
+    }
+
+    /// Read the sizes as big endian, like IFF files such as AIFF store them
+    pub fn big_endian(self) -> Self {
+        ChunkWalker {
+            big_endian: true,
+            ..self
+        }
+    }
+
+    /// Byte offset of the next chunk header
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    /// Continue the walk at the chunk header at byte offset `offset`, e.g. after a chunk whose
+    /// size was corrected
+    pub fn seek(&mut self, offset: usize) {
+        self.next = offset;
+    }
+
+    /// Size field of the last chunk header, as stored in the file
+    pub fn size_field(&self) -> u32 {
+        self.size_field
+    }
+
+    /// Read the next chunk header from `source`, `None` at the end of the walk or of the source.
+    ///
+    /// A trailing fragment too short to hold a header ends the walk.
+    pub fn next_chunk<S: AudioSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<Chunk>, Error<S::Error>> {
+        if self.next.saturating_add(8) > self.end {
+            return Ok(None);
+        }
+
+        let mut header = [0; 8];
+        source.seek(self.next as u32).map_err(Error::Source)?;
+
+        if read_full(source, &mut header)? != header.len() {
+            return Ok(None);
+        }
+
+        let read_size: fn([u8; 4]) -> u32 = match self.big_endian {
+            true => u32::from_be_bytes,
+            false => u32::from_le_bytes,
+        };
+        let chunk = Chunk::parse(&header, self.next, read_size).map_err(Error::widen)?;
+
+        self.size_field = read_size([header[4], header[5], header[6], header[7]]);
+        self.next = chunk.next_offset();
+
+        Ok(Some(chunk))
+    }
+}
+
+/// Read the 64 bit size of the data chunk from the body of a `ds64` chunk
+pub(crate) fn ds64_data_size(body: &[u8]) -> Result<u64, Error> {
+    body.get(8..16)
+        .and_then(|b| b.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or(Error::CantParseChunk(ChunkTag::Ds64))
+}
+
+/// List the chunks of the RIFF file in `bytes`, those past the first [`MAX_CHUNKS`] are skipped
+pub fn parse_chunks(bytes: &[u8]) -> Result<Vec<Chunk, MAX_CHUNKS>, Error> {
+    let mut chunks: Vec<Chunk, MAX_CHUNKS> = Vec::new();
+
+    // editors may leave dozens of JUNK or PAD chunks, they don't stop the file from playing
+    walk_chunks(bytes, |chunk| {
+        let _ = chunks.push(chunk);
+    })?;
+
+    Ok(chunks)
+}
+
+/// Call `f` with every chunk of the RIFF file in `bytes`, in order
+pub(crate) fn walk_chunks<F: FnMut(Chunk)>(bytes: &[u8], mut f: F) -> Result<(), Error> {
+    let riff = Chunk::from_bytes(bytes, 0)?;
+
+    if riff.id != ChunkTag::Riff && riff.id != ChunkTag::Rf64 {
+        return Err(Error::NoRiffChunkFound);
+    }
+
+    let tag: [u8; 4] = bytes
+        .get(8..12)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(Error::CantParseSliceInto)?;
+
+    if tag != ChunkTag::Wave.to_bytes() {
+        return Err(Error::NoWaveTagFound);
+    }
+
+    let mut source = SliceSource::new(bytes);
+    let mut walker = ChunkWalker::new(bytes.len());
+    let mut data_size = None;
+
+    while let Some(chunk) = walker.next_chunk(&mut source)? {
+        let chunk = chunk.with_ds64(walker.size_field(), data_size)?;
+        walker.seek(chunk.next_offset());
+
+        // the samples of a partially copied file are still usable
+        if chunk.id != ChunkTag::Data {
+            chunk.check_within(bytes.len())?;
+        }
+
+        if chunk.id == ChunkTag::Ds64 {
+            data_size = Some(ds64_data_size(&bytes[chunk.start..])?);
+        }
+
+        f(chunk);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn should_parse_chunks() {
+        let bytes: [u8; 60] = [
+            0x52, 0x49, 0x46, 0x46, // RIFF
+            0x34, 0x00, 0x00, 0x00, // chunk size
+            0x57, 0x41, 0x56, 0x45, // WAVE
+            0x66, 0x6d, 0x74, 0x20, // fmt_
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x01, 0x00, // audio format
+            0x02, 0x00, // num channels
+            0x22, 0x56, 0x00, 0x00, // sample rate
+            0x88, 0x58, 0x01, 0x00, // byte rate
+            0x04, 0x00, // block align
+            0x10, 0x00, // bits per sample
+            0x64, 0x61, 0x74, 0x61, // data
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+            0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+            0x3c, 0x13, 0x3c, 0x14, // sample 3 L+R
+            0x16, 0xf9, 0x18, 0xf9, // sample 4 L+R
+        ];
+
+        let chunks = parse_chunks(&bytes).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().find(|c| c.id == ChunkTag::Fmt).is_some());
+        assert!(chunks.iter().find(|c| c.id == ChunkTag::Data).is_some());
+    }
+
+    #[test]
+    fn should_fail_on_non_wave_files() {
+        let bytes: [u8; 60] = [
+            0x52, 0x49, 0x46, 0x46, // RIFF
+            0x34, 0x00, 0x00, 0x00, // chunk size
+            0x57, 0x41, 0x56, 0x56, // WAVV
+            0x66, 0x6d, 0x74, 0x20, // fmt_
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x01, 0x00, // audio format
+            0x02, 0x00, // num channels
+            0x22, 0x56, 0x00, 0x00, // sample rate
+            0x88, 0x58, 0x01, 0x00, // byte rate
+            0x04, 0x00, // block align
+            0x10, 0x00, // bits per sample
+            0x64, 0x61, 0x74, 0x61, // data
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+            0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+            0x3c, 0x13, 0x3c, 0x14, // sample 3 L+R
+            0x16, 0xf9, 0x18, 0xf9, // sample 4 L+R
+        ];
+
+        assert_eq!(parse_chunks(&bytes).unwrap_err(), Error::NoWaveTagFound);
+    }
+
+    #[test]
+    fn should_not_fail_with_random_chunks_added() {
+        let bytes: [u8; 88] = [
+            0x52, 0x49, 0x46, 0x46, // RIFF
+            0x34, 0x00, 0x00, 0x00, // chunk size
+            0x57, 0x41, 0x56, 0x56, // WAVV
+            0x66, 0x6d, 0x74, 0x20, // fmt_
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x01, 0x00, // audio format
+            0x02, 0x00, // num channels
+            0x22, 0x56, 0x00, 0x00, // sample rate
+            0x88, 0x58, 0x01, 0x00, // byte rate
+            0x04, 0x00, // block align
+            0x10, 0x00, // bits per sample
+            0x72, 0x6e, 0x64, 0x6d, // rndm
+            0x04, 0x00, 0x00, 0x00, // chunk size
+            0xaa, 0xaa, 0xaa, 0xaa, // ...
+            0x8b, 0xad, 0xf0, 0x0d, // 8badfood
+            0x08, 0x00, 0x00, 0x00, // chunk size
+            0xaa, 0xff, 0xaa, 0xff, // ...
+            0xff, 0xaa, 0xff, 0xaa, // ...
+            0x64, 0x61, 0x74, 0x61, // data
+            0x10, 0x00, 0x00, 0x00, // chunk size
+            0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+            0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+            0x3c, 0x13, 0x3c, 0x14, // sample 3 L+R
+            0x16, 0xf9, 0x18, 0xf9, // sample 4 L+R
+        ];
+
+        assert_eq!(parse_chunks(&bytes).unwrap_err(), Error::NoWaveTagFound);
+    }
+
+    #[test]
+    fn should_take_data_size_from_ds64() {
+        let mut bytes: [u8; 64] = [
+            0x52, 0x46, 0x36, 0x34, // RF64
+            0xff, 0xff, 0xff, 0xff, // chunk size
+            0x57, 0x41, 0x56, 0x45, // WAVE
+            0x64, 0x73, 0x36, 0x34, // ds64
+            0x1c, 0x00, 0x00, 0x00, // chunk size
+            0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, // riff size
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // data size
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // sample count
+            0x00, 0x00, 0x00, 0x00, // table length
+            0x64, 0x61, 0x74, 0x61, // data
+            0xff, 0xff, 0xff, 0xff, // chunk size
+            0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+            0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+        ];
+
+        let chunks = parse_chunks(&bytes).unwrap();
+        let data = chunks.iter().find(|c| c.id == ChunkTag::Data).unwrap();
+
+        assert_eq!(data.start, 56);
+        assert_eq!(data.end, 56 + 0x10);
+
+        // past 4 GiB
+        bytes[32] = 0x01;
+        assert_eq!(parse_chunks(&bytes).unwrap_err(), Error::TooLarge);
+    }
+
+    #[test]
+    fn should_walk_list_sub_chunks() {
+        let bytes: [u8; 44] = [
+            0x52, 0x49, 0x46, 0x46, // RIFF
+            0x24, 0x00, 0x00, 0x00, // chunk size
+            0x73, 0x66, 0x62, 0x6b, // sfbk
+            0x4c, 0x49, 0x53, 0x54, // LIST
+            0x18, 0x00, 0x00, 0x00, // chunk size
+            0x49, 0x4e, 0x46, 0x4f, // INFO
+            0x69, 0x66, 0x69, 0x6c, // ifil
+            0x04, 0x00, 0x00, 0x00, // chunk size
+            0x02, 0x00, 0x01, 0x00, // version 2.01
+            0x49, 0x4e, 0x41, 0x4d, // INAM
+            0x01, 0x00, 0x00, 0x00, // chunk size
+        ];
+        let mut source = SliceSource::new(&bytes);
+
+        let mut walker = ChunkWalker::new(bytes.len());
+        let list = walker.next_chunk(&mut source).unwrap().unwrap();
+        assert_eq!(list.id, ChunkTag::List);
+        assert_eq!(walker.next_chunk(&mut source).unwrap(), None);
+
+        let mut walker = ChunkWalker::list(&list);
+        let ifil = walker.next_chunk(&mut source).unwrap().unwrap();
+        assert_eq!(ifil.id, ChunkTag::Unknown(*b"ifil"));
+        assert_eq!((ifil.start, ifil.end), (32, 36));
+
+        // the body is left to the caller, the name is cut off by the end of the file
+        let inam = walker.next_chunk(&mut source).unwrap().unwrap();
+        assert_eq!(inam.id.to_bytes(), *b"INAM");
+        assert_eq!((inam.next_offset(), walker.position()), (46, 46));
+        assert_eq!(walker.next_chunk(&mut source).unwrap(), None);
+
+        let mut walker = ChunkWalker::range(12, bytes.len()).big_endian();
+        let list = walker.next_chunk(&mut source).unwrap().unwrap();
+        assert_eq!(list.end, 20 + 0x1800_0000);
+        assert_eq!(walker.size_field(), 0x1800_0000);
+    }
+}
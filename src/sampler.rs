@@ -0,0 +1,258 @@
+use crate::error::Error;
+use crate::riff::ChunkTag;
+use crate::source::AudioSource;
+use crate::wav::{read_full, DataBulk, Wav};
+use core::convert::TryInto;
+use heapless::Vec;
+
+/// Size in bytes of the fixed part of the `smpl` chunk, before the loops
+const SMPL_HEADER_SIZE: usize = 36;
+/// Size in bytes of a single loop entry in the `smpl` chunk
+const SAMPLE_LOOP_SIZE: usize = 24;
+
+/// How a sampler plays a [`SampleLoop`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopKind {
+    /// Play from start to end, then jump back to the start
+    Forward,
+    /// Alternate between playing forwards and backwards
+    PingPong,
+    /// Play from end to start, then jump back to the end
+    Backward,
+    /// Manufacturer specific loop type
+    Unknown(u32),
+}
+
+impl From<u32> for LoopKind {
+    fn from(kind: u32) -> Self {
+        match kind {
+            0 => LoopKind::Forward,
+            1 => LoopKind::PingPong,
+            2 => LoopKind::Backward,
+            kind => LoopKind::Unknown(kind),
+        }
+    }
+}
+
+/// Loop stored in the `smpl` chunk of a WAV file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLoop {
+    /// Identifier, may match a cue point
+    pub id: u32,
+    /// How the loop is played
+    pub kind: LoopKind,
+    /// First frame of the loop
+    pub start: u32,
+    /// Last frame of the loop, played before jumping back to `start`
+    pub end: u32,
+    /// Number of times to play the loop, `0` for endlessly
+    pub play_count: u32,
+}
+
+impl SampleLoop {
+    fn from_bytes(bytes: &[u8; SAMPLE_LOOP_SIZE]) -> Self {
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        SampleLoop {
+            id: field(0),
+            kind: LoopKind::from(field(4)),
+            start: field(8),
+            end: field(12),
+            play_count: field(20),
+        }
+    }
+}
+
+/// Instrument data of the `smpl` chunk, how a sampler should play the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplerInfo<const N: usize> {
+    /// MIDI note played back at the recorded pitch, `60` is middle C
+    pub unity_note: u8,
+    /// Fraction of a semitone above `unity_note` the sample is pitched at, `0x8000_0000` is half
+    pub pitch_fraction: u32,
+    /// Loops of the file, the first `N` of them
+    pub loops: Vec<SampleLoop, N>,
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Parse the `smpl` chunk, `None` if the file has none. Only the first `N` loops are kept.
+    ///
+    /// The read position is left unchanged.
+    pub fn sampler<const N: usize>(&mut self) -> Result<Option<SamplerInfo<N>>, Error<S::Error>> {
+        let chunk = match self.find_chunk(ChunkTag::Smpl)? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let mut header = [0; SMPL_HEADER_SIZE];
+
+        if read_full(&mut self.source, &mut header)? != SMPL_HEADER_SIZE {
+            self.source.seek(position).map_err(Error::Source)?;
+            return Err(Error::CantParseChunk(ChunkTag::Smpl));
+        }
+
+        let field = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        let mut info = SamplerInfo {
+            unity_note: field(12).min(127) as u8,
+            pitch_fraction: field(16),
+            loops: Vec::new(),
+        };
+
+        // never trust the count beyond what the chunk can hold
+        let room = (chunk.end - chunk.start).saturating_sub(SMPL_HEADER_SIZE) / SAMPLE_LOOP_SIZE;
+        let count = (field(28) as usize).min(room).min(N);
+
+        for _ in 0..count {
+            let mut bytes = [0; SAMPLE_LOOP_SIZE];
+
+            if read_full(&mut self.source, &mut bytes)? != SAMPLE_LOOP_SIZE {
+                break;
+            }
+
+            // count is capped at N
+            let _ = info.loops.push(SampleLoop::from_bytes(&bytes));
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(Some(info))
+    }
+
+    /// Same as [`Wav::next_n`], wrapping back to the start of `sample_loop` once its end is played,
+    /// e.g. to sustain a note of a sampler for as long as the key is held.
+    ///
+    /// Frames before the loop start are played once, as the attack. Buffers come back shorter
+    /// when they reach the end of the loop, which is capped at the end of the data. Every loop
+    /// kind is played forward, the loop's play count is left to the caller.
+    pub fn next_n_in_loop<const NUM: usize>(
+        &mut self,
+        sample_loop: &SampleLoop,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        let block_align = self.fmt.block_align().max(1);
+        let total = self.data_end().saturating_sub(self.data.start) / block_align;
+
+        if total == 0 {
+            return Err(Error::EndOfData);
+        }
+
+        let end = (sample_loop.end as usize).min(total - 1) + 1;
+        let start = (sample_loop.start as usize).min(end - 1);
+        let mut frame = self.data_offset() / block_align;
+
+        if frame >= end {
+            self.seek_data(start * block_align)?;
+            frame = start;
+        }
+
+        self.next_frames(end - frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mono 16 bit file with 8 frames `0, 1000, .. 7000`, looping frames 2 to 4, unity note 69
+    fn sampled() -> std::vec::Vec<u8> {
+        let mut smpl = [0u32, 0, 22_675, 69, 0x8000_0000, 0, 0, 2, 0]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect::<std::vec::Vec<u8>>();
+        smpl.extend([1u32, 0, 2, 4, 0, 0].iter().flat_map(|f| f.to_le_bytes()));
+        smpl.extend([2u32, 1, 0, 7, 0, 3].iter().flat_map(|f| f.to_le_bytes()));
+
+        let mut bytes = b"RIFF\0\0\0\0WAVEfmt \x10\0\0\0".to_vec();
+        bytes.extend_from_slice(&[1, 0, 1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 1, 0, 2, 0, 16, 0]);
+        bytes.extend_from_slice(b"data\x10\0\0\0");
+        bytes.extend((0..8i16).flat_map(|i| (i * 1000).to_le_bytes()));
+        bytes.extend_from_slice(b"smpl");
+        bytes.extend_from_slice(&(smpl.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&smpl);
+
+        let riff_len = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        bytes
+    }
+
+    fn samples(bulk: DataBulk<16>) -> heapless::Vec<i16, 16> {
+        match bulk {
+            DataBulk::BitDepth16(samples) => samples,
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_parse_smpl_chunk() {
+        let bytes = sampled();
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+        let info = wav.sampler::<4>().unwrap().unwrap();
+
+        assert_eq!(info.unity_note, 69);
+        assert_eq!(info.pitch_fraction, 0x8000_0000);
+        assert_eq!(
+            info.loops,
+            [
+                SampleLoop {
+                    id: 1,
+                    kind: LoopKind::Forward,
+                    start: 2,
+                    end: 4,
+                    play_count: 0,
+                },
+                SampleLoop {
+                    id: 2,
+                    kind: LoopKind::PingPong,
+                    start: 0,
+                    end: 7,
+                    play_count: 3,
+                },
+            ]
+        );
+
+        assert_eq!(wav.sampler::<1>().unwrap().unwrap().loops.len(), 1);
+        assert_eq!(wav.timestamp().frames, 0);
+
+        let plain = include_bytes!("../test_files/stereo_16_48000.wav");
+        assert_eq!(
+            Wav::from_bytes(plain).unwrap().sampler::<1>().unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn should_wrap_reads_between_loop_points() {
+        let bytes = sampled();
+        let mut wav = Wav::from_bytes(&bytes).unwrap();
+        let sample_loop = wav.sampler::<1>().unwrap().unwrap().loops[0];
+
+        assert_eq!(
+            samples(wav.next_n_in_loop(&sample_loop).unwrap()),
+            [0, 1000, 2000, 3000, 4000]
+        );
+        assert_eq!(
+            samples(wav.next_n_in_loop(&sample_loop).unwrap()),
+            [2000, 3000, 4000]
+        );
+        assert_eq!(
+            samples(wav.next_n_in_loop(&sample_loop).unwrap()),
+            [2000, 3000, 4000]
+        );
+
+        // loop points past the data are capped
+        let long = SampleLoop {
+            end: 100,
+            ..sample_loop
+        };
+        wav.seek_to_sample(6).unwrap();
+        assert_eq!(samples(wav.next_n_in_loop(&long).unwrap()), [6000, 7000]);
+        assert_eq!(
+            samples(wav.next_n_in_loop(&long).unwrap())[..2],
+            [2000, 3000]
+        );
+    }
+}
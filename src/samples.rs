@@ -0,0 +1,437 @@
+use crate::audio_file::AudioFile;
+use crate::decoder::Decoder;
+use crate::error::Error;
+use crate::fixed::{f32_to_i16, to_q15};
+use crate::flac::Flac;
+use crate::source::{AudioSource, CHUNK_LEN};
+use crate::wav::{read_full, Data, DataBulk, Wav};
+use core::marker::PhantomData;
+use heapless::Vec;
+
+/// Samples decoded per read of [`Wav::read_frames`], enough for frames of up to 192 channels
+const FRAME_CHUNK_LEN: usize = 192;
+
+/// Sample representation a [`Samples`] iterator converts to, whatever the bit depth of the file
+pub trait Sample: Copy {
+    /// Convert a decoded sample, 8 bit samples are made signed
+    fn from_data(data: Data) -> Self;
+}
+
+/// Normalized to -1.0 up to just below 1.0, float files pass through unchanged
+impl Sample for f32 {
+    fn from_data(data: Data) -> Self {
+        match data {
+            Data::BitDepth8(sample) => (sample as f32 - 128.0) / 128.0,
+            Data::BitDepth16(sample) => sample as f32 / 32_768.0,
+            Data::BitDepth24(sample) => sample as f32 / 8_388_608.0,
+            Data::Float32(sample) => sample,
+        }
+    }
+}
+
+/// Q15 fixed point, 24 bit samples are truncated and float samples saturate at full scale
+impl Sample for i16 {
+    fn from_data(data: Data) -> Self {
+        match data {
+            Data::BitDepth8(sample) => ((sample as i16) - 128) << 8,
+            Data::BitDepth16(sample) => sample,
+            Data::BitDepth24(sample) => to_q15(sample, 24),
+            Data::Float32(sample) => f32_to_i16(sample),
+        }
+    }
+}
+
+/// Q31 fixed point, left justified like the 32 bit slots of an I2S bus
+impl Sample for i32 {
+    fn from_data(data: Data) -> Self {
+        match data {
+            Data::BitDepth8(sample) => ((sample as i32) - 128) << 24,
+            Data::BitDepth16(sample) => (sample as i32) << 16,
+            Data::BitDepth24(sample) => sample << 8,
+            Data::Float32(sample) => (sample.clamp(-1.0, 1.0) as f64 * 2_147_483_648.0) as i32,
+        }
+    }
+}
+
+impl<const NUM: usize> DataBulk<NUM> {
+    fn get(&self, index: usize) -> Option<Data> {
+        match self {
+            DataBulk::BitDepth8(samples) => samples.get(index).map(|&s| Data::BitDepth8(s)),
+            DataBulk::BitDepth16(samples) => samples.get(index).map(|&s| Data::BitDepth16(s)),
+            DataBulk::BitDepth24(samples) => samples.get(index).map(|&s| Data::BitDepth24(s)),
+            DataBulk::Float32(samples) => samples.get(index).map(|&s| Data::Float32(s)),
+        }
+    }
+}
+
+/// Iterator over the interleaved samples of a [`Wav`] converted to `T`, reading `NUM` samples
+/// from the source at a time.
+///
+/// Stops after the last sample or after yielding the first error.
+pub struct Samples<'a, S: AudioSource, T: Sample, const NUM: usize> {
+    input: Input<'a, S>,
+    bulk: Option<DataBulk<NUM>>,
+    index: usize,
+    done: bool,
+    sample: PhantomData<T>,
+}
+
+/// File a [`Samples`] iterator reads from
+enum Input<'a, S: AudioSource> {
+    Wav(&'a mut Wav<S>),
+    Flac(&'a mut Flac<S>),
+}
+
+impl<S: AudioSource> Input<'_, S> {
+    /// Up to `NUM` decoded samples, empty at the end of the stream
+    fn next_n<const NUM: usize>(&mut self) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        match self {
+            Input::Wav(wav) => wav.next_n::<NUM>(),
+            Input::Flac(flac) => {
+                let mut block = [0; NUM];
+                let len = flac.decode(&mut block)?;
+
+                // can't fail, the block fits the bulk
+                Ok(DataBulk::BitDepth16(
+                    Vec::from_slice(&block[..len]).unwrap(),
+                ))
+            }
+        }
+    }
+}
+
+impl<'a, S: AudioSource, T: Sample, const NUM: usize> Iterator for Samples<'a, S, T, NUM> {
+    type Item = Result<T, Error<S::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let buffered = self.bulk.as_ref().and_then(|bulk| bulk.get(self.index));
+
+        let data = match buffered {
+            Some(data) => data,
+            None => match self.input.next_n::<NUM>() {
+                Ok(bulk) => {
+                    self.index = 0;
+                    let first = bulk.get(0);
+                    self.bulk = Some(bulk);
+
+                    match first {
+                        Some(data) => data,
+                        None => {
+                            self.done = true;
+                            return None;
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            },
+        };
+
+        self.index += 1;
+
+        Some(Ok(T::from_data(data)))
+    }
+}
+
+impl<S: AudioSource> Wav<S> {
+    /// Iterate over the remaining samples converted to normalized `f32` or Q15 `i16`, so DSP code
+    /// doesn't need to match on the bit depth. Fails on the first sample for IMA ADPCM, which is
+    /// read through [`Wav::next_adpcm_block`] instead
+    pub fn samples<T: Sample, const NUM: usize>(&mut self) -> Samples<'_, S, T, NUM> {
+        Samples {
+            input: Input::Wav(self),
+            bulk: None,
+            index: 0,
+            done: false,
+            sample: PhantomData,
+        }
+    }
+
+    /// Read one whole frame of `CHANNELS` samples converted to `T`, returns [`Error::EndOfData`]
+    /// once no whole frame is left
+    pub fn read_frame<T: Sample, const CHANNELS: usize>(
+        &mut self,
+    ) -> Result<[T; CHANNELS], Error<S::Error>> {
+        let mut frame = [[T::from_data(Data::BitDepth16(0)); CHANNELS]];
+
+        match self.read_frames(&mut frame)? {
+            0 => Err(Error::EndOfData),
+            _ => Ok(frame[0]),
+        }
+    }
+
+    /// Fill `out` with whole interleaved frames converted to `T`, returns the number of frames
+    /// read, fewer than `out.len()` only at the end of the data chunk.
+    ///
+    /// Reads never end in the middle of a frame. Returns [`Error::FormatMismatch`] if the file
+    /// doesn't have `CHANNELS` channels.
+    pub fn read_frames<T: Sample, const CHANNELS: usize>(
+        &mut self,
+        out: &mut [[T; CHANNELS]],
+    ) -> Result<usize, Error<S::Error>> {
+        if self.fmt.num_channels as usize != CHANNELS || CHANNELS == 0 {
+            return Err(Error::FormatMismatch);
+        }
+
+        let mut read = 0;
+
+        while read < out.len() {
+            let bulk: DataBulk<FRAME_CHUNK_LEN> = self.next_frames(out.len() - read)?;
+
+            if bulk.is_empty() {
+                break;
+            }
+
+            for (i, data) in (0..bulk.len()).filter_map(|i| bulk.get(i)).enumerate() {
+                out[read + i / CHANNELS][i % CHANNELS] = T::from_data(data);
+            }
+
+            read += bulk.len() / CHANNELS;
+        }
+
+        Ok(read)
+    }
+
+    /// Fill `out` with whole frames of interleaved samples converted to `T`, returns the number
+    /// of samples written, fewer than `out.len()` only at the end of the data chunk.
+    ///
+    /// The source is read [`CHUNK_LEN`] bytes at a time into a stack buffer and converted straight
+    /// into `out`, without a [`DataBulk`] in between, e.g. to fill I2S DMA buffers.
+    pub fn read_samples<T: Sample>(&mut self, out: &mut [T]) -> Result<usize, Error<S::Error>> {
+        let bytes_per_sample = (self.fmt.bit_depth / 8) as usize;
+        let channels = (self.fmt.num_channels as usize).max(1);
+
+        if bytes_per_sample == 0 {
+            return Err(Error::UnsupportedBitDepth(self.fmt.bit_depth));
+        }
+
+        let samples = (out.len() / channels).min(self.frames_left()) * channels;
+        let mut buf = [0; CHUNK_LEN];
+        let mut written = 0;
+
+        while written < samples {
+            let wanted = (samples - written).min(CHUNK_LEN / bytes_per_sample) * bytes_per_sample;
+            let read = read_full(&mut self.source, &mut buf[..wanted])?;
+
+            for (out, bytes) in out[written..]
+                .iter_mut()
+                .zip(buf[..read].chunks_exact(bytes_per_sample))
+            {
+                *out = T::from_data(Data::from_bytes(&self.fmt, bytes).map_err(Error::widen)?);
+            }
+
+            written += read / bytes_per_sample;
+
+            if read < wanted {
+                break;
+            }
+        }
+
+        // a source cut short can end mid frame
+        Ok(written / channels * channels)
+    }
+
+    /// [`Wav::read_samples`] into Q15 samples
+    pub fn read_samples_i16(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        self.read_samples(out)
+    }
+
+    /// [`Wav::read_samples`] into left justified Q31 samples
+    pub fn read_samples_i32(&mut self, out: &mut [i32]) -> Result<usize, Error<S::Error>> {
+        self.read_samples(out)
+    }
+
+    /// [`Wav::read_samples`] into normalized `f32` samples
+    pub fn read_samples_f32(&mut self, out: &mut [f32]) -> Result<usize, Error<S::Error>> {
+        self.read_samples(out)
+    }
+}
+
+impl<S: AudioSource> AudioFile<S> {
+    /// Same as [`Wav::samples`], `None` for formats this crate doesn't decode.
+    ///
+    /// FLAC is decoded a block at a time to 16 bit through its [`Decoder`], `NUM` has to hold
+    /// twice [`StreamInfo::max_block_size`](crate::StreamInfo::max_block_size) frames or the
+    /// first item is [`Error::BufferTooSmall`].
+    pub fn samples<T: Sample, const NUM: usize>(&mut self) -> Option<Samples<'_, S, T, NUM>> {
+        let input = match self {
+            AudioFile::Wav(wav) => Input::Wav(wav),
+            AudioFile::Flac(flac) => Input::Flac(flac),
+            AudioFile::Mp3(_) | AudioFile::Ogg(_) | AudioFile::Avi(_) => return None,
+        };
+
+        Some(Samples {
+            input,
+            bulk: None,
+            index: 0,
+            done: false,
+            sample: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_normalize_any_bit_depth() {
+        let wav_16 = include_bytes!("../test_files/stereo_16_48000.wav");
+        let wav_24 = include_bytes!("../test_files/stereo_24_48000.wav");
+
+        for bytes in [&wav_16[..], &wav_24[..]] {
+            let mut wav = Wav::from_bytes(bytes).unwrap();
+            let mut reference = Wav::from_bytes(bytes).unwrap();
+
+            let samples = wav
+                .samples::<f32, 64>()
+                .take(200)
+                .collect::<Result<heapless::Vec<f32, 200>, _>>()
+                .unwrap();
+
+            assert_eq!(samples.len(), 200);
+
+            for sample in samples {
+                let expected = match reference.next().unwrap() {
+                    Data::BitDepth16(s) => s as f32 / 32_768.0,
+                    Data::BitDepth24(s) => s as f32 / 8_388_608.0,
+                    _ => unreachable!(),
+                };
+
+                assert_eq!(sample, expected);
+                assert!((-1.0..1.0).contains(&sample));
+            }
+        }
+    }
+
+    #[test]
+    fn should_convert_to_q15() {
+        assert_eq!(i16::from_data(Data::BitDepth8(0)), i16::MIN);
+        assert_eq!(i16::from_data(Data::BitDepth8(128)), 0);
+        assert_eq!(i16::from_data(Data::BitDepth24(0x7f_ffff)), i16::MAX);
+        assert_eq!(i16::from_data(Data::Float32(2.0)), i16::MAX);
+        assert_eq!(i16::from_data(Data::Float32(-1.0)), i16::MIN);
+        assert_eq!(f32::from_data(Data::BitDepth8(64)), -0.5);
+    }
+
+    #[test]
+    fn should_read_whole_frames() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+
+        let first: [i16; 2] = wav.read_frame().unwrap();
+        let mut frames = [[0i16; 2]; 150];
+        assert_eq!(wav.read_frames(&mut frames).unwrap(), 150);
+
+        for frame in core::iter::once(&first).chain(&frames) {
+            for &sample in frame {
+                assert_eq!(sample, i16::from_data(reference.next().unwrap()));
+            }
+        }
+
+        assert!(matches!(
+            wav.read_frame::<f32, 1>(),
+            Err(Error::FormatMismatch)
+        ));
+    }
+
+    #[test]
+    fn should_stop_at_the_last_whole_frame() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let total = wav.duration().unwrap().frames as usize;
+
+        let mut frames = [[0.0f32; 2]; 1000];
+        let mut read = 0;
+
+        loop {
+            match wav.read_frames(&mut frames).unwrap() {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        assert_eq!(read, total);
+        assert!(matches!(wav.read_frame::<i16, 2>(), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_read_samples_in_chunks() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+
+        let mut q31 = [0; 601];
+        assert_eq!(wav.read_samples_i32(&mut q31).unwrap(), 600);
+
+        let mut q15 = [0; 100];
+        assert_eq!(wav.read_samples_i16(&mut q15).unwrap(), 100);
+
+        for (&q31, data) in q31[..600].iter().zip(reference.samples::<i32, 100>()) {
+            assert_eq!(q31, data.unwrap());
+        }
+
+        for (&q15, data) in q15.iter().zip(reference.samples::<i16, 100>()) {
+            assert_eq!(q15, data.unwrap());
+        }
+
+        let total = wav.duration().unwrap().frames as usize * 2;
+        let mut rest = [0.0; 4096];
+        let mut read = 700;
+
+        loop {
+            match wav.read_samples_f32(&mut rest).unwrap() {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        assert_eq!(read, total);
+    }
+
+    #[test]
+    fn should_end_with_the_data() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+        let frames = wav.duration().unwrap().frames as usize;
+
+        assert_eq!(wav.samples::<i16, 100>().count(), frames * 2);
+        assert!(wav.samples::<i16, 100>().next().is_none());
+    }
+
+    #[test]
+    fn should_iterate_over_flac_samples() {
+        let bytes = include_bytes!("../test_files/stereo_16_8000.flac");
+        let open = || AudioFile::new_auto(crate::source::SliceSource::new(bytes)).unwrap();
+        let mut file = open();
+        let mut reference = open();
+
+        let samples = file
+            .samples::<i16, 20_000>()
+            .unwrap()
+            .collect::<Result<std::vec::Vec<i16>, _>>()
+            .unwrap();
+
+        let decoder = reference.decoder().unwrap();
+        let mut block = std::vec![0; 20_000];
+        let mut decoded = std::vec::Vec::new();
+
+        while let len @ 1.. = decoder.decode(&mut block).unwrap() {
+            decoded.extend_from_slice(&block[..len]);
+        }
+
+        assert!(!samples.is_empty());
+        assert_eq!(samples, decoded);
+        assert!(matches!(
+            open().samples::<i16, 16>().unwrap().next(),
+            Some(Err(Error::BufferTooSmall(_)))
+        ));
+    }
+}
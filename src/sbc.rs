@@ -0,0 +1,1010 @@
+use crate::encode::PacketEncoder;
+use crate::error::Error;
+
+/// Sync word starting every SBC frame
+const SYNC_WORD: u8 = 0x9c;
+/// Most subbands and channels a frame holds
+const MAX_SUBBANDS: usize = 8;
+const MAX_CHANNELS: usize = 2;
+const MAX_BLOCKS: usize = 16;
+/// Most SBC frames an A2DP media packet can announce
+const MAX_FRAMES_PER_PACKET: usize = 15;
+
+/// First half of the symmetric prototype filter for 8 subbands from the A2DP specification, Q31
+const PROTO_8: [i32; 41] = [
+    0, 336243, 737138, 1191038, 1769354, 2447970, 3170548, 3830504, 4320362, 4517704, 4283254,
+    3471542, 1937362, -383982, -3542770, -7510125, -12153672, -17243030, -22459338, -27374475,
+    -31466061, -34154783, -34834004, -32896036, -27782384, -19021498, -6279423, 10556558, 31440036,
+    56070530, 83913220, 114218864, 146026618, 178208410, 209541558, 238793071, 264708601,
+    286183152, 302265850, 312222319, 315583606,
+];
+/// First half of the symmetric prototype filter for 4 subbands from the A2DP specification, Q31
+const PROTO_4: [i32; 21] = [
+    0, 1152230, 3203796, 5870595, 8240328, 8358117, 4006811, -6571564, -23437125, -43891363,
+    -62010178, -69135936, -55569964, -13169340, 61894188, 166744266, 291184339, 418733200,
+    529648199, 605221457, 632037363,
+];
+/// `cos(i * pi / 32)` for the first quarter period, Q15
+const COS: [i32; 17] = [
+    32767, 32609, 32137, 31356, 30273, 28898, 27245, 25329, 23170, 20787, 18204, 15446, 12539,
+    9512, 6393, 3212, 0,
+];
+
+/// Analysis window `C` for 8 and 4 subbands
+const WINDOW_8: [i32; 80] = window(8);
+const WINDOW_4: [i32; 40] = window(4);
+/// Cosine modulation matrix `M` for 8 and 4 subbands
+const MATRIX_8: [[i32; 16]; 8] = matrix(8);
+const MATRIX_4: [[i32; 16]; 8] = matrix(4);
+
+/// Loudness offsets of the bit allocation for 16, 32, 44.1 and 48 kHz
+const OFFSET_4: [[i32; 4]; 4] = [[-1, 0, 0, 0], [-2, 0, 0, 1], [-2, 0, 0, 1], [-2, 0, 0, 1]];
+const OFFSET_8: [[i32; 8]; 4] = [
+    [-2, 0, 0, 0, 0, 0, 0, 1],
+    [-3, 0, 0, 0, 0, 0, 1, 2],
+    [-4, 0, 0, 0, 0, 0, 1, 2],
+    [-4, 0, 0, 0, 0, 0, 1, 2],
+];
+
+/// Mirror the prototype filter and flip the sign of every other group of `2 * subbands` taps
+const fn window<const N: usize>(subbands: usize) -> [i32; N] {
+    let mut window = [0; N];
+    let mut i = 0;
+
+    while i < N {
+        let tap = if i <= N / 2 { i } else { N - i };
+        let value = if subbands == 8 {
+            PROTO_8[tap]
+        } else {
+            PROTO_4[tap]
+        };
+
+        window[i] = if (i / (2 * subbands)) % 2 == 1 {
+            -value
+        } else {
+            value
+        };
+        i += 1;
+    }
+
+    window
+}
+
+/// `cos((k + 0.5) * (i - subbands / 2) * pi / subbands)` for every subband `k`
+const fn matrix(subbands: usize) -> [[i32; 16]; 8] {
+    let mut matrix = [[0; 16]; 8];
+    let mut k = 0;
+
+    while k < subbands {
+        let mut i = 0;
+
+        while i < 2 * subbands {
+            // angle in multiples of pi / 32
+            let angle =
+                (2 * k as i32 + 1) * (2 * i as i32 - subbands as i32) * (8 / subbands as i32);
+            let angle = angle.rem_euclid(64);
+            let angle = if angle > 32 { 64 - angle } else { angle };
+
+            matrix[k][i] = if angle <= 16 {
+                COS[angle as usize]
+            } else {
+                -COS[32 - angle as usize]
+            };
+            i += 1;
+        }
+        k += 1;
+    }
+
+    matrix
+}
+
+/// How the channels of an SBC stream are coded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbcChannelMode {
+    /// A single channel
+    Mono,
+    /// Two channels, each with its own bitpool
+    DualChannel,
+    /// Two channels sharing one bitpool
+    Stereo,
+    /// Two channels sharing one bitpool, no subbands are joined by this encoder
+    JointStereo,
+}
+
+/// How the bitpool is spread over the subbands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbcAllocation {
+    /// Weighted by a model of the hearing threshold, the common choice
+    Loudness,
+    /// Weighted by the scale factors only
+    Snr,
+}
+
+/// Parameters of an SBC stream, as negotiated with the A2DP sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SbcConfig {
+    /// `16_000`, `32_000`, `44_100` or `48_000`
+    pub sample_rate: u32,
+    /// Blocks per frame, `4`, `8`, `12` or `16`
+    pub blocks: u8,
+    /// Coding of the channels
+    pub channel_mode: SbcChannelMode,
+    /// Bit allocation method
+    pub allocation: SbcAllocation,
+    /// `4` or `8`
+    pub subbands: u8,
+    /// Bits per block to spread over the subbands, sets the bitrate
+    pub bitpool: u8,
+}
+
+impl SbcConfig {
+    /// Number of audio channels, PCM handed to the encoder is interleaved
+    pub fn channels(&self) -> usize {
+        match self.channel_mode {
+            SbcChannelMode::Mono => 1,
+            _ => 2,
+        }
+    }
+
+    /// Number of PCM frames every SBC frame holds
+    pub fn frames_per_sbc_frame(&self) -> usize {
+        self.blocks as usize * self.subbands as usize
+    }
+
+    /// Length in bytes of every encoded frame
+    pub fn frame_len(&self) -> usize {
+        let (blocks, subbands) = (self.blocks as usize, self.subbands as usize);
+        let channels = self.channels();
+        let bitpool = self.bitpool as usize;
+
+        let data_bits = match self.channel_mode {
+            SbcChannelMode::Mono | SbcChannelMode::DualChannel => blocks * channels * bitpool,
+            SbcChannelMode::Stereo => blocks * bitpool,
+            SbcChannelMode::JointStereo => subbands + blocks * bitpool,
+        };
+
+        4 + 4 * subbands * channels / 8 + data_bits.div_ceil(8)
+    }
+
+    fn sample_rate_index(&self) -> Option<usize> {
+        match self.sample_rate {
+            16_000 => Some(0),
+            32_000 => Some(1),
+            44_100 => Some(2),
+            48_000 => Some(3),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let max_bitpool = match self.channel_mode {
+            SbcChannelMode::Mono | SbcChannelMode::DualChannel => 16 * self.subbands as u16,
+            _ => 32 * self.subbands as u16,
+        };
+
+        if self.sample_rate_index().is_none()
+            || !matches!(self.blocks, 4 | 8 | 12 | 16)
+            || !matches!(self.subbands, 4 | 8)
+            || self.bitpool < 2
+            || self.bitpool as u16 > max_bitpool
+        {
+            return Err(Error::InvalidEncoderConfig);
+        }
+
+        Ok(())
+    }
+}
+
+/// Encoder turning 16 bit PCM into SBC frames, e.g. for a Bluetooth module in the A2DP source role
+///
+/// All arithmetic is fixed point, so no FPU is needed.
+pub struct SbcEncoder {
+    config: SbcConfig,
+    /// Analysis filter history of every channel
+    history: [[i32; 10 * MAX_SUBBANDS]; MAX_CHANNELS],
+}
+
+impl SbcEncoder {
+    /// Create an encoder for frames in `config`
+    pub fn new(config: SbcConfig) -> Result<Self, Error> {
+        config.validate()?;
+
+        Ok(SbcEncoder {
+            config,
+            history: [[0; 10 * MAX_SUBBANDS]; MAX_CHANNELS],
+        })
+    }
+
+    /// Parameters of the encoded frames
+    pub fn config(&self) -> &SbcConfig {
+        &self.config
+    }
+
+    /// Encode one frame from interleaved `pcm` holding [`SbcConfig::frames_per_sbc_frame`] frames,
+    /// returning the number of bytes written to `out`
+    pub fn encode(&mut self, pcm: &[i16], out: &mut [u8]) -> Result<usize, Error> {
+        let config = self.config;
+        let (blocks, subbands) = (config.blocks as usize, config.subbands as usize);
+        let channels = config.channels();
+        let frame_len = config.frame_len();
+
+        if pcm.len() < blocks * subbands * channels {
+            return Err(Error::BufferTooSmall(blocks * subbands * channels));
+        }
+
+        if out.len() < frame_len {
+            return Err(Error::BufferTooSmall(frame_len));
+        }
+
+        let mut samples = [[[0; MAX_SUBBANDS]; MAX_CHANNELS]; MAX_BLOCKS];
+
+        for (block, pcm) in samples
+            .iter_mut()
+            .zip(pcm.chunks_exact(subbands * channels))
+        {
+            for (channel, samples) in block[..channels].iter_mut().enumerate() {
+                self.analyse(channel, pcm, samples);
+            }
+        }
+
+        let mut scale_factors = [[0; MAX_SUBBANDS]; MAX_CHANNELS];
+
+        for (channel, scale_factors) in scale_factors[..channels].iter_mut().enumerate() {
+            for (subband, scale_factor) in scale_factors[..subbands].iter_mut().enumerate() {
+                let peak = samples[..blocks]
+                    .iter()
+                    .map(|block| block[channel][subband].unsigned_abs())
+                    .max()
+                    .unwrap_or(0);
+
+                // subband samples carry 8 fractional bits
+                while *scale_factor < 15 && peak >= 1 << (*scale_factor + 9) {
+                    *scale_factor += 1;
+                }
+            }
+        }
+
+        let bits = self.allocate_bits(&scale_factors);
+
+        let out = &mut out[..frame_len];
+        out.fill(0);
+
+        let mut writer = BitWriter { bytes: out, bit: 0 };
+        let channel_mode = match config.channel_mode {
+            SbcChannelMode::Mono => 0,
+            SbcChannelMode::DualChannel => 1,
+            SbcChannelMode::Stereo => 2,
+            SbcChannelMode::JointStereo => 3,
+        };
+
+        writer.write(SYNC_WORD as u32, 8);
+        writer.write(config.sample_rate_index().unwrap_or(0) as u32, 2);
+        writer.write(blocks as u32 / 4 - 1, 2);
+        writer.write(channel_mode, 2);
+        writer.write((config.allocation == SbcAllocation::Snr) as u32, 1);
+        writer.write((subbands == 8) as u32, 1);
+        writer.write(config.bitpool as u32, 8);
+        // CRC, filled in once the scale factors are written
+        writer.write(0, 8);
+
+        if config.channel_mode == SbcChannelMode::JointStereo {
+            writer.write(0, subbands as u32);
+        }
+
+        for scale_factors in &scale_factors[..channels] {
+            for &scale_factor in &scale_factors[..subbands] {
+                writer.write(scale_factor, 4);
+            }
+        }
+
+        let crc_end = writer.bit;
+
+        for block in &samples[..blocks] {
+            for channel in 0..channels {
+                for subband in 0..subbands {
+                    let bits = bits[channel][subband];
+
+                    if bits == 0 {
+                        continue;
+                    }
+
+                    let levels = (1i64 << bits) - 1;
+                    let scale_factor = scale_factors[channel][subband];
+                    let sample = block[channel][subband] as i64 + (1 << (scale_factor + 9));
+                    let quantized = ((sample * levels) >> (scale_factor + 10)).clamp(0, levels - 1);
+
+                    writer.write(quantized as u32, bits);
+                }
+            }
+        }
+
+        out[3] = crc8(out, 8..24, 32..crc_end);
+
+        Ok(frame_len)
+    }
+
+    /// Encode as many whole frames as `pcm` holds into an A2DP media payload written to `out`.
+    ///
+    /// The payload starts with the header byte announcing the number of frames, at most 15 frames
+    /// are written and never more than fit in `out`. Returns the number of bytes written.
+    pub fn encode_packet(&mut self, pcm: &[i16], out: &mut [u8]) -> Result<usize, Error> {
+        let pcm_len = self.config.frames_per_sbc_frame() * self.config.channels();
+        let frame_len = self.config.frame_len();
+
+        let frames = (pcm.len() / pcm_len)
+            .min(out.len().saturating_sub(1) / frame_len)
+            .min(MAX_FRAMES_PER_PACKET);
+
+        if frames == 0 {
+            return Err(Error::BufferTooSmall(1 + frame_len));
+        }
+
+        out[0] = frames as u8;
+
+        for (pcm, out) in pcm
+            .chunks_exact(pcm_len)
+            .zip(out[1..].chunks_exact_mut(frame_len))
+            .take(frames)
+        {
+            self.encode(pcm, out)?;
+        }
+
+        Ok(1 + frames * frame_len)
+    }
+
+    /// Push one block of `channel` through the analysis filter bank, subband samples get 8
+    /// fractional bits
+    fn analyse(&mut self, channel: usize, pcm: &[i16], out: &mut [i32; MAX_SUBBANDS]) {
+        let subbands = self.config.subbands as usize;
+        let channels = self.config.channels();
+        let taps = 10 * subbands;
+
+        let (window, matrix): (&[i32], _) = match subbands {
+            8 => (&WINDOW_8, &MATRIX_8),
+            _ => (&WINDOW_4, &MATRIX_4),
+        };
+
+        let history = &mut self.history[channel][..taps];
+        history.copy_within(..taps - subbands, subbands);
+
+        for i in 0..subbands {
+            history[subbands - 1 - i] = pcm[i * channels + channel] as i32;
+        }
+
+        let mut partial = [0i64; 2 * MAX_SUBBANDS];
+
+        for (i, partial) in partial[..2 * subbands].iter_mut().enumerate() {
+            let sum: i64 = (i..taps)
+                .step_by(2 * subbands)
+                .map(|tap| window[tap] as i64 * history[tap] as i64)
+                .sum();
+
+            *partial = sum >> 15;
+        }
+
+        for (out, row) in out[..subbands].iter_mut().zip(matrix) {
+            let sum: i64 = row[..2 * subbands]
+                .iter()
+                .zip(&partial)
+                .map(|(m, y)| *m as i64 * y)
+                .sum();
+
+            *out = (sum >> 23) as i32;
+        }
+    }
+
+    /// Spread the bitpool over the subbands, channel by channel or over both channels at once
+    fn allocate_bits(
+        &self,
+        scale_factors: &[[u32; MAX_SUBBANDS]; MAX_CHANNELS],
+    ) -> [[u32; MAX_SUBBANDS]; MAX_CHANNELS] {
+        let config = &self.config;
+        let subbands = config.subbands as usize;
+        let channels = config.channels();
+        let sample_rate = config.sample_rate_index().unwrap_or(0);
+
+        let mut needs = [[0; MAX_SUBBANDS]; MAX_CHANNELS];
+
+        for (needs, scale_factors) in needs.iter_mut().zip(scale_factors) {
+            for subband in 0..subbands {
+                let scale_factor = scale_factors[subband] as i32;
+
+                needs[subband] = match config.allocation {
+                    SbcAllocation::Snr => scale_factor,
+                    SbcAllocation::Loudness if scale_factor == 0 => -5,
+                    SbcAllocation::Loudness => {
+                        let offset = match subbands {
+                            8 => OFFSET_8[sample_rate][subband],
+                            _ => OFFSET_4[sample_rate][subband],
+                        };
+                        let loudness = scale_factor - offset;
+
+                        if loudness > 0 {
+                            loudness / 2
+                        } else {
+                            loudness
+                        }
+                    }
+                };
+            }
+        }
+
+        let mut bits = [[0; MAX_SUBBANDS]; MAX_CHANNELS];
+
+        match config.channel_mode {
+            SbcChannelMode::Mono | SbcChannelMode::DualChannel => {
+                for (bits, needs) in bits[..channels].iter_mut().zip(&needs) {
+                    allocate(&needs[..subbands], config.bitpool, &mut bits[..subbands]);
+                }
+            }
+            SbcChannelMode::Stereo | SbcChannelMode::JointStereo => {
+                // both channels share the bitpool, subband by subband
+                let mut joint_needs = [0; MAX_CHANNELS * MAX_SUBBANDS];
+                let mut joint_bits = [0; MAX_CHANNELS * MAX_SUBBANDS];
+
+                for subband in 0..subbands {
+                    joint_needs[2 * subband] = needs[0][subband];
+                    joint_needs[2 * subband + 1] = needs[1][subband];
+                }
+
+                allocate(
+                    &joint_needs[..2 * subbands],
+                    config.bitpool,
+                    &mut joint_bits[..2 * subbands],
+                );
+
+                for subband in 0..subbands {
+                    bits[0][subband] = joint_bits[2 * subband];
+                    bits[1][subband] = joint_bits[2 * subband + 1];
+                }
+            }
+        }
+
+        bits
+    }
+}
+
+impl PacketEncoder for SbcEncoder {
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate
+    }
+
+    fn num_channels(&self) -> u16 {
+        self.config.channels() as u16
+    }
+
+    fn frame_size(&self) -> usize {
+        self.config.frames_per_sbc_frame()
+    }
+
+    fn encode(&mut self, pcm: &[i16], out: &mut [u8]) -> Result<usize, Error> {
+        SbcEncoder::encode(self, pcm, out)
+    }
+}
+
+/// Bit allocation of the A2DP specification, slicing `bitpool` over the `needs` of the subbands
+fn allocate(needs: &[i32], bitpool: u8, bits: &mut [u32]) {
+    let bitpool = bitpool as i32;
+    let max_need = needs.iter().copied().max().unwrap_or(0);
+
+    let mut bit_count = 0;
+    let mut slice_count = 0;
+    let mut bit_slice = max_need + 1;
+
+    loop {
+        bit_slice -= 1;
+        bit_count += slice_count;
+        slice_count = 0;
+
+        for &need in needs {
+            if need > bit_slice + 1 && need < bit_slice + 16 {
+                slice_count += 1;
+            } else if need == bit_slice + 1 {
+                slice_count += 2;
+            }
+        }
+
+        if bit_count + slice_count >= bitpool {
+            break;
+        }
+    }
+
+    if bit_count + slice_count == bitpool {
+        bit_count += slice_count;
+        bit_slice -= 1;
+    }
+
+    for (bits, &need) in bits.iter_mut().zip(needs) {
+        *bits = if need < bit_slice + 2 {
+            0
+        } else {
+            (need - bit_slice).min(16) as u32
+        };
+    }
+
+    for (bits, &need) in bits.iter_mut().zip(needs) {
+        if bit_count >= bitpool {
+            break;
+        }
+
+        if *bits >= 2 && *bits < 16 {
+            *bits += 1;
+            bit_count += 1;
+        } else if need == bit_slice + 1 && bitpool > bit_count + 1 {
+            *bits = 2;
+            bit_count += 2;
+        }
+    }
+
+    for bits in bits.iter_mut() {
+        if bit_count >= bitpool {
+            break;
+        }
+
+        if *bits < 16 {
+            *bits += 1;
+            bit_count += 1;
+        }
+    }
+}
+
+/// Writes values most significant bit first into a zeroed buffer
+struct BitWriter<'b> {
+    bytes: &'b mut [u8],
+    bit: usize,
+}
+
+impl<'b> BitWriter<'b> {
+    fn write(&mut self, value: u32, count: u32) {
+        for shift in (0..count).rev() {
+            if (value >> shift) & 1 == 1 {
+                self.bytes[self.bit / 8] |= 0x80 >> (self.bit % 8);
+            }
+            self.bit += 1;
+        }
+    }
+}
+
+/// CRC-8 with polynomial `0x1d` over the given bit ranges of `bytes`
+fn crc8(bytes: &[u8], header: core::ops::Range<usize>, body: core::ops::Range<usize>) -> u8 {
+    let mut crc = 0x0f_u8;
+
+    for bit in header.chain(body) {
+        let input = (bytes[bit / 8] >> (7 - bit % 8)) & 1;
+        let feedback = (crc >> 7) ^ input;
+
+        crc <<= 1;
+
+        if feedback == 1 {
+            crc ^= 0x1d;
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: SbcConfig = SbcConfig {
+        sample_rate: 44_100,
+        blocks: 16,
+        channel_mode: SbcChannelMode::JointStereo,
+        allocation: SbcAllocation::Loudness,
+        subbands: 8,
+        bitpool: 53,
+    };
+
+    #[test]
+    fn should_build_analysis_tables() {
+        assert_eq!(WINDOW_8[16], -PROTO_8[16]);
+        assert_eq!(WINDOW_8[79], PROTO_8[1]);
+        assert_eq!(WINDOW_4[39], PROTO_4[1]);
+        assert_eq!(MATRIX_8[0][4], 32767);
+        assert_eq!(MATRIX_4[0][2], 32767);
+        assert_eq!(MATRIX_8[0][0], COS[8]);
+    }
+
+    #[test]
+    fn should_encode_frames_with_valid_header() {
+        let mut encoder = SbcEncoder::new(CONFIG).unwrap();
+        let pcm: std::vec::Vec<i16> = (0..2 * 128)
+            .map(|i| ((i * 7919) % 20_000 - 10_000) as i16)
+            .collect();
+
+        let mut out = [0; 128];
+        let len = encoder.encode(&pcm, &mut out).unwrap();
+
+        assert_eq!(len, 119);
+        assert_eq!(CONFIG.frame_len(), 119);
+        assert_eq!(out[..3], [0x9c, 0xbd, 53]);
+
+        let mut packet = [0; 1 + 2 * 119];
+        assert_eq!(encoder.encode_packet(&pcm, &mut packet).unwrap(), 1 + 119);
+        assert_eq!(packet[0], 1);
+    }
+
+    #[test]
+    fn should_reject_invalid_configs() {
+        for config in [
+            SbcConfig {
+                sample_rate: 22_050,
+                ..CONFIG
+            },
+            SbcConfig {
+                subbands: 6,
+                ..CONFIG
+            },
+            SbcConfig {
+                bitpool: 1,
+                ..CONFIG
+            },
+            SbcConfig {
+                channel_mode: SbcChannelMode::Mono,
+                bitpool: 200,
+                ..CONFIG
+            },
+        ] {
+            assert!(matches!(
+                SbcEncoder::new(config),
+                Err(Error::InvalidEncoderConfig)
+            ));
+        }
+    }
+
+    #[test]
+    fn should_allocate_the_whole_bitpool() {
+        let mut bits = [0; 8];
+        allocate(&[4, 3, 3, 2, 2, 1, 0, -5], 32, &mut bits);
+
+        assert_eq!(bits.iter().sum::<u32>(), 32);
+        assert!(bits.iter().all(|&bits| bits <= 16));
+    }
+
+    /// Reads values most significant bit first
+    struct BitReader<'b> {
+        bytes: &'b [u8],
+        bit: usize,
+    }
+
+    impl<'b> BitReader<'b> {
+        fn read(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |value, _| {
+                let bit = (self.bytes[self.bit / 8] >> (7 - self.bit % 8)) & 1;
+                self.bit += 1;
+                value << 1 | bit as u32
+            })
+        }
+    }
+
+    /// Bit allocation written after the pseudo code of the A2DP specification, apart from the
+    /// encoder's, `stereo` slicing the bitpool over both channels at once
+    fn spec_bits(
+        bitneed: &[[i32; 8]; 2],
+        channels: usize,
+        subbands: usize,
+        bitpool: i32,
+        stereo: bool,
+    ) -> [[u32; 8]; 2] {
+        let mut bits = [[0i32; 8]; 2];
+        let groups: &[&[usize]] = match (stereo, channels) {
+            (true, _) => &[&[0, 1]],
+            (false, 1) => &[&[0]],
+            (false, _) => &[&[0], &[1]],
+        };
+
+        for group in groups {
+            let max_bitneed = group
+                .iter()
+                .flat_map(|&ch| bitneed[ch][..subbands].iter().copied())
+                .max()
+                .unwrap();
+
+            let (mut bitcount, mut slicecount, mut bitslice) = (0, 0, max_bitneed + 1);
+
+            loop {
+                bitslice -= 1;
+                bitcount += slicecount;
+                slicecount = 0;
+
+                for &ch in group.iter() {
+                    for &need in &bitneed[ch][..subbands] {
+                        if need > bitslice + 1 && need < bitslice + 16 {
+                            slicecount += 1;
+                        } else if need == bitslice + 1 {
+                            slicecount += 2;
+                        }
+                    }
+                }
+
+                if bitcount + slicecount >= bitpool {
+                    break;
+                }
+            }
+
+            if bitcount + slicecount == bitpool {
+                bitcount += slicecount;
+                bitslice -= 1;
+            }
+
+            for &ch in group.iter() {
+                for sb in 0..subbands {
+                    bits[ch][sb] = match bitneed[ch][sb] < bitslice + 2 {
+                        true => 0,
+                        false => (bitneed[ch][sb] - bitslice).min(16),
+                    };
+                }
+            }
+
+            // channels alternate within a subband when they share the bitpool
+            let order: std::vec::Vec<(usize, usize)> = (0..subbands)
+                .flat_map(|sb| group.iter().map(move |&ch| (ch, sb)))
+                .collect();
+
+            for &(ch, sb) in &order {
+                if bitcount >= bitpool {
+                    break;
+                }
+
+                if bits[ch][sb] >= 2 && bits[ch][sb] < 16 {
+                    bits[ch][sb] += 1;
+                    bitcount += 1;
+                } else if bitneed[ch][sb] == bitslice + 1 && bitpool > bitcount + 1 {
+                    bits[ch][sb] = 2;
+                    bitcount += 2;
+                }
+            }
+
+            for &(ch, sb) in &order {
+                if bitcount >= bitpool {
+                    break;
+                }
+
+                if bits[ch][sb] < 16 {
+                    bits[ch][sb] += 1;
+                    bitcount += 1;
+                }
+            }
+        }
+
+        bits.map(|bits| bits.map(|bits| bits as u32))
+    }
+
+    /// Floating point SBC decoder after the A2DP specification, to check the encoded frames
+    /// against instead of bytes the encoder produced itself
+    struct ReferenceDecoder {
+        /// Synthesis filter history of every channel
+        v: [[f64; 160]; 2],
+    }
+
+    impl ReferenceDecoder {
+        fn new() -> Self {
+            ReferenceDecoder { v: [[0.0; 160]; 2] }
+        }
+
+        /// Decode one frame, returns the interleaved PCM and the length of the frame in bytes
+        fn decode(&mut self, frame: &[u8]) -> (std::vec::Vec<f64>, usize) {
+            let mut reader = BitReader {
+                bytes: frame,
+                bit: 0,
+            };
+
+            assert_eq!(reader.read(8), 0x9c);
+            let sample_rate = reader.read(2) as usize;
+            let blocks = 4 * (reader.read(2) as usize + 1);
+            let channel_mode = reader.read(2);
+            let snr = reader.read(1) == 1;
+            let subbands = 4 * (reader.read(1) as usize + 1);
+            let bitpool = reader.read(8) as i32;
+            let crc = reader.read(8) as u8;
+            let channels = 1 + (channel_mode != 0) as usize;
+
+            let mut join = [false; 8];
+
+            if channel_mode == 3 {
+                for join in &mut join[..subbands] {
+                    *join = reader.read(1) == 1;
+                }
+            }
+
+            let mut scale_factors = [[0; 8]; 2];
+
+            for scale_factors in &mut scale_factors[..channels] {
+                for scale_factor in &mut scale_factors[..subbands] {
+                    *scale_factor = reader.read(4) as i32;
+                }
+            }
+
+            // CRC-8, polynomial x^8 + x^4 + x^3 + x^2 + 1, over the header and scale factors
+            let mut check = 0x0f_u8;
+
+            for bit in (8..24).chain(32..reader.bit) {
+                let input = (frame[bit / 8] >> (7 - bit % 8)) & 1;
+                let msb = check >> 7;
+                check <<= 1;
+
+                if msb ^ input == 1 {
+                    check ^= 0x1d;
+                }
+            }
+
+            assert_eq!(check, crc);
+
+            let offset: &[i32] = match (subbands, sample_rate) {
+                (4, 0) => &[-1, 0, 0, 0],
+                (4, _) => &[-2, 0, 0, 1],
+                (8, 0) => &[-2, 0, 0, 0, 0, 0, 0, 1],
+                (8, 1) => &[-3, 0, 0, 0, 0, 0, 1, 2],
+                _ => &[-4, 0, 0, 0, 0, 0, 1, 2],
+            };
+
+            let mut bitneed = [[0; 8]; 2];
+
+            for ch in 0..channels {
+                for sb in 0..subbands {
+                    let scale_factor = scale_factors[ch][sb];
+
+                    bitneed[ch][sb] = match (snr, scale_factor - offset[sb]) {
+                        (true, _) => scale_factor,
+                        (false, _) if scale_factor == 0 => -5,
+                        (false, loudness) if loudness > 0 => loudness / 2,
+                        (false, loudness) => loudness,
+                    };
+                }
+            }
+
+            let stereo = channel_mode >= 2;
+            let bits = spec_bits(&bitneed, channels, subbands, bitpool, stereo);
+
+            let mut pcm = std::vec::Vec::new();
+
+            for _ in 0..blocks {
+                let mut sb_sample = [[0.0; 8]; 2];
+
+                for ch in 0..channels {
+                    for sb in 0..subbands {
+                        let bits = bits[ch][sb];
+
+                        if bits == 0 {
+                            continue;
+                        }
+
+                        let quantized = reader.read(bits) as f64;
+                        let range = 2f64.powi(scale_factors[ch][sb] + 1);
+                        let levels = 2f64.powi(bits as i32) - 1.0;
+
+                        sb_sample[ch][sb] = (2.0 * quantized + 1.0) * range / levels - range;
+                    }
+                }
+
+                for sb in 0..subbands {
+                    if join[sb] {
+                        let (mid, side) = (sb_sample[0][sb], sb_sample[1][sb]);
+                        sb_sample[0][sb] = mid + side;
+                        sb_sample[1][sb] = mid - side;
+                    }
+                }
+
+                let mut out = [[0.0; 8]; 2];
+
+                for ch in 0..channels {
+                    self.synthesize(&sb_sample[ch][..subbands], ch, &mut out[ch][..subbands]);
+                }
+
+                for i in 0..subbands {
+                    pcm.extend(out[..channels].iter().map(|out| out[i]));
+                }
+            }
+
+            (pcm, reader.bit.div_ceil(8))
+        }
+
+        /// Synthesis filter bank of the specification, windowed by `-M` times the analysis
+        /// window
+        fn synthesize(&mut self, sb_sample: &[f64], ch: usize, out: &mut [f64]) {
+            let m = sb_sample.len();
+            let v = &mut self.v[ch][..20 * m];
+            let window: std::vec::Vec<f64> = match m {
+                8 => WINDOW_8.iter().map(|&c| c as f64 / 2f64.powi(31)).collect(),
+                _ => WINDOW_4.iter().map(|&c| c as f64 / 2f64.powi(31)).collect(),
+            };
+
+            v.copy_within(..18 * m, 2 * m);
+
+            for (k, v) in v[..2 * m].iter_mut().enumerate() {
+                *v = (0..m)
+                    .map(|i| {
+                        let angle = (i as f64 + 0.5) * (k as f64 + m as f64 / 2.0);
+                        (angle * core::f64::consts::PI / m as f64).cos() * sb_sample[i]
+                    })
+                    .sum();
+            }
+
+            let mut u = [0.0; 80];
+
+            for i in 0..5 {
+                for j in 0..m {
+                    u[i * 2 * m + j] = v[i * 4 * m + j];
+                    u[i * 2 * m + m + j] = v[i * 4 * m + 3 * m + j];
+                }
+            }
+
+            for (j, out) in out.iter_mut().enumerate() {
+                *out = (0..10)
+                    .map(|i| u[j + m * i] * window[j + m * i] * -(m as f64))
+                    .sum();
+            }
+        }
+    }
+
+    /// Signal to noise ratio in dB of `decoded` against `pcm`, `delay` frames later
+    fn snr(pcm: &[i16], decoded: &[f64], channels: usize, delay: usize) -> f64 {
+        let skip = channels * 2 * delay;
+        let (signal, noise) = pcm
+            .iter()
+            .zip(&decoded[channels * delay..])
+            .skip(skip)
+            .fold((0.0, 0.0), |(signal, noise), (&x, &y)| {
+                let x = x as f64;
+                (signal + x * x, noise + (x - y) * (x - y))
+            });
+
+        10.0 * (signal / noise).log10()
+    }
+
+    #[test]
+    fn should_decode_with_a_reference_decoder() {
+        let tone = |i: usize, hz: f64, rate: f64| {
+            (2.0 * core::f64::consts::PI * hz * i as f64 / rate).sin()
+        };
+
+        for (config, delay) in [
+            (CONFIG, 73),
+            (
+                SbcConfig {
+                    sample_rate: 32_000,
+                    blocks: 8,
+                    channel_mode: SbcChannelMode::Mono,
+                    allocation: SbcAllocation::Snr,
+                    subbands: 4,
+                    bitpool: 31,
+                },
+                37,
+            ),
+            (
+                SbcConfig {
+                    channel_mode: SbcChannelMode::DualChannel,
+                    bitpool: 32,
+                    ..CONFIG
+                },
+                73,
+            ),
+        ] {
+            let channels = config.channels();
+            let rate = config.sample_rate as f64;
+            let pcm: std::vec::Vec<i16> = (0..20 * config.frames_per_sbc_frame())
+                .flat_map(|i| {
+                    let left = 8_000.0 * tone(i, 440.0, rate) + 4_000.0 * tone(i, 3_000.0, rate);
+                    let right = 10_000.0 * tone(i, 1_000.0, rate);
+                    [left as i16, right as i16][..channels].to_vec()
+                })
+                .collect();
+
+            let mut encoder = SbcEncoder::new(config).unwrap();
+            let mut decoder = ReferenceDecoder::new();
+            let mut decoded = std::vec::Vec::new();
+
+            for pcm in pcm.chunks_exact(config.frames_per_sbc_frame() * channels) {
+                let mut frame = [0; 512];
+                let len = encoder.encode(pcm, &mut frame).unwrap();
+                let (samples, read) = decoder.decode(&frame[..len]);
+
+                assert_eq!(read, len);
+                decoded.extend(samples);
+            }
+
+            assert!(snr(&pcm, &decoded, channels, delay) > 50.0, "{:?}", config);
+        }
+    }
+}
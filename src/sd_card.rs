@@ -0,0 +1,659 @@
+use crate::audio_file::AudioFile;
+use crate::error::Error;
+use crate::identity::{fat_timestamp, TrackIdentity};
+use crate::index::Index;
+#[cfg(feature = "write")]
+use crate::index::{IndexEntry, IndexWriter, INDEX_NAME_LEN};
+#[cfg(feature = "write")]
+use crate::play_stats::PlayStats;
+use crate::resume::{ResumeToken, RESUME_PATH_LEN};
+#[cfg(feature = "write")]
+use core::fmt::Write;
+#[cfg(feature = "write")]
+use embedded_sdmmc::ShortFileName;
+use embedded_sdmmc::{
+    BlockDevice, Directory, File, Mode, RawDirectory, RawFile, RawVolume, TimeSource, VolumeIdx,
+    VolumeManager,
+};
+#[cfg(feature = "write")]
+use heapless::{String, Vec};
+
+/// Names gathered, and entries written, per pass over a directory while indexing it
+#[cfg(feature = "write")]
+const SCAN_BATCH: usize = 8;
+
+/// Id offset `VolumeManager::new` gives its handles
+const ID_OFFSET: u32 = 5000;
+
+/// Audio files opened by path from the first FAT volume of a card, without handling the
+/// `VolumeManager`, volume and directories of embedded_sdmmc by hand
+///
+/// The volume stays open until [`SdCard::close`]. The limits are those of the underlying
+/// `VolumeManager`, each open file takes one of `MAX_FILES`.
+pub struct SdCard<
+    D: BlockDevice,
+    T: TimeSource,
+    const MAX_DIRS: usize = 4,
+    const MAX_FILES: usize = 4,
+    const MAX_VOLUMES: usize = 1,
+> {
+    volume_mgr: VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    volume: RawVolume,
+}
+
+/// [`AudioFile`] opened by [`SdCard::open`]
+pub type SdAudioFile<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize = 4,
+    const MAX_FILES: usize = 4,
+    const MAX_VOLUMES: usize = 1,
+> = AudioFile<File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>;
+
+/// [`Index`] opened by [`SdCard::open_index`]
+pub type SdIndex<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize = 4,
+    const MAX_FILES: usize = 4,
+    const MAX_VOLUMES: usize = 1,
+> = Index<File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>;
+
+/// [`PlayStats`] opened by [`SdCard::open_stats`]
+#[cfg(feature = "write")]
+pub type SdPlayStats<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize = 4,
+    const MAX_FILES: usize = 4,
+    const MAX_VOLUMES: usize = 1,
+> = PlayStats<File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>;
+
+impl<
+        D: BlockDevice,
+        T: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > SdCard<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    /// Open the first volume of `block_device`, `time_source` dates files written to it
+    pub fn new(block_device: D, time_source: T) -> Result<Self, embedded_sdmmc::Error<D::Error>> {
+        let mut volume_mgr = VolumeManager::new_with_limits(block_device, time_source, ID_OFFSET);
+        let volume = volume_mgr.open_raw_volume(VolumeIdx(0))?;
+
+        Ok(SdCard { volume_mgr, volume })
+    }
+
+    /// Open the file at `path` read only and sniff its format with [`AudioFile::new_auto`].
+    ///
+    /// `path` names 8.3 directories separated by `/`, relative to the root directory, e.g.
+    /// `MUSIC/TRACK01.WAV`. Failing to open it is reported as [`Error::Source`].
+    pub fn open(
+        &mut self,
+        path: &str,
+    ) -> Result<
+        SdAudioFile<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        Error<embedded_sdmmc::Error<D::Error>>,
+    > {
+        let file = self
+            .open_file(path, Mode::ReadOnly)
+            .map_err(Error::Source)?;
+
+        AudioFile::new_auto(file)
+    }
+
+    /// Probe every audio file of the directory at `dir` and write their format, length and tags
+    /// to a new index file at `index_path`, returns the number of indexed files.
+    ///
+    /// Subdirectories and files that aren't audio are skipped. Reading the index with
+    /// [`SdCard::open_index`] at the next boot is much faster than scanning again.
+    #[cfg(feature = "write")]
+    pub fn build_index(
+        &mut self,
+        dir: &str,
+        index_path: &str,
+    ) -> Result<u32, Error<embedded_sdmmc::Error<D::Error>>> {
+        let (_, index_name) = split_path(index_path);
+        let index = self
+            .open_raw_file(index_path, Mode::ReadWriteCreateOrTruncate)
+            .map_err(Error::Source)?;
+
+        let dir = match self.open_raw_dir(dir.split('/')) {
+            Ok(dir) => dir,
+            Err(e) => {
+                let _ = self.volume_mgr.close_file(index);
+                return Err(Error::Source(e));
+            }
+        };
+
+        let len = self.write_index(dir, index, index_name);
+
+        // both are closed whatever happened, failing to close only matters if indexing worked
+        let closed = self.volume_mgr.close_dir(dir);
+        let closed = closed.and(self.volume_mgr.close_file(index));
+        let len = len?;
+        closed.map_err(Error::Source)?;
+
+        Ok(len)
+    }
+
+    /// Write the header and an entry for every audio file of `dir` to `index`
+    #[cfg(feature = "write")]
+    fn write_index(
+        &mut self,
+        dir: RawDirectory,
+        index: RawFile,
+        index_name: &str,
+    ) -> Result<u32, Error<embedded_sdmmc::Error<D::Error>>> {
+        let writer = IndexWriter::new(index.to_file(&mut self.volume_mgr)).map_err(Error::widen)?;
+        let mut index = writer.into_inner().to_raw_file();
+        let mut len = 0;
+        let mut done = 0;
+
+        // names are gathered in batches, files can't be opened while the directory is iterated.
+        // The entries of a batch are written once the files probed for them are closed again.
+        loop {
+            let mut names: Vec<ShortFileName, SCAN_BATCH> = Vec::new();
+            let mut seen = 0;
+
+            self.volume_mgr
+                .iterate_dir(dir, |entry| {
+                    if entry.attributes.is_directory() || entry.attributes.is_volume() {
+                        return;
+                    }
+
+                    if seen >= done {
+                        let _ = names.push(entry.name.clone());
+                    }
+
+                    seen += 1;
+                })
+                .map_err(Error::Source)?;
+
+            if names.is_empty() {
+                return Ok(len);
+            }
+
+            done += names.len();
+
+            let mut entries: Vec<IndexEntry, SCAN_BATCH> = Vec::new();
+
+            for name in &names {
+                let mut text: String<INDEX_NAME_LEN> = String::new();
+                let _ = write!(text, "{}", name);
+
+                if text.eq_ignore_ascii_case(index_name) {
+                    continue;
+                }
+
+                let file = self
+                    .volume_mgr
+                    .open_file_in_dir(dir, name, Mode::ReadOnly)
+                    .map_err(Error::Source)?
+                    .to_file(&mut self.volume_mgr);
+
+                let entry = AudioFile::new_auto(file)
+                    .and_then(|mut file| IndexEntry::probe(&text, &mut file));
+
+                match entry {
+                    // one entry per name of the batch at most
+                    Ok(entry) => {
+                        let _ = entries.push(entry);
+                    }
+                    Err(Error::Source(e)) => return Err(Error::Source(e)),
+                    // not audio, or damaged
+                    Err(_) => {}
+                }
+            }
+
+            let mut writer = IndexWriter::append(index.to_file(&mut self.volume_mgr), len);
+
+            for entry in &entries {
+                writer.push(entry).map_err(Error::widen)?;
+            }
+
+            len = writer.len();
+            index = writer.into_inner().to_raw_file();
+        }
+    }
+
+    /// Open the index file at `index_path` written by [`SdCard::build_index`].
+    ///
+    /// Returns [`Error::UnknownFileFormat`] if it isn't an index file of this version.
+    pub fn open_index(
+        &mut self,
+        index_path: &str,
+    ) -> Result<
+        SdIndex<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        Error<embedded_sdmmc::Error<D::Error>>,
+    > {
+        let file = self
+            .open_file(index_path, Mode::ReadOnly)
+            .map_err(Error::Source)?;
+
+        Index::new(file)
+    }
+
+    /// Open the play statistics file at `path`, creating it if it doesn't exist yet.
+    ///
+    /// Returns [`Error::UnknownFileFormat`] if it holds something else.
+    #[cfg(feature = "write")]
+    pub fn open_stats(
+        &mut self,
+        path: &str,
+    ) -> Result<
+        SdPlayStats<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        Error<embedded_sdmmc::Error<D::Error>>,
+    > {
+        let file = self
+            .open_file(path, Mode::ReadWriteCreateOrAppend)
+            .map_err(Error::Source)?;
+
+        PlayStats::new(file)
+    }
+
+    /// Identity of the file at `path` from the size and modification time of its directory entry,
+    /// to store in place of the path.
+    ///
+    /// Failing to find it is reported as [`Error::Source`].
+    pub fn identify(
+        &mut self,
+        path: &str,
+    ) -> Result<TrackIdentity, Error<embedded_sdmmc::Error<D::Error>>> {
+        let (dir, name) = split_path(path);
+        let dir = self.open_raw_dir(dir.split('/')).map_err(Error::Source)?;
+        let entry = self.volume_mgr.find_directory_entry(dir, name);
+        self.volume_mgr.close_dir(dir).map_err(Error::Source)?;
+        let entry = entry.map_err(Error::Source)?;
+
+        let time = entry.mtime;
+        let modified = fat_timestamp(
+            1970 + time.year_since_1970 as u16,
+            time.zero_indexed_month + 1,
+            time.zero_indexed_day + 1,
+            time.hours,
+            time.minutes,
+            time.seconds,
+        );
+
+        Ok(TrackIdentity::new(path, entry.size, modified))
+    }
+
+    /// Record where playback of the file at `path` stands, e.g. before entering deep sleep in the
+    /// middle of a track.
+    ///
+    /// `position` is [`AudioFile::resume_position`] of the file, taken before closing it: an open
+    /// file borrows the card. The card may then be closed or lose power. Pass the token to
+    /// [`SdCard::resume`] to continue, after waking up or the next boot. Returns
+    /// [`Error::BufferTooSmall`] with the length of `path` if it is longer than
+    /// [`RESUME_PATH_LEN`].
+    pub fn suspend(
+        &mut self,
+        path: &str,
+        position: u32,
+    ) -> Result<ResumeToken, Error<embedded_sdmmc::Error<D::Error>>> {
+        if path.len() > RESUME_PATH_LEN {
+            return Err(Error::BufferTooSmall(path.len()));
+        }
+
+        // identified once closed, a file open for writing may still have changed
+        let identity = self.identify(path)?;
+
+        ResumeToken::new(path, identity, position).ok_or(Error::BufferTooSmall(path.len()))
+    }
+
+    /// Reopen the file a token of [`SdCard::suspend`] was recorded for and continue from where
+    /// playback stood, see [`AudioFile::resume_at`].
+    ///
+    /// Returns [`Error::TrackChanged`] if the size or modification time of the file differs,
+    /// playing it from a stale position would be garbage.
+    pub fn resume(
+        &mut self,
+        token: &ResumeToken,
+    ) -> Result<
+        SdAudioFile<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        Error<embedded_sdmmc::Error<D::Error>>,
+    > {
+        if self.identify(token.path())? != *token.identity() {
+            return Err(Error::TrackChanged);
+        }
+
+        let mut file = self.open(token.path())?;
+        file.resume_at(token.position())?;
+
+        Ok(file)
+    }
+
+    /// The underlying `VolumeManager`, e.g. to list directories or write files
+    pub fn volume_mgr(&mut self) -> &mut VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
+        &mut self.volume_mgr
+    }
+
+    /// Close the volume and get the block device and time source back.
+    ///
+    /// Fails if files of the card are still open.
+    pub fn close(mut self) -> Result<(D, T), embedded_sdmmc::Error<D::Error>> {
+        self.volume_mgr.close_volume(self.volume)?;
+
+        Ok(self.volume_mgr.free())
+    }
+
+    /// Open the file at `path` in `mode`
+    fn open_file(
+        &mut self,
+        path: &str,
+        mode: Mode,
+    ) -> Result<File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, embedded_sdmmc::Error<D::Error>>
+    {
+        let file = self.open_raw_file(path, mode)?;
+
+        Ok(file.to_file(&mut self.volume_mgr))
+    }
+
+    /// Open the file at `path` in `mode`, its directory is closed again
+    fn open_raw_file(
+        &mut self,
+        path: &str,
+        mode: Mode,
+    ) -> Result<RawFile, embedded_sdmmc::Error<D::Error>> {
+        let (dir, name) = split_path(path);
+        let dir = self.open_raw_dir(dir.split('/'))?;
+        let file = self.volume_mgr.open_file_in_dir(dir, name, mode);
+
+        match self.volume_mgr.close_dir(dir) {
+            Ok(()) => file,
+            Err(e) => {
+                if let Ok(file) = file {
+                    let _ = self.volume_mgr.close_file(file);
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    /// Open the directory reached through the directories `names` from the root, the root
+    /// directory if there are none
+    pub(crate) fn open_dir<'p>(
+        &mut self,
+        names: impl IntoIterator<Item = &'p str>,
+    ) -> Result<
+        Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        embedded_sdmmc::Error<D::Error>,
+    > {
+        let dir = self.open_raw_dir(names)?;
+
+        Ok(dir.to_directory(&mut self.volume_mgr))
+    }
+
+    /// Raw handle of [`SdCard::open_dir`], closed by the caller
+    fn open_raw_dir<'p>(
+        &mut self,
+        names: impl IntoIterator<Item = &'p str>,
+    ) -> Result<RawDirectory, embedded_sdmmc::Error<D::Error>> {
+        let mut dir = self.volume_mgr.open_root_dir(self.volume)?;
+
+        // only the deepest directory stays open
+        for name in names.into_iter().filter(|name| !name.is_empty()) {
+            let child = self.volume_mgr.open_dir(dir, name);
+            self.volume_mgr.close_dir(dir)?;
+            dir = child?;
+        }
+
+        Ok(dir)
+    }
+}
+
+/// Split `path` into its directory and file name
+fn split_path(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(i) => (&path[..i], &path[i + 1..]),
+        None => ("", path),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::audio_file::FileFormat;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use embedded_sdmmc::{Block, BlockCount, BlockIdx, Timestamp};
+
+    /// Blocks of the FAT16 volume, behind the partition table
+    const VOLUME_BLOCKS: u32 = 4_267;
+    /// Blocks of each of the two FATs
+    const FAT_BLOCKS: usize = 17;
+
+    /// Card in RAM
+    pub(crate) struct RamDisk(RefCell<std::vec::Vec<Block>>);
+
+    impl BlockDevice for RamDisk {
+        type Error = Infallible;
+
+        fn read(&self, blocks: &mut [Block], start: BlockIdx, _: &str) -> Result<(), Infallible> {
+            let disk = self.0.borrow();
+            let start = start.0 as usize;
+
+            for (block, stored) in blocks.iter_mut().zip(&disk[start..]) {
+                block.contents = stored.contents;
+            }
+
+            Ok(())
+        }
+
+        fn write(&self, blocks: &[Block], start: BlockIdx) -> Result<(), Infallible> {
+            let mut disk = self.0.borrow_mut();
+            let start = start.0 as usize;
+
+            for (block, stored) in blocks.iter().zip(&mut disk[start..]) {
+                stored.contents = block.contents;
+            }
+
+            Ok(())
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, Infallible> {
+            Ok(BlockCount(self.0.borrow().len() as u32))
+        }
+    }
+
+    /// Clock stopped at 2024-05-17 21:04:30
+    pub(crate) struct Clock;
+
+    impl TimeSource for Clock {
+        fn get_timestamp(&self) -> Timestamp {
+            Timestamp {
+                year_since_1970: 54,
+                zero_indexed_month: 4,
+                zero_indexed_day: 16,
+                hours: 21,
+                minutes: 4,
+                seconds: 30,
+            }
+        }
+    }
+
+    pub(crate) type TestCard = SdCard<RamDisk, Clock>;
+
+    /// Empty FAT16 volume of one block per cluster in the first partition
+    fn format() -> RamDisk {
+        let mut disk = vec![Block::new(); 1 + VOLUME_BLOCKS as usize];
+
+        let mbr = &mut disk[0].contents;
+        mbr[446 + 4] = 0x06;
+        mbr[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+        mbr[446 + 12..446 + 16].copy_from_slice(&VOLUME_BLOCKS.to_le_bytes());
+        mbr[510..].copy_from_slice(&[0x55, 0xaa]);
+
+        let bpb = &mut disk[1].contents;
+        bpb[..11].copy_from_slice(b"\xeb\x3c\x90MSWIN4.1");
+        bpb[11..13].copy_from_slice(&512u16.to_le_bytes());
+        bpb[13] = 1;
+        bpb[14..16].copy_from_slice(&1u16.to_le_bytes());
+        bpb[16] = 2;
+        bpb[17..19].copy_from_slice(&512u16.to_le_bytes());
+        bpb[19..21].copy_from_slice(&(VOLUME_BLOCKS as u16).to_le_bytes());
+        bpb[21] = 0xf8;
+        bpb[22..24].copy_from_slice(&(FAT_BLOCKS as u16).to_le_bytes());
+        bpb[38] = 0x29;
+        bpb[43..62].copy_from_slice(b"NO NAME    FAT16   ");
+        bpb[510..].copy_from_slice(&[0x55, 0xaa]);
+
+        for fat in 0..2 {
+            disk[2 + fat * FAT_BLOCKS].contents[..4].copy_from_slice(&[0xf8, 0xff, 0xff, 0xff]);
+        }
+
+        RamDisk(RefCell::new(disk))
+    }
+
+    /// Card holding `files`, by paths of at most one directory
+    pub(crate) fn card(files: &[(&str, &[u8])]) -> TestCard {
+        let mut volume_mgr = VolumeManager::new(format(), Clock);
+        let volume = volume_mgr.open_raw_volume(VolumeIdx(0)).unwrap();
+        let root = volume_mgr.open_root_dir(volume).unwrap();
+
+        for (path, bytes) in files {
+            let (dir, name) = split_path(path);
+            let dir = match dir {
+                "" => volume_mgr.open_dir(root, ".").unwrap(),
+                dir => {
+                    // a directory is only made for its first file
+                    let _ = volume_mgr.make_dir_in_dir(root, dir);
+                    volume_mgr.open_dir(root, dir).unwrap()
+                }
+            };
+
+            let file = volume_mgr
+                .open_file_in_dir(dir, name, Mode::ReadWriteCreate)
+                .unwrap();
+            volume_mgr.write(file, bytes).unwrap();
+            volume_mgr.close_file(file).unwrap();
+            volume_mgr.close_dir(dir).unwrap();
+        }
+
+        volume_mgr.close_dir(root).unwrap();
+        volume_mgr.close_volume(volume).unwrap();
+        let (disk, clock) = volume_mgr.free();
+
+        SdCard::new(disk, clock).unwrap()
+    }
+
+    #[test]
+    fn should_open_files_by_path() {
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut card = card(&[("MUSIC/TRACK01.WAV", wav), ("NOTES.TXT", b"not audio")]);
+
+        let file = card.open("MUSIC/TRACK01.WAV").unwrap();
+        assert_eq!(file.format(), FileFormat::Wav);
+        drop(file);
+
+        assert!(matches!(
+            card.open("NOTES.TXT"),
+            Err(Error::UnknownFileFormat)
+        ));
+        assert!(matches!(
+            card.open("MUSIC/TRACK02.WAV"),
+            Err(Error::Source(embedded_sdmmc::Error::NotFound))
+        ));
+
+        card.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_index_the_audio_files_of_a_directory() {
+        let flac = include_bytes!("../test_files/stereo_16_8000.flac");
+        let wav = include_bytes!("../test_files/mono_16_48000.wav");
+
+        // more files than a batch, and the index amid them
+        let names: std::vec::Vec<_> = (0..10).map(|i| format!("MUSIC/T{:02}.FLA", i)).collect();
+        let mut files: std::vec::Vec<(&str, &[u8])> = vec![
+            ("MUSIC/INDEX.IDX", b"stale"),
+            ("MUSIC/NOTES.TXT", b"not audio"),
+            ("MUSIC/VOICE.WAV", wav),
+        ];
+        files.extend(names.iter().map(|name| (name.as_str(), &flac[..])));
+
+        let mut card = card(&files);
+        assert_eq!(card.build_index("MUSIC", "MUSIC/INDEX.IDX").unwrap(), 11);
+
+        let mut index = card.open_index("MUSIC/INDEX.IDX").unwrap();
+        assert_eq!(index.len(), 11);
+
+        let mut indexed = std::vec::Vec::new();
+
+        while let Some(entry) = index.get(indexed.len() as u32).unwrap() {
+            indexed.push(entry);
+        }
+
+        assert_eq!(indexed[0].name(), "VOICE.WAV");
+        assert_eq!(indexed[0].format(), FileFormat::Wav);
+        assert_eq!(indexed[0].num_channels(), 1);
+        assert_eq!(indexed[1].name(), "T00.FLA");
+        assert_eq!(indexed[1].format(), FileFormat::Flac);
+        assert_eq!(indexed[1].sample_rate(), 8_000);
+        assert_eq!(indexed[10].name(), "T09.FLA");
+        drop(index);
+
+        card.close().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn should_keep_play_stats_on_the_card() {
+        let wav = include_bytes!("../test_files/mono_16_48000.wav");
+        let mut card = card(&[("MUSIC/TRACK01.WAV", wav)]);
+        let identity = card.identify("MUSIC/TRACK01.WAV").unwrap();
+
+        let mut stats = card.open_stats("STATS.BIN").unwrap();
+        assert!(stats.is_empty());
+        stats.played(identity, "TRACK01.WAV", 100).unwrap();
+        stats.played(identity, "TRACK01.WAV", 200).unwrap();
+        stats.destroy().close().unwrap();
+
+        let mut stats = card.open_stats("STATS.BIN").unwrap();
+        let (_, record) = stats.find(&identity).unwrap().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(record.plays(), 2);
+        assert_eq!(record.last_played(), Some(200));
+        drop(stats);
+
+        card.close().unwrap();
+    }
+
+    #[test]
+    fn should_resume_on_the_same_sample() {
+        let wav = include_bytes!("../test_files/stereo_16_48000.wav");
+        let mut card = card(&[("MUSIC/TRACK01.WAV", wav)]);
+
+        let mut file = card.open("MUSIC/TRACK01.WAV").unwrap();
+        let mut out = [0; 64];
+        file.decoder().unwrap().decode(&mut out).unwrap();
+        let position = file.resume_position();
+        file.decoder().unwrap().decode(&mut out).unwrap();
+        drop(file);
+
+        let token = card.suspend("MUSIC/TRACK01.WAV", position).unwrap();
+        let (disk, clock) = card.close().unwrap();
+
+        let mut card: TestCard = SdCard::new(disk, clock).unwrap();
+        let mut resumed = card.resume(&token).unwrap();
+        let mut again = [0; 64];
+        resumed.decoder().unwrap().decode(&mut again).unwrap();
+        assert_eq!(again, out);
+        drop(resumed);
+
+        let identity = card.identify("MUSIC/TRACK01.WAV").unwrap();
+        assert_eq!(identity, *token.identity());
+        assert_eq!(identity.modified(), fat_timestamp(2024, 5, 17, 21, 4, 30));
+
+        let changed = TrackIdentity::new("MUSIC/TRACK01.WAV", 0, identity.modified());
+        let stale = ResumeToken::new("MUSIC/TRACK01.WAV", changed, position).unwrap();
+        assert!(matches!(card.resume(&stale), Err(Error::TrackChanged)));
+    }
+}
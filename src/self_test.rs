@@ -0,0 +1,167 @@
+use crate::error::Error;
+use crate::tone::ToneGenerator;
+
+/// Lowest frequency of the test sweep in Hz
+const SWEEP_START: u32 = 300;
+/// Highest frequency of the test sweep in Hz, lowered for low sample rates
+const SWEEP_END: u32 = 4_000;
+/// Peak of the test sweep, -6 dBFS so the codec isn't driven into clipping
+const SWEEP_AMPLITUDE: i16 = 16_384;
+/// Length of the fades at both ends of the sweep, in samples
+const FADE_LEN: usize = 64;
+/// Shortest sweep the measurement is meaningful for, in samples
+const MIN_SWEEP_LEN: usize = 256;
+
+/// Playback and record path through external codec hardware, wired back to back for the test,
+/// e.g. the line out looped into the line in on a test fixture
+pub trait Loopback {
+    /// Error reported by the codec driver
+    type Error;
+
+    /// Play mono `output` while capturing as many mono samples into `input`, both starting at the
+    /// same sample clock edge
+    fn transfer(&mut self, output: &[i16], input: &mut [i16]) -> Result<(), Self::Error>;
+}
+
+/// Outcome of a [`self_test`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Round trip delay from playback to capture in frames
+    pub latency_frames: usize,
+    /// Round trip delay from playback to capture in microseconds
+    pub latency_micros: u32,
+    /// Signed Q16.16 gain from playback to capture, negative if the path inverts polarity and
+    /// about `0` if nothing came back
+    pub gain: i32,
+}
+
+/// Measure the round trip latency and level of `loopback` for manufacturing tests.
+///
+/// A faded sine sweep fills the first `N - max_latency` samples of a buffer that is played while
+/// `N` samples are captured. The capture is cross-correlated with the sweep, the strongest peak up
+/// to `max_latency` frames gives the latency and its height the gain. Returns
+/// [`Error::BufferTooSmall`] if `N` leaves too little room for the sweep.
+pub fn self_test<L: Loopback, const N: usize>(
+    loopback: &mut L,
+    sample_rate: u32,
+    max_latency: usize,
+) -> Result<SelfTestReport, Error<L::Error>> {
+    let sweep_len = N.saturating_sub(max_latency);
+
+    if sweep_len < MIN_SWEEP_LEN {
+        return Err(Error::BufferTooSmall(max_latency + MIN_SWEEP_LEN));
+    }
+
+    let mut output = [0; N];
+    sweep(&mut output[..sweep_len], sample_rate);
+
+    let mut input = [0; N];
+    loopback
+        .transfer(&output, &mut input)
+        .map_err(Error::Source)?;
+
+    let sweep = &output[..sweep_len];
+    let energy: i64 = sweep.iter().map(|&s| s as i64 * s as i64).sum();
+
+    let (latency, peak) = (0..=max_latency)
+        .map(|lag| {
+            let correlation: i64 = sweep
+                .iter()
+                .zip(&input[lag..])
+                .map(|(&s, &i)| s as i64 * i as i64)
+                .sum();
+
+            (lag, correlation)
+        })
+        .fold((0, 0i64), |best, candidate| {
+            if candidate.1.abs() > best.1.abs() {
+                candidate
+            } else {
+                best
+            }
+        });
+
+    Ok(SelfTestReport {
+        latency_frames: latency,
+        latency_micros: (latency as u64 * 1_000_000 / sample_rate.max(1) as u64) as u32,
+        gain: ((peak << 16) / energy.max(1)).clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+    })
+}
+
+/// Fill `out` with a linear sine sweep faded in and out, whose correlation has a single sharp peak
+fn sweep(out: &mut [i16], sample_rate: u32) {
+    let end = SWEEP_END.min(sample_rate / 4).max(SWEEP_START);
+    let len = out.len();
+    let mut tone = ToneGenerator::new(sample_rate, SWEEP_START, SWEEP_AMPLITUDE);
+
+    for (i, sample) in out.iter_mut().enumerate() {
+        let frequency = SWEEP_START + ((end - SWEEP_START) as u64 * i as u64 / len as u64) as u32;
+        tone.set_frequency(frequency);
+
+        let fade = i.min(len - 1 - i).min(FADE_LEN) as i32;
+        *sample = (tone.next_sample() as i32 * fade / FADE_LEN as i32) as i16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Codec delaying by a fixed number of samples, scaling by a Q15 gain and adding a hum
+    struct FakeCodec {
+        delay: usize,
+        gain: i32,
+    }
+
+    impl Loopback for FakeCodec {
+        type Error = ();
+
+        fn transfer(&mut self, output: &[i16], input: &mut [i16]) -> Result<(), ()> {
+            let mut hum = ToneGenerator::new(16_000, 50, 300);
+
+            for (i, sample) in input.iter_mut().enumerate() {
+                let played = i
+                    .checked_sub(self.delay)
+                    .map_or(0, |i| (output[i] as i32 * self.gain) >> 15);
+                *sample = (played + hum.next_sample() as i32) as i16;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn should_measure_latency_and_level() {
+        let mut codec = FakeCodec {
+            delay: 37,
+            gain: 16_384,
+        };
+        let report = self_test::<_, 2048>(&mut codec, 16_000, 200).unwrap();
+
+        assert_eq!(report.latency_frames, 37);
+        assert_eq!(report.latency_micros, 2312);
+        assert!((report.gain - 32_768).abs() < 300, "gain {}", report.gain);
+    }
+
+    #[test]
+    fn should_detect_inverted_polarity() {
+        let mut codec = FakeCodec {
+            delay: 0,
+            gain: -32_768,
+        };
+        let report = self_test::<_, 1024>(&mut codec, 48_000, 100).unwrap();
+
+        assert_eq!(report.latency_frames, 0);
+        assert!((report.gain + 65_536).abs() < 600, "gain {}", report.gain);
+    }
+
+    #[test]
+    fn should_need_room_for_the_sweep() {
+        let mut codec = FakeCodec { delay: 0, gain: 0 };
+
+        assert!(matches!(
+            self_test::<_, 300>(&mut codec, 16_000, 100),
+            Err(Error::BufferTooSmall(356))
+        ));
+    }
+}
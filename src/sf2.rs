@@ -0,0 +1,603 @@
+use crate::error::Error;
+use crate::fmt::{AudioCodec, Fmt};
+use crate::metadata::to_string;
+use crate::riff::{Chunk, ChunkTag, ChunkWalker};
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+use core::convert::TryInto;
+use heapless::{String, Vec};
+
+/// Bytes of the names of presets, instruments and samples
+pub const SF2_NAME_LEN: usize = 20;
+/// Generators kept per zone, SoundFont 2.04 defines 59
+pub const MAX_ZONE_GENERATORS: usize = 64;
+
+/// Chunks of the `sdta` and `pdta` lists a bank is read from
+const CHUNKS: [[u8; 4]; 8] = [
+    *b"smpl", *b"phdr", *b"pbag", *b"pgen", *b"inst", *b"ibag", *b"igen", *b"shdr",
+];
+
+/// Bytes of a `phdr` record
+const PHDR_LEN: usize = 38;
+/// Bytes of an `inst` record
+const INST_LEN: usize = 22;
+/// Bytes of a `shdr` record
+const SHDR_LEN: usize = 46;
+/// Bytes of a `pbag` or `ibag` record
+const BAG_LEN: usize = 4;
+/// Bytes of a `pgen` or `igen` record
+const GEN_LEN: usize = 4;
+
+/// Generator of a preset zone naming its instrument
+const GEN_INSTRUMENT: u16 = 41;
+/// Generator with the lowest and highest MIDI key of a zone
+const GEN_KEY_RANGE: u16 = 43;
+/// Generator with the lowest and highest MIDI velocity of a zone
+const GEN_VELOCITY_RANGE: u16 = 44;
+/// Generator of an instrument zone naming its sample
+const GEN_SAMPLE_ID: u16 = 53;
+
+/// Preset of a SoundFont, what a MIDI program change selects
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetHeader {
+    /// Name of the preset
+    pub name: String<SF2_NAME_LEN>,
+    /// MIDI program number
+    pub program: u16,
+    /// MIDI bank number, `128` for percussion
+    pub bank: u16,
+    bag: u16,
+}
+
+/// Instrument of a SoundFont, a set of samples mapped over keys and velocities
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstrumentHeader {
+    /// Name of the instrument
+    pub name: String<SF2_NAME_LEN>,
+    bag: u16,
+}
+
+/// Sample of a SoundFont, 16 bit mono PCM in the `smpl` chunk
+///
+/// Positions are counted in samples from the start of the `smpl` chunk, as stored in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleHeader {
+    /// Name of the sample
+    pub name: String<SF2_NAME_LEN>,
+    /// First sample
+    pub start: u32,
+    /// Sample after the last one
+    pub end: u32,
+    /// First sample of the loop
+    pub loop_start: u32,
+    /// Sample after the last one of the loop
+    pub loop_end: u32,
+    /// Sample rate the sample was recorded at
+    pub sample_rate: u32,
+    /// MIDI key the sample plays at its original pitch
+    pub original_pitch: u8,
+    /// Pitch correction in cents
+    pub pitch_correction: i8,
+    /// Index of the other sample of a stereo pair
+    pub sample_link: u16,
+    /// Mono `1`, right `2`, left `4` or linked `8`, with `0x8000` set for samples in ROM
+    pub sample_type: u16,
+}
+
+/// Generator of a zone, one parameter of the sound such as a key range or an envelope time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Generator {
+    /// Parameter set by the generator, as numbered by the SoundFont specification
+    pub oper: u16,
+    /// Raw value, signed or a low and high byte depending on `oper`
+    pub amount: u16,
+}
+
+/// Zone of a preset or instrument: the generators applying to a range of keys and velocities
+///
+/// A zone without an instrument or sample is the global zone, its generators apply to every
+/// other zone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Zone {
+    /// Generators of the zone in file order, those past [`MAX_ZONE_GENERATORS`] are skipped
+    pub generators: Vec<Generator, MAX_ZONE_GENERATORS>,
+}
+
+impl Zone {
+    /// Raw value of generator `oper`, if the zone has it
+    pub fn amount(&self, oper: u16) -> Option<u16> {
+        self.generators
+            .iter()
+            .find(|generator| generator.oper == oper)
+            .map(|generator| generator.amount)
+    }
+
+    /// Lowest and highest MIDI key of the zone, every key if it has no key range
+    pub fn key_range(&self) -> (u8, u8) {
+        self.range(GEN_KEY_RANGE)
+    }
+
+    /// Lowest and highest MIDI velocity of the zone, every velocity if it has no velocity range
+    pub fn velocity_range(&self) -> (u8, u8) {
+        self.range(GEN_VELOCITY_RANGE)
+    }
+
+    /// True if the zone plays `key` at `velocity`
+    pub fn contains(&self, key: u8, velocity: u8) -> bool {
+        let (low_key, high_key) = self.key_range();
+        let (low_velocity, high_velocity) = self.velocity_range();
+
+        (low_key..=high_key).contains(&key) && (low_velocity..=high_velocity).contains(&velocity)
+    }
+
+    /// Index of the instrument played by a preset zone
+    pub fn instrument(&self) -> Option<u16> {
+        self.amount(GEN_INSTRUMENT)
+    }
+
+    /// Index of the sample played by an instrument zone
+    pub fn sample(&self) -> Option<u16> {
+        self.amount(GEN_SAMPLE_ID)
+    }
+
+    fn range(&self, oper: u16) -> (u8, u8) {
+        match self.amount(oper) {
+            Some(amount) => (amount as u8, (amount >> 8) as u8),
+            None => (0, 127),
+        }
+    }
+}
+
+/// The chunks of the `pdta` list
+#[derive(Debug, Clone, Copy)]
+struct Tables {
+    phdr: Chunk,
+    pbag: Chunk,
+    pgen: Chunk,
+    inst: Chunk,
+    ibag: Chunk,
+    igen: Chunk,
+    shdr: Chunk,
+}
+
+/// SoundFont 2 bank, e.g. the instruments of a MIDI synth read from an SD card.
+///
+/// Presets, instruments, samples and zones are read from the file when asked for, nothing but
+/// the positions of the tables is kept in RAM. [`SoundFont::sample_data`] streams one sample as
+/// a [`Wav`]. The 24 bit extension in `sm24` is ignored, samples play with 16 bits.
+pub struct SoundFont<S: AudioSource> {
+    source: S,
+    smpl: Chunk,
+    tables: Tables,
+}
+
+impl<S: AudioSource> SoundFont<S> {
+    /// Find the sample data and the tables of presets, instruments and samples of the bank in
+    /// `source`.
+    ///
+    /// Returns [`Error::UnknownFileFormat`] if it isn't a SoundFont, or
+    /// [`Error::CantParseChunk`] if one of the mandatory chunks is missing.
+    pub fn new(mut source: S) -> Result<Self, Error<S::Error>> {
+        let mut riff = [0; 12];
+        source.seek(0).map_err(Error::Source)?;
+
+        if read_full(&mut source, &mut riff)? != riff.len()
+            || &riff[..4] != b"RIFF"
+            || &riff[8..] != b"sfbk"
+        {
+            return Err(Error::UnknownFileFormat);
+        }
+
+        let length = source.length() as usize;
+        let mut found: [Option<Chunk>; 8] = [None; 8];
+        let mut walker = ChunkWalker::new(length);
+
+        while let Some(list) = walker.next_chunk(&mut source)? {
+            let mut list_type = [0; 4];
+
+            if list.id != ChunkTag::List
+                || read_full(&mut source, &mut list_type)? != list_type.len()
+                || !matches!(&list_type, b"sdta" | b"pdta")
+            {
+                continue;
+            }
+
+            let mut chunks = ChunkWalker::list(&list);
+
+            while let Some(chunk) = chunks.next_chunk(&mut source)? {
+                if let Some(slot) = CHUNKS.iter().position(|tag| chunk.id.to_bytes() == *tag) {
+                    chunk.check_within(length).map_err(Error::widen)?;
+                    found[slot] = Some(chunk);
+                }
+            }
+        }
+
+        let chunk = |slot: usize| {
+            found[slot].ok_or(Error::CantParseChunk(ChunkTag::from_bytes(&CHUNKS[slot])))
+        };
+
+        Ok(SoundFont {
+            smpl: chunk(0)?,
+            tables: Tables {
+                phdr: chunk(1)?,
+                pbag: chunk(2)?,
+                pgen: chunk(3)?,
+                inst: chunk(4)?,
+                ibag: chunk(5)?,
+                igen: chunk(6)?,
+                shdr: chunk(7)?,
+            },
+            source,
+        })
+    }
+
+    /// Number of presets
+    pub fn presets(&self) -> u16 {
+        records(&self.tables.phdr, PHDR_LEN)
+    }
+
+    /// Number of instruments
+    pub fn instruments(&self) -> u16 {
+        records(&self.tables.inst, INST_LEN)
+    }
+
+    /// Number of samples
+    pub fn samples(&self) -> u16 {
+        records(&self.tables.shdr, SHDR_LEN)
+    }
+
+    /// Read the header of preset `index`, `None` past the last preset
+    pub fn preset(&mut self, index: u16) -> Result<Option<PresetHeader>, Error<S::Error>> {
+        if index >= self.presets() {
+            return Ok(None);
+        }
+
+        let bytes: [u8; PHDR_LEN] = self.record(self.tables.phdr, index)?;
+        let field = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+
+        Ok(Some(PresetHeader {
+            name: to_string(&bytes[..SF2_NAME_LEN]),
+            program: field(20),
+            bank: field(22),
+            bag: field(24),
+        }))
+    }
+
+    /// Find the preset a MIDI bank select and program change pick, with its index
+    pub fn find_preset(
+        &mut self,
+        bank: u16,
+        program: u16,
+    ) -> Result<Option<(u16, PresetHeader)>, Error<S::Error>> {
+        for index in 0..self.presets() {
+            match self.preset(index)? {
+                Some(preset) if preset.bank == bank && preset.program == program => {
+                    return Ok(Some((index, preset)))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read the header of instrument `index`, `None` past the last instrument
+    pub fn instrument(&mut self, index: u16) -> Result<Option<InstrumentHeader>, Error<S::Error>> {
+        if index >= self.instruments() {
+            return Ok(None);
+        }
+
+        let bytes: [u8; INST_LEN] = self.record(self.tables.inst, index)?;
+
+        Ok(Some(InstrumentHeader {
+            name: to_string(&bytes[..SF2_NAME_LEN]),
+            bag: u16::from_le_bytes([bytes[20], bytes[21]]),
+        }))
+    }
+
+    /// Read the header of sample `index`, `None` past the last sample
+    pub fn sample(&mut self, index: u16) -> Result<Option<SampleHeader>, Error<S::Error>> {
+        if index >= self.samples() {
+            return Ok(None);
+        }
+
+        let bytes: [u8; SHDR_LEN] = self.record(self.tables.shdr, index)?;
+        let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+
+        Ok(Some(SampleHeader {
+            name: to_string(&bytes[..SF2_NAME_LEN]),
+            start: field(20),
+            end: field(24),
+            loop_start: field(28),
+            loop_end: field(32),
+            sample_rate: field(36),
+            original_pitch: bytes[40],
+            pitch_correction: bytes[41] as i8,
+            sample_link: u16::from_le_bytes([bytes[42], bytes[43]]),
+            sample_type: u16::from_le_bytes([bytes[44], bytes[45]]),
+        }))
+    }
+
+    /// Call `f` with every zone of preset `index`, in file order
+    pub fn for_each_preset_zone<F>(&mut self, index: u16, f: F) -> Result<(), Error<S::Error>>
+    where
+        F: FnMut(&Zone),
+    {
+        let first = match self.preset(index)? {
+            Some(preset) => preset.bag,
+            None => return Ok(()),
+        };
+        // the terminal record closes the bags of the last preset
+        let bytes: [u8; PHDR_LEN] = self.record(self.tables.phdr, index + 1)?;
+        let last = u16::from_le_bytes([bytes[24], bytes[25]]);
+
+        self.for_each_zone(self.tables.pbag, self.tables.pgen, first..last, f)
+    }
+
+    /// Call `f` with every zone of instrument `index`, in file order
+    pub fn for_each_instrument_zone<F>(&mut self, index: u16, f: F) -> Result<(), Error<S::Error>>
+    where
+        F: FnMut(&Zone),
+    {
+        let first = match self.instrument(index)? {
+            Some(instrument) => instrument.bag,
+            None => return Ok(()),
+        };
+        let bytes: [u8; INST_LEN] = self.record(self.tables.inst, index + 1)?;
+        let last = u16::from_le_bytes([bytes[20], bytes[21]]);
+
+        self.for_each_zone(self.tables.ibag, self.tables.igen, first..last, f)
+    }
+
+    /// The samples of `sample` as a mono 16 bit [`Wav`] reading from the bank, positioned at its
+    /// first sample.
+    ///
+    /// Everything of [`Wav`] works on it, e.g. [`Wav::next_n`] to stream it to a voice or
+    /// [`Wav::seek_to_sample`] to jump back to the loop start, the loop points are relative to
+    /// [`SampleHeader::start`]. ROM samples, which have no data in the file, come out empty.
+    pub fn sample_data(&mut self, sample: &SampleHeader) -> Result<Wav<&mut S>, Error<S::Error>> {
+        let position = |point: u32| {
+            self.smpl
+                .start
+                .saturating_add(point as usize * 2)
+                .min(self.smpl.end)
+        };
+
+        let data = Chunk {
+            id: ChunkTag::Data,
+            start: position(sample.start),
+            end: position(sample.end.max(sample.start)),
+        };
+
+        self.source.seek(data.start as u32).map_err(Error::Source)?;
+
+        Ok(Wav {
+            source: &mut self.source,
+            data,
+            fmt: Fmt {
+                codec: AudioCodec::Pcm,
+                sample_rate: sample.sample_rate,
+                num_channels: 1,
+                bit_depth: 16,
+                block_size: 2,
+            },
+            chunks: Vec::new(),
+        })
+    }
+
+    /// Destroy the [`SoundFont`] instance and get the underlying source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+
+    fn for_each_zone<F>(
+        &mut self,
+        bags: Chunk,
+        generators: Chunk,
+        zones: core::ops::Range<u16>,
+        mut f: F,
+    ) -> Result<(), Error<S::Error>>
+    where
+        F: FnMut(&Zone),
+    {
+        for bag in zones {
+            let first: [u8; BAG_LEN] = self.record(bags, bag)?;
+            let last: [u8; BAG_LEN] = self.record(bags, bag + 1)?;
+            let mut zone = Zone::default();
+
+            for index in
+                u16::from_le_bytes([first[0], first[1]])..u16::from_le_bytes([last[0], last[1]])
+            {
+                let bytes: [u8; GEN_LEN] = self.record(generators, index)?;
+
+                let _ = zone.generators.push(Generator {
+                    oper: u16::from_le_bytes([bytes[0], bytes[1]]),
+                    amount: u16::from_le_bytes([bytes[2], bytes[3]]),
+                });
+            }
+
+            f(&zone);
+        }
+
+        Ok(())
+    }
+
+    /// Read record `index` of `LEN` bytes from the table in `chunk`
+    fn record<const LEN: usize>(
+        &mut self,
+        chunk: Chunk,
+        index: u16,
+    ) -> Result<[u8; LEN], Error<S::Error>> {
+        let start = chunk.start + index as usize * LEN;
+        let mut bytes = [0; LEN];
+
+        if start + LEN > chunk.end {
+            return Err(Error::CantParseChunk(chunk.id));
+        }
+
+        self.source.seek(start as u32).map_err(Error::Source)?;
+
+        match read_full(&mut self.source, &mut bytes)? == LEN {
+            true => Ok(bytes),
+            false => Err(Error::EndOfData),
+        }
+    }
+}
+
+/// Number of records in the table `chunk` of `len` byte records, without the terminal record
+fn records(chunk: &Chunk, len: usize) -> u16 {
+    ((chunk.end - chunk.start) / len).saturating_sub(1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SliceSource;
+    use crate::wav::DataBulk;
+
+    fn chunk(tag: &[u8; 4], body: &[u8]) -> std::vec::Vec<u8> {
+        let mut bytes = tag.to_vec();
+        bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(body);
+
+        if body.len() % 2 == 1 {
+            bytes.push(0);
+        }
+
+        bytes
+    }
+
+    fn list(list_type: &[u8; 4], chunks: &[std::vec::Vec<u8>]) -> std::vec::Vec<u8> {
+        let mut body = list_type.to_vec();
+        chunks.iter().for_each(|c| body.extend_from_slice(c));
+
+        chunk(b"LIST", &body)
+    }
+
+    fn name(name: &str) -> std::vec::Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(SF2_NAME_LEN, 0);
+        bytes
+    }
+
+    fn words(words: &[u16]) -> std::vec::Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn sample(title: &str, start: u32, end: u32, rate: u32, pitch: u8) -> std::vec::Vec<u8> {
+        let mut bytes = name(title);
+        for field in [start, end, start + 1, end.saturating_sub(1), rate].iter() {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[pitch, 0xfe, 0, 0, 1, 0]);
+        bytes
+    }
+
+    /// One preset of one instrument splitting the keyboard at middle C over two samples
+    fn bank() -> std::vec::Vec<u8> {
+        let preset = |title, bag: u16| [name(title), words(&[0, 0, bag]), vec![0; 12]].concat();
+        let instrument = |title, bag: u16| [name(title), words(&[bag])].concat();
+        let samples: std::vec::Vec<i16> = (0..8).map(|i| i * 100).collect();
+        let smpl: std::vec::Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+        let sdta = list(b"sdta", &[chunk(b"smpl", &smpl)]);
+        let pdta = list(
+            b"pdta",
+            &[
+                chunk(b"phdr", &[preset("Piano", 0), preset("EOP", 1)].concat()),
+                chunk(b"pbag", &words(&[0, 0, 1, 0])),
+                chunk(b"pmod", &[0; 10]),
+                chunk(b"pgen", &words(&[41, 0, 0, 0])),
+                chunk(
+                    b"inst",
+                    &[instrument("Grand", 0), instrument("EOI", 2)].concat(),
+                ),
+                chunk(b"ibag", &words(&[0, 0, 2, 0, 4, 0])),
+                chunk(b"imod", &[0; 10]),
+                chunk(
+                    b"igen",
+                    &words(&[43, 59 << 8, 53, 0, 43, 127 << 8 | 60, 53, 1, 0, 0]),
+                ),
+                chunk(
+                    b"shdr",
+                    &[
+                        sample("Low", 0, 4, 22_050, 48),
+                        sample("High", 4, 8, 44_100, 72),
+                        sample("EOS", 0, 0, 0, 0),
+                    ]
+                    .concat(),
+                ),
+            ],
+        );
+        let info = list(b"INFO", &[chunk(b"ifil", &words(&[2, 1]))]);
+
+        let body = [b"sfbk".to_vec(), info, sdta, pdta].concat();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn should_map_a_key_to_its_sample() {
+        let bytes = bank();
+        let mut bank = SoundFont::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(
+            (bank.presets(), bank.instruments(), bank.samples()),
+            (1, 1, 2)
+        );
+
+        let (index, preset) = bank.find_preset(0, 0).unwrap().unwrap();
+        assert_eq!(preset.name, "Piano");
+
+        let mut instrument = None;
+        bank.for_each_preset_zone(index, |zone| instrument = zone.instrument())
+            .unwrap();
+        assert_eq!(bank.instrument(0).unwrap().unwrap().name, "Grand");
+
+        let mut zones = std::vec::Vec::new();
+        bank.for_each_instrument_zone(instrument.unwrap(), |zone| zones.push(zone.clone()))
+            .unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].key_range(), (0, 59));
+        assert_eq!(zones[1].velocity_range(), (0, 127));
+
+        let zone = zones.iter().find(|zone| zone.contains(64, 100)).unwrap();
+        let sample = bank.sample(zone.sample().unwrap()).unwrap().unwrap();
+        assert_eq!(sample.name, "High");
+        assert_eq!(
+            (
+                sample.sample_rate,
+                sample.original_pitch,
+                sample.pitch_correction
+            ),
+            (44_100, 72, -2)
+        );
+        assert_eq!(bank.sample(2).unwrap(), None);
+
+        let mut wav = bank.sample_data(&sample).unwrap();
+        assert_eq!(wav.fmt.sample_rate, 44_100);
+
+        match wav.next_n::<8>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [400, 500, 600, 700]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_reject_other_files() {
+        let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+
+        assert!(matches!(
+            SoundFont::new(SliceSource::new(bytes)),
+            Err(Error::UnknownFileFormat)
+        ));
+
+        let mut bytes = bank();
+        let shdr = bytes.windows(4).position(|w| w == b"shdr").unwrap();
+        bytes[shdr..shdr + 4].copy_from_slice(b"xxxx");
+
+        assert!(matches!(
+            SoundFont::new(SliceSource::new(&bytes)),
+            Err(Error::CantParseChunk(ChunkTag::Unknown(tag))) if &tag == b"shdr"
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::fixed::{apply_gain, mix_i16};
+use heapless::Vec;
+
+/// Playback rate of 1.0, pitches are unsigned Q16.16 fixed point ratios
+pub const UNITY_PITCH: u32 = 1 << 16;
+
+/// Handle to a clip registered in an [`SfxBank`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClipId(usize);
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    clip: usize,
+    /// Read position within the clip in Q32.32
+    position: u64,
+    /// Position increment per output frame in Q32.32
+    step: u64,
+    gain: u16,
+    /// Trigger order, used to steal the oldest voice
+    age: u32,
+}
+
+/// Bank of short mono clips held in RAM that can be triggered as overlapping voices.
+///
+/// `CLIPS` is the number of clips the bank holds, `VOICES` how many of them can play at once.
+/// Triggering while all voices are busy steals the oldest one.
+pub struct SfxBank<'a, const CLIPS: usize, const VOICES: usize> {
+    clips: Vec<&'a [i16], CLIPS>,
+    voices: [Option<Voice>; VOICES],
+    triggered: u32,
+}
+
+impl<'a, const CLIPS: usize, const VOICES: usize> SfxBank<'a, CLIPS, VOICES> {
+    /// Create an empty bank
+    pub fn new() -> Self {
+        SfxBank {
+            clips: Vec::new(),
+            voices: [None; VOICES],
+            triggered: 0,
+        }
+    }
+
+    /// Register a preloaded clip of mono samples, handing it back if the bank is full
+    pub fn add_clip(&mut self, samples: &'a [i16]) -> Result<ClipId, &'a [i16]> {
+        self.clips.push(samples)?;
+        Ok(ClipId(self.clips.len() - 1))
+    }
+
+    /// Start playing `clip` with a Q1.15 `gain` and a Q16.16 `pitch` ratio
+    pub fn trigger(&mut self, clip: ClipId, gain: u16, pitch: u32) {
+        if clip.0 >= self.clips.len() || VOICES == 0 {
+            return;
+        }
+
+        let voice = Voice {
+            clip: clip.0,
+            position: 0,
+            step: (pitch as u64) << 16,
+            gain,
+            age: self.triggered,
+        };
+
+        self.triggered = self.triggered.wrapping_add(1);
+
+        let slot = match self.voices.iter().position(|v| v.is_none()) {
+            Some(slot) => slot,
+            None => self
+                .voices
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, v)| v.map(|v| self.triggered.wrapping_sub(v.age)))
+                .map(|(i, _)| i)
+                .unwrap_or(0),
+        };
+
+        self.voices[slot] = Some(voice);
+    }
+
+    /// Stop every playing voice
+    pub fn stop_all(&mut self) {
+        self.voices = [None; VOICES];
+    }
+
+    /// Number of voices currently playing
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|v| v.is_some()).count()
+    }
+
+    /// Mix all playing voices onto the interleaved `output`, every channel gets the same signal
+    pub fn render(&mut self, output: &mut [i16], num_channels: u16) {
+        let channels = (num_channels as usize).max(1);
+
+        for slot in self.voices.iter_mut() {
+            let voice = match slot {
+                Some(voice) => voice,
+                None => continue,
+            };
+
+            let clip = self.clips[voice.clip];
+
+            for frame in output.chunks_mut(channels) {
+                let index = (voice.position >> 32) as usize;
+
+                let a = match clip.get(index) {
+                    Some(a) => *a as i32,
+                    None => {
+                        *slot = None;
+                        break;
+                    }
+                };
+
+                // linear interpolation towards the next sample
+                let b = clip.get(index + 1).map(|b| *b as i32).unwrap_or(a);
+                let fraction = ((voice.position >> 16) & 0xffff) as i64;
+                let sample = (a + (((b - a) as i64 * fraction) >> 16) as i32) as i16;
+                let sample = apply_gain(sample, voice.gain);
+
+                for out in frame.iter_mut() {
+                    *out = mix_i16(*out, sample);
+                }
+
+                voice.position += voice.step;
+            }
+        }
+    }
+}
+
+impl<'a, const CLIPS: usize, const VOICES: usize> Default for SfxBank<'a, CLIPS, VOICES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixed::UNITY_GAIN;
+
+    const CLICK: [i16; 4] = [100, 200, 300, 400];
+
+    #[test]
+    fn should_mix_overlapping_voices() {
+        let mut bank: SfxBank<1, 2> = SfxBank::new();
+        let click = bank.add_clip(&CLICK).unwrap();
+
+        bank.trigger(click, UNITY_GAIN, UNITY_PITCH);
+        bank.trigger(click, UNITY_GAIN / 2, UNITY_PITCH);
+
+        let mut output = [0; 6];
+        bank.render(&mut output, 1);
+
+        assert_eq!(output, [150, 300, 450, 600, 0, 0]);
+        assert_eq!(bank.active_voices(), 0);
+    }
+
+    #[test]
+    fn should_resample_with_pitch() {
+        let mut bank: SfxBank<1, 1> = SfxBank::new();
+        let click = bank.add_clip(&CLICK).unwrap();
+
+        bank.trigger(click, UNITY_GAIN, UNITY_PITCH / 2);
+
+        let mut output = [0; 4];
+        bank.render(&mut output, 2);
+
+        assert_eq!(output, [100, 100, 150, 150]);
+    }
+
+    #[test]
+    fn should_steal_oldest_voice() {
+        let mut bank: SfxBank<2, 1> = SfxBank::new();
+        let first = bank.add_clip(&CLICK).unwrap();
+        let second = bank.add_clip(&[1, 1, 1, 1]).unwrap();
+
+        bank.trigger(first, UNITY_GAIN, UNITY_PITCH);
+        bank.trigger(second, UNITY_GAIN, UNITY_PITCH);
+
+        let mut output = [0; 1];
+        bank.render(&mut output, 1);
+
+        assert_eq!(output, [1]);
+    }
+}
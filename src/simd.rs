@@ -0,0 +1,160 @@
+//! Hot loop kernels using the DSP instructions of the target when compiled for one, with a scalar
+//! fallback giving identical results.
+//!
+//! - Cortex-M4/M7/M33 with the DSP extension (`target_feature = "dsp"`, e.g. `thumbv7em`) use
+//!   `SSAT` to saturate and `SMLAD` for dual 16 bit multiply-accumulates.
+//! - AArch64 Cortex-A cores with NEON narrow 24 bit samples four at a time with `SQSHRN`.
+
+/// Narrow a wide result to a 16 bit sample, clipping instead of wrapping around, a single `SSAT`
+/// with the DSP extension
+#[inline]
+pub(crate) fn saturate_i16(value: i32) -> i16 {
+    #[cfg(all(target_arch = "arm", target_feature = "dsp"))]
+    {
+        let saturated: i32;
+
+        // SAFETY: SSAT only touches the given registers and the Q flag
+        unsafe {
+            core::arch::asm!(
+                "ssat {0}, #16, {1}",
+                lateout(reg) saturated,
+                in(reg) value,
+                options(pure, nomem, nostack),
+            );
+        }
+
+        saturated as i16
+    }
+
+    #[cfg(not(all(target_arch = "arm", target_feature = "dsp")))]
+    {
+        saturate_i16_scalar(value)
+    }
+}
+
+#[inline]
+fn saturate_i16_scalar(value: i32) -> i16 {
+    value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+/// `acc + a[0] * b[0] + a[1] * b[1]`, a single `SMLAD` with the DSP extension.
+///
+/// The caller keeps the result within an `i32`, e.g. by never passing `i16::MIN` in `b`.
+#[inline]
+pub(crate) fn dual_mac(a: [i16; 2], b: [i16; 2], acc: i32) -> i32 {
+    #[cfg(all(target_arch = "arm", target_feature = "dsp"))]
+    {
+        let pack = |x: [i16; 2]| (x[0] as u16 as u32) | ((x[1] as u16 as u32) << 16);
+        let result: i32;
+
+        // SAFETY: SMLAD only touches the given registers and the Q flag
+        unsafe {
+            core::arch::asm!(
+                "smlad {0}, {1}, {2}, {3}",
+                lateout(reg) result,
+                in(reg) pack(a),
+                in(reg) pack(b),
+                in(reg) acc,
+                options(pure, nomem, nostack),
+            );
+        }
+
+        result
+    }
+
+    #[cfg(not(all(target_arch = "arm", target_feature = "dsp")))]
+    {
+        dual_mac_scalar(a, b, acc)
+    }
+}
+
+#[inline]
+fn dual_mac_scalar(a: [i16; 2], b: [i16; 2], acc: i32) -> i32 {
+    acc + a[0] as i32 * b[0] as i32 + a[1] as i32 * b[1] as i32
+}
+
+/// Shift 24 bit samples in `input` down to 16 bit samples in `output`, saturating, as many as
+/// both hold
+pub(crate) fn narrow_24_to_16(input: &[i32], output: &mut [i16]) {
+    let len = input.len().min(output.len());
+    let (input, output) = (&input[..len], &mut output[..len]);
+
+    #[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+    let done = {
+        use core::arch::aarch64::{vld1q_s32, vqshrn_n_s32, vst1_s16};
+
+        let mut i = 0;
+
+        while i + 4 <= len {
+            // SAFETY: `i + 4 <= len` keeps both accesses within the slices
+            unsafe {
+                let wide = vld1q_s32(input.as_ptr().add(i));
+                vst1_s16(output.as_mut_ptr().add(i), vqshrn_n_s32::<8>(wide));
+            }
+
+            i += 4;
+        }
+
+        i
+    };
+
+    #[cfg(not(all(target_arch = "aarch64", target_feature = "neon")))]
+    let done = 0;
+
+    for (out, &sample) in output[done..].iter_mut().zip(&input[done..]) {
+        *out = saturate_i16(sample >> 8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDGES: [i32; 9] = [
+        i32::MIN,
+        -(1 << 23),
+        i16::MIN as i32 - 1,
+        i16::MIN as i32,
+        -1,
+        0,
+        i16::MAX as i32,
+        (1 << 23) - 1,
+        i32::MAX,
+    ];
+
+    #[test]
+    fn should_saturate_like_scalar() {
+        for value in EDGES.iter().copied().chain((-70_000..70_000).step_by(97)) {
+            assert_eq!(saturate_i16(value), saturate_i16_scalar(value));
+        }
+    }
+
+    #[test]
+    fn should_multiply_accumulate_like_scalar() {
+        let samples = [i16::MIN, -12_345, -1, 0, 1, 23_456, i16::MAX];
+        let coefficients = [-i16::MAX, -16_384, 0, 16_384, i16::MAX];
+
+        for &a in &samples {
+            for &b in &samples {
+                for &c in &coefficients {
+                    let expected = dual_mac_scalar([a, b], [c, -c], 1000);
+                    assert_eq!(dual_mac([a, b], [c, -c], 1000), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_narrow_like_scalar() {
+        let input: [i32; 23] = core::array::from_fn(|i| match EDGES.get(i) {
+            Some(&edge) => edge,
+            None => (i as i32 - 16) * 1_234_567,
+        });
+        let mut output = [0; 23];
+        narrow_24_to_16(&input, &mut output);
+
+        for (&out, &sample) in output.iter().zip(&input) {
+            assert_eq!(out, saturate_i16_scalar(sample >> 8));
+        }
+    }
+}
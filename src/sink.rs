@@ -0,0 +1,131 @@
+use crate::error::Error;
+#[cfg(feature = "write")]
+use embedded_sdmmc::{BlockDevice, File, TimeSource};
+
+/// Storage audio data is written to
+pub trait AudioSink {
+    /// Error reported by the underlying storage
+    type Error: core::fmt::Debug;
+
+    /// Write all of `bytes` at the current position
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+// without it no file of the card can be handed to anything that writes
+#[cfg(feature = "write")]
+impl<
+        'a,
+        BD: BlockDevice,
+        TS: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > AudioSink for File<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Error = embedded_sdmmc::Error<BD::Error>;
+
+    // the inherent method is called by path so it can't resolve back to this trait
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        File::write(self, bytes)
+    }
+}
+
+/// [`AudioSink`] writing into a byte slice held in RAM
+#[derive(Debug)]
+pub struct SliceSink<'b> {
+    bytes: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> SliceSink<'b> {
+    /// Create a sink writing from the start of `bytes`
+    pub fn new(bytes: &'b mut [u8]) -> Self {
+        SliceSink { bytes, len: 0 }
+    }
+
+    /// Bytes written so far
+    pub fn written(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<'b> AudioSink for SliceSink<'b> {
+    type Error = Error;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let end = self.len + bytes.len();
+
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(Error::BufferTooSmall(end))?
+            .copy_from_slice(bytes);
+        self.len = end;
+
+        Ok(())
+    }
+}
+
+/// File held in RAM that can be read, overwritten in place and grown by writing past its end
+#[cfg(all(test, feature = "write"))]
+pub(crate) struct RamFile {
+    pub(crate) bytes: std::vec::Vec<u8>,
+    pub(crate) offset: usize,
+}
+
+#[cfg(all(test, feature = "write"))]
+impl crate::source::AudioSource for RamFile {
+    type Error = Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let read = buf.len().min(self.bytes.len() - self.offset);
+        buf[..read].copy_from_slice(&self.bytes[self.offset..][..read]);
+        self.offset += read;
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = (offset as usize).min(self.bytes.len());
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset as u32
+    }
+
+    fn length(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+}
+
+#[cfg(all(test, feature = "write"))]
+impl AudioSink for RamFile {
+    type Error = Error;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let end = self.offset + bytes.len();
+
+        if end > self.bytes.len() {
+            self.bytes.resize(end, 0);
+        }
+
+        self.bytes[self.offset..end].copy_from_slice(bytes);
+        self.offset = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_write_until_slice_is_full() {
+        let mut buf = [0; 4];
+        let mut sink = SliceSink::new(&mut buf);
+
+        sink.write(&[1, 2, 3]).unwrap();
+        assert_eq!(sink.write(&[4, 5]), Err(Error::BufferTooSmall(5)));
+        assert_eq!(sink.written(), [1, 2, 3]);
+    }
+}
@@ -0,0 +1,613 @@
+use crate::bad_blocks::BLOCK_SIZE;
+use embedded_sdmmc::{BlockDevice, File, TimeSource};
+
+/// Bytes read from storage at a time by the bulk readers and [`BufferedSource`], one SD card block
+pub const CHUNK_LEN: usize = 512;
+
+// staged chunks must line up with SD card blocks, or every read would touch two of them
+const _: () = assert!(
+    CHUNK_LEN.is_multiple_of(BLOCK_SIZE as usize),
+    "CHUNK_LEN must be a multiple of the SD card block size"
+);
+// and hold whole frames of 8, 16 and 32 bit mono and stereo files, the common block aligns
+const _: () = assert!(
+    CHUNK_LEN.is_multiple_of(8),
+    "CHUNK_LEN must be a multiple of the block align of 32 bit stereo"
+);
+
+/// Random access storage the audio data is read from.
+///
+/// Implemented for embedded_sdmmc files, byte slices and forward only streams. Other storage such
+/// as SPI NOR flash only needs these four methods to be played with the same API.
+///
+/// Offsets are 32 bit, like those of FAT files, so files of up to 4 GiB are read. RF64 files
+/// whose sample data ends past that are refused with [`Error::TooLarge`](crate::Error::TooLarge).
+pub trait AudioSource {
+    /// Error reported by the underlying storage
+    type Error: core::fmt::Debug;
+
+    /// Read into `buf` from the current position, returning the number of bytes read
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Move the read position to `offset` bytes from the start
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error>;
+
+    /// Current read position in bytes from the start
+    fn offset(&self) -> u32;
+
+    /// Total length in bytes
+    fn length(&self) -> u32;
+}
+
+impl<
+        'a,
+        BD: BlockDevice,
+        TS: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > AudioSource for File<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Error = embedded_sdmmc::Error<BD::Error>;
+
+    // the inherent methods are called by path so they can't resolve back to this trait
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        File::read(self, buf)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        File::seek_from_start(self, offset)
+    }
+
+    fn offset(&self) -> u32 {
+        File::offset(self)
+    }
+
+    fn length(&self) -> u32 {
+        File::length(self)
+    }
+}
+
+// lets a reader borrow the source of its owner, e.g. a `Wav` over one sample of a bank
+impl<S: AudioSource + ?Sized> AudioSource for &mut S {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        (**self).read(buf)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        (**self).seek(offset)
+    }
+
+    fn offset(&self) -> u32 {
+        (**self).offset()
+    }
+
+    fn length(&self) -> u32 {
+        (**self).length()
+    }
+}
+
+/// [`AudioSource`] reading from a byte slice held in RAM or flash
+#[derive(Debug, Clone)]
+pub struct SliceSource<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> SliceSource<'b> {
+    /// Create a source reading from the start of `bytes`
+    pub fn new(bytes: &'b [u8]) -> Self {
+        SliceSource { bytes, offset: 0 }
+    }
+}
+
+impl<'b> AudioSource for SliceSource<'b> {
+    type Error = core::convert::Infallible;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let remaining = &self.bytes[self.offset..];
+        let len = remaining.len().min(buf.len());
+
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.offset += len;
+
+        Ok(len)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = (offset as usize).min(self.bytes.len());
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset as u32
+    }
+
+    fn length(&self) -> u32 {
+        self.bytes.len() as u32
+    }
+}
+
+/// [`AudioSource`] serving a range of bytes from RAM and everything else from another source.
+///
+/// Used to pre-roll the start of the sample data so playback can begin without waiting on slow
+/// storage, the inner source is only touched once reads move past the RAM copy.
+#[derive(Debug)]
+pub struct HybridSource<'b, S: AudioSource> {
+    inner: S,
+    ram: &'b [u8],
+    ram_start: u32,
+    offset: u32,
+}
+
+impl<'b, S: AudioSource> HybridSource<'b, S> {
+    /// Serve `ram` in place of the bytes of `inner` starting at `ram_start`
+    pub fn new(inner: S, ram: &'b [u8], ram_start: u32) -> Self {
+        let offset = inner.offset();
+
+        HybridSource {
+            inner,
+            ram,
+            ram_start,
+            offset,
+        }
+    }
+
+    /// Give back the inner source
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn ram_range(&self) -> core::ops::Range<u32> {
+        self.ram_start..self.ram_start + self.ram.len() as u32
+    }
+}
+
+impl<'b, S: AudioSource> AudioSource for HybridSource<'b, S> {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            let len = if self.ram_range().contains(&self.offset) {
+                let remaining = &self.ram[(self.offset - self.ram_start) as usize..];
+                let len = remaining.len().min(buf.len() - read);
+
+                buf[read..read + len].copy_from_slice(&remaining[..len]);
+                len
+            } else {
+                // stop at the RAM copy when reading up to it from storage
+                let end = if self.offset < self.ram_start {
+                    buf.len()
+                        .min(read + (self.ram_start - self.offset) as usize)
+                } else {
+                    buf.len()
+                };
+
+                // only seek the inner source when it isn't already in place
+                if self.inner.offset() != self.offset {
+                    self.inner.seek(self.offset)?;
+                }
+
+                self.inner.read(&mut buf[read..end])?
+            };
+
+            if len == 0 {
+                break;
+            }
+
+            self.offset += len as u32;
+            read += len;
+        }
+
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = offset.min(self.length());
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    fn length(&self) -> u32 {
+        self.inner.length()
+    }
+}
+
+/// [`AudioSource`] staging [`CHUNK_LEN`] bytes of another source in RAM.
+///
+/// Small reads, such as a sample or a frame at a time, are served from the staged block so the
+/// inner source sees whole block aligned reads instead of many tiny ones. Reads of at least a
+/// block bypass the stage and go straight to the inner source.
+#[derive(Debug)]
+pub struct BufferedSource<S: AudioSource> {
+    inner: S,
+    stage: [u8; CHUNK_LEN],
+    stage_start: u32,
+    stage_len: usize,
+    offset: u32,
+}
+
+impl<S: AudioSource> BufferedSource<S> {
+    /// Stage reads of `inner`, starting at its current position
+    pub fn new(inner: S) -> Self {
+        let offset = inner.offset();
+
+        BufferedSource {
+            inner,
+            stage: [0; CHUNK_LEN],
+            stage_start: 0,
+            stage_len: 0,
+            offset,
+        }
+    }
+
+    /// Give back the inner source
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn staged(&self) -> core::ops::Range<u32> {
+        self.stage_start..self.stage_start + self.stage_len as u32
+    }
+
+    /// Move the inner source to `offset` unless it's already there
+    fn seek_inner(&mut self, offset: u32) -> Result<(), S::Error> {
+        if self.inner.offset() != offset {
+            self.inner.seek(offset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stage the block holding the current position
+    fn fill(&mut self) -> Result<(), S::Error> {
+        let start = self.offset - self.offset % CHUNK_LEN as u32;
+        self.seek_inner(start)?;
+
+        self.stage_start = start;
+        self.stage_len = 0;
+
+        while self.stage_len < CHUNK_LEN {
+            match self.inner.read(&mut self.stage[self.stage_len..])? {
+                0 => break,
+                n => self.stage_len += n,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: AudioSource> AudioSource for BufferedSource<S> {
+    type Error = S::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let mut read = 0;
+
+        while read < buf.len() {
+            let len = if self.staged().contains(&self.offset) {
+                let remaining =
+                    &self.stage[(self.offset - self.stage_start) as usize..self.stage_len];
+                let len = remaining.len().min(buf.len() - read);
+
+                buf[read..read + len].copy_from_slice(&remaining[..len]);
+                len
+            } else if buf.len() - read >= CHUNK_LEN {
+                self.seek_inner(self.offset)?;
+                self.inner.read(&mut buf[read..])?
+            } else {
+                self.fill()?;
+
+                if !self.staged().contains(&self.offset) {
+                    break;
+                }
+
+                continue;
+            };
+
+            if len == 0 {
+                break;
+            }
+
+            self.offset += len as u32;
+            read += len;
+        }
+
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.offset = offset.min(self.length());
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    fn length(&self) -> u32 {
+        self.inner.length()
+    }
+}
+
+/// Error of an [`EitherSource`], from whichever source was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EitherError<A, B> {
+    /// Error of the first kind of source
+    First(A),
+    /// Error of the second kind of source
+    Second(B),
+}
+
+/// [`AudioSource`] that is one of two kinds of source, so files on different storage, such as an
+/// SD card and the flash of the MCU, share one type in a [`Library`](crate::Library) or a player
+#[derive(Debug)]
+pub enum EitherSource<A: AudioSource, B: AudioSource> {
+    /// Source of the first kind
+    First(A),
+    /// Source of the second kind
+    Second(B),
+}
+
+impl<A: AudioSource, B: AudioSource> AudioSource for EitherSource<A, B> {
+    type Error = EitherError<A::Error, B::Error>;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self {
+            EitherSource::First(source) => source.read(buf).map_err(EitherError::First),
+            EitherSource::Second(source) => source.read(buf).map_err(EitherError::Second),
+        }
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        match self {
+            EitherSource::First(source) => source.seek(offset).map_err(EitherError::First),
+            EitherSource::Second(source) => source.seek(offset).map_err(EitherError::Second),
+        }
+    }
+
+    fn offset(&self) -> u32 {
+        match self {
+            EitherSource::First(source) => source.offset(),
+            EitherSource::Second(source) => source.offset(),
+        }
+    }
+
+    fn length(&self) -> u32 {
+        match self {
+            EitherSource::First(source) => source.length(),
+            EitherSource::Second(source) => source.length(),
+        }
+    }
+}
+
+/// Forward only byte stream such as a TCP socket or a UART
+pub trait ByteStream {
+    /// Error reported by the underlying stream
+    type Error: core::fmt::Debug;
+
+    /// Read into `buf`, returning the number of bytes read, `0` once the stream ended
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// Error of a [`StreamSource`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamError<E> {
+    /// The underlying stream failed
+    Stream(E),
+    /// A stream can't go back to bytes it already handed out
+    BackwardSeek,
+}
+
+/// [`AudioSource`] over a [`ByteStream`] that can only move forward.
+///
+/// Seeking forward reads and discards the skipped bytes, seeking backward fails. Reads fill the
+/// whole buffer unless the stream ends, so short network reads never split a header or sample.
+#[derive(Debug)]
+pub struct StreamSource<R: ByteStream> {
+    inner: R,
+    offset: u32,
+    length: u32,
+}
+
+impl<R: ByteStream> StreamSource<R> {
+    /// Create a source over `inner` carrying `length` bytes, e.g. from a Content-Length header.
+    ///
+    /// Pass `u32::MAX` when the length isn't known up front.
+    pub fn new(inner: R, length: u32) -> Self {
+        StreamSource {
+            inner,
+            offset: 0,
+            length,
+        }
+    }
+
+    /// Give back the inner stream
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: ByteStream> AudioSource for StreamSource<R> {
+    type Error = StreamError<R::Error>;
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min((self.length - self.offset) as usize);
+        let mut read = 0;
+
+        while read < len {
+            match self
+                .inner
+                .read(&mut buf[read..len])
+                .map_err(StreamError::Stream)?
+            {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        self.offset += read as u32;
+
+        Ok(read)
+    }
+
+    fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+        if offset < self.offset {
+            return Err(StreamError::BackwardSeek);
+        }
+
+        let mut discard = [0; 64];
+
+        while self.offset < offset.min(self.length) {
+            let len = discard.len().min((offset - self.offset) as usize);
+
+            if self.read(&mut discard[..len])? == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_and_seek_slice() {
+        let mut source = SliceSource::new(&[1, 2, 3, 4, 5]);
+        let mut buf = [0; 3];
+
+        assert_eq!(source.read(&mut buf), Ok(3));
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(source.read(&mut buf), Ok(2));
+        assert_eq!(source.read(&mut buf), Ok(0));
+
+        source.seek(1).unwrap();
+        assert_eq!(source.offset(), 1);
+        assert_eq!(source.read(&mut buf), Ok(3));
+        assert_eq!(buf, [2, 3, 4]);
+
+        source.seek(10).unwrap();
+        assert_eq!(source.offset(), 5);
+    }
+
+    #[test]
+    fn should_serve_ram_range_from_ram() {
+        let ram = [0xaa; 2];
+        let mut source = HybridSource::new(SliceSource::new(&[1, 2, 3, 4, 5, 6]), &ram, 2);
+        let mut buf = [0; 6];
+
+        assert_eq!(source.read(&mut buf), Ok(6));
+        assert_eq!(buf, [1, 2, 0xaa, 0xaa, 5, 6]);
+
+        source.seek(3).unwrap();
+        assert_eq!(source.read(&mut buf), Ok(3));
+        assert_eq!(buf[..3], [0xaa, 5, 6]);
+        assert_eq!(source.into_inner().offset(), 6);
+    }
+
+    /// Source counting the reads reaching the storage
+    struct Counting<'a> {
+        inner: SliceSource<'a>,
+        reads: usize,
+    }
+
+    impl<'a> AudioSource for Counting<'a> {
+        type Error = core::convert::Infallible;
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.reads += 1;
+            self.inner.read(buf)
+        }
+
+        fn seek(&mut self, offset: u32) -> Result<(), Self::Error> {
+            self.inner.seek(offset)
+        }
+
+        fn offset(&self) -> u32 {
+            self.inner.offset()
+        }
+
+        fn length(&self) -> u32 {
+            self.inner.length()
+        }
+    }
+
+    #[test]
+    fn should_serve_small_reads_from_the_stage() {
+        let bytes: [u8; 2000] = core::array::from_fn(|i| i as u8);
+        let counting = Counting {
+            inner: SliceSource::new(&bytes),
+            reads: 0,
+        };
+        let mut source = BufferedSource::new(counting);
+        let mut buf = [0; 3];
+
+        for i in 0..300 {
+            assert_eq!(source.read(&mut buf), Ok(3));
+            assert_eq!(buf, [(3 * i) as u8, (3 * i + 1) as u8, (3 * i + 2) as u8]);
+        }
+
+        // 900 bytes span two blocks
+        assert_eq!(source.inner.reads, 2);
+
+        source.seek(1990).unwrap();
+        assert_eq!(source.read(&mut [0; 20]), Ok(10));
+        assert_eq!(source.read(&mut buf), Ok(0));
+
+        source.seek(10).unwrap();
+        let mut large = [0; CHUNK_LEN];
+        assert_eq!(source.read(&mut large), Ok(CHUNK_LEN));
+        assert_eq!(large[0], 10);
+    }
+
+    /// Stream handing out at most 3 bytes per read, like a slow socket
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> ByteStream for Trickle<'a> {
+        type Error = core::convert::Infallible;
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn should_fill_reads_and_only_seek_forward() {
+        let bytes = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut source = StreamSource::new(Trickle(&bytes), bytes.len() as u32);
+        let mut buf = [0; 4];
+
+        assert_eq!(source.read(&mut buf), Ok(4));
+        assert_eq!(buf, [0, 1, 2, 3]);
+
+        source.seek(7).unwrap();
+        assert_eq!(source.read(&mut buf), Ok(3));
+        assert_eq!(buf[..3], [7, 8, 9]);
+
+        assert_eq!(source.seek(2), Err(StreamError::BackwardSeek));
+        assert_eq!(source.offset(), source.length());
+    }
+}
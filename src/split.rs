@@ -0,0 +1,158 @@
+use crate::error::Error;
+use crate::fmt::Fmt;
+use crate::remux::write_header;
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::wav::{read_full, Wav};
+
+impl<S: AudioSource> Wav<S> {
+    /// Demultiplex every channel into its own mono WAV file, one sink per channel.
+    ///
+    /// The samples are copied unchanged, so the outputs keep the format of this file. Returns the
+    /// number of frames written to every sink, the read position of `self` is left unchanged.
+    pub fn split_channels<K: AudioSink>(
+        &mut self,
+        sinks: &mut [K],
+    ) -> Result<usize, Error<S::Error>> {
+        let channels = self.fmt.num_channels as usize;
+
+        if sinks.len() != channels {
+            return Err(Error::FormatMismatch);
+        }
+
+        let block_align = self.fmt.block_align();
+        let bytes_per_sample = block_align / channels.max(1);
+
+        // one batch of whole frames is read at a time and scattered over the outputs
+        let mut frames_buf = [0; 240];
+        let mut channel_buf = [0; 240];
+        let frames_per_batch = frames_buf.len() / block_align.max(1);
+
+        if frames_per_batch == 0 {
+            return Err(Error::BufferTooSmall(block_align));
+        }
+
+        let data_end = self.data_end();
+        let frames = data_end.saturating_sub(self.data.start) / block_align;
+        let mono_len = frames * bytes_per_sample;
+
+        let mono = Fmt {
+            num_channels: 1,
+            block_size: bytes_per_sample as u16,
+            ..self.fmt
+        };
+
+        for sink in sinks.iter_mut() {
+            write_header(sink, &mono, mono_len)?;
+        }
+
+        let position = self.source.offset();
+        self.source
+            .seek(self.data.start as u32)
+            .map_err(Error::Source)?;
+
+        let mut written = 0;
+
+        while written < frames {
+            let batch = frames_per_batch.min(frames - written);
+            let batch_buf = &mut frames_buf[..batch * block_align];
+
+            let read = read_full(&mut self.source, batch_buf)?;
+
+            let batch = read / block_align;
+
+            for (channel, sink) in sinks.iter_mut().enumerate() {
+                let samples = batch_buf[..batch * block_align]
+                    .chunks_exact(block_align)
+                    .map(|frame| &frame[channel * bytes_per_sample..][..bytes_per_sample]);
+
+                for (out, sample) in channel_buf.chunks_exact_mut(bytes_per_sample).zip(samples) {
+                    out.copy_from_slice(sample);
+                }
+
+                sink.write(&channel_buf[..batch * bytes_per_sample])
+                    .map_err(|_| Error::Io)?;
+            }
+
+            written += batch;
+
+            if read < batch_buf.len() {
+                break;
+            }
+        }
+
+        if mono_len & 1 == 1 {
+            for sink in sinks.iter_mut() {
+                sink.write(&[0]).map_err(|_| Error::Io)?;
+            }
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+    use crate::wav::{decode_block, parse_header_bytes, DataBulk};
+
+    const WAV: [u8; 60] = [
+        0x52, 0x49, 0x46, 0x46, // RIFF
+        0x34, 0x00, 0x00, 0x00, // chunk size
+        0x57, 0x41, 0x56, 0x45, // WAVE
+        0x66, 0x6d, 0x74, 0x20, // fmt_
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, // audio format
+        0x02, 0x00, // num channels
+        0x22, 0x56, 0x00, 0x00, // sample rate
+        0x88, 0x58, 0x01, 0x00, // byte rate
+        0x04, 0x00, // block align
+        0x10, 0x00, // bits per sample
+        0x64, 0x61, 0x74, 0x61, // data
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+        0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+        0x3c, 0x13, 0x3c, 0x14, // sample 3 L+R
+        0x16, 0xf9, 0x18, 0xf9, // sample 4 L+R
+    ];
+
+    #[test]
+    fn should_split_stereo_into_mono_files() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let (mut left, mut right) = ([0; 64], [0; 64]);
+        let mut sinks = [SliceSink::new(&mut left), SliceSink::new(&mut right)];
+
+        assert_eq!(wav.split_channels(&mut sinks).unwrap(), 4);
+        assert_eq!(wav.timestamp().frames, 0);
+
+        let expected = [[0, 0x1724, 0x133c, -1770], [0, -3298, 0x143c, -1768]];
+
+        for (sink, expected) in sinks.iter().zip(expected) {
+            let bytes = sink.written();
+            let header = parse_header_bytes(bytes).unwrap();
+
+            assert_eq!(header.fmt.num_channels, 1);
+            assert_eq!(header.fmt.block_align(), 2);
+
+            match decode_block::<8>(&header.fmt, &bytes[header.data.start..]).unwrap() {
+                DataBulk::BitDepth16(samples) => assert_eq!(samples, expected),
+                _ => panic!("expected 16 bit samples"),
+            }
+        }
+    }
+
+    #[test]
+    fn should_need_one_sink_per_channel() {
+        let mut wav = Wav::new(SliceSource::new(&WAV)).unwrap();
+        let mut buf = [0; 64];
+
+        assert_eq!(
+            wav.split_channels(&mut [SliceSink::new(&mut buf)]),
+            Err(Error::FormatMismatch)
+        );
+    }
+}
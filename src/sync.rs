@@ -0,0 +1,111 @@
+use crate::timestamp::Timestamp;
+
+/// Source of time shared between devices, e.g. a clock disciplined over PTP or ESP-NOW
+pub trait Clock {
+    /// Current time in microseconds
+    fn now_micros(&self) -> u64;
+}
+
+/// Time spent opening a file, measured with a user supplied [`Clock`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenTiming {
+    /// Microseconds spent reading and parsing the header
+    pub header_micros: u64,
+    /// Microseconds spent reading and decoding the first buffer of samples
+    pub first_buffer_micros: u64,
+}
+
+impl OpenTiming {
+    /// Time from starting to open the file until the first buffer was ready
+    pub fn total_micros(&self) -> u64 {
+        self.header_micros + self.first_buffer_micros
+    }
+}
+
+/// Playback start scheduled at a point in time on a shared [`Clock`]
+///
+/// Devices that agree on the start time and the clock can each derive which
+/// frame should be playing at any moment, both to start together and to
+/// correct drift or join late.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStart {
+    start_micros: u64,
+    sample_rate: u32,
+}
+
+impl SyncStart {
+    /// Schedule playback of audio with the given sample rate to start at `start_micros`
+    pub fn new(start_micros: u64, sample_rate: u32) -> Self {
+        SyncStart {
+            start_micros,
+            sample_rate,
+        }
+    }
+
+    /// Time at which playback starts
+    pub fn start_micros(&self) -> u64 {
+        self.start_micros
+    }
+
+    /// Number of frames of silence to output before the first frame, zero once started
+    pub fn frames_until_start<C: Clock>(&self, clock: &C) -> u64 {
+        let wait = self.start_micros.saturating_sub(clock.now_micros());
+        self.micros_to_frames(wait)
+    }
+
+    /// True once the start time has been reached
+    pub fn has_started<C: Clock>(&self, clock: &C) -> bool {
+        clock.now_micros() >= self.start_micros
+    }
+
+    /// Frame that should be playing right now, `None` before the start time
+    pub fn expected<C: Clock>(&self, clock: &C) -> Option<Timestamp> {
+        let elapsed = clock.now_micros().checked_sub(self.start_micros)?;
+        let frames = self.micros_to_frames(elapsed);
+
+        Some(Timestamp::from_frames(frames, self.sample_rate))
+    }
+
+    /// Frames played minus frames expected, positive when playback runs ahead of the clock
+    pub fn drift<C: Clock>(&self, clock: &C, frames_played: u64) -> i64 {
+        let expected = self.expected(clock).map(|t| t.frames).unwrap_or(0);
+        frames_played as i64 - expected as i64
+    }
+
+    fn micros_to_frames(&self, micros: u64) -> u64 {
+        (micros as u128 * self.sample_rate as u128 / 1_000_000) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedClock(u64);
+
+    impl Clock for FixedClock {
+        fn now_micros(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn should_count_down_to_start() {
+        let sync = SyncStart::new(2_000_000, 48_000);
+
+        assert_eq!(sync.frames_until_start(&FixedClock(1_000_000)), 48_000);
+        assert_eq!(sync.frames_until_start(&FixedClock(3_000_000)), 0);
+        assert!(!sync.has_started(&FixedClock(1_999_999)));
+        assert!(sync.expected(&FixedClock(1_999_999)).is_none());
+    }
+
+    #[test]
+    fn should_report_expected_frame_and_drift() {
+        let sync = SyncStart::new(1_000_000, 44_100);
+        let clock = FixedClock(1_500_000);
+
+        assert_eq!(sync.expected(&clock).unwrap().frames, 22_050);
+        assert_eq!(sync.drift(&clock, 22_000), -50);
+        assert_eq!(sync.drift(&clock, 22_100), 50);
+    }
+}
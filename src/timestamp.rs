@@ -0,0 +1,51 @@
+/// Position in the sample data, counted from the first frame of the data chunk
+///
+/// A frame holds one sample for every channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp {
+    /// Number of whole frames since the start of the sample data
+    pub frames: u64,
+    /// `frames` converted to microseconds at the sample rate of the file
+    pub micros: u64,
+}
+
+impl Timestamp {
+    /// Create a [`Timestamp`] from a frame count at the given sample rate
+    pub fn from_frames(frames: u64, sample_rate: u32) -> Self {
+        let micros = if sample_rate == 0 {
+            0
+        } else {
+            (frames as u128 * 1_000_000 / sample_rate as u128) as u64
+        };
+
+        Timestamp { frames, micros }
+    }
+
+    /// `micros` rounded down to whole milliseconds, e.g. to drive a progress bar
+    pub fn millis(&self) -> u64 {
+        self.micros / 1000
+    }
+}
+
+/// Buffer of samples tagged with the [`Timestamp`] of its first frame
+#[derive(Debug)]
+pub struct Stamped<T> {
+    /// Position of the first sample in the buffer
+    pub timestamp: Timestamp,
+    /// The delivered samples
+    pub data: T,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_frames_to_micros() {
+        assert_eq!(Timestamp::from_frames(48_000, 48_000).micros, 1_000_000);
+        assert_eq!(Timestamp::from_frames(44_099, 44_100).millis(), 999);
+        assert_eq!(Timestamp::from_frames(441, 44_100).micros, 10_000);
+        assert_eq!(Timestamp::from_frames(1, 48_000).micros, 20);
+        assert_eq!(Timestamp::from_frames(10, 0).micros, 0);
+    }
+}
@@ -0,0 +1,95 @@
+/// Sine of a phase given as a fraction of a full turn, in Q15
+fn sine(phase: u32) -> i16 {
+    // fold the phase into the quarter turn around 0 or half a turn, where z runs from -1 to 1
+    let quarter = (phase >> 30) as u8;
+    let fraction = (phase & 0x3fff_ffff) as i64;
+
+    let z = match quarter {
+        0 => fraction,
+        1 => (1 << 30) - fraction,
+        2 => -fraction,
+        _ => fraction - (1 << 30),
+    };
+
+    // sin(pi / 2 * z) ~= z * (a - z^2 * (b - z^2 * c)), exact at z = 0 and +-1 and within 15 LSB
+    // in between, z and the coefficients in Q30
+    const A: i64 = 1_686_629_713;
+    const B: i64 = 688_904_866;
+    const C: i64 = 76_016_977;
+
+    let z2 = (z * z) >> 30;
+    let poly = A - ((z2 * (B - ((z2 * C) >> 30))) >> 30);
+    let sine = (z * poly) >> 45;
+
+    sine.clamp(-(i16::MAX as i64), i16::MAX as i64) as i16
+}
+
+/// Fixed point sine generator, e.g. for test tones or sweeps
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    sample_rate: u32,
+    phase: u32,
+    step: u32,
+    amplitude: i16,
+}
+
+impl ToneGenerator {
+    /// Create a generator of `frequency` Hz at `sample_rate` with a peak of `amplitude`
+    pub fn new(sample_rate: u32, frequency: u32, amplitude: i16) -> Self {
+        let mut tone = ToneGenerator {
+            sample_rate: sample_rate.max(1),
+            phase: 0,
+            step: 0,
+            amplitude,
+        };
+        tone.set_frequency(frequency);
+
+        tone
+    }
+
+    /// Change the frequency without a jump in phase, so sweeps stay click free
+    pub fn set_frequency(&mut self, frequency: u32) {
+        self.step = ((frequency as u64) << 32).wrapping_div(self.sample_rate as u64) as u32;
+    }
+
+    /// Next sample of the tone
+    pub fn next_sample(&mut self) -> i16 {
+        let sample = (sine(self.phase) as i32 * self.amplitude as i32) >> 15;
+        self.phase = self.phase.wrapping_add(self.step);
+
+        sample as i16
+    }
+
+    /// Fill `out` with consecutive mono samples
+    pub fn fill(&mut self, out: &mut [i16]) {
+        out.iter_mut().for_each(|s| *s = self.next_sample());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_approximate_sine() {
+        for i in 0..1024u32 {
+            let phase = i << 22;
+            let exact = (i as f64 / 1024.0 * 2.0 * core::f64::consts::PI).sin() * 32767.0;
+
+            assert!((sine(phase) as f64 - exact).abs() < 15.0, "phase {}", i);
+        }
+    }
+
+    #[test]
+    fn should_generate_tone_at_frequency() {
+        let mut tone = ToneGenerator::new(48_000, 1_000, 16_384);
+        let mut out = [0; 480];
+        tone.fill(&mut out);
+
+        let crossings = out.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count();
+
+        assert_eq!(crossings, 19);
+        assert!(out.iter().all(|&s| s.abs() <= 16_384));
+        assert!(out.iter().any(|&s| s.abs() >= 16_380));
+    }
+}
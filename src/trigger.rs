@@ -0,0 +1,93 @@
+use heapless::Vec;
+
+/// Event scheduled at a frame position in the sample data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trigger {
+    /// Frame at which the trigger fires
+    pub frame: u64,
+    /// Caller chosen identifier handed back when the trigger fires
+    pub id: u32,
+}
+
+/// Fixed capacity list of [`Trigger`]s, kept sorted by frame
+///
+/// Triggers are not consumed when they fire, so they fire again when the
+/// read position moves backwards, e.g. when a file is looped.
+#[derive(Debug, Clone, Default)]
+pub struct Triggers<const N: usize> {
+    triggers: Vec<Trigger, N>,
+}
+
+impl<const N: usize> Triggers<N> {
+    /// Create an empty trigger list
+    pub fn new() -> Self {
+        Triggers {
+            triggers: Vec::new(),
+        }
+    }
+
+    /// Schedule trigger `id` at `frame`, handing the trigger back if the list is full
+    pub fn schedule(&mut self, frame: u64, id: u32) -> Result<(), Trigger> {
+        let trigger = Trigger { frame, id };
+        let index = self.triggers.partition_point(|t| t.frame <= frame);
+
+        self.triggers.insert(index, trigger)
+    }
+
+    /// Remove every trigger with the given id
+    pub fn cancel(&mut self, id: u32) {
+        self.triggers.retain(|t| t.id != id);
+    }
+
+    /// Remove all triggers
+    pub fn clear(&mut self) {
+        self.triggers.clear();
+    }
+
+    /// Scheduled triggers, ordered by frame
+    pub fn iter(&self) -> impl Iterator<Item = &Trigger> {
+        self.triggers.iter()
+    }
+
+    /// Call `callback` for every trigger in the frame range `from..to`, in frame order
+    pub fn fire<F: FnMut(&Trigger)>(&self, from: u64, to: u64, callback: F) {
+        let start = self.triggers.partition_point(|t| t.frame < from);
+
+        self.triggers[start..]
+            .iter()
+            .take_while(|t| t.frame < to)
+            .for_each(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_fire_triggers_crossed_by_range() {
+        let mut triggers: Triggers<4> = Triggers::new();
+        triggers.schedule(441_000, 1).unwrap();
+        triggers.schedule(100, 2).unwrap();
+        triggers.schedule(200, 3).unwrap();
+
+        let mut fired: Vec<u32, 4> = Vec::new();
+        triggers.fire(0, 200, |t| fired.push(t.id).unwrap());
+        assert_eq!(fired, [2]);
+
+        fired.clear();
+        triggers.fire(200, 441_001, |t| fired.push(t.id).unwrap());
+        assert_eq!(fired, [3, 1]);
+    }
+
+    #[test]
+    fn should_reject_triggers_when_full() {
+        let mut triggers: Triggers<1> = Triggers::new();
+        triggers.schedule(1, 1).unwrap();
+
+        assert_eq!(triggers.schedule(2, 2), Err(Trigger { frame: 2, id: 2 }));
+
+        triggers.cancel(1);
+        assert!(triggers.schedule(2, 2).is_ok());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::error::Error;
+use crate::wav::DataBulk;
+use heapless::Vec;
+
+/// Length of the windows the energy is measured over, in milliseconds
+const WINDOW_MILLIS: u32 = 10;
+/// Number of quiet windows after which a segment of speech ends
+const HANGOVER_WINDOWS: u32 = 30;
+/// Energy of a window has to exceed the noise floor by this factor, about 9 dB, to count as speech
+const SPEECH_RATIO: u64 = 8;
+/// Mean square energy below which a window never counts as speech, about -50 dBFS
+const MIN_SPEECH_ENERGY: u64 = 10_000;
+/// Shift setting how slowly the noise floor rises towards louder windows, a time constant of
+/// about 40 s so even long speech barely lifts it
+const NOISE_RISE_SHIFT: u32 = 12;
+
+/// Stretch of speech found in a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoiceSegment {
+    /// Frame the speech starts at
+    pub start_frame: u32,
+    /// Number of frames of speech
+    pub len_frames: u32,
+}
+
+/// Energy based voice activity detector collecting the [`VoiceSegment`]s of a recording
+///
+/// Fed the same samples as the [`WavWriter`](crate::WavWriter), its segments are stored in the
+/// file by [`WavWriter::finalize_with_segments`](crate::WavWriter::finalize_with_segments). The
+/// noise floor is tracked as the recording goes, so no calibration is needed. At most `N`
+/// segments are kept.
+#[derive(Debug, Clone)]
+pub struct VoiceLog<const N: usize> {
+    num_channels: u16,
+    window_frames: u32,
+    window_energy: u64,
+    window_samples: u32,
+    frames: u32,
+    noise_floor: Option<u64>,
+    speech_start: Option<u32>,
+    speech_end: u32,
+    quiet_windows: u32,
+    segments: Vec<VoiceSegment, N>,
+}
+
+impl<const N: usize> VoiceLog<N> {
+    /// Create a detector for interleaved samples at `sample_rate` with `num_channels` channels
+    pub fn new(sample_rate: u32, num_channels: u16) -> Self {
+        VoiceLog {
+            num_channels: num_channels.max(1),
+            window_frames: (sample_rate * WINDOW_MILLIS / 1000).max(1),
+            window_energy: 0,
+            window_samples: 0,
+            frames: 0,
+            noise_floor: None,
+            speech_start: None,
+            speech_end: 0,
+            quiet_windows: 0,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Segments of speech found so far, a segment still going on is left out until
+    /// [`VoiceLog::finish`]
+    pub fn segments(&self) -> &[VoiceSegment] {
+        &self.segments
+    }
+
+    /// Feed interleaved samples, returns [`Error::TooManyCuePoints`] once more than `N` segments
+    /// were found
+    pub fn write_samples<const NUM: usize>(
+        &mut self,
+        samples: &DataBulk<NUM>,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+
+        samples.for_each_i16(|sample| {
+            if result.is_ok() {
+                result = self.write_sample(sample);
+            }
+        });
+
+        result
+    }
+
+    /// Feed interleaved 16 bit samples, see [`VoiceLog::write_samples`]
+    pub fn write_i16(&mut self, samples: &[i16]) -> Result<(), Error> {
+        samples
+            .iter()
+            .try_for_each(|&sample| self.write_sample(sample))
+    }
+
+    /// End a segment still going on at the end of the recording
+    pub fn finish(&mut self) -> Result<(), Error> {
+        match self.speech_start.take() {
+            Some(start) => self.push(start, self.speech_end),
+            None => Ok(()),
+        }
+    }
+
+    fn write_sample(&mut self, sample: i16) -> Result<(), Error> {
+        let window_samples = self.window_frames * self.num_channels as u32;
+
+        self.window_energy += (sample as i64 * sample as i64) as u64;
+        self.window_samples += 1;
+
+        if self.window_samples < window_samples {
+            return Ok(());
+        }
+
+        let energy = self.window_energy / window_samples as u64;
+
+        self.window_energy = 0;
+        self.window_samples = 0;
+        self.frames = self.frames.saturating_add(self.window_frames);
+
+        self.classify(energy)
+    }
+
+    /// Judge the window that just ended on its mean square `energy`
+    fn classify(&mut self, energy: u64) -> Result<(), Error> {
+        let noise = *self.noise_floor.get_or_insert(energy);
+        let is_speech = energy >= MIN_SPEECH_ENERGY && energy > noise.saturating_mul(SPEECH_RATIO);
+
+        // drop fast to quieter windows, rise slowly so speech doesn't lift the floor
+        self.noise_floor = Some(if energy < noise {
+            energy
+        } else {
+            noise + ((energy - noise) >> NOISE_RISE_SHIFT)
+        });
+
+        if is_speech {
+            if self.speech_start.is_none() {
+                self.speech_start = Some(self.frames - self.window_frames);
+            }
+
+            self.speech_end = self.frames;
+            self.quiet_windows = 0;
+        } else if let Some(start) = self.speech_start {
+            self.quiet_windows += 1;
+
+            if self.quiet_windows >= HANGOVER_WINDOWS {
+                self.speech_start = None;
+                self.push(start, self.speech_end)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, start: u32, end: u32) -> Result<(), Error> {
+        let segment = VoiceSegment {
+            start_frame: start,
+            len_frames: end - start,
+        };
+
+        self.segments
+            .push(segment)
+            .map_err(|_| Error::TooManyCuePoints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 ms of a 500 Hz square wave at 16 kHz and the given amplitude
+    fn window(amplitude: i16) -> [i16; 160] {
+        let mut window = [0; 160];
+
+        for (i, sample) in window.iter_mut().enumerate() {
+            *sample = if i % 32 < 16 { amplitude } else { -amplitude };
+        }
+
+        window
+    }
+
+    #[test]
+    fn should_find_speech_between_noise() {
+        let mut log = VoiceLog::<4>::new(16_000, 1);
+
+        for _ in 0..50 {
+            log.write_i16(&window(20)).unwrap();
+        }
+
+        for _ in 0..100 {
+            log.write_i16(&window(8000)).unwrap();
+        }
+
+        // a short pause within the hangover doesn't split the segment
+        for _ in 0..10 {
+            log.write_i16(&window(20)).unwrap();
+        }
+
+        for _ in 0..20 {
+            log.write_i16(&window(8000)).unwrap();
+        }
+
+        for _ in 0..50 {
+            log.write_i16(&window(20)).unwrap();
+        }
+
+        for _ in 0..10 {
+            log.write_i16(&window(8000)).unwrap();
+        }
+
+        assert_eq!(
+            log.segments(),
+            [VoiceSegment {
+                start_frame: 50 * 160,
+                len_frames: 130 * 160,
+            }]
+        );
+
+        log.finish().unwrap();
+
+        assert_eq!(
+            log.segments()[1],
+            VoiceSegment {
+                start_frame: 230 * 160,
+                len_frames: 10 * 160,
+            }
+        );
+    }
+
+    #[test]
+    fn should_ignore_digital_silence_and_report_overflow() {
+        let mut log = VoiceLog::<1>::new(16_000, 2);
+
+        for _ in 0..20 {
+            log.write_i16(&[0; 320]).unwrap();
+        }
+
+        assert!(log.segments().is_empty());
+
+        for _ in 0..10 {
+            log.write_i16(&[8000; 320]).unwrap();
+        }
+
+        for _ in 0..40 {
+            log.write_i16(&[0; 320]).unwrap();
+        }
+
+        assert_eq!(log.segments().len(), 1);
+
+        log.write_i16(&[8000; 320]).unwrap();
+
+        assert_eq!(log.finish(), Err(Error::TooManyCuePoints));
+    }
+}
@@ -0,0 +1,345 @@
+use crate::error::Error;
+use crate::identity::path_hash;
+use crate::index::{Index, IndexEntry};
+use crate::library::{collect_page, count, nth_page, Catalog, Page, Query, Search, SearchMode};
+use crate::source::AudioSource;
+use core::cmp::Ordering;
+use heapless::Vec;
+
+/// Bits of a [`TrackId`] holding the position in the index of its volume
+const POSITION_BITS: u32 = 24;
+/// Entries of an index that can be addressed by a [`TrackId`]
+pub const MAX_VOLUME_TRACKS: u32 = 1 << POSITION_BITS;
+
+/// Identifier of a track of a [`Library`], the volume it is on and a hash of the name of the
+/// track, with its position in the index of that volume to find it quickly.
+///
+/// Fits in a `u64` to be stored in playlists or bookmarks. It keeps pointing at the same track
+/// whatever other volumes are mounted and when the index of its volume is built again with
+/// tracks added or removed, the track is then looked up by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TrackId(u64);
+
+impl TrackId {
+    /// Track named `name` at `position` of the index of `volume`, `None` if `position` isn't
+    /// below [`MAX_VOLUME_TRACKS`]
+    pub fn new(volume: u8, position: u32, name: &str) -> Option<Self> {
+        Self::from_hash(volume, position, path_hash(name))
+    }
+
+    fn from_hash(volume: u8, position: u32, name_hash: u32) -> Option<Self> {
+        match position < MAX_VOLUME_TRACKS {
+            true => {
+                let location = (volume as u32) << POSITION_BITS | position;
+                Some(TrackId((location as u64) << 32 | name_hash as u64))
+            }
+            false => None,
+        }
+    }
+
+    /// Volume the track is on
+    pub fn volume(self) -> u8 {
+        (self.0 >> (32 + POSITION_BITS)) as u8
+    }
+
+    /// Position of the track in the index of its volume when the identifier was taken
+    pub fn position(self) -> u32 {
+        (self.0 >> 32) as u32 & (MAX_VOLUME_TRACKS - 1)
+    }
+
+    /// Hash of the name of the track, the same as
+    /// [`TrackIdentity::path_hash`](crate::TrackIdentity::path_hash) of its path
+    pub fn name_hash(self) -> u32 {
+        self.0 as u32
+    }
+
+    /// The identifier as stored in a file
+    pub fn to_bits(self) -> u64 {
+        self.0
+    }
+
+    /// Identifier read back from a file
+    pub fn from_bits(bits: u64) -> Self {
+        TrackId(bits)
+    }
+}
+
+/// Indexes of up to `VOLUMES` volumes browsed and searched as one, e.g. the sounds built into the
+/// flash of the device next to the music on an SD card.
+///
+/// Each index is mounted under a volume number of the caller's choice, which [`TrackId`]s carry.
+/// The indexes share one source type, [`EitherSource`](crate::EitherSource) combines two kinds of
+/// storage.
+pub struct Library<S: AudioSource, const VOLUMES: usize> {
+    volumes: Vec<(u8, Index<S>), VOLUMES>,
+}
+
+impl<S: AudioSource, const VOLUMES: usize> Default for Library<S, VOLUMES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: AudioSource, const VOLUMES: usize> Library<S, VOLUMES> {
+    /// Library without volumes
+    pub fn new() -> Self {
+        Library {
+            volumes: Vec::new(),
+        }
+    }
+
+    /// Add the entries of `index` as volume `volume`.
+    ///
+    /// Gives `index` back if `VOLUMES` indexes are mounted already or `volume` is taken.
+    pub fn mount(&mut self, volume: u8, index: Index<S>) -> Result<(), Index<S>> {
+        if self.slot(volume).is_some() {
+            return Err(index);
+        }
+
+        self.volumes
+            .push((volume, index))
+            .map_err(|(_, index)| index)
+    }
+
+    /// Remove volume `volume`, e.g. when its card is pulled, and get its index back
+    pub fn unmount(&mut self, volume: u8) -> Option<Index<S>> {
+        let slot = self.slot(volume)?;
+
+        Some(self.volumes.remove(slot).1)
+    }
+
+    /// Number of entries of all volumes
+    pub fn len(&self) -> u32 {
+        self.volumes.iter().map(|(_, index)| index.len()).sum()
+    }
+
+    /// True if no volume holds entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read the entry of `track`, `None` if its volume isn't mounted or no longer has the track.
+    ///
+    /// Looks at the position the track had first and searches the volume by name if another
+    /// track is there, the index having been built again since.
+    pub fn get(&mut self, track: TrackId) -> Result<Option<IndexEntry>, Error<S::Error>> {
+        Ok(self.find(track)?.map(|(_, entry)| entry))
+    }
+
+    /// Same as [`Library::get`], with the identifier of the track at its current position, to
+    /// store instead of `track` once the index was built again
+    pub fn find(
+        &mut self,
+        track: TrackId,
+    ) -> Result<Option<(TrackId, IndexEntry)>, Error<S::Error>> {
+        let index = match self.slot(track.volume()) {
+            Some(slot) => &mut self.volumes[slot].1,
+            None => return Ok(None),
+        };
+
+        let matches = |entry: &IndexEntry| path_hash(entry.name()) == track.name_hash();
+
+        if let Some(entry) = index.get(track.position())?.filter(matches) {
+            return Ok(Some((track, entry)));
+        }
+
+        for position in 0..index.len() {
+            if let Some(entry) = index.get(position)?.filter(matches) {
+                let found = TrackId::from_hash(track.volume(), position, track.name_hash());
+                return Ok(found.map(|found| (found, entry)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Number of entries of all volumes passing the filters of `query`
+    pub fn count(&mut self, query: &Query) -> Result<u32, Error<S::Error>> {
+        count(self, query)
+    }
+
+    /// Same as [`Index::page_after`] over the entries of all volumes
+    pub fn page_after<const N: usize>(
+        &mut self,
+        query: &Query,
+        after: Option<TrackId>,
+    ) -> Result<Page<N, TrackId>, Error<S::Error>> {
+        collect_page(self, query, after, Ordering::Greater)
+    }
+
+    /// Same as [`Index::page_before`] over the entries of all volumes
+    pub fn page_before<const N: usize>(
+        &mut self,
+        query: &Query,
+        before: Option<TrackId>,
+    ) -> Result<Page<N, TrackId>, Error<S::Error>> {
+        collect_page(self, query, before, Ordering::Less)
+    }
+
+    /// Same as [`Index::page`] over the entries of all volumes
+    pub fn page<const N: usize>(
+        &mut self,
+        query: &Query,
+        page: u32,
+    ) -> Result<Page<N, TrackId>, Error<S::Error>> {
+        nth_page(self, query, page)
+    }
+
+    /// Same as [`Index::search`] over the entries of all volumes, in the order they were mounted
+    pub fn search<'l, 't>(
+        &'l mut self,
+        text: &'t str,
+        mode: SearchMode,
+        limit: usize,
+    ) -> Search<'l, 't, Self> {
+        Search::new(self, text, mode, limit)
+    }
+
+    fn slot(&self, volume: u8) -> Option<usize> {
+        self.volumes
+            .iter()
+            .position(|(number, _)| *number == volume)
+    }
+}
+
+impl<S: AudioSource, const VOLUMES: usize> Catalog for Library<S, VOLUMES> {
+    type Key = TrackId;
+    type Error = Error<S::Error>;
+
+    fn entry(&mut self, key: TrackId) -> Result<Option<IndexEntry>, Self::Error> {
+        self.get(key)
+    }
+
+    fn next_key(&mut self, key: Option<TrackId>) -> Result<Option<TrackId>, Self::Error> {
+        let (slot, mut position) = match key {
+            Some(key) => match self.slot(key.volume()) {
+                Some(slot) => (slot, key.position() + 1),
+                None => return Ok(None),
+            },
+            None => (0, 0),
+        };
+
+        // the rest of the volume, then the entries of the next volumes
+        for (volume, index) in self.volumes.iter_mut().skip(slot) {
+            if let Some(entry) = index.get(position)? {
+                return Ok(TrackId::new(*volume, position, entry.name()));
+            }
+
+            position = 0;
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_file::FileFormat;
+    use crate::index::IndexWriter;
+    use crate::library::SortKey;
+    use crate::metadata::to_string;
+    use crate::sink::SliceSink;
+    use crate::source::SliceSource;
+
+    fn index<'a>(out: &'a mut [u8], tracks: &[(&str, &str)]) -> Index<SliceSource<'a>> {
+        let mut writer = IndexWriter::new(SliceSink::new(out)).unwrap();
+
+        for (name, title) in tracks.iter() {
+            let entry = IndexEntry {
+                name: to_string(name.as_bytes()),
+                format: FileFormat::Wav,
+                num_channels: 1,
+                sample_rate: 16_000,
+                total_frames: 0,
+                title: to_string(title.as_bytes()),
+                artist: to_string(b""),
+                album: to_string(b""),
+                genre: to_string(b""),
+                date: to_string(b""),
+            };
+
+            writer.push(&entry).unwrap();
+        }
+
+        let len = writer.into_inner().written().len();
+        Index::new(SliceSource::new(&out[..len])).unwrap()
+    }
+
+    #[test]
+    fn should_pack_volume_and_position() {
+        let id = TrackId::new(7, 1234, "BEEP.WAV").unwrap();
+
+        assert_eq!((id.volume(), id.position()), (7, 1234));
+        assert_eq!(id.name_hash(), path_hash("beep.wav"));
+        assert_eq!(TrackId::from_bits(id.to_bits()), id);
+        assert_eq!(TrackId::new(0, MAX_VOLUME_TRACKS, "BEEP.WAV"), None);
+    }
+
+    #[test]
+    fn should_browse_several_volumes_as_one() {
+        let (mut flash, mut card, mut empty) = ([0; 1024], [0; 1024], [0; 64]);
+        let mut library: Library<_, 3> = Library::new();
+
+        let flash = index(&mut flash, &[("BEEP.WAV", "Beep"), ("BOOT.WAV", "Boot")]);
+        let card = index(&mut card, &[("A.WAV", "Alarm"), ("B.WAV", "Bells")]);
+        library.mount(1, flash).ok().unwrap();
+        library.mount(0, index(&mut empty, &[])).ok().unwrap();
+        library.mount(9, card).ok().unwrap();
+        assert_eq!(library.len(), 4);
+
+        let query = Query::new(SortKey::Title);
+        let page = library.page_after::<3>(&query, None).unwrap();
+        let ids: std::vec::Vec<_> = page
+            .iter()
+            .map(|(id, _)| (id.volume(), id.position()))
+            .collect();
+        assert_eq!(ids, [(9, 0), (1, 0), (9, 1)]);
+
+        let rest = library.page_after::<3>(&query, Some(page[2].0)).unwrap();
+        assert_eq!(rest[0].1.name(), "BOOT.WAV");
+
+        let found: std::vec::Vec<_> = library
+            .search("b", SearchMode::Prefix, 8)
+            .map(|found| found.unwrap().0)
+            .collect();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[2], TrackId::new(9, 1, "B.WAV").unwrap());
+
+        let card = library.unmount(9).unwrap();
+        assert_eq!(card.len(), 2);
+        assert_eq!(library.get(found[2]).unwrap(), None);
+        assert_eq!(library.count(&query).unwrap(), 2);
+    }
+
+    #[test]
+    fn should_keep_track_ids_when_the_index_is_built_again() {
+        let (mut before, mut after) = ([0; 1024], [0; 1024]);
+        let mut library: Library<_, 1> = Library::new();
+
+        library
+            .mount(
+                3,
+                index(&mut before, &[("A.WAV", "Alarm"), ("B.WAV", "Bells")]),
+            )
+            .ok()
+            .unwrap();
+
+        let bells = library.search("bells", SearchMode::Prefix, 1).next();
+        let bells = bells.unwrap().unwrap().0;
+        assert_eq!(bells.position(), 1);
+
+        // a track added in front, the old identifier finds the entry at its new position
+        let tracks = [("0.WAV", "Zero"), ("A.WAV", "Alarm"), ("B.WAV", "Bells")];
+        library.unmount(3).unwrap();
+        library.mount(3, index(&mut after, &tracks)).ok().unwrap();
+
+        assert_eq!(library.get(bells).unwrap().unwrap().name(), "B.WAV");
+        let (moved, _) = library.find(bells).unwrap().unwrap();
+        assert_eq!((moved.volume(), moved.position()), (3, 2));
+        assert_eq!(moved.name_hash(), bells.name_hash());
+
+        let gone = TrackId::new(3, 1, "C.WAV").unwrap();
+        assert_eq!(library.get(gone).unwrap(), None);
+    }
+}
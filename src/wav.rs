@@ -1,14 +1,41 @@
-use crate::chunk::{parse_chunks, Chunk, ChunkTag};
+use crate::adpcm::{decode_group, ImaState, MAX_ADPCM_CHANNELS};
+use crate::aiff;
 use crate::error::Error;
-use crate::fmt::Fmt;
-use embedded_sdmmc::{BlockDevice, File, TimeSource};
+use crate::fixed::{f32_to_i16, to_q15};
+use crate::fmt::{AudioCodec, Fmt, FMT_MAX_LEN};
+use crate::g711::{A_LAW_TABLE, MU_LAW_TABLE};
+use crate::metadata::{ListChunkTag, Metadata, INFO};
+use crate::riff::{ds64_data_size, parse_chunks, walk_chunks, Chunk, ChunkTag, ChunkWalker};
+use crate::source::{AudioSource, BufferedSource, HybridSource, SliceSource};
+use crate::sync::{Clock, OpenTiming, SyncStart};
+use crate::timestamp::{Stamped, Timestamp};
+use crate::trigger::{Trigger, Triggers};
 use heapless::Vec;
 
-pub(crate) const HEADER_SIZE: usize = 44;
-pub(crate) const MAX_CHUNKS: usize = 20;
+/// Chunks listed in [`Wav::chunks`], further chunks are skipped but still found by walking the file
+pub const MAX_CHUNKS: usize = 20;
+/// Bytes read from the source at a time by [`Wav::next_n`]
+const READ_BUF_LEN: usize = 192;
+
+// every read ends on a sample boundary for 8, 16, 24 and 32 bit samples
+const _: () = assert!(
+    READ_BUF_LEN.is_multiple_of(12),
+    "READ_BUF_LEN must hold whole samples of every bit depth"
+);
+
+/// Compile time checks of the buffer length `NUM` of the bulk readers
+pub(crate) struct BufferLen<const NUM: usize>;
+
+impl<const NUM: usize> BufferLen<NUM> {
+    /// Fails the build for a buffer that can't hold a single sample. Samples of every bit depth
+    /// are widened into their own buffer type, so any other length suits them all
+    pub(crate) const NOT_EMPTY: () = assert!(NUM > 0, "NUM must hold at least one sample");
+}
+/// Root chunk of IFF files such as AIFF
+const FORM: &[u8] = b"FORM";
 
 /// Enum to hold samples for different bit depths
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Data {
     /// 8 bit audio
     BitDepth8(u8),
@@ -16,10 +43,74 @@ pub enum Data {
     BitDepth16(i16),
     /// 24 bit audio
     BitDepth24(i32),
+    /// 32 bit IEEE float audio, nominally between -1.0 and 1.0
+    Float32(f32),
+}
+
+/// Sign extend a little endian 24 bit sample
+fn i24_from_le_bytes(bytes: [u8; 3]) -> i32 {
+    let sign = bytes[2] >> 7;
+    let sign_byte = if sign == 1 { 0xff } else { 0x0 };
+
+    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], sign_byte])
+}
+
+/// Sign extend a big endian 24 bit sample
+fn i24_from_be_bytes(bytes: [u8; 3]) -> i32 {
+    i24_from_le_bytes([bytes[2], bytes[1], bytes[0]])
+}
+
+impl Data {
+    /// Decode a single sample in the given format, little endian unless the codec says otherwise
+    pub(crate) fn from_bytes(fmt: &Fmt, bytes: &[u8]) -> Result<Self, Error> {
+        match (fmt.codec, fmt.bit_depth, bytes) {
+            (AudioCodec::Pcm, 8, [b0, ..]) => Ok(Data::BitDepth8(*b0)),
+            (AudioCodec::Pcm, 16, [b0, b1, ..]) => {
+                Ok(Data::BitDepth16(i16::from_le_bytes([*b0, *b1])))
+            }
+            (AudioCodec::Pcm, 24, [b0, b1, b2, ..]) => {
+                Ok(Data::BitDepth24(i24_from_le_bytes([*b0, *b1, *b2])))
+            }
+            (AudioCodec::IeeeFloat, 32, [b0, b1, b2, b3, ..]) => {
+                Ok(Data::Float32(f32::from_le_bytes([*b0, *b1, *b2, *b3])))
+            }
+            (AudioCodec::PcmBigEndian, 8, [b0, ..]) => Ok(Data::BitDepth8(*b0 ^ 0x80)),
+            (AudioCodec::PcmBigEndian, 16, [b0, b1, ..]) => {
+                Ok(Data::BitDepth16(i16::from_be_bytes([*b0, *b1])))
+            }
+            (AudioCodec::PcmBigEndian, 24, [b0, b1, b2, ..]) => {
+                Ok(Data::BitDepth24(i24_from_be_bytes([*b0, *b1, *b2])))
+            }
+            (AudioCodec::ALaw, 8, [b0, ..]) => Ok(Data::BitDepth16(A_LAW_TABLE[*b0 as usize])),
+            (AudioCodec::MuLaw, 8, [b0, ..]) => Ok(Data::BitDepth16(MU_LAW_TABLE[*b0 as usize])),
+            (AudioCodec::Pcm, 8, _)
+            | (AudioCodec::Pcm, 16, _)
+            | (AudioCodec::Pcm, 24, _)
+            | (AudioCodec::IeeeFloat, 32, _)
+            | (AudioCodec::PcmBigEndian, 8, _)
+            | (AudioCodec::PcmBigEndian, 16, _)
+            | (AudioCodec::PcmBigEndian, 24, _)
+            | (AudioCodec::ALaw, 8, _)
+            | (AudioCodec::MuLaw, 8, _) => Err(Error::CantParseSliceInto),
+            _ => Err(Error::UnsupportedBitDepth(fmt.bit_depth)),
+        }
+    }
+
+    /// Sample value widened to an `i32`, 8 bit samples stay unsigned and
+    /// float samples are scaled to the full 32 bit range
+    #[cfg(any(test, feature = "std"))]
+    pub(crate) fn as_i32(&self) -> i32 {
+        match *self {
+            Data::BitDepth8(sample) => sample as i32,
+            Data::BitDepth16(sample) => sample as i32,
+            Data::BitDepth24(sample) => sample,
+            Data::Float32(sample) => (sample.clamp(-1.0, 1.0) as f64 * i32::MAX as f64) as i32,
+        }
+    }
 }
 
 /// Enum to hold samples for different bit depths
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataBulk<const NUM: usize> {
     /// 8 bit audio
     BitDepth8(Vec<u8, NUM>),
@@ -27,71 +118,400 @@ pub enum DataBulk<const NUM: usize> {
     BitDepth16(Vec<i16, NUM>),
     /// 24 bit audio
     BitDepth24(Vec<i32, NUM>),
+    /// 32 bit IEEE float audio, nominally between -1.0 and 1.0
+    Float32(Vec<f32, NUM>),
+}
+
+impl<const NUM: usize> DataBulk<NUM> {
+    /// Empty buffer for samples in the given format
+    pub(crate) fn with_fmt(fmt: &Fmt) -> Result<Self, Error> {
+        match (fmt.codec, fmt.bit_depth) {
+            (AudioCodec::Pcm, 8) | (AudioCodec::PcmBigEndian, 8) => {
+                Ok(DataBulk::BitDepth8(Vec::new()))
+            }
+            (AudioCodec::Pcm, 16)
+            | (AudioCodec::PcmBigEndian, 16)
+            | (AudioCodec::ALaw, 8)
+            | (AudioCodec::MuLaw, 8) => Ok(DataBulk::BitDepth16(Vec::new())),
+            (AudioCodec::Pcm, 24) | (AudioCodec::PcmBigEndian, 24) => {
+                Ok(DataBulk::BitDepth24(Vec::new()))
+            }
+            (AudioCodec::IeeeFloat, 32) => Ok(DataBulk::Float32(Vec::new())),
+            _ => Err(Error::UnsupportedBitDepth(fmt.bit_depth)),
+        }
+    }
+
+    /// Buffer filled with as many whole frames of silence in the given format as fit in `NUM`
+    pub(crate) fn silence(fmt: &Fmt) -> Result<Self, Error> {
+        let mut bulk = DataBulk::with_fmt(fmt)?;
+        let channels = (fmt.num_channels as usize).max(1);
+        let len = NUM / channels * channels;
+
+        // resizing up to the capacity can't fail
+        let _ = match &mut bulk {
+            DataBulk::BitDepth8(samples) => samples.resize(len, 128),
+            DataBulk::BitDepth16(samples) => samples.resize(len, 0),
+            DataBulk::BitDepth24(samples) => samples.resize(len, 0),
+            DataBulk::Float32(samples) => samples.resize(len, 0.0),
+        };
+
+        Ok(bulk)
+    }
+
+    /// Call `f` with every sample scaled to 16 bit, 8 bit samples are made signed
+    pub(crate) fn for_each_i16(&self, mut f: impl FnMut(i16)) {
+        match self {
+            DataBulk::BitDepth8(samples) => {
+                samples.iter().for_each(|&s| f(((s as i16) - 128) << 8))
+            }
+            DataBulk::BitDepth16(samples) => samples.iter().for_each(|&s| f(s)),
+            DataBulk::BitDepth24(samples) => samples.iter().for_each(|&s| f(to_q15(s, 24))),
+            DataBulk::Float32(samples) => samples.iter().for_each(|&s| f(f32_to_i16(s))),
+        }
+    }
+
+    /// Decode whole samples in the given format from `bytes` until the buffer is full, returns the
+    /// number of bytes consumed. Samples are little endian unless the codec says otherwise
+    pub(crate) fn extend_from_le_bytes(&mut self, fmt: &Fmt, bytes: &[u8]) -> usize {
+        fn extend<T, const NUM: usize>(
+            samples: &mut Vec<T, NUM>,
+            bytes: &[u8],
+            size: usize,
+            decode: impl Fn(&[u8]) -> T,
+        ) -> usize {
+            let count = (bytes.len() / size).min(NUM - samples.len());
+
+            for b in bytes.chunks_exact(size).take(count) {
+                // can't fail, count is bounded by the remaining capacity
+                let _ = samples.push(decode(b));
+            }
+
+            count * size
+        }
+
+        let big_endian = fmt.codec == AudioCodec::PcmBigEndian;
+
+        match self {
+            DataBulk::BitDepth8(samples) if big_endian => {
+                extend(samples, bytes, 1, |b| b[0] ^ 0x80)
+            }
+            DataBulk::BitDepth8(samples) => extend(samples, bytes, 1, |b| b[0]),
+            DataBulk::BitDepth16(samples) if big_endian => {
+                extend(samples, bytes, 2, |b| i16::from_be_bytes([b[0], b[1]]))
+            }
+            DataBulk::BitDepth16(samples) if fmt.codec == AudioCodec::ALaw => {
+                extend(samples, bytes, 1, |b| A_LAW_TABLE[b[0] as usize])
+            }
+            DataBulk::BitDepth16(samples) if fmt.codec == AudioCodec::MuLaw => {
+                extend(samples, bytes, 1, |b| MU_LAW_TABLE[b[0] as usize])
+            }
+            DataBulk::BitDepth16(samples) => {
+                extend(samples, bytes, 2, |b| i16::from_le_bytes([b[0], b[1]]))
+            }
+            DataBulk::BitDepth24(samples) if big_endian => {
+                extend(samples, bytes, 3, |b| i24_from_be_bytes([b[0], b[1], b[2]]))
+            }
+            DataBulk::BitDepth24(samples) => {
+                extend(samples, bytes, 3, |b| i24_from_le_bytes([b[0], b[1], b[2]]))
+            }
+            DataBulk::Float32(samples) => extend(samples, bytes, 4, |b| {
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            }),
+        }
+    }
+}
+
+/// Chunks found in the leading bytes of a WAV file
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Contains data from the fmt chunk
+    pub fmt: Fmt,
+    /// Location of the audio sample data
+    pub data: Chunk,
+    /// Contains raw chunk data that is either unimplemented or unknown
+    pub chunks: Vec<Chunk, MAX_CHUNKS>,
+}
+
+/// Parse the fmt chunk, the data chunk and any remaining chunks out of the leading bytes of a file.
+///
+/// AIFF files are recognized by their `FORM` header, their `COMM` and `SSND` chunks are reported as
+/// fmt and data. Does no IO and never panics, whatever the input, which makes it a suitable fuzzing
+/// target.
+pub fn parse_header_bytes(bytes: &[u8]) -> Result<Header, Error> {
+    if bytes.starts_with(FORM) {
+        return aiff::scan_header(&mut SliceSource::new(bytes), bytes);
+    }
+
+    let mut fmt = None;
+    let mut data = None;
+    let mut chunks = Vec::new();
+
+    walk_chunks(bytes, |chunk| match chunk.id {
+        ChunkTag::Fmt => {
+            fmt.get_or_insert(chunk);
+        }
+        ChunkTag::Data => {
+            data.get_or_insert(chunk);
+        }
+        // chunks past MAX_CHUNKS are skipped
+        _ => {
+            let _ = chunks.push(chunk);
+        }
+    })?;
+
+    let fmt = fmt
+        .ok_or(Error::NoFmtChunkFound)
+        .and_then(|c| {
+            bytes
+                .get(c.start..c.end)
+                .ok_or(Error::CantParseChunk(ChunkTag::Fmt))
+        })
+        .and_then(Fmt::from_chunk)?;
+
+    let data = data.ok_or(Error::NoDataChunkFound)?;
+
+    Ok(Header { fmt, data, chunks })
+}
+
+/// Decode as many whole samples from the raw data chunk bytes as fit in `NUM`.
+///
+/// A trailing partial sample is ignored. Does no IO and never panics, whatever the input.
+pub fn decode_block<const NUM: usize>(fmt: &Fmt, bytes: &[u8]) -> Result<DataBulk<NUM>, Error> {
+    let mut bulk = DataBulk::with_fmt(fmt)?;
+    bulk.extend_from_le_bytes(fmt, bytes);
+
+    Ok(bulk)
+}
+
+/// Read until `buf` is full or the source ends, returning the number of bytes read
+pub(crate) fn read_full<S: AudioSource>(
+    source: &mut S,
+    buf: &mut [u8],
+) -> Result<usize, Error<S::Error>> {
+    let mut read = 0;
+
+    while read < buf.len() {
+        match source.read(&mut buf[read..]).map_err(Error::Source)? {
+            0 => break,
+            n => read += n,
+        }
+    }
+
+    Ok(read)
+}
+
+/// How [`Wav`] treats chunk sizes that don't fit the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sizes {
+    /// Cut the data chunk short to the end of the file
+    Clamp,
+    /// Reject a data chunk reaching past the end of the file
+    Strict,
+    /// Clamp the data chunk and look for the next chunk past a damaged header
+    Recover,
+}
+
+/// Chunks whose id marks the start of a chunk when recovering from a damaged size
+fn is_known_chunk(id: &[u8]) -> bool {
+    matches!(
+        id,
+        b"fmt " | b"data" | b"LIST" | b"fact" | b"cue " | b"smpl" | b"bext" | b"JUNK"
+    )
+}
+
+/// Offset of the first header of a known chunk at or after `from`, trying every byte
+fn find_known_chunk<S: AudioSource>(
+    source: &mut S,
+    from: usize,
+    length: usize,
+) -> Result<Option<usize>, Error<S::Error>> {
+    let mut buf = [0; 64];
+    let mut index = from;
+
+    while index + 8 <= length {
+        source.seek(index as u32).map_err(Error::Source)?;
+        let read = read_full(source, &mut buf)?;
+
+        if read < 8 {
+            break;
+        }
+
+        if let Some(at) = buf[..read].windows(8).position(|w| is_known_chunk(&w[..4])) {
+            return Ok(Some(index + at));
+        }
+
+        // the last 7 bytes may start a header cut in half
+        index += read - 7;
+    }
+
+    Ok(None)
+}
+
+/// Walk the chunk headers of `source` until both the fmt and the data chunk are found,
+/// reading only the chunk headers and the bodies of the fmt and ds64 chunks.
+///
+/// When recovering, a header with an id that isn't printable or a chunk reaching past the end of
+/// the file is taken for a damaged size, the walk resumes at the next known chunk id found past
+/// the start of the last intact chunk.
+fn scan_header<S: AudioSource>(source: &mut S, recover: bool) -> Result<Header, Error<S::Error>> {
+    let mut riff = [0; 12];
+    let read = read_full(source, &mut riff)?;
+
+    if riff[..read].starts_with(FORM) {
+        return aiff::scan_header(source, &riff[..read]);
+    }
+
+    parse_chunks(&riff[..read]).map_err(Error::widen)?;
+
+    let length = source.length() as usize;
+    let mut chunks = Vec::new();
+    let mut fmt = None;
+    let mut data = None;
+    let mut data_size = None;
+
+    let mut walker = ChunkWalker::new(length);
+    // where a search for the next chunk starts when recovering, always moving forward
+    let mut search_from = 12;
+
+    while fmt.is_none() || data.is_none() {
+        let chunk = match walker.next_chunk(source)? {
+            Some(chunk) => chunk
+                .with_ds64(walker.size_field(), data_size)
+                .map_err(Error::widen)?,
+            None => break,
+        };
+
+        walker.seek(chunk.next_offset());
+
+        let printable = chunk
+            .id
+            .to_bytes()
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || *b == b' ');
+
+        // the data chunk is checked by the caller
+        let fits = match chunk.id {
+            ChunkTag::Data => Ok(()),
+            _ => chunk.check_within(length),
+        };
+
+        if recover && (!printable || fits.is_err()) {
+            match find_known_chunk(source, search_from, length)? {
+                Some(found) => {
+                    walker.seek(found);
+                    search_from = found + 1;
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        fits.map_err(Error::widen)?;
+        search_from = search_from.max(chunk.start);
+
+        match chunk.id {
+            ChunkTag::Fmt => {
+                let mut body = [0; FMT_MAX_LEN];
+                let len = body.len().min(chunk.end - chunk.start);
+                let read = read_full(source, &mut body[..len])?;
+
+                fmt = Some(Fmt::from_chunk(&body[..read]).map_err(Error::widen)?);
+            }
+            ChunkTag::Data => data = Some(chunk),
+            ChunkTag::Ds64 => {
+                let mut body = [0; 16];
+                let read = read_full(source, &mut body)?;
+
+                data_size = Some(ds64_data_size(&body[..read]).map_err(Error::widen)?);
+                let _ = chunks.push(chunk);
+            }
+            // chunks past MAX_CHUNKS are skipped
+            _ => {
+                let _ = chunks.push(chunk);
+            }
+        }
+    }
+
+    Ok(Header {
+        fmt: fmt.ok_or(Error::NoFmtChunkFound)?,
+        data: data.ok_or(Error::NoDataChunkFound)?,
+        chunks,
+    })
+}
+
+/// True when `chunk` is a `LIST` chunk of the given list type, moves the read position of `source`
+fn is_list<S: AudioSource>(source: &mut S, chunk: &Chunk, list_type: [u8; 4]) -> bool {
+    let mut found = [0; 4];
+
+    chunk.id == ChunkTag::List
+        && source.seek(chunk.start as u32).is_ok()
+        && matches!(source.read(&mut found), Ok(4))
+        && found == list_type
 }
 
 /// Struct representing a WAV file
-pub struct Wav<
-    'a,
-    BD: BlockDevice,
-    TS: TimeSource,
-    const MAX_DIRS: usize,
-    const MAX_FILES: usize,
-    const MAX_VOLUMES: usize,
-> {
-    file: File<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    read: usize,
+pub struct Wav<S: AudioSource> {
+    pub(crate) source: S,
     /// The Audio sample data
     pub data: Chunk,
     /// Contains data from the fmt chunk / header part of the file
     pub fmt: Fmt,
-    /// Contains raw chunk data that is either unimplemented or unknown
+    /// Contains raw chunk data that is either unimplemented or unknown, up to [`MAX_CHUNKS`] of
+    /// them, [`Wav::for_each_chunk`] visits every chunk
     pub chunks: Vec<Chunk, MAX_CHUNKS>,
 }
 
-impl<
-        'a,
-        BD: BlockDevice,
-        TS: TimeSource,
-        const MAX_DIRS: usize,
-        const MAX_FILES: usize,
-        const MAX_VOLUMES: usize,
-    > Wav<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
-{
-    /// Create new [`Wav`] instance from a embedded_sdmmc File
-    ///
-    pub fn new(
-        mut file: File<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> Result<Self, Error> {
-        let mut bytes: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
-        let read = file.read(&mut bytes).unwrap();
-        assert!(bytes.len() == read);
-        let parsed_chunks = parse_chunks(&bytes)?;
-
-        let fmt = parsed_chunks
-            .iter()
-            .find(|c| c.id == ChunkTag::Fmt)
-            .ok_or(Error::NoFmtChunkFound)
-            .and_then(|c| {
-                let (start, end) = (c.start, c.end);
-                Fmt::from_chunk(&bytes[start..end])
-            })?;
-
-        let data = parsed_chunks
-            .iter()
-            .find(|c| c.id == ChunkTag::Data)
-            .ok_or(Error::NoDataChunkFound)?
-            .clone();
+impl<S: AudioSource> Wav<S> {
+    /// Create new [`Wav`] instance from an [`AudioSource`], such as an embedded_sdmmc File
+    ///
+    /// The chunk headers are read one at a time until the data chunk is found, so chunks such as
+    /// `JUNK`, `bext` or a large `LIST` in front of the samples are skipped without buffering them.
+    /// AIFF files are opened as well, their big endian samples are read like any other.
+    ///
+    /// Chunks in front of the samples that reach past the end of the source return
+    /// [`Error::Truncated`]. The data chunk of a partially copied file is cut short to what is
+    /// present so it still plays, use [`Wav::new_strict`] to reject it instead.
+    pub fn new(source: S) -> Result<Self, Error<S::Error>> {
+        Self::open(source, Sizes::Clamp)
+    }
+
+    /// Same as [`Wav::new`], but a data chunk reaching past the end of the source returns
+    /// [`Error::Truncated`] as well
+    pub fn new_strict(source: S) -> Result<Self, Error<S::Error>> {
+        Self::open(source, Sizes::Strict)
+    }
+
+    /// Same as [`Wav::new`] for damaged files, such as those read from a scratched or failing
+    /// card.
+    ///
+    /// A chunk header whose id isn't printable, or whose size reaches past the end of the file,
+    /// is taken for a corrupted size: the file is searched byte by byte for the next known chunk
+    /// id, e.g. `data`, and parsing resumes there. Chunks skipped this way are not listed in
+    /// [`Wav::chunks`].
+    pub fn new_recovering(source: S) -> Result<Self, Error<S::Error>> {
+        Self::open(source, Sizes::Recover)
+    }
+
+    fn open(mut source: S, sizes: Sizes) -> Result<Self, Error<S::Error>> {
+        let Header { fmt, data, chunks } = scan_header(&mut source, sizes == Sizes::Recover)?;
+        let length = source.length() as usize;
+
+        if sizes == Sizes::Strict {
+            data.check_within(length).map_err(Error::widen)?;
+        }
 
-        let chunks = parsed_chunks
-            .into_iter()
-            .filter(|c| c.id != ChunkTag::Data && c.id != ChunkTag::Fmt)
-            .collect();
+        // an AIFF sound data offset may point past the end
+        let end = data.end.min(length);
+        let data = Chunk {
+            start: data.start.min(end),
+            end,
+            ..data
+        };
 
-        file.seek_from_start(HEADER_SIZE as u32 + 1).unwrap();
+        source.seek(data.start as u32).map_err(Error::Source)?;
 
         let wave = Wav {
-            file,
-            read: HEADER_SIZE,
+            source,
             data,
             fmt,
             chunks,
@@ -100,75 +520,1232 @@ impl<
         Ok(wave)
     }
 
+    /// Same as [`Wav::new`], staging reads through a [`BufferedSource`] so reading a sample or a
+    /// frame at a time doesn't turn into a storage read for every few bytes
+    pub fn new_buffered(source: S) -> Result<Wav<BufferedSource<S>>, Error<S::Error>> {
+        Wav::new(BufferedSource::new(source))
+    }
+
+    /// True once the read position reached the end of the sample data
     pub fn is_end(&self) -> bool {
-        self.file.offset() == self.file.length()
+        self.source.offset() as usize >= self.data_end()
+    }
+
+    /// Byte offset the sample data ends at, the data chunk cut short to the length of the source
+    pub(crate) fn data_end(&self) -> usize {
+        self.data.end.min(self.source.length() as usize)
+    }
+
+    /// Read a single sample, [`Error::EndOfData`] once no whole sample is left
+    pub fn next(&mut self) -> Result<Data, Error<S::Error>> {
+        let bytes_per_sample = (self.fmt.bit_depth / 8) as usize;
+        let mut buf: [u8; 4] = [0; 4];
+
+        if bytes_per_sample == 0 || bytes_per_sample > buf.len() {
+            return Err(Error::UnsupportedBitDepth(self.fmt.bit_depth));
+        }
+
+        let left = self
+            .data_end()
+            .saturating_sub(self.source.offset() as usize);
+        let buf = &mut buf[..bytes_per_sample];
+
+        if left < bytes_per_sample || read_full(&mut self.source, buf)? != bytes_per_sample {
+            return Err(Error::EndOfData);
+        }
+
+        Data::from_bytes(&self.fmt, buf).map_err(Error::widen)
+    }
+
+    /// Read one sample for every channel, whatever the channel count
+    pub fn next_frame<const MAX_CHANNELS: usize>(
+        &mut self,
+    ) -> Result<Vec<Data, MAX_CHANNELS>, Error<S::Error>> {
+        let channels = self.fmt.num_channels;
+
+        if channels as usize > MAX_CHANNELS {
+            return Err(Error::TooManyChannels(channels));
+        }
+
+        let mut frame = Vec::new();
+
+        for _ in 0..channels {
+            // can't fail, the channel count is bounded by the capacity
+            let _ = frame.push(self.next()?);
+        }
+
+        Ok(frame)
+    }
+
+    /// Read the next `NUM` samples rounded down to whole frames, fewer when the end of the data chunk is reached.
+    ///
+    /// Reads stop at the data chunk boundary, so chunks following the samples such as `LIST` or
+    /// `id3 ` are never played as audio. Every buffer starts on a frame boundary, so channel `c` of frame `f` is always at index
+    /// `f * num_channels + c`, for any channel count.
+    ///
+    /// A `NUM` of zero is rejected at build time:
+    ///
+    /// ```compile_fail
+    /// let bytes = include_bytes!("../test_files/stereo_16_48000.wav");
+    /// let mut wav = audio_parser::Wav::from_bytes(bytes).unwrap();
+    ///
+    /// wav.next_n::<0>();
+    /// ```
+    pub fn next_n<const NUM: usize>(&mut self) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        self.next_frames(usize::MAX)
+    }
+
+    /// Same as [`Wav::next_n`], reading at most `max_frames` frames
+    pub(crate) fn next_frames<const NUM: usize>(
+        &mut self,
+        max_frames: usize,
+    ) -> Result<DataBulk<NUM>, Error<S::Error>> {
+        let () = BufferLen::<NUM>::NOT_EMPTY;
+
+        let mut bulk = DataBulk::with_fmt(&self.fmt).map_err(Error::widen)?;
+        let bytes_per_sample = (self.fmt.bit_depth / 8) as usize;
+        let channels = (self.fmt.num_channels as usize).max(1);
+        if NUM < channels {
+            return Err(Error::BufferTooSmall(self.fmt.block_align()));
+        }
+
+        let samples = (NUM / channels).min(max_frames).min(self.frames_left()) * channels;
+
+        let mut buf = [0; READ_BUF_LEN];
+
+        while bulk.len() < samples {
+            let wanted = ((samples - bulk.len()) * bytes_per_sample).min(buf.len());
+            let read = self
+                .source
+                .read(&mut buf[..wanted])
+                .map_err(Error::Source)?;
+            let consumed = bulk.extend_from_le_bytes(&self.fmt, &buf[..read]);
+
+            if consumed == 0 {
+                break;
+            }
+
+            // step back over a sample that was only partially read
+            if consumed < read {
+                let offset = self.source.offset() - (read - consumed) as u32;
+                self.source.seek(offset).map_err(Error::Source)?;
+            }
+        }
+
+        Ok(bulk)
+    }
+
+    /// Decode the next IMA ADPCM block into interleaved 16 bit PCM `out`, returns the number of samples written.
+    ///
+    /// `out` must hold [`Fmt::frames_per_block`] frames, a truncated final block decodes as far as it goes.
+    /// The block is streamed from the source a few bytes at a time, so no block sized buffer is needed.
+    pub fn next_adpcm_block(&mut self, out: &mut [i16]) -> Result<usize, Error<S::Error>> {
+        if self.fmt.codec != AudioCodec::ImaAdpcm {
+            return Err(Error::FormatMismatch);
+        }
+
+        let channels = self.fmt.num_channels as usize;
+        let needed = self.fmt.frames_per_block() * channels;
+
+        if out.len() < needed {
+            return Err(Error::BufferTooSmall(needed * 2));
+        }
+
+        let data_end = self.data_end();
+        let block_end = (self.source.offset() as usize + self.fmt.block_align()).min(data_end);
+
+        let mut buf = [0; 4 * MAX_ADPCM_CHANNELS];
+        let group = &mut buf[..4 * channels];
+
+        if self.source.offset() as usize + group.len() > block_end
+            || self.source.read(group).map_err(Error::Source)? != group.len()
+        {
+            return Ok(0);
+        }
+
+        let mut states = [ImaState::default(); MAX_ADPCM_CHANNELS];
+        let states = &mut states[..channels];
+
+        for ((state, header), out) in states
+            .iter_mut()
+            .zip(group.chunks_exact(4))
+            .zip(out.iter_mut())
+        {
+            *state = ImaState::from_header(header);
+            *out = state.predictor();
+        }
+
+        let mut written = channels;
+
+        while self.source.offset() as usize + group.len() <= block_end {
+            if self.source.read(group).map_err(Error::Source)? != group.len() {
+                break;
+            }
+
+            decode_group(states, group, &mut out[written..written + 8 * channels]);
+            written += 8 * channels;
+        }
+
+        // skip a trailing partial group so the next read starts on a block boundary
+        if (self.source.offset() as usize) < block_end {
+            self.source.seek(block_end as u32).map_err(Error::Source)?;
+        }
+
+        Ok(written)
+    }
+
+    /// Number of whole frames between the read position and the end of the data chunk
+    pub(crate) fn frames_left(&self) -> usize {
+        let left = self
+            .data_end()
+            .saturating_sub(self.source.offset() as usize);
+
+        left / self.fmt.block_align().max(1)
+    }
+
+    /// Byte offset of the read position within the data chunk
+    pub(crate) fn data_offset(&self) -> usize {
+        (self.source.offset() as usize).saturating_sub(self.data.start)
+    }
+
+    /// Move the read position to `offset` bytes into the data chunk, clamped to its end
+    pub(crate) fn seek_data(&mut self, offset: usize) -> Result<(), Error<S::Error>> {
+        let offset = offset.saturating_add(self.data.start).min(self.data.end);
+        self.source.seek(offset as u32).map_err(Error::Source)
+    }
+
+    /// Position of the next sample to be read
+    pub fn timestamp(&self) -> Timestamp {
+        let blocks = self.data_offset() / self.fmt.block_align().max(1);
+        let frames = blocks * self.fmt.frames_per_block();
+
+        Timestamp::from_frames(frames as u64, self.fmt.sample_rate)
+    }
+
+    /// Move the read position to frame `sample` of the data chunk, clamped to its end.
+    ///
+    /// Compressed files land on the start of the block holding the frame. Returns the position
+    /// actually reached.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<Timestamp, Error<S::Error>> {
+        let blocks = sample / self.fmt.frames_per_block().max(1) as u64;
+        let offset = blocks.saturating_mul(self.fmt.block_align() as u64);
+
+        self.seek_data(offset.min(usize::MAX as u64) as usize)?;
+
+        Ok(self.timestamp())
+    }
+
+    /// Move the read position to `millis` milliseconds into the sample data, see [`Wav::seek_to_sample`]
+    pub fn seek_to_millis(&mut self, millis: u32) -> Result<Timestamp, Error<S::Error>> {
+        self.seek_to_sample(millis as u64 * self.fmt.sample_rate as u64 / 1000)
+    }
+
+    /// Current playback position, the same as [`Wav::timestamp`]
+    pub fn position(&self) -> Timestamp {
+        self.timestamp()
+    }
+
+    /// Number of frames in the file, samples per channel.
+    ///
+    /// Derived from the size of the data chunk. Compressed formats such as IMA ADPCM pad their
+    /// last block, their length is taken from the `fact` chunk when the file has one. The read
+    /// position is left unchanged.
+    pub fn total_samples(&mut self) -> Result<u64, Error<S::Error>> {
+        let data_end = self.data_end();
+        let blocks = data_end.saturating_sub(self.data.start) / self.fmt.block_align().max(1);
+        let derived = (blocks * self.fmt.frames_per_block()) as u64;
+
+        // the fact chunk of PCM files is optional and often stale after editing
+        if self.fmt.codec != AudioCodec::ImaAdpcm {
+            return Ok(derived);
+        }
+
+        Ok(self.fact_frames()?.unwrap_or(derived))
+    }
+
+    /// Frame count of the `fact` chunk, `None` if the file has no complete one
+    fn fact_frames(&mut self) -> Result<Option<u64>, Error<S::Error>> {
+        let fact = match self.find_chunk(ChunkTag::Fact)? {
+            Some(fact) if fact.end - fact.start >= 4 => fact,
+            _ => return Ok(None),
+        };
+
+        let position = self.source.offset();
+        let mut count = [0; 4];
+
+        self.source.seek(fact.start as u32).map_err(Error::Source)?;
+        let read = read_full(&mut self.source, &mut count);
+        self.source.seek(position).map_err(Error::Source)?;
+
+        match read? {
+            4 => Ok(Some(u32::from_le_bytes(count) as u64)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Total length of the sample data, [`Wav::total_samples`] as a [`Timestamp`]
+    pub fn duration(&mut self) -> Result<Timestamp, Error<S::Error>> {
+        let frames = self.total_samples()?;
+
+        Ok(Timestamp::from_frames(frames, self.fmt.sample_rate))
+    }
+
+    /// Same as [`Wav::next_n`], tagging the buffer with the [`Timestamp`] of its first sample
+    pub fn next_n_stamped<const NUM: usize>(
+        &mut self,
+    ) -> Result<Stamped<DataBulk<NUM>>, Error<S::Error>> {
+        let timestamp = self.timestamp();
+        let data = self.next_n()?;
+
+        Ok(Stamped { timestamp, data })
+    }
+
+    /// Same as [`Wav::next_n_stamped`], calling `callback` for every trigger whose frame lies within the buffer
+    pub fn next_n_triggered<const NUM: usize, const N: usize, F: FnMut(&Trigger)>(
+        &mut self,
+        triggers: &Triggers<N>,
+        callback: F,
+    ) -> Result<Stamped<DataBulk<NUM>>, Error<S::Error>> {
+        let stamped = self.next_n_stamped()?;
+        let end = self.timestamp().frames;
+
+        triggers.fire(stamped.timestamp.frames, end, callback);
+
+        Ok(stamped)
+    }
+
+    /// Call `f` with the header of every chunk of the file in order, however many there are.
+    ///
+    /// The read position is left unchanged.
+    pub fn for_each_chunk<F: FnMut(&Chunk)>(&mut self, mut f: F) -> Result<(), Error<S::Error>> {
+        self.find_chunk_by(|_, chunk| {
+            f(chunk);
+            false
+        })?;
+
+        Ok(())
+    }
+
+    /// Copy the body of the first chunk tagged `tag` into `buf`, e.g. vendor calibration data kept
+    /// in a custom chunk, returning its length. `None` if the file has no such chunk.
+    ///
+    /// Returns [`Error::BufferTooSmall`] with the size of the chunk if it doesn't fit in `buf`. A
+    /// chunk cut short by the end of the file is read as far as it goes. The read position is left
+    /// unchanged.
+    pub fn read_chunk(
+        &mut self,
+        tag: [u8; 4],
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, Error<S::Error>> {
+        let chunk = match self.find_chunk(ChunkTag::from_bytes(&tag))? {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let len = chunk.end - chunk.start;
+        let buf = buf.get_mut(..len).ok_or(Error::BufferTooSmall(len))?;
+
+        let position = self.source.offset();
+        self.source
+            .seek(chunk.start as u32)
+            .map_err(Error::Source)?;
+
+        let read = read_full(&mut self.source, buf);
+        self.source.seek(position).map_err(Error::Source)?;
+
+        read.map(Some)
+    }
+
+    /// Find the first chunk with the given tag anywhere in the file, the read position is left unchanged
+    pub(crate) fn find_chunk(&mut self, tag: ChunkTag) -> Result<Option<Chunk>, Error<S::Error>> {
+        self.find_chunk_by(|_, chunk| chunk.id == tag)
     }
 
-    pub fn next(&mut self) -> Result<Data, Error> {
-        assert!(!self.is_end());
-        self.read += 1;
+    /// Find the first `LIST` chunk of the given list type, e.g. `INFO` or `adtl`
+    pub(crate) fn find_list(
+        &mut self,
+        list_type: [u8; 4],
+    ) -> Result<Option<Chunk>, Error<S::Error>> {
+        self.find_chunk_by(|source, chunk| is_list(source, chunk, list_type))
+    }
+
+    /// Walk the chunk headers of the whole file until `predicate` matches a chunk,
+    /// the read position is left unchanged
+    pub(crate) fn find_chunk_by<F>(
+        &mut self,
+        predicate: F,
+    ) -> Result<Option<Chunk>, Error<S::Error>>
+    where
+        F: FnMut(&mut S, &Chunk) -> bool,
+    {
+        // skip the RIFF header and WAVE tag
+        self.find_chunk_from(12, predicate)
+    }
+
+    /// Walk the chunk headers from byte offset `index` on until `predicate` matches a chunk,
+    /// the read position is left unchanged
+    fn find_chunk_from<F>(
+        &mut self,
+        index: usize,
+        mut predicate: F,
+    ) -> Result<Option<Chunk>, Error<S::Error>>
+    where
+        F: FnMut(&mut S, &Chunk) -> bool,
+    {
+        let position = self.source.offset();
+        let mut walker = ChunkWalker::range(index, self.source.length() as usize);
+        let mut found = None;
 
-        match self.fmt.bit_depth {
-            8 => {
-                let mut buf: [u8; 1] = [0; 1];
-                assert!(self.file.read(&mut buf).unwrap() == 1);
-                Ok(Data::BitDepth8(buf[0]))
+        if self.fmt.codec == AudioCodec::PcmBigEndian {
+            walker = walker.big_endian();
+        }
+
+        while let Some(chunk) = walker.next_chunk(&mut self.source)? {
+            if predicate(&mut self.source, &chunk) {
+                found = Some(chunk);
+                break;
             }
-            16 => {
-                let mut buf: [u8; 2] = [0; 2];
-                assert!(self.file.read(&mut buf).unwrap() == 2);
-                Ok(Data::BitDepth16(i16::from_le_bytes([buf[0], buf[1]])))
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(found)
+    }
+
+    /// Read the artist, title and other tags of the `LIST` `INFO` chunk, if the file has one.
+    ///
+    /// Tags missing from it are taken from the ID3v2 tag of an `id3 ` chunk, as written by some
+    /// Windows tools. Values longer than `MAX_STRING_LEN` bytes are cut short. The read position
+    /// is left unchanged.
+    pub fn metadata<const MAX_STRING_LEN: usize>(
+        &mut self,
+    ) -> Result<Metadata<MAX_STRING_LEN>, Error<S::Error>> {
+        let mut metadata = match self.find_list(INFO)? {
+            Some(list) => self.read_info(list)?,
+            None => Metadata::default(),
+        };
+
+        self.read_id3_chunk(&mut metadata)?;
+
+        Ok(metadata)
+    }
+
+    /// Read the chunks behind the data chunk, where many taggers append their `LIST` `INFO`, and
+    /// return the metadata found there.
+    ///
+    /// The chunks are added to [`Wav::chunks`], which otherwise only holds the chunks in front of
+    /// the samples. Only the trailing chunks are read, the read position is left unchanged.
+    pub fn load_trailing_metadata<const MAX_STRING_LEN: usize>(
+        &mut self,
+    ) -> Result<Metadata<MAX_STRING_LEN>, Error<S::Error>> {
+        let start = self
+            .data
+            .end
+            .saturating_add(self.data.end.saturating_sub(self.data.start) & 1);
+        let mut trailing: Vec<Chunk, MAX_CHUNKS> = Vec::new();
+        let mut info = None;
+
+        self.find_chunk_from(start, |source, chunk| {
+            if info.is_none() && is_list(source, chunk, INFO) {
+                info = Some(*chunk);
             }
-            24 => {
-                let mut buf: [u8; 3] = [0; 3];
-                assert!(self.file.read(&mut buf).unwrap() == 3);
 
-                let sign = buf[2] >> 7;
-                let sign_byte = if sign == 1 { 0xff } else { 0x0 };
+            let _ = trailing.push(*chunk);
 
-                Ok(Data::BitDepth24(i32::from_le_bytes([
-                    buf[0], buf[1], buf[2], sign_byte,
-                ])))
+            false
+        })?;
+
+        // chunks past MAX_CHUNKS are skipped
+        for chunk in trailing {
+            if !self.chunks.iter().any(|c| c.start == chunk.start) {
+                let _ = self.chunks.push(chunk);
             }
-            _ => Err(Error::UnsupportedBitDepth(self.fmt.bit_depth)),
         }
+
+        match info {
+            Some(list) => self.read_info(list),
+            None => Ok(Metadata::default()),
+        }
+    }
+
+    /// Read the entries of the `LIST` `INFO` chunk `list`, the read position is left unchanged
+    fn read_info<const MAX_STRING_LEN: usize>(
+        &mut self,
+        list: Chunk,
+    ) -> Result<Metadata<MAX_STRING_LEN>, Error<S::Error>> {
+        let mut metadata = Metadata::default();
+        let position = self.source.offset();
+        let end = list.end.min(self.source.length() as usize);
+        // skip the list type
+        let mut walker = ChunkWalker::range(list.start + 4, end);
+
+        while let Some(entry) = walker.next_chunk(&mut self.source)? {
+            let tag = ListChunkTag::from_bytes(&entry.id.to_bytes());
+
+            let mut value = [0; MAX_STRING_LEN];
+            let len = (entry.end.min(end) - entry.start).min(MAX_STRING_LEN);
+            let read = self.source.read(&mut value[..len]).map_err(Error::Source)?;
+
+            metadata.set(tag, &value[..read]);
+        }
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(metadata)
+    }
+
+    /// Read the markers of the `cue ` chunk, if the file has one, into a [`Triggers`] list.
+    ///
+    /// Each trigger fires at the frame of its marker with the cue point id, so passing the list to
+    /// [`Wav::next_n_triggered`] turns the embedded markers into events during playback.
+    pub fn cue_triggers<const N: usize>(&mut self) -> Result<Triggers<N>, Error<S::Error>> {
+        let mut triggers = Triggers::new();
+
+        self.for_each_cue_point(|cue| {
+            triggers
+                .schedule(cue.sample_offset as u64, cue.id)
+                .map_err(|_| Error::TooManyCuePoints)
+        })?;
+
+        Ok(triggers)
+    }
+
+    /// Schedule playback of this file to start at `start_micros` on a shared [`Clock`]
+    pub fn start_at(&self, start_micros: u64) -> SyncStart {
+        SyncStart::new(start_micros, self.fmt.sample_rate)
+    }
+
+    /// Number of frames read so far, i.e. the exact amount of samples per channel handed out
+    pub fn frames_played(&self) -> u64 {
+        self.timestamp().frames
+    }
+
+    /// Move the read position to the frame that should be playing now according to `sync`,
+    /// used to join a synchronized playback late or to correct accumulated drift
+    pub fn resync<C: Clock>(
+        &mut self,
+        sync: &SyncStart,
+        clock: &C,
+    ) -> Result<Timestamp, Error<S::Error>> {
+        let frames = sync.expected(clock).map(|t| t.frames).unwrap_or(0);
+
+        self.seek_to_sample(frames)
+    }
+
+    /// Read the whole data chunk into `buf` and return a [`Wav`] playing it from RAM.
+    ///
+    /// Meant for short clips such as UI sounds, so playing them never touches the storage.
+    /// The read position of `self` is left unchanged.
+    pub fn preload<'b>(
+        &mut self,
+        buf: &'b mut [u8],
+    ) -> Result<Wav<SliceSource<'b>>, Error<S::Error>> {
+        let end = self.data_end();
+        let len = end.saturating_sub(self.data.start);
+
+        if len > buf.len() {
+            return Err(Error::BufferTooSmall(len));
+        }
+
+        let position = self.source.offset();
+        self.source
+            .seek(self.data.start as u32)
+            .map_err(Error::Source)?;
+
+        let read = read_full(&mut self.source, &mut buf[..len])?;
+
+        self.source.seek(position).map_err(Error::Source)?;
+
+        Ok(Wav {
+            source: SliceSource::new(&buf[..read]),
+            data: Chunk {
+                id: ChunkTag::Data,
+                start: 0,
+                end: read,
+            },
+            fmt: self.fmt,
+            chunks: Vec::new(),
+        })
+    }
+
+    /// Create a new [`Wav`] and read its first buffer, reporting how long each step took.
+    ///
+    /// Used to verify and tune the time from a keypress to the first sound, the timings are
+    /// taken with the user supplied `clock`.
+    pub fn new_timed<C: Clock, const NUM: usize>(
+        source: S,
+        clock: &C,
+    ) -> Result<(Self, DataBulk<NUM>, OpenTiming), Error<S::Error>> {
+        let start = clock.now_micros();
+        let mut wav = Wav::new(source)?;
+        let opened = clock.now_micros();
+        let first = wav.next_n()?;
+        let filled = clock.now_micros();
+
+        let timing = OpenTiming {
+            header_micros: opened.saturating_sub(start),
+            first_buffer_micros: filled.saturating_sub(opened),
+        };
+
+        Ok((wav, first, timing))
+    }
+
+    /// Create a new [`Wav`] that pre-rolls the first `millis` milliseconds of audio into `buf`.
+    ///
+    /// Reading starts from RAM, so playback can begin right away on a button press while the rest
+    /// streams from `source` once the pre-roll runs out. The pre-roll is cut short to fit `buf`.
+    pub fn new_with_preroll<'b>(
+        source: S,
+        buf: &'b mut [u8],
+        millis: u32,
+    ) -> Result<Wav<HybridSource<'b, S>>, Error<S::Error>> {
+        let mut wav = Wav::new(source)?;
+
+        let block_align = wav.fmt.block_align().max(1);
+        let byte_rate = wav.fmt.sample_rate as u64 * block_align as u64;
+        let wanted = (byte_rate * millis as u64 / 1000) as usize;
+        let data_len = wav.data.end.saturating_sub(wav.data.start);
+
+        // keep whole frames in RAM so no sample is split between RAM and storage
+        let len = wanted.min(buf.len()).min(data_len) / block_align * block_align;
+        let read = read_full(&mut wav.source, &mut buf[..len])?;
+
+        wav.source
+            .seek(wav.data.start as u32)
+            .map_err(Error::Source)?;
+
+        Ok(Wav {
+            source: HybridSource::new(wav.source, &buf[..read], wav.data.start as u32),
+            data: wav.data,
+            fmt: wav.fmt,
+            chunks: wav.chunks,
+        })
+    }
+
+    /// Give back the source
+    pub fn destroy(self) -> S {
+        self.source
+    }
+}
+
+impl<'b> Wav<SliceSource<'b>> {
+    /// Create a new [`Wav`] playing from a byte slice, such as a sound effect baked into the
+    /// firmware with `include_bytes!`.
+    ///
+    /// The whole slice is scanned for chunks, so headers of any size are supported.
+    pub fn from_bytes(bytes: &'b [u8]) -> Result<Self, Error> {
+        let Header { fmt, data, chunks } = parse_header_bytes(bytes)?;
+        let mut source = SliceSource::new(bytes);
+
+        // the samples of a partially copied file still play
+        let end = data.end.min(bytes.len());
+        let data = Chunk {
+            start: data.start.min(end),
+            end,
+            ..data
+        };
+
+        // seeking a slice never fails
+        let _ = source.seek(data.start as u32);
+
+        Ok(Wav {
+            source,
+            data,
+            fmt,
+            chunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: [u8; 60] = [
+        0x52, 0x49, 0x46, 0x46, // RIFF
+        0x34, 0x00, 0x00, 0x00, // chunk size
+        0x57, 0x41, 0x56, 0x45, // WAVE
+        0x66, 0x6d, 0x74, 0x20, // fmt_
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x01, 0x00, // audio format
+        0x02, 0x00, // num channels
+        0x22, 0x56, 0x00, 0x00, // sample rate
+        0x88, 0x58, 0x01, 0x00, // byte rate
+        0x04, 0x00, // block align
+        0x10, 0x00, // bits per sample
+        0x64, 0x61, 0x74, 0x61, // data
+        0x10, 0x00, 0x00, 0x00, // chunk size
+        0x00, 0x00, 0x00, 0x00, // sample 1 L+R
+        0x24, 0x17, 0x1e, 0xf3, // sample 2 L+R
+        0x3c, 0x13, 0x3c, 0x14, // sample 3 L+R
+        0x16, 0xf9, 0x18, 0xf9, // sample 4 L+R
+    ];
+
+    #[test]
+    fn should_parse_header_bytes() {
+        let header = parse_header_bytes(&HEADER).unwrap();
+
+        assert_eq!(header.fmt.num_channels, 2);
+        assert_eq!(header.fmt.sample_rate, 22_050);
+        assert_eq!(header.fmt.bit_depth, 16);
+        assert_eq!((header.data.start, header.data.end), (44, 60));
+    }
+
+    #[test]
+    fn should_not_panic_on_truncated_or_corrupted_headers() {
+        for len in 0..HEADER.len() {
+            let _ = parse_header_bytes(&HEADER[..len]);
+        }
+
+        for i in 0..HEADER.len() {
+            let mut bytes = HEADER;
+            bytes[i] = 0xff;
+            let _ = parse_header_bytes(&bytes);
+        }
+    }
+
+    #[test]
+    fn should_report_chunks_past_the_end_of_the_file() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&HEADER[..36]);
+        bytes.extend_from_slice(b"JUNK");
+        bytes.extend_from_slice(&1000u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+
+        let truncated = || Error::Truncated {
+            expected: 36 + 8 + 1000,
+            found: 54,
+        };
+
+        assert_eq!(Wav::new(SliceSource::new(&bytes)).err(), Some(truncated()));
+        assert_eq!(Wav::from_bytes(&bytes).err(), Some(truncated()));
+    }
+
+    #[test]
+    fn should_read_custom_chunks() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&HEADER[..36]);
+        bytes.extend_from_slice(b"cal1\x05\0\0\0");
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 0]);
+        bytes.extend_from_slice(&HEADER[36..]);
+
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        let position = wav.source.offset();
+        let mut buf = [0; 8];
+
+        assert_eq!(wav.read_chunk(*b"cal1", &mut buf).unwrap(), Some(5));
+        assert_eq!(buf[..5], [1, 2, 3, 4, 5]);
+        assert_eq!(wav.source.offset(), position);
+
+        assert_eq!(wav.read_chunk(*b"cal2", &mut buf).unwrap(), None);
+        assert_eq!(
+            wav.read_chunk(*b"cal1", &mut buf[..4]),
+            Err(Error::BufferTooSmall(5))
+        );
+        assert_eq!(
+            wav.read_chunk(*b"fmt ", &mut buf[..4]).err(),
+            Some(Error::BufferTooSmall(16))
+        );
     }
 
-    pub fn next_n<const NUM: usize>(&mut self) -> Result<DataBulk<NUM>, Error> {
-        assert!(!self.is_end());
+    #[test]
+    fn should_skip_chunks_past_the_chunk_list() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&HEADER[..12]);
+
+        for _ in 0..MAX_CHUNKS + 10 {
+            bytes.extend_from_slice(b"PAD \x02\0\0\0\0\0");
+        }
+
+        bytes.extend_from_slice(&HEADER[12..]);
+
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.chunks.len(), MAX_CHUNKS);
+        assert_eq!(wav.next().unwrap(), Data::BitDepth16(0));
+
+        let mut count = 0;
+        wav.for_each_chunk(|_| count += 1).unwrap();
+        assert_eq!(count, MAX_CHUNKS + 12);
+
+        let wav = Wav::from_bytes(&bytes).unwrap();
+        assert_eq!(wav.chunks.len(), MAX_CHUNKS);
+        assert_eq!(wav.fmt.sample_rate, 22_050);
+    }
 
-        match self.fmt.bit_depth {
-            8 => {
-                self.read += NUM;
-                let mut buf: [u8; NUM] = [0; NUM];
-                self.file.read(&mut buf).unwrap();
-                Ok(DataBulk::BitDepth8(Vec::from_slice(&buf).unwrap()))
+    #[test]
+    fn should_recover_from_corrupted_chunk_sizes() {
+        // size past the end of the file, and too small landing in the middle of the chunk
+        for size in [0x00ff_0000u32, 2].iter() {
+            let mut bytes = std::vec::Vec::new();
+            bytes.extend_from_slice(&HEADER[..36]);
+            bytes.extend_from_slice(b"LIST");
+            bytes.extend_from_slice(&size.to_le_bytes());
+            bytes.extend_from_slice(&[0xfe; 10]);
+            bytes.extend_from_slice(&HEADER[36..]);
+
+            assert!(Wav::new(SliceSource::new(&bytes)).is_err());
+
+            let mut wav = Wav::new_recovering(SliceSource::new(&bytes)).unwrap();
+            assert_eq!(wav.fmt.sample_rate, 22_050);
+            assert_eq!((wav.data.start, wav.data.end), (62, 78));
+            assert_eq!(wav.next_n::<8>().unwrap().len(), 8);
+        }
+
+        // a corrupted data size is cut short to the file
+        let mut bytes = HEADER;
+        bytes[40..44].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let wav = Wav::new_recovering(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.data.end, HEADER.len());
+    }
+
+    #[test]
+    fn should_clamp_a_truncated_data_chunk() {
+        let bytes = &HEADER[..52];
+
+        let mut wav = Wav::new(SliceSource::new(bytes)).unwrap();
+        assert_eq!((wav.data.start, wav.data.end), (44, 52));
+        assert_eq!(wav.next_n::<8>().unwrap().len(), 4);
+        assert_eq!(wav.next(), Err(Error::EndOfData));
+
+        assert_eq!(Wav::from_bytes(bytes).unwrap().data.end, 52);
+        assert_eq!(
+            Wav::new_strict(SliceSource::new(bytes)).err(),
+            Some(Error::Truncated {
+                expected: 60,
+                found: 52
+            })
+        );
+        assert!(Wav::new_strict(SliceSource::new(&HEADER)).is_ok());
+    }
+
+    #[test]
+    fn should_skip_large_chunks_before_data() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(&HEADER[..36]);
+        bytes.extend_from_slice(b"JUNK");
+        bytes.extend_from_slice(&1001u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 1002]);
+        bytes.extend_from_slice(&HEADER[36..]);
+
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(wav.fmt.sample_rate, 22_050);
+        assert_eq!(wav.chunks.len(), 1);
+        assert_eq!(wav.data.start, 36 + 8 + 1002 + 8);
+        assert_eq!(wav.next().unwrap(), Data::BitDepth16(0));
+
+        let file = include_bytes!("../test_files/stereo_16_48000.wav");
+        let wav = Wav::new(SliceSource::new(file)).unwrap();
+
+        assert_eq!(wav.data.start, Wav::from_bytes(file).unwrap().data.start);
+    }
+
+    /// `HEADER` with `extension` appended to the fmt chunk
+    fn with_fmt_extension(extension: &[u8]) -> std::vec::Vec<u8> {
+        let mut bytes = HEADER[..36].to_vec();
+        bytes[16..20].copy_from_slice(&(16 + extension.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(extension);
+        bytes.extend_from_slice(&HEADER[36..]);
+
+        bytes
+    }
+
+    #[test]
+    fn should_parse_extended_fmt_chunks() {
+        // ffmpeg writes an empty extension
+        let bytes = with_fmt_extension(&[0, 0]);
+        let wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.fmt, Wav::from_bytes(&bytes).unwrap().fmt);
+        assert_eq!((wav.fmt.num_channels, wav.fmt.bit_depth), (2, 16));
+        assert_eq!(wav.data.start, 46);
+
+        // WAVE_FORMAT_EXTENSIBLE holding PCM
+        let mut extensible = [22, 0, 16, 0, 3, 0, 0, 0, 1, 0].to_vec();
+        extensible.extend_from_slice(&[0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xaa, 0, 0x38, 0x9b, 0x71]);
+        let mut bytes = with_fmt_extension(&extensible);
+        bytes[20..22].copy_from_slice(&0xfffeu16.to_le_bytes());
+
+        let wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.fmt.codec, AudioCodec::Pcm);
+        assert_eq!(wav.fmt, parse_header_bytes(&bytes).unwrap().fmt);
+
+        // cut off in cbSize, or shorter than the extension cbSize announces
+        for extension in [&[0][..], &[4, 0, 1, 2]].iter() {
+            let bytes = with_fmt_extension(extension);
+
+            assert_eq!(
+                Wav::new(SliceSource::new(&bytes)).err(),
+                Some(Error::CantParseChunk(ChunkTag::Fmt))
+            );
+        }
+    }
+
+    #[test]
+    fn should_open_rf64_files() {
+        let mut bytes = std::vec::Vec::new();
+        bytes.extend_from_slice(b"RF64");
+        bytes.extend_from_slice(&[0xff; 4]);
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"ds64");
+        bytes.extend_from_slice(&28u32.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&12u64.to_le_bytes());
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&HEADER[12..40]);
+        bytes.extend_from_slice(&[0xff; 4]);
+        bytes.extend_from_slice(&HEADER[44..]);
+
+        let wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(wav.fmt.num_channels, 2);
+        assert_eq!(wav.data.end - wav.data.start, 12);
+        assert_eq!(wav.chunks[0].id, ChunkTag::Ds64);
+
+        // data ending past 4 GiB can't be addressed by an AudioSource
+        bytes[28..36].copy_from_slice(&(u32::MAX as u64).to_le_bytes());
+        assert!(matches!(
+            Wav::new(SliceSource::new(&bytes)),
+            Err(Error::TooLarge)
+        ));
+        assert_eq!(parse_chunks(&bytes), Err(Error::TooLarge));
+    }
+
+    #[test]
+    fn should_return_errors_instead_of_panicking() {
+        assert!(Wav::new(SliceSource::new(&HEADER[..30])).is_err());
+
+        let mut wav = Wav::new(SliceSource::new(&HEADER)).unwrap();
+        wav.seek_data(15).unwrap();
+
+        assert!(matches!(wav.next(), Err(Error::EndOfData)));
+        assert!(matches!(wav.next(), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_decode_block() {
+        let header = parse_header_bytes(&HEADER).unwrap();
+
+        match decode_block::<8>(&header.fmt, &HEADER[header.data.start..]).unwrap() {
+            DataBulk::BitDepth16(samples) => {
+                assert_eq!(samples[..4], [0, 0, 0x1724, -3298]);
             }
-            16 => {
-                self.read += NUM * 2;
-                // let mut buf: [u8; 2] = [0; 2];
-                // assert!(self.file.read(&mut buf).unwrap() == 2);
-                // // Ok(Data::BitDepth16(i16::from_le_bytes([buf[0], buf[1]])))
-                Err(Error::UnsupportedBitDepth(16))
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_stream_adpcm_blocks() {
+        let mut bytes = HEADER;
+        bytes[20..22].copy_from_slice(&0x11u16.to_le_bytes());
+        bytes[22..24].copy_from_slice(&1u16.to_le_bytes());
+        bytes[32..34].copy_from_slice(&8u16.to_le_bytes());
+        bytes[34..36].copy_from_slice(&4u16.to_le_bytes());
+        bytes[44..52].copy_from_slice(&[0, 0, 0, 0, 0x10, 0x32, 0x98, 0xba]);
+        bytes[52..60].copy_from_slice(&[0x10, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.fmt.frames_per_block(), 9);
+
+        let mut out = [0; 9];
+        assert_eq!(wav.next_adpcm_block(&mut out).unwrap(), 9);
+        assert_eq!(out, [0, 0, 1, 4, 8, 8, 7, 4, 0]);
+        assert_eq!(wav.timestamp().frames, 9);
+
+        assert_eq!(wav.next_adpcm_block(&mut out).unwrap(), 9);
+        assert_eq!(out, [16; 9]);
+        assert!(wav.is_end());
+        assert_eq!(wav.next_adpcm_block(&mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn should_read_whole_frames_of_many_channels() {
+        let mut bytes = HEADER;
+        bytes[22..24].copy_from_slice(&6u16.to_le_bytes());
+        bytes[32..34].copy_from_slice(&12u16.to_le_bytes());
+
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert!(matches!(
+            wav.next_frame::<4>(),
+            Err(Error::TooManyChannels(6))
+        ));
+
+        let frame = wav.next_frame::<8>().unwrap();
+        assert_eq!(frame.len(), 6);
+        assert_eq!(frame[2], Data::BitDepth16(0x1724));
+        assert_eq!(wav.timestamp().frames, 1);
+
+        wav.seek_data(0).unwrap();
+
+        match wav.next_n::<8>().unwrap() {
+            DataBulk::BitDepth16(samples) => {
+                assert_eq!(samples[..], [0, 0, 0x1724, -3298, 0x133c, 0x143c])
             }
-            24 => {
-                self.read += NUM * 3;
-                // let mut buf: [u8; 3] = [0; 3];
-                // assert!(self.file.read(&mut buf).unwrap() == 3);
+            _ => panic!("expected 16 bit samples"),
+        }
+        assert_eq!(wav.timestamp().frames, 1);
+    }
+
+    #[test]
+    fn should_play_from_embedded_bytes() {
+        let bytes = include_bytes!("../test_files/mono_16_48000.wav");
+        let mut wav = Wav::from_bytes(bytes).unwrap();
+
+        assert_eq!(wav.fmt.num_channels, 1);
+        assert_eq!(wav.fmt.sample_rate, 48_000);
+        assert_eq!(wav.data.start, 690);
+
+        match wav.next_n::<16>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples.len(), 16),
+            _ => panic!("expected 16 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_preload_data_chunk() {
+        let mut wav = Wav::new(SliceSource::new(&HEADER)).unwrap();
+        let mut ram = [0; 16];
+
+        assert!(matches!(
+            wav.preload(&mut [0; 8]),
+            Err(Error::BufferTooSmall(16))
+        ));
+
+        let mut preloaded = wav.preload(&mut ram).unwrap();
+
+        assert_eq!(preloaded.fmt, wav.fmt);
+        assert_eq!(preloaded.next().unwrap().as_i32(), 0);
+        assert_eq!(wav.timestamp().frames, 0);
+    }
 
-                // let sign = buf[2] >> 7;
-                // let sign_byte = if sign == 1 { 0xff } else { 0x0 };
+    #[test]
+    fn should_read_through_preroll() {
+        let mut ram = [0; 8];
+        let mut wav = Wav::new_with_preroll(SliceSource::new(&HEADER), &mut ram, 1).unwrap();
 
-                // Ok(Data::BitDepth24(i32::from_le_bytes([
-                //     buf[0], buf[1], buf[2], sign_byte,
-                // ])))
-                Err(Error::UnsupportedBitDepth(24))
+        let samples: [i32; 8] = core::array::from_fn(|_| wav.next().unwrap().as_i32());
+
+        assert_eq!(samples, [0, 0, 0x1724, -3298, 0x133c, 0x143c, -1770, -1768]);
+        assert_eq!(wav.timestamp().frames, 4);
+    }
+
+    #[test]
+    fn should_read_the_same_samples_buffered() {
+        let bytes = include_bytes!("../test_files/stereo_24_48000.wav");
+        let mut wav = Wav::new_buffered(SliceSource::new(bytes)).unwrap();
+        let mut reference = Wav::from_bytes(bytes).unwrap();
+
+        for _ in 0..1000 {
+            assert_eq!(wav.next().unwrap(), reference.next().unwrap());
+        }
+
+        assert_eq!(wav.next_n::<300>(), reference.next_n::<300>());
+        assert_eq!(wav.timestamp(), reference.timestamp());
+    }
+
+    #[test]
+    fn should_read_bulk_16_bit_samples() {
+        let mut wav = Wav::new(SliceSource::new(&HEADER)).unwrap();
+
+        match wav.next_n::<6>().unwrap() {
+            DataBulk::BitDepth16(samples) => {
+                assert_eq!(samples, [0, 0, 0x1724, -3298, 0x133c, 0x143c]);
+            }
+            _ => panic!("expected 16 bit samples"),
+        }
+
+        // only two samples are left
+        match wav.next_n::<6>().unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [-1770, -1768]),
+            _ => panic!("expected 16 bit samples"),
+        }
+
+        assert!(wav.is_end());
+    }
+
+    #[test]
+    fn should_time_opening() {
+        struct StepClock(core::cell::Cell<u64>);
+
+        impl Clock for StepClock {
+            fn now_micros(&self) -> u64 {
+                let now = self.0.get();
+                self.0.set(now + 10);
+                now
             }
-            _ => Err(Error::UnsupportedBitDepth(self.fmt.bit_depth)),
         }
+
+        let clock = StepClock(core::cell::Cell::new(0));
+        let (_, first, timing) = Wav::new_timed::<_, 4>(SliceSource::new(&HEADER), &clock).unwrap();
+
+        assert_eq!(first.len(), 4);
+        assert_eq!(timing.header_micros, 10);
+        assert_eq!(timing.total_micros(), 20);
     }
 
-    pub fn destroy(self) -> File<'a, BD, TS, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
-        self.file
+    #[test]
+    fn should_read_info_metadata_after_data() {
+        let info: [u8; 50] = [
+            0x4c, 0x49, 0x53, 0x54, // LIST
+            0x2a, 0x00, 0x00, 0x00, // chunk size
+            0x49, 0x4e, 0x46, 0x4f, // INFO
+            0x49, 0x41, 0x52, 0x54, // IART
+            0x05, 0x00, 0x00, 0x00, // chunk size
+            b'B', b'e', b'c', b'k', 0x00, 0x00, // value and padding byte
+            0x49, 0x54, 0x52, 0x4b, // ITRK
+            0x02, 0x00, 0x00, 0x00, // chunk size
+            b'1', 0x00, // value
+            0x49, 0x4e, 0x41, 0x4d, // INAM
+            0x06, 0x00, 0x00, 0x00, // chunk size
+            b'L', b'o', b's', b'e', b'r', 0x00, // value
+        ];
+
+        let bytes: std::vec::Vec<u8> = HEADER.iter().chain(info.iter()).copied().collect();
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        let metadata: Metadata<16> = wav.metadata().unwrap();
+
+        assert_eq!(metadata.artist(), Some("Beck"));
+        assert_eq!(metadata.title(), Some("Loser"));
+        assert_eq!(metadata.genre(), None);
+        assert_eq!(wav.timestamp().frames, 0);
+
+        // the trailing chunks aren't known until loaded
+        assert_eq!(wav.chunks.len(), 0);
+        wav.next_n::<2>().unwrap();
+
+        let trailing: Metadata<16> = wav.load_trailing_metadata().unwrap();
+        assert_eq!(trailing, metadata);
+        assert_eq!(wav.chunks.len(), 1);
+        assert_eq!(wav.chunks[0].id, ChunkTag::List);
+        assert_eq!(wav.timestamp().frames, 1);
+
+        // loading twice doesn't add the chunks again
+        let _: Metadata<16> = wav.load_trailing_metadata().unwrap();
+        assert_eq!(wav.chunks.len(), 1);
+
+        let mut plain = Wav::new(SliceSource::new(&HEADER)).unwrap();
+        let none: Metadata<16> = plain.load_trailing_metadata().unwrap();
+        assert_eq!(none, Metadata::default());
+    }
+
+    #[test]
+    fn should_report_duration_and_position() {
+        let mut wav = Wav::new(SliceSource::new(&HEADER)).unwrap();
+        assert_eq!(wav.duration().unwrap().frames, 4);
+        assert_eq!(wav.total_samples().unwrap(), 4);
+
+        wav.next_n::<2>().unwrap();
+        assert_eq!(wav.position().frames, 1);
+
+        let fact = [
+            0x66, 0x61, 0x63, 0x74, 0x04, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00,
+        ];
+
+        // PCM lengths come from the data chunk, whatever the fact chunk says
+        let bytes: std::vec::Vec<u8> = HEADER.iter().chain(fact.iter()).copied().collect();
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+        assert_eq!(wav.total_samples().unwrap(), 4);
+
+        let mut adpcm = HEADER;
+        adpcm[20..22].copy_from_slice(&0x11u16.to_le_bytes());
+        adpcm[22..24].copy_from_slice(&1u16.to_le_bytes());
+        adpcm[32..34].copy_from_slice(&8u16.to_le_bytes());
+        adpcm[34..36].copy_from_slice(&4u16.to_le_bytes());
+
+        let bytes: std::vec::Vec<u8> = adpcm.iter().chain(fact.iter()).copied().collect();
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(wav.duration().unwrap().frames, 15);
+        assert_eq!(wav.total_samples().unwrap(), 15);
+        assert_eq!(wav.position().frames, 0);
+
+        // a fact chunk too short for the count is ignored
+        let fact = [0x66, 0x61, 0x63, 0x74, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00];
+        let bytes: std::vec::Vec<u8> = adpcm.iter().chain(fact.iter()).copied().collect();
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(wav.total_samples().unwrap(), 18);
+    }
+
+    #[test]
+    fn should_seek_to_whole_frames() {
+        let mut wav = Wav::new(SliceSource::new(&HEADER)).unwrap();
+
+        assert_eq!(wav.seek_to_sample(2).unwrap().frames, 2);
+        assert_eq!(wav.next(), Ok(Data::BitDepth16(0x133c)));
+
+        assert_eq!(wav.seek_to_sample(100).unwrap().frames, 4);
+        assert!(wav.is_end());
+
+        // 22050 Hz, so one frame lasts about 45 µs
+        assert_eq!(wav.seek_to_millis(0).unwrap().frames, 0);
+    }
+
+    #[test]
+    fn should_stop_reading_at_data_chunk_end() {
+        let list = [
+            0x4c, 0x49, 0x53, 0x54, 0x04, 0x00, 0x00, 0x00, 0x49, 0x4e, 0x46, 0x4f,
+        ];
+        let bytes: std::vec::Vec<u8> = HEADER.iter().chain(list.iter()).copied().collect();
+        let mut wav = Wav::new(SliceSource::new(&bytes)).unwrap();
+
+        assert_eq!(wav.next_n::<16>().unwrap().len(), 8);
+        assert!(wav.is_end());
+        assert!(wav.next_n::<16>().unwrap().is_empty());
+        assert!(matches!(wav.next(), Err(Error::EndOfData)));
+    }
+
+    #[test]
+    fn should_decode_float_block() {
+        let fmt = Fmt {
+            codec: AudioCodec::IeeeFloat,
+            sample_rate: 48_000,
+            num_channels: 1,
+            bit_depth: 32,
+            block_size: 4,
+        };
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&0.5f32.to_le_bytes());
+        bytes[4..].copy_from_slice(&(-1.0f32).to_le_bytes());
+
+        match decode_block::<4>(&fmt, &bytes).unwrap() {
+            DataBulk::Float32(samples) => assert_eq!(samples, [0.5, -1.0]),
+            _ => panic!("expected float samples"),
+        }
+    }
+
+    #[test]
+    fn should_expand_companded_block() {
+        let mut fmt = Fmt {
+            codec: AudioCodec::MuLaw,
+            sample_rate: 8_000,
+            num_channels: 1,
+            bit_depth: 8,
+            block_size: 1,
+        };
+
+        match decode_block::<4>(&fmt, &[0xff, 0x80, 0x00]).unwrap() {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, [0, 32_124, -32_124]),
+            _ => panic!("expected 16 bit samples"),
+        }
+
+        fmt.codec = AudioCodec::ALaw;
+        assert_eq!(Data::from_bytes(&fmt, &[0xd5]), Ok(Data::BitDepth16(8)));
+    }
+
+    #[test]
+    fn should_decode_24_bit_block() {
+        let fmt = Fmt {
+            codec: AudioCodec::Pcm,
+            sample_rate: 48_000,
+            num_channels: 1,
+            bit_depth: 24,
+            block_size: 3,
+        };
+        let bytes = [0x01, 0x00, 0x80, 0xff, 0xff, 0x7f, 0xaa];
+
+        match decode_block::<4>(&fmt, &bytes).unwrap() {
+            DataBulk::BitDepth24(samples) => assert_eq!(samples, [-8_388_607, 8_388_607]),
+            _ => panic!("expected 24 bit samples"),
+        }
     }
 }
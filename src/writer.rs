@@ -0,0 +1,505 @@
+use crate::cue::{CuePoint, CUE_POINT_SIZE};
+use crate::error::Error;
+use crate::fmt::{AudioCodec, Fmt};
+use crate::g711::{linear_to_a_law, linear_to_mu_law};
+use crate::monitor::Monitor;
+use crate::remux::write_header;
+use crate::sink::AudioSink;
+use crate::source::AudioSource;
+use crate::vad::VoiceSegment;
+use crate::wav::DataBulk;
+use core::convert::TryFrom;
+use heapless::Vec;
+
+/// Size of an `ltxt` entry without text, the cue id, length, purpose and four language fields
+const LTXT_SIZE: u32 = 20;
+/// Label given to voice segments, null terminated and of even length so no padding is needed
+const VOICE_LABEL: &[u8; 6] = b"voice\0";
+/// Size of the `labl` entry of a voice segment, the cue id and the label
+const LABL_SIZE: u32 = 4 + VOICE_LABEL.len() as u32;
+
+/// Records a WAV file, e.g. from a microphone, to storage that can seek such as an embedded_sdmmc File
+///
+/// A header without samples is written up front and its RIFF and data sizes are patched by
+/// [`WavWriter::finalize`] once all samples are written.
+pub struct WavWriter<W> {
+    sink: W,
+    fmt: Fmt,
+    start: u32,
+    header_len: u32,
+    data_len: u32,
+}
+
+impl<W: AudioSource + AudioSink> WavWriter<W> {
+    /// Start a file for samples in `fmt` at the current position of `sink`
+    pub fn new(mut sink: W, fmt: Fmt) -> Result<Self, Error<<W as AudioSource>::Error>> {
+        let start = sink.offset();
+        let header_len = write_header(&mut sink, &fmt, 0)?;
+
+        Ok(WavWriter {
+            sink,
+            fmt,
+            start,
+            header_len,
+            data_len: 0,
+        })
+    }
+
+    /// Format of the samples being written
+    pub fn fmt(&self) -> &Fmt {
+        &self.fmt
+    }
+
+    /// Number of whole frames written so far
+    pub fn frames_written(&self) -> usize {
+        self.data_len as usize / self.fmt.block_align().max(1)
+    }
+
+    /// Append raw interleaved little endian sample bytes, which are written unchanged.
+    ///
+    /// Returns [`Error::TooLarge`] instead of growing the data chunk past 4 GiB.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error<<W as AudioSource>::Error>> {
+        let data_len = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|len| self.data_len.checked_add(len))
+            .ok_or(Error::TooLarge)?;
+
+        AudioSink::write(&mut self.sink, bytes).map_err(|_| Error::Io)?;
+        self.data_len = data_len;
+
+        Ok(())
+    }
+
+    /// Append interleaved samples, which have to match the codec and bit depth of the file.
+    ///
+    /// A-law and µ-law files take 16 bit samples, which are compressed as they are written.
+    pub fn write_samples<const NUM: usize>(
+        &mut self,
+        samples: &DataBulk<NUM>,
+    ) -> Result<(), Error<<W as AudioSource>::Error>> {
+        match (self.fmt.codec, self.fmt.bit_depth, samples) {
+            (AudioCodec::Pcm, 8, DataBulk::BitDepth8(samples)) => self.write_bytes(samples),
+            (AudioCodec::Pcm, 16, DataBulk::BitDepth16(samples)) => {
+                self.write_encoded(samples, |s| s.to_le_bytes())
+            }
+            (AudioCodec::Pcm, 24, DataBulk::BitDepth24(samples)) => {
+                self.write_encoded(samples, |s| {
+                    let [b0, b1, b2, _] = s.to_le_bytes();
+                    [b0, b1, b2]
+                })
+            }
+            (AudioCodec::IeeeFloat, 32, DataBulk::Float32(samples)) => {
+                self.write_encoded(samples, |s| s.to_le_bytes())
+            }
+            (AudioCodec::ALaw, 8, DataBulk::BitDepth16(samples)) => {
+                self.write_encoded(samples, |&s| [linear_to_a_law(s)])
+            }
+            (AudioCodec::MuLaw, 8, DataBulk::BitDepth16(samples)) => {
+                self.write_encoded(samples, |&s| [linear_to_mu_law(s)])
+            }
+            _ => Err(Error::FormatMismatch),
+        }
+    }
+
+    /// Same as [`WavWriter::write_samples`], also passing the samples through `monitor` into
+    /// `output` for the playback path, e.g. headphone monitoring while recording.
+    ///
+    /// Samples are monitored at 16 bit whatever the format of the file. Returns the number of
+    /// samples written to `output`, which has to hold all of them.
+    pub fn write_monitored<const NUM: usize, const N: usize>(
+        &mut self,
+        samples: &DataBulk<NUM>,
+        monitor: &mut Monitor<N>,
+        output: &mut [i16],
+    ) -> Result<usize, Error<<W as AudioSource>::Error>> {
+        if output.len() < samples.len() {
+            return Err(Error::BufferTooSmall(samples.len()));
+        }
+
+        self.write_samples(samples)?;
+
+        let mut written = 0;
+        samples.for_each_i16(|sample| {
+            output[written] = monitor.process_sample(sample);
+            written += 1;
+        });
+
+        Ok(written)
+    }
+
+    /// Encode `samples` through a small buffer, so storage sees few large writes
+    fn write_encoded<T, const N: usize>(
+        &mut self,
+        samples: &[T],
+        encode: impl Fn(&T) -> [u8; N],
+    ) -> Result<(), Error<<W as AudioSource>::Error>> {
+        let mut buf = [0; 240];
+
+        for batch in samples.chunks(buf.len() / N) {
+            for (out, sample) in buf.chunks_exact_mut(N).zip(batch) {
+                out.copy_from_slice(&encode(sample));
+            }
+
+            self.write_bytes(&buf[..batch.len() * N])?;
+        }
+
+        Ok(())
+    }
+
+    /// Pad the data chunk and patch the sizes in the header, returning the sink positioned after
+    /// the end of the file. Returns [`Error::TooLarge`] if the file doesn't fit the 32 bit RIFF
+    /// sizes.
+    pub fn finalize(self) -> Result<W, Error<<W as AudioSource>::Error>> {
+        self.finalize_with_segments(&[])
+    }
+
+    /// Same as [`WavWriter::finalize`], marking `segments` found by a [`VoiceLog`](crate::VoiceLog).
+    ///
+    /// Every segment becomes a cue point at its start, numbered from `1`, and an `ltxt` region
+    /// labeled `voice` in a `LIST` `adtl` chunk, so [`Wav::cue_triggers`](crate::Wav::cue_triggers)
+    /// jumps straight to the speech of the recording.
+    pub fn finalize_with_segments(
+        mut self,
+        segments: &[VoiceSegment],
+    ) -> Result<W, Error<<W as AudioSource>::Error>> {
+        let padding = self.data_len & 1;
+        let mut riff_len = (self.header_len - 8 + padding)
+            .checked_add(self.data_len)
+            .ok_or(Error::TooLarge)?;
+
+        if padding == 1 {
+            AudioSink::write(&mut self.sink, &[0]).map_err(|_| Error::Io)?;
+        }
+
+        if !segments.is_empty() {
+            riff_len = riff_len
+                .checked_add(self.write_segments(segments)?)
+                .ok_or(Error::TooLarge)?;
+        }
+
+        self.sink.seek(self.start + 4).map_err(Error::Source)?;
+        AudioSink::write(&mut self.sink, &riff_len.to_le_bytes()).map_err(|_| Error::Io)?;
+
+        self.sink
+            .seek(self.start + self.header_len - 4)
+            .map_err(Error::Source)?;
+        AudioSink::write(&mut self.sink, &self.data_len.to_le_bytes()).map_err(|_| Error::Io)?;
+
+        let end = (self.start + 8)
+            .checked_add(riff_len)
+            .ok_or(Error::TooLarge)?;
+        self.sink.seek(end).map_err(Error::Source)?;
+
+        Ok(self.sink)
+    }
+
+    /// Write the `cue ` and `LIST` `adtl` chunks marking `segments`, returning their size
+    fn write_segments(
+        &mut self,
+        segments: &[VoiceSegment],
+    ) -> Result<u32, Error<<W as AudioSource>::Error>> {
+        let count = segments.len() as u32;
+        let cue_len = 4 + count * CUE_POINT_SIZE as u32;
+        let adtl_len = 4 + count * (8 + LTXT_SIZE + 8 + LABL_SIZE);
+
+        self.write_chunk_header(b"cue ", cue_len)?;
+        AudioSink::write(&mut self.sink, &count.to_le_bytes()).map_err(|_| Error::Io)?;
+
+        for (id, segment) in (1..).zip(segments) {
+            let cue = CuePoint {
+                id,
+                position: id - 1,
+                sample_offset: segment.start_frame,
+            };
+
+            AudioSink::write(&mut self.sink, &cue.to_bytes()).map_err(|_| Error::Io)?;
+        }
+
+        self.write_chunk_header(b"LIST", adtl_len)?;
+        AudioSink::write(&mut self.sink, b"adtl").map_err(|_| Error::Io)?;
+
+        for (id, segment) in (1u32..).zip(segments) {
+            let mut ltxt = [0; LTXT_SIZE as usize];
+            ltxt[0..4].copy_from_slice(&id.to_le_bytes());
+            ltxt[4..8].copy_from_slice(&segment.len_frames.to_le_bytes());
+            ltxt[8..12].copy_from_slice(b"rgn ");
+
+            self.write_chunk_header(b"ltxt", LTXT_SIZE)?;
+            AudioSink::write(&mut self.sink, &ltxt).map_err(|_| Error::Io)?;
+
+            let mut labl = [0; LABL_SIZE as usize];
+            labl[0..4].copy_from_slice(&id.to_le_bytes());
+            labl[4..].copy_from_slice(VOICE_LABEL);
+
+            self.write_chunk_header(b"labl", LABL_SIZE)?;
+            AudioSink::write(&mut self.sink, &labl).map_err(|_| Error::Io)?;
+        }
+
+        Ok(8 + cue_len + 8 + adtl_len)
+    }
+
+    fn write_chunk_header(
+        &mut self,
+        tag: &[u8; 4],
+        len: u32,
+    ) -> Result<(), Error<<W as AudioSource>::Error>> {
+        AudioSink::write(&mut self.sink, tag).map_err(|_| Error::Io)?;
+        AudioSink::write(&mut self.sink, &len.to_le_bytes()).map_err(|_| Error::Io)
+    }
+}
+
+/// Records the same input to a full quality archive and a small G.711 proxy at once, e.g. a file
+/// kept on the SD card and one uploaded over a slow network link
+///
+/// The proxy keeps the sample rate and channels of the archive, its samples are companded to 8 bit
+/// A-law or µ-law, about a third of the size of 24 bit audio.
+pub struct DualWriter<W> {
+    archive: WavWriter<W>,
+    proxy: WavWriter<W>,
+}
+
+impl<W: AudioSource + AudioSink> DualWriter<W> {
+    /// Start an `archive` file in `fmt` and a `proxy` file in `proxy_codec`, which has to be
+    /// [`AudioCodec::ALaw`] or [`AudioCodec::MuLaw`]
+    pub fn new(
+        archive: W,
+        proxy: W,
+        fmt: Fmt,
+        proxy_codec: AudioCodec,
+    ) -> Result<Self, Error<<W as AudioSource>::Error>> {
+        if !matches!(proxy_codec, AudioCodec::ALaw | AudioCodec::MuLaw) {
+            return Err(Error::FormatMismatch);
+        }
+
+        let proxy_fmt = Fmt {
+            codec: proxy_codec,
+            bit_depth: 8,
+            block_size: fmt.num_channels,
+            ..fmt
+        };
+
+        Ok(DualWriter {
+            archive: WavWriter::new(archive, fmt)?,
+            proxy: WavWriter::new(proxy, proxy_fmt)?,
+        })
+    }
+
+    /// Number of whole frames written so far
+    pub fn frames_written(&self) -> usize {
+        self.archive.frames_written()
+    }
+
+    /// Append interleaved samples matching the format of the archive to both files
+    pub fn write_samples<const NUM: usize>(
+        &mut self,
+        samples: &DataBulk<NUM>,
+    ) -> Result<(), Error<<W as AudioSource>::Error>> {
+        self.archive.write_samples(samples)?;
+
+        let mut proxy = Vec::<i16, NUM>::new();
+        // can't fail, the proxy holds as many samples as the input
+        samples.for_each_i16(|sample| {
+            let _ = proxy.push(sample);
+        });
+
+        self.proxy.write_samples(&DataBulk::BitDepth16(proxy))
+    }
+
+    /// Finalize both files, returning the archive and the proxy sink
+    pub fn finalize(self) -> Result<(W, W), Error<<W as AudioSource>::Error>> {
+        Ok((self.archive.finalize()?, self.proxy.finalize()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::RamFile;
+    use crate::wav::Wav;
+
+    const FMT: Fmt = Fmt {
+        codec: AudioCodec::Pcm,
+        sample_rate: 16_000,
+        num_channels: 1,
+        bit_depth: 24,
+        block_size: 3,
+    };
+
+    #[test]
+    fn should_refuse_files_past_4_gib() {
+        let file = RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = WavWriter::new(file, FMT).unwrap();
+
+        // as if nearly 4 GiB were recorded already
+        writer.data_len = u32::MAX - 40;
+        assert_eq!(writer.write_bytes(&[0; 48]), Err(Error::TooLarge));
+        assert_eq!(writer.data_len, u32::MAX - 40);
+
+        writer.write_bytes(&[0; 6]).unwrap();
+        assert!(matches!(writer.finalize(), Err(Error::TooLarge)));
+    }
+
+    #[test]
+    fn should_write_readable_file() {
+        let file = RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = WavWriter::new(file, FMT).unwrap();
+
+        let samples: Vec<i32, 4> = Vec::from_slice(&[0, 1, -1, 0x7f_ffff]).unwrap();
+        writer
+            .write_samples(&DataBulk::BitDepth24(samples.clone()))
+            .unwrap();
+        writer
+            .write_samples(&DataBulk::BitDepth24(samples))
+            .unwrap();
+        assert_eq!(writer.frames_written(), 8);
+
+        let mut file = writer.finalize().unwrap();
+        assert_eq!(file.offset, 44 + 24);
+
+        file.offset = 0;
+        let mut wav = Wav::new(file).unwrap();
+        assert_eq!(wav.fmt, FMT);
+        assert_eq!(wav.data.end - wav.data.start, 24);
+
+        match wav.next_n::<8>().unwrap() {
+            DataBulk::BitDepth24(read) => {
+                assert_eq!(read, [0, 1, -1, 0x7f_ffff, 0, 1, -1, 0x7f_ffff])
+            }
+            _ => panic!("expected 24 bit samples"),
+        }
+    }
+
+    #[test]
+    fn should_pad_odd_data_and_reject_other_formats() {
+        let file = RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = WavWriter::new(file, FMT).unwrap();
+
+        writer.write_bytes(&[1, 2, 3]).unwrap();
+        assert_eq!(
+            writer.write_samples(&DataBulk::BitDepth16(Vec::<i16, 1>::new())),
+            Err(Error::FormatMismatch)
+        );
+
+        let file = writer.finalize().unwrap();
+
+        assert_eq!(file.bytes.len(), 48);
+        assert_eq!(file.bytes[4..8], 40u32.to_le_bytes());
+        assert_eq!(file.bytes[40..44], 3u32.to_le_bytes());
+    }
+
+    #[test]
+    fn should_mark_voice_segments_with_cue_points() {
+        let file = RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = WavWriter::new(file, FMT).unwrap();
+        writer.write_bytes(&[0; 30]).unwrap();
+
+        let segments = [
+            VoiceSegment {
+                start_frame: 2,
+                len_frames: 4,
+            },
+            VoiceSegment {
+                start_frame: 8,
+                len_frames: 1,
+            },
+        ];
+        let mut file = writer.finalize_with_segments(&segments).unwrap();
+
+        assert_eq!(file.offset, file.bytes.len());
+        assert_eq!(
+            file.bytes[4..8],
+            (file.bytes.len() as u32 - 8).to_le_bytes()
+        );
+
+        file.offset = 0;
+        let mut wav = Wav::new(file).unwrap();
+        let triggers = wav.cue_triggers::<4>().unwrap();
+        let frames: Vec<(u64, u32), 4> = triggers.iter().map(|t| (t.frame, t.id)).collect();
+
+        assert_eq!(frames, [(2, 1), (8, 2)]);
+        assert!(wav.find_list(*b"adtl").unwrap().is_some());
+
+        let file = wav.destroy();
+        let ltxt = file.bytes.windows(4).position(|w| w == b"ltxt").unwrap();
+
+        assert_eq!(file.bytes[ltxt + 8..ltxt + 12], 1u32.to_le_bytes());
+        assert_eq!(file.bytes[ltxt + 12..ltxt + 16], 4u32.to_le_bytes());
+        assert_eq!(&file.bytes[ltxt + 16..ltxt + 20], b"rgn ");
+    }
+
+    #[test]
+    fn should_write_archive_and_proxy() {
+        let file = || RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = DualWriter::new(file(), file(), FMT, AudioCodec::MuLaw).unwrap();
+
+        let samples: Vec<i32, 4> = Vec::from_slice(&[0, 0x10_0000, -0x10_0000, 0x7f_ffff]).unwrap();
+        writer
+            .write_samples(&DataBulk::BitDepth24(samples))
+            .unwrap();
+        assert_eq!(writer.frames_written(), 4);
+
+        let (archive, mut proxy) = writer.finalize().unwrap();
+        assert_eq!(archive.bytes.len(), 44 + 12);
+        // µ-law fmt chunks carry a cbSize
+        assert_eq!(proxy.bytes.len(), 46 + 4);
+
+        proxy.offset = 0;
+        let mut proxy = Wav::new(proxy).unwrap();
+        assert_eq!(proxy.fmt.codec, AudioCodec::MuLaw);
+        assert_eq!(proxy.fmt.sample_rate, FMT.sample_rate);
+
+        match proxy.next_n::<4>().unwrap() {
+            DataBulk::BitDepth16(read) => assert_eq!(read, [0, 4092, -4092, 32_124]),
+            _ => panic!("expected expanded µ-law samples"),
+        }
+
+        assert!(matches!(
+            DualWriter::new(file(), file(), FMT, AudioCodec::Pcm),
+            Err(Error::FormatMismatch)
+        ));
+    }
+
+    #[test]
+    fn should_monitor_recorded_samples() {
+        let file = RamFile {
+            bytes: std::vec::Vec::new(),
+            offset: 0,
+        };
+        let mut writer = WavWriter::new(file, FMT).unwrap();
+        let mut monitor = Monitor::<4>::new(1);
+        monitor.set_latency_frames(1).unwrap();
+
+        let samples: Vec<i32, 3> = Vec::from_slice(&[0x100, 0x200, 0x300]).unwrap();
+        let mut output = [0; 3];
+        let written = writer
+            .write_monitored(&DataBulk::BitDepth24(samples), &mut monitor, &mut output)
+            .unwrap();
+
+        assert_eq!(written, 3);
+        assert_eq!(output, [0, 1, 2]);
+        assert_eq!(writer.frames_written(), 3);
+        assert_eq!(
+            writer.write_monitored(
+                &DataBulk::BitDepth24(Vec::<i32, 3>::from_slice(&[0; 3]).unwrap()),
+                &mut monitor,
+                &mut [0; 2]
+            ),
+            Err(Error::BufferTooSmall(3))
+        );
+    }
+}
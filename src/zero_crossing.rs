@@ -0,0 +1,142 @@
+use crate::wav::DataBulk;
+
+/// Midpoint of unsigned 8 bit samples
+const SILENCE_8: u8 = 128;
+
+impl<const NUM: usize> DataBulk<NUM> {
+    /// Number of samples in the buffer, counting every channel
+    pub fn len(&self) -> usize {
+        match self {
+            DataBulk::BitDepth8(samples) => samples.len(),
+            DataBulk::BitDepth16(samples) => samples.len(),
+            DataBulk::BitDepth24(samples) => samples.len(),
+            DataBulk::Float32(samples) => samples.len(),
+        }
+    }
+
+    /// True when the buffer holds no samples
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Frame index of the zero crossing closest to `frame`, searching the whole buffer.
+    ///
+    /// Channels are summed before looking for a sign change, a frame that sums to zero counts as
+    /// a crossing as well. Returns `None` when the buffer has no zero crossing at all.
+    pub fn nearest_zero_crossing(&self, num_channels: u16, frame: usize) -> Option<usize> {
+        let channels = (num_channels as usize).max(1);
+        let frames = self.len() / channels;
+        let level = |f: usize| {
+            (0..channels)
+                .map(|c| self.centered(f * channels + c))
+                .sum::<i64>()
+        };
+
+        (0..frames)
+            .filter(|&f| {
+                let current = level(f);
+                current == 0 || (f > 0 && (level(f - 1) < 0) != (current < 0))
+            })
+            .min_by_key(|&f| (f as i64 - frame as i64).abs())
+    }
+
+    /// Silence every frame before `frame`, used to start playback on a zero crossing
+    pub fn mute_before(&mut self, num_channels: u16, frame: usize) {
+        let end = frame
+            .saturating_mul((num_channels as usize).max(1))
+            .min(self.len());
+        self.mute(0, end);
+    }
+
+    /// Silence `frame` and every frame after it, used to stop playback on a zero crossing
+    pub fn mute_after(&mut self, num_channels: u16, frame: usize) {
+        let start = frame
+            .saturating_mul((num_channels as usize).max(1))
+            .min(self.len());
+        self.mute(start, self.len());
+    }
+
+    fn mute(&mut self, start: usize, end: usize) {
+        match self {
+            DataBulk::BitDepth8(samples) => samples[start..end].fill(SILENCE_8),
+            DataBulk::BitDepth16(samples) => samples[start..end].fill(0),
+            DataBulk::BitDepth24(samples) => samples[start..end].fill(0),
+            DataBulk::Float32(samples) => samples[start..end].fill(0.0),
+        }
+    }
+
+    /// Sample at `index` as a signed value centered around zero
+    fn centered(&self, index: usize) -> i64 {
+        match self {
+            DataBulk::BitDepth8(samples) => samples[index] as i64 - SILENCE_8 as i64,
+            DataBulk::BitDepth16(samples) => samples[index] as i64,
+            DataBulk::BitDepth24(samples) => samples[index] as i64,
+            // scaled to 24 bits, only the sign and relative level matter
+            DataBulk::Float32(samples) => (samples[index] * 8_388_608.0) as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::Vec;
+
+    #[test]
+    fn should_find_nearest_zero_crossing() {
+        let samples: Vec<i16, 8> = Vec::from_slice(&[-30, -10, 10, 30, 20, 5, -5, -20]).unwrap();
+        let data = DataBulk::BitDepth16(samples);
+
+        assert_eq!(data.nearest_zero_crossing(1, 0), Some(2));
+        assert_eq!(data.nearest_zero_crossing(1, 5), Some(6));
+        assert_eq!(data.nearest_zero_crossing(2, 3), Some(3));
+    }
+
+    #[test]
+    fn should_not_find_crossing_without_sign_change() {
+        let samples: Vec<u8, 4> = Vec::from_slice(&[200, 210, 220, 230]).unwrap();
+        let data = DataBulk::BitDepth8(samples);
+
+        assert_eq!(data.nearest_zero_crossing(1, 2), None);
+    }
+
+    #[test]
+    fn should_mute_around_frame() {
+        let samples: Vec<u8, 4> = Vec::from_slice(&[1, 2, 3, 4]).unwrap();
+        let mut data = DataBulk::BitDepth8(samples);
+
+        data.mute_before(2, 1);
+
+        match &data {
+            DataBulk::BitDepth8(samples) => assert_eq!(samples, &[128, 128, 3, 4]),
+            _ => unreachable!(),
+        }
+
+        data.mute_after(1, 3);
+
+        match &data {
+            DataBulk::BitDepth8(samples) => assert_eq!(samples, &[128, 128, 3, 128]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn should_clamp_frames_past_the_end() {
+        let samples: Vec<i16, 4> = Vec::from_slice(&[1, 2, 3, 4]).unwrap();
+        let mut data = DataBulk::BitDepth16(samples);
+
+        data.mute_after(2, usize::MAX);
+
+        match &data {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, &[1, 2, 3, 4]),
+            _ => unreachable!(),
+        }
+
+        data.mute_before(2, usize::MAX);
+
+        match &data {
+            DataBulk::BitDepth16(samples) => assert_eq!(samples, &[0, 0, 0, 0]),
+            _ => unreachable!(),
+        }
+    }
+}